@@ -0,0 +1,24 @@
+import { z } from "zod";
+
+export const CaptionConfigSchema = z.object({
+	// The format of each frame, ex. "wvtt", "stpp" or "cea-608".
+	format: z.string(),
+
+	// The language as an ISO 639-2 code, ex. "eng".
+	language: z.string().optional(),
+
+	// Some formats include a header, ex. the WebVTT file header.
+	description: z.string().optional(), // hex encoded
+});
+
+export const CaptionsSchema = z.object({
+	// A map of track name to rendition configuration.
+	// This is not an array so it will work with JSON Merge Patch.
+	renditions: z.record(z.string(), CaptionConfigSchema),
+
+	// The priority of the caption tracks, relative to other tracks in the broadcast.
+	priority: z.number().int().min(0).max(255),
+});
+
+export type Captions = z.infer<typeof CaptionsSchema>;
+export type CaptionConfig = z.infer<typeof CaptionConfigSchema>;
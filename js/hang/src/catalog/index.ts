@@ -1,5 +1,6 @@
 export * from "./audio";
 export * from "./capabilities";
+export * from "./captions";
 export * from "./chat";
 export * from "./integers";
 export * from "./location";
@@ -3,6 +3,7 @@ import { z } from "zod";
 
 import { AudioSchema } from "./audio";
 import { CapabilitiesSchema } from "./capabilities";
+import { CaptionsSchema } from "./captions";
 import { ChatSchema } from "./chat";
 import { LocationSchema } from "./location";
 import { TrackSchema } from "./track";
@@ -12,6 +13,7 @@ import { VideoSchema } from "./video";
 export const RootSchema = z.object({
 	video: VideoSchema.optional(),
 	audio: AudioSchema.optional(),
+	captions: CaptionsSchema.optional(),
 	location: LocationSchema.optional(),
 	user: UserSchema.optional(),
 	chat: ChatSchema.optional(),
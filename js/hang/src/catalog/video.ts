@@ -29,6 +29,17 @@ export const VideoConfigSchema = z.object({
 	displayAspectWidth: u53Schema.optional(),
 	displayAspectHeight: u53Schema.optional(),
 
+	// The color space of the video, based on VideoColorSpaceInit.
+	// Required to render HDR content correctly.
+	colorSpace: z
+		.object({
+			primaries: z.string().optional(),
+			transfer: z.string().optional(),
+			matrix: z.string().optional(),
+			fullRange: z.boolean().optional(),
+		})
+		.optional(),
+
 	// The frame rate of the video in frames per second
 	framerate: z.number().optional(),
 
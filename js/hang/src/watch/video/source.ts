@@ -122,6 +122,8 @@ export class Source {
 				const { supported: valid } = await VideoDecoder.isConfigSupported({
 					...rendition,
 					description,
+					// The catalog uses strings, but the values match the WebCodecs enums.
+					colorSpace: rendition.colorSpace as VideoColorSpaceInit | undefined,
 					optimizeForLatency: rendition.optimizeForLatency ?? true,
 				});
 				if (valid) supported[name] = rendition;
@@ -288,6 +290,7 @@ export class Source {
 		decoder.configure({
 			...config,
 			description: config.description ? Hex.toBytes(config.description) : undefined,
+			colorSpace: config.colorSpace as VideoColorSpaceInit | undefined,
 			optimizeForLatency: config.optimizeForLatency ?? true,
 			// @ts-expect-error Only supported by Chrome, so the renderer has to flip manually.
 			flip: false,
@@ -3,6 +3,7 @@ import { z } from "zod";
 export const ClaimsSchema = z
 	.object({
 		root: z.string(),
+		sub: z.string().optional(),
 		put: z.union([z.string(), z.array(z.string())]).optional(),
 		cluster: z.boolean().optional(),
 		get: z.union([z.string(), z.array(z.string())]).optional(),
@@ -226,10 +226,25 @@ test("claims validation - must have pub or sub", async () => {
 	});
 });
 
+test("claims validation - sub must be a string", async () => {
+	const invalidClaims = {
+		root: "test-path",
+		sub: 123,
+		put: "test-pub",
+	};
+
+	const key = load(encodeJwk(testKey));
+
+	await assert.rejects(async () => {
+		await sign(key, invalidClaims as unknown as Claims);
+	});
+});
+
 test("round-trip - sign and verify", async () => {
 	const key = load(encodeJwk(testKey));
 	const originalClaims: Claims = {
 		root: "test-path",
+		sub: "user-123",
 		put: "test-pub",
 		get: "test-sub",
 		cluster: true,
@@ -241,6 +256,7 @@ test("round-trip - sign and verify", async () => {
 	const verifiedClaims = await verify(key, token, originalClaims.root);
 
 	assert.strictEqual(verifiedClaims.root, originalClaims.root);
+	assert.strictEqual(verifiedClaims.sub, originalClaims.sub);
 	assert.strictEqual(verifiedClaims.put, originalClaims.put);
 	assert.strictEqual(verifiedClaims.get, originalClaims.get);
 	assert.strictEqual(verifiedClaims.cluster, originalClaims.cluster);
@@ -0,0 +1,880 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{routing::get, Router};
+use bytes::{Bytes, BytesMut};
+use hang::catalog::{AudioConfig, Catalog, CatalogConsumer, VideoCodec, VideoConfig};
+use hang::export::Cmaf;
+use hang::{moq_lite, Frame, Timestamp};
+use serde::Deserialize;
+use tokio::sync::watch;
+use tower_http::cors::{Any, CorsLayer};
+use url::Url;
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct HlsConfig {
+	/// Serve the HLS playlists and segments over HTTP on this address.
+	#[arg(long, default_value = "[::]:8080")]
+	pub listen: SocketAddr,
+
+	/// The maximum duration of each LL-HLS partial segment, unless a single frame is longer.
+	#[arg(long, default_value = "200ms", value_parser = humantime::parse_duration)]
+	pub part: Duration,
+
+	/// The minimum duration of each segment.
+	///
+	/// Each group (starting with a keyframe) becomes a segment, unless it's shorter than this.
+	#[arg(long, default_value = "1s", value_parser = humantime::parse_duration)]
+	pub segment: Duration,
+
+	/// The number of segments to keep in each playlist.
+	#[arg(long, default_value_t = 6)]
+	pub segments: usize,
+}
+
+pub async fn hls(config: moq_native::ClientConfig, url: Url, name: String, hls: HlsConfig) -> anyhow::Result<()> {
+	let client = config.init()?;
+
+	tracing::info!(%url, %name, "connecting");
+	let session = client.connect(url).await?;
+
+	// Establish the connection, not providing a publisher.
+	let origin = moq_lite::Origin::produce();
+	let session = moq_lite::Session::connect(session, None, Some(origin.producer)).await?;
+
+	let path: moq_lite::Path<'_> = name.as_str().into();
+	let origin = origin
+		.consumer
+		.consume_only(&[path])
+		.context("not allowed to consume broadcast")?;
+
+	let gateway = Gateway::new(hls.clone());
+
+	tokio::select! {
+		res = gateway.clone().run(origin) => res,
+		res = web(hls.listen, gateway) => res,
+		res = session.closed() => res.map_err(Into::into),
+	}
+}
+
+// A complete moof + mdat, which is an LL-HLS partial segment.
+struct Part {
+	data: Bytes,
+	duration: Duration,
+	independent: bool,
+}
+
+struct Segment {
+	sequence: u64,
+	parts: Vec<Part>,
+	complete: bool,
+}
+
+impl Segment {
+	fn duration(&self) -> Duration {
+		self.parts.iter().map(|part| part.duration).sum()
+	}
+}
+
+#[derive(Default)]
+struct Playlist {
+	init: Option<Bytes>,
+	segments: VecDeque<Segment>,
+	closed: bool,
+
+	// The sequence number of the next segment.
+	next: u64,
+
+	// The longest segment duration in seconds, which must not decrease between reloads.
+	target: u64,
+}
+
+impl Playlist {
+	// A new playlist for the same rendition, continuing the sequence numbers and target duration.
+	// Players keep reloading the same URL when the publisher reconnects, so neither can go backwards.
+	fn restart(&self) -> Self {
+		Self {
+			next: self.next,
+			target: self.target,
+			..Default::default()
+		}
+	}
+
+	fn segment(&self, sequence: u64) -> Option<&Segment> {
+		let first = self.segments.front()?.sequence;
+		self.segments.get(sequence.checked_sub(first)? as usize)
+	}
+
+	// Returns true if the given segment (and part) are available, or will never be available.
+	fn ready(&self, sequence: u64, part: Option<usize>) -> bool {
+		if self.closed || self.segments.back().is_some_and(|s| s.sequence > sequence) {
+			return true;
+		}
+
+		match self.segment(sequence) {
+			Some(segment) => segment.complete || part.is_some_and(|part| part < segment.parts.len()),
+			None => false,
+		}
+	}
+
+	fn render(&self, part: Duration) -> String {
+		use std::fmt::Write;
+
+		let target = self.target.max(1);
+
+		let mut out = String::new();
+		writeln!(out, "#EXTM3U").unwrap();
+		writeln!(out, "#EXT-X-VERSION:9").unwrap();
+		writeln!(out, "#EXT-X-TARGETDURATION:{target}").unwrap();
+		writeln!(out, "#EXT-X-PART-INF:PART-TARGET={:.3}", part.as_secs_f64()).unwrap();
+		writeln!(
+			out,
+			"#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3}",
+			3.0 * part.as_secs_f64()
+		)
+		.unwrap();
+
+		let first = self.segments.front().map(|s| s.sequence).unwrap_or_default();
+		writeln!(out, "#EXT-X-MEDIA-SEQUENCE:{first}").unwrap();
+		writeln!(out, "#EXT-X-MAP:URI=\"init.mp4\"").unwrap();
+
+		// Only the most recent segments list their parts, as recommended by the spec.
+		let recent = self.segments.len().saturating_sub(3);
+
+		for (index, segment) in self.segments.iter().enumerate() {
+			if index >= recent {
+				for (i, part) in segment.parts.iter().enumerate() {
+					write!(
+						out,
+						"#EXT-X-PART:DURATION={:.3},URI=\"{}.{}.m4s\"",
+						part.duration.as_secs_f64(),
+						segment.sequence,
+						i
+					)
+					.unwrap();
+
+					if part.independent {
+						write!(out, ",INDEPENDENT=YES").unwrap();
+					}
+
+					writeln!(out).unwrap();
+				}
+			}
+
+			if segment.complete {
+				writeln!(out, "#EXTINF:{:.3},", segment.duration().as_secs_f64()).unwrap();
+				writeln!(out, "{}.m4s", segment.sequence).unwrap();
+			}
+		}
+
+		if self.closed {
+			writeln!(out, "#EXT-X-ENDLIST").unwrap();
+		} else if let Some(segment) = self.segments.back() {
+			// Let the player request the next part before it exists.
+			let (sequence, index) = match segment.complete {
+				true => (segment.sequence + 1, 0),
+				false => (segment.sequence, segment.parts.len()),
+			};
+
+			writeln!(out, "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"{sequence}.{index}.m4s\"").unwrap();
+		}
+
+		out
+	}
+}
+
+#[derive(Clone, PartialEq)]
+enum Kind {
+	Video(VideoConfig),
+	Audio(AudioConfig),
+}
+
+// Packages a rendition into segments and parts as frames arrive.
+struct Packager {
+	config: HlsConfig,
+	kind: Kind,
+	playlist: watch::Sender<Playlist>,
+
+	// Created on the first keyframe.
+	cmaf: Option<Cmaf>,
+
+	// The frames for the next part.
+	frames: Vec<Frame>,
+
+	// The timestamp of the first frame in the current segment.
+	start: Option<Timestamp>,
+
+	// The sequence number of the next fragment.
+	fragment: u32,
+}
+
+impl Packager {
+	fn new(config: HlsConfig, kind: Kind, playlist: watch::Sender<Playlist>) -> Self {
+		// Segments are at least this long, so start with it rather than raising the target on the first segment.
+		let minimum = config.segment.as_secs_f64().ceil() as u64;
+		playlist.send_modify(|playlist| playlist.target = playlist.target.max(minimum));
+
+		Self {
+			config,
+			kind,
+			playlist,
+			cmaf: None,
+			frames: Vec::new(),
+			start: None,
+			fragment: 1,
+		}
+	}
+
+	async fn run(mut self, mut track: moq_lite::TrackConsumer) -> anyhow::Result<()> {
+		// Each group starts with a keyframe and maps to a segment.
+		while let Some(group) = track.next_group().await? {
+			let mut group = hang::GroupConsumer::new(group);
+			while let Some(frame) = group.read().await? {
+				self.frame(frame)?;
+			}
+		}
+
+		self.finish()
+	}
+
+	fn frame(&mut self, frame: Frame) -> anyhow::Result<()> {
+		if self.cmaf.is_none() {
+			if !frame.keyframe {
+				return Ok(());
+			}
+
+			let cmaf = match &self.kind {
+				Kind::Video(config) => Cmaf::video(config, &frame)?,
+				Kind::Audio(config) => Cmaf::audio(config)?,
+			};
+
+			let init = cmaf.init()?;
+			self.playlist.send_modify(|playlist| playlist.init = Some(init));
+			self.cmaf = Some(cmaf);
+		}
+
+		let elapsed =
+			|start: Timestamp| Duration::from_micros(frame.timestamp.as_micros().saturating_sub(start.as_micros()));
+
+		if frame.keyframe && self.start.is_none_or(|start| elapsed(start) >= self.config.segment) {
+			self.flush(frame.timestamp)?;
+			self.complete();
+
+			self.start = Some(frame.timestamp);
+
+			self.playlist.send_modify(|playlist| {
+				let sequence = playlist.next;
+				playlist.next += 1;
+
+				playlist.segments.push_back(Segment {
+					sequence,
+					parts: Vec::new(),
+					complete: false,
+				})
+			});
+		} else if self.overflows(&frame) {
+			self.flush(frame.timestamp)?;
+		}
+
+		self.frames.push(frame);
+
+		Ok(())
+	}
+
+	// Returns true if adding the frame would push the part past PART-TARGET, which is a maximum.
+	// The frame's duration isn't known until the next one arrives, so assume it's as long as the gap before it.
+	fn overflows(&self, frame: &Frame) -> bool {
+		let (first, last) = match (self.frames.first(), self.frames.last()) {
+			(Some(first), Some(last)) => (first.timestamp.as_micros(), last.timestamp.as_micros()),
+			_ => return false,
+		};
+
+		let timestamp = frame.timestamp.as_micros();
+		let end = timestamp + timestamp.saturating_sub(last);
+
+		Duration::from_micros(end.saturating_sub(first)) > self.config.part
+	}
+
+	// Encode the buffered frames as a part, using the timestamp of the next frame as the end.
+	fn flush(&mut self, end: Timestamp) -> anyhow::Result<()> {
+		let (first, cmaf) = match (self.frames.first(), &self.cmaf) {
+			(Some(first), Some(cmaf)) => (first, cmaf),
+			_ => return Ok(()),
+		};
+
+		let part = Part {
+			data: cmaf.fragment(self.fragment, &self.frames, end)?,
+			duration: Duration::from_micros(end.as_micros().saturating_sub(first.timestamp.as_micros())),
+			independent: first.keyframe,
+		};
+
+		self.fragment += 1;
+		self.frames.clear();
+
+		self.playlist.send_modify(|playlist| {
+			if let Some(segment) = playlist.segments.back_mut() {
+				segment.parts.push(part);
+			}
+		});
+
+		Ok(())
+	}
+
+	fn complete(&mut self) {
+		let capacity = self.config.segments.max(1);
+
+		self.playlist.send_if_modified(|playlist| {
+			let segment = match playlist.segments.back_mut() {
+				Some(segment) => segment,
+				None => return false,
+			};
+
+			segment.complete = true;
+
+			let duration = segment.duration().as_secs_f64().ceil() as u64;
+			playlist.target = playlist.target.max(duration);
+
+			while playlist.segments.len() > capacity {
+				playlist.segments.pop_front();
+			}
+
+			true
+		});
+	}
+
+	fn finish(&mut self) -> anyhow::Result<()> {
+		// There's no next frame, so assume the last frame is as long as the one before it.
+		let end = match self.frames.as_slice() {
+			[.., prev, last] => last.timestamp + (last.timestamp - prev.timestamp),
+			[last] => last.timestamp + self.config.part.try_into()?,
+			[] => Timestamp::ZERO,
+		};
+
+		self.flush(end)?;
+		self.complete();
+		self.playlist.send_modify(|playlist| playlist.closed = true);
+
+		Ok(())
+	}
+}
+
+struct Rendition {
+	kind: Kind,
+	playlist: watch::Receiver<Playlist>,
+	task: tokio::task::AbortHandle,
+}
+
+impl Drop for Rendition {
+	fn drop(&mut self) {
+		self.task.abort();
+	}
+}
+
+#[derive(Default)]
+struct GatewayState {
+	renditions: HashMap<String, Rendition>,
+
+	// Where each removed rendition's playlist left off, so it continues if the rendition comes back.
+	ended: HashMap<String, Playlist>,
+}
+
+impl GatewayState {
+	// Stop packaging the renditions that don't match, remembering where their playlists left off.
+	fn retain(&mut self, mut f: impl FnMut(&String, &Rendition) -> bool) {
+		let ended = &mut self.ended;
+
+		self.renditions.retain(|name, rendition| {
+			let keep = f(name, rendition);
+			if !keep {
+				ended.insert(name.clone(), rendition.playlist.borrow().restart());
+			}
+			keep
+		});
+	}
+}
+
+/// Subscribes to a hang broadcast and packages each rendition for LL-HLS.
+#[derive(Clone)]
+struct Gateway {
+	config: HlsConfig,
+	state: Arc<Mutex<GatewayState>>,
+}
+
+impl Gateway {
+	fn new(config: HlsConfig) -> Self {
+		Self {
+			config,
+			state: Default::default(),
+		}
+	}
+
+	async fn run(self, mut origin: moq_lite::OriginConsumer) -> anyhow::Result<()> {
+		tracing::info!("waiting for broadcast to be online");
+
+		// The current broadcast if any, replaced after each announce.
+		let mut broadcast = None;
+
+		loop {
+			tokio::select! {
+				Some(announce) = origin.announced() => match announce {
+					(path, Some(active)) => {
+						tracing::info!(broadcast = %path, "broadcast is online, subscribing to catalog");
+						broadcast = Some(active);
+					}
+					(path, None) => {
+						tracing::warn!(broadcast = %path, "broadcast is offline, waiting...");
+						broadcast = None;
+						self.state.lock().unwrap().retain(|_, _| false);
+					}
+				},
+				// NOTE: This is dropped when a new announce arrives, canceling it.
+				Some(res) = async { Some(self.subscribe(broadcast.clone()?).await) } => {
+					if let Err(err) = res {
+						tracing::warn!(%err, "failed to read catalog");
+					}
+					broadcast = None;
+				}
+				else => return Ok(()),
+			}
+		}
+	}
+
+	async fn subscribe(&self, broadcast: moq_lite::BroadcastConsumer) -> anyhow::Result<()> {
+		let mut catalog = CatalogConsumer::new(broadcast.subscribe_track(&Catalog::default_track()));
+
+		while let Some(catalog) = catalog.next().await? {
+			self.update(&broadcast, catalog);
+		}
+
+		Ok(())
+	}
+
+	fn update(&self, broadcast: &moq_lite::BroadcastConsumer, catalog: Catalog) {
+		let mut renditions = Vec::new();
+
+		if let Some(video) = catalog.video {
+			for (name, config) in video.renditions {
+				renditions.push((name, video.priority, Kind::Video(config)));
+			}
+		}
+
+		if let Some(audio) = catalog.audio {
+			for (name, config) in audio.renditions {
+				renditions.push((name, audio.priority, Kind::Audio(config)));
+			}
+		}
+
+		let mut state = self.state.lock().unwrap();
+		state.retain(|name, rendition| {
+			renditions
+				.iter()
+				.any(|(n, _, kind)| n == name && kind == &rendition.kind)
+		});
+
+		for (name, priority, kind) in renditions {
+			if state.renditions.contains_key(&name) {
+				continue;
+			}
+
+			tracing::info!(track = %name, "packaging rendition");
+
+			let track = broadcast.subscribe_track(&moq_lite::Track {
+				name: name.clone(),
+				priority,
+			});
+
+			let playlist = state.ended.remove(&name).unwrap_or_default();
+			let (playlist, consumer) = watch::channel(playlist);
+			let packager = Packager::new(self.config.clone(), kind.clone(), playlist);
+
+			let task = tokio::spawn({
+				let name = name.clone();
+				async move {
+					if let Err(err) = packager.run(track).await {
+						tracing::warn!(track = %name, %err, "failed to package rendition");
+					}
+				}
+			});
+
+			state.renditions.insert(
+				name,
+				Rendition {
+					kind,
+					playlist: consumer,
+					task: task.abort_handle(),
+				},
+			);
+		}
+	}
+
+	fn playlist(&self, name: &str) -> Option<watch::Receiver<Playlist>> {
+		let state = self.state.lock().unwrap();
+		Some(state.renditions.get(name)?.playlist.clone())
+	}
+
+	// Renders the multivariant playlist.
+	fn master(&self) -> String {
+		use std::fmt::Write;
+
+		let state = self.state.lock().unwrap();
+
+		let mut names: Vec<_> = state.renditions.keys().collect();
+		names.sort();
+
+		let mut video = Vec::new();
+		let mut audio = Vec::new();
+
+		for name in names {
+			match &state.renditions[name].kind {
+				Kind::Video(config) => video.push((name, config)),
+				Kind::Audio(config) => audio.push((name, config)),
+			}
+		}
+
+		let mut out = String::new();
+		writeln!(out, "#EXTM3U").unwrap();
+		writeln!(out, "#EXT-X-VERSION:9").unwrap();
+		writeln!(out, "#EXT-X-INDEPENDENT-SEGMENTS").unwrap();
+
+		for (i, (name, _)) in audio.iter().enumerate() {
+			let default = if i == 0 { "YES" } else { "NO" };
+			writeln!(
+				out,
+				"#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",NAME=\"{name}\",DEFAULT={default},AUTOSELECT=YES,URI=\"{name}/playlist.m3u8\""
+			)
+			.unwrap();
+		}
+
+		// Advertise the first audio rendition's codec alongside each video rendition.
+		let audio_codec = audio.first().map(|(_, config)| config.codec.to_string());
+
+		for (name, config) in &video {
+			let mut codecs = video_codec(config);
+			if let Some(audio) = &audio_codec {
+				codecs = format!("{codecs},{audio}");
+			}
+
+			let bandwidth = config.bitrate.unwrap_or(DEFAULT_VIDEO_BITRATE)
+				+ audio.first().and_then(|(_, c)| c.bitrate).unwrap_or_default();
+
+			write!(out, "#EXT-X-STREAM-INF:BANDWIDTH={bandwidth},CODECS=\"{codecs}\"").unwrap();
+
+			if let (Some(width), Some(height)) = (config.coded_width, config.coded_height) {
+				write!(out, ",RESOLUTION={width}x{height}").unwrap();
+			}
+
+			if !audio.is_empty() {
+				write!(out, ",AUDIO=\"audio\"").unwrap();
+			}
+
+			writeln!(out).unwrap();
+			writeln!(out, "{name}/playlist.m3u8").unwrap();
+		}
+
+		// Audio-only broadcasts list each audio rendition as a variant instead.
+		if video.is_empty() {
+			for (name, config) in &audio {
+				let bandwidth = config.bitrate.unwrap_or(DEFAULT_AUDIO_BITRATE);
+				writeln!(
+					out,
+					"#EXT-X-STREAM-INF:BANDWIDTH={bandwidth},CODECS=\"{}\"",
+					config.codec
+				)
+				.unwrap();
+				writeln!(out, "{name}/playlist.m3u8").unwrap();
+			}
+		}
+
+		out
+	}
+}
+
+// Used for BANDWIDTH when the catalog doesn't include a bitrate.
+const DEFAULT_VIDEO_BITRATE: u64 = 2_000_000;
+const DEFAULT_AUDIO_BITRATE: u64 = 128_000;
+
+// The codec string of the packaged track.
+fn video_codec(config: &VideoConfig) -> String {
+	match &config.codec {
+		// Inline SPS/PPS are moved into the avcC box, so it's packaged as avc1.
+		VideoCodec::H264(h264) => hang::catalog::H264 {
+			inline: false,
+			..h264.clone()
+		}
+		.to_string(),
+		codec => codec.to_string(),
+	}
+}
+
+async fn web(bind: SocketAddr, gateway: Gateway) -> anyhow::Result<()> {
+	let app = Router::new()
+		.route("/master.m3u8", get(serve_master))
+		.route("/{track}/playlist.m3u8", get(serve_playlist))
+		.route("/{track}/init.mp4", get(serve_init))
+		.route("/{track}/{segment}", get(serve_segment))
+		.layer(CorsLayer::new().allow_origin(Any).allow_methods([Method::GET]))
+		.with_state(gateway);
+
+	tracing::info!(%bind, "serving HLS");
+
+	let server = hyper_serve::bind(bind);
+	server.serve(app.into_make_service()).await?;
+
+	Ok(())
+}
+
+// How long to hold a blocking request before giving up.
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Wait until the segment (and part) are available, or time out.
+async fn wait(playlist: &mut watch::Receiver<Playlist>, sequence: u64, part: Option<usize>) -> Result<(), StatusCode> {
+	match tokio::time::timeout(BLOCK_TIMEOUT, playlist.wait_for(|p| p.ready(sequence, part))).await {
+		Ok(Ok(_)) => Ok(()),
+		Ok(Err(_)) => Err(StatusCode::NOT_FOUND),
+		Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
+	}
+}
+
+async fn serve_master(State(gateway): State<Gateway>) -> Response {
+	(
+		[(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+		gateway.master(),
+	)
+		.into_response()
+}
+
+#[derive(Deserialize)]
+struct PlaylistParams {
+	#[serde(rename = "_HLS_msn")]
+	msn: Option<u64>,
+
+	#[serde(rename = "_HLS_part")]
+	part: Option<usize>,
+}
+
+async fn serve_playlist(
+	State(gateway): State<Gateway>,
+	Path(track): Path<String>,
+	Query(params): Query<PlaylistParams>,
+) -> Result<Response, StatusCode> {
+	let mut playlist = gateway.playlist(&track).ok_or(StatusCode::NOT_FOUND)?;
+
+	// Blocking playlist reload.
+	if let Some(msn) = params.msn {
+		wait(&mut playlist, msn, params.part).await?;
+	}
+
+	let body = playlist.borrow().render(gateway.config.part);
+
+	Ok((
+		[
+			(header::CONTENT_TYPE, "application/vnd.apple.mpegurl"),
+			(header::CACHE_CONTROL, "no-cache"),
+		],
+		body,
+	)
+		.into_response())
+}
+
+async fn serve_init(State(gateway): State<Gateway>, Path(track): Path<String>) -> Result<Response, StatusCode> {
+	let mut playlist = gateway.playlist(&track).ok_or(StatusCode::NOT_FOUND)?;
+
+	let init = tokio::time::timeout(BLOCK_TIMEOUT, playlist.wait_for(|p| p.init.is_some() || p.closed))
+		.await
+		.map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?
+		.map_err(|_| StatusCode::NOT_FOUND)?
+		.init
+		.clone()
+		.ok_or(StatusCode::NOT_FOUND)?;
+
+	Ok(([(header::CONTENT_TYPE, "video/mp4")], init).into_response())
+}
+
+// Either `{sequence}.m4s` or `{sequence}.{part}.m4s`.
+async fn serve_segment(
+	State(gateway): State<Gateway>,
+	Path((track, segment)): Path<(String, String)>,
+) -> Result<Response, StatusCode> {
+	let name = segment.strip_suffix(".m4s").ok_or(StatusCode::NOT_FOUND)?;
+
+	let (sequence, part) = match name.split_once('.') {
+		Some((sequence, part)) => (sequence, Some(part.parse().map_err(|_| StatusCode::NOT_FOUND)?)),
+		None => (name, None),
+	};
+	let sequence: u64 = sequence.parse().map_err(|_| StatusCode::NOT_FOUND)?;
+
+	let mut playlist = gateway.playlist(&track).ok_or(StatusCode::NOT_FOUND)?;
+
+	// Parts are requested before they're available via the preload hint.
+	wait(&mut playlist, sequence, part).await?;
+
+	let playlist = playlist.borrow();
+	let segment = playlist.segment(sequence).ok_or(StatusCode::NOT_FOUND)?;
+
+	let body = match part {
+		Some(part) => segment.parts.get(part).ok_or(StatusCode::NOT_FOUND)?.data.clone(),
+		None if segment.complete => {
+			let mut body = BytesMut::new();
+			for part in &segment.parts {
+				body.extend_from_slice(&part.data);
+			}
+			body.freeze()
+		}
+		None => return Err(StatusCode::NOT_FOUND),
+	};
+
+	Ok((
+		[
+			(header::CONTENT_TYPE, "video/mp4"),
+			(header::CACHE_CONTROL, "public, max-age=3600"),
+		],
+		body,
+	)
+		.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hang::catalog::AudioCodec;
+
+	fn setup(playlist: Playlist) -> (Packager, watch::Receiver<Playlist>) {
+		let config = HlsConfig {
+			listen: "[::]:0".parse().unwrap(),
+			part: Duration::from_millis(200),
+			segment: Duration::from_secs(1),
+			segments: 3,
+		};
+
+		let kind = Kind::Audio(AudioConfig {
+			codec: AudioCodec::Opus,
+			sample_rate: 48_000,
+			channel_count: 2,
+			bitrate: None,
+			description: None,
+		});
+
+		let (playlist, consumer) = watch::channel(playlist);
+		(Packager::new(config, kind, playlist), consumer)
+	}
+
+	// Write a frame every 20ms, starting a new group at each keyframe time.
+	fn write(packager: &mut Packager, from: u64, to: u64, keyframes: &[u64]) {
+		for millis in (from..to).step_by(20) {
+			packager
+				.frame(Frame {
+					timestamp: Timestamp::from_millis(millis).unwrap(),
+					keyframe: keyframes.contains(&millis),
+					payload: Bytes::from_static(b"opus").into(),
+				})
+				.unwrap();
+		}
+	}
+
+	#[test]
+	fn test_packager_parts() {
+		let (mut packager, playlist) = setup(Playlist::default());
+		write(&mut packager, 0, 1100, &[0, 1000]);
+
+		let playlist = playlist.borrow();
+		let sequences: Vec<_> = playlist.segments.iter().map(|s| s.sequence).collect();
+		assert_eq!(sequences, vec![0, 1]);
+
+		// The part is flushed before the frame that would push it past the target.
+		let segment = playlist.segment(0).unwrap();
+		assert!(segment.complete);
+		assert_eq!(segment.duration(), Duration::from_secs(1));
+		assert_eq!(segment.parts.len(), 5);
+		assert!(segment
+			.parts
+			.iter()
+			.all(|part| part.duration <= Duration::from_millis(200)));
+		assert!(segment.parts[0].independent);
+		assert!(!segment.parts[1].independent);
+
+		// The next segment's frames are still buffered.
+		let segment = playlist.segment(1).unwrap();
+		assert!(!segment.complete);
+		assert!(segment.parts.is_empty());
+	}
+
+	#[test]
+	fn test_packager_segments() {
+		let (mut packager, playlist) = setup(Playlist::default());
+
+		// A keyframe before the minimum duration doesn't start a segment.
+		write(&mut packager, 0, 4000, &[0, 500, 1000, 3500]);
+		assert_eq!(playlist.borrow().segments.len(), 3);
+		assert_eq!(playlist.borrow().segment(0).unwrap().duration(), Duration::from_secs(1));
+		assert_eq!(
+			playlist.borrow().segment(1).unwrap().duration(),
+			Duration::from_millis(2500)
+		);
+		assert_eq!(playlist.borrow().target, 3);
+
+		// The target duration doesn't decrease when the long segment is removed.
+		write(&mut packager, 4000, 7100, &[4500, 5500, 6500]);
+		let sequences: Vec<_> = playlist.borrow().segments.iter().map(|s| s.sequence).collect();
+		assert_eq!(sequences, vec![2, 3, 4, 5]);
+		assert_eq!(playlist.borrow().target, 3);
+		assert!(playlist
+			.borrow()
+			.render(Duration::from_millis(200))
+			.contains("#EXT-X-TARGETDURATION:3\n"));
+
+		// The publisher reconnects and the timestamps start over.
+		packager.finish().unwrap();
+		let (mut packager, playlist) = setup(playlist.borrow().restart());
+		write(&mut packager, 0, 1100, &[0, 1000]);
+
+		let playlist = playlist.borrow();
+		let sequences: Vec<_> = playlist.segments.iter().map(|s| s.sequence).collect();
+		assert_eq!(sequences, vec![6, 7]);
+		assert_eq!(playlist.target, 3);
+
+		let rendered = playlist.render(Duration::from_millis(200));
+		assert!(rendered.contains("#EXT-X-MEDIA-SEQUENCE:6\n"));
+		assert!(rendered.contains("#EXT-X-TARGETDURATION:3\n"));
+	}
+
+	fn part(millis: u64) -> Part {
+		Part {
+			data: Bytes::new(),
+			duration: Duration::from_millis(millis),
+			independent: false,
+		}
+	}
+
+	#[test]
+	fn test_playlist() {
+		let mut playlist = Playlist::default();
+		playlist.segments.push_back(Segment {
+			sequence: 4,
+			parts: vec![part(500), part(500)],
+			complete: true,
+		});
+		playlist.segments.push_back(Segment {
+			sequence: 5,
+			parts: vec![part(200)],
+			complete: false,
+		});
+
+		assert!(playlist.ready(4, None));
+		assert!(playlist.ready(5, Some(0)));
+		assert!(!playlist.ready(5, Some(1)));
+		assert!(!playlist.ready(5, None));
+
+		let rendered = playlist.render(Duration::from_millis(200));
+		assert!(rendered.contains("#EXT-X-MEDIA-SEQUENCE:4\n"));
+		assert!(rendered.contains("#EXTINF:1.000,\n4.m4s\n"));
+		assert!(rendered.contains("#EXT-X-PART:DURATION=0.200,URI=\"5.0.m4s\"\n"));
+		assert!(rendered.contains("#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"5.1.m4s\"\n"));
+		assert!(!rendered.contains("5.m4s"));
+	}
+}
@@ -5,14 +5,29 @@ use tokio::io::{AsyncRead, AsyncReadExt};
 #[derive(ValueEnum, Clone)]
 pub enum ImportType {
 	AnnexB,
+	/// H.265 Annex B with inline VPS/SPS/PPS.
+	Hev1,
+	/// AV1 in the low overhead bitstream format.
+	Av1,
+	/// IVF, containing AV1, VP8 or VP9.
+	Ivf,
 	Cmaf,
+	/// AAC with ADTS headers.
+	Adts,
+	/// Ogg-encapsulated Opus.
+	Opus,
 }
 
 impl ImportType {
 	fn as_str(&self) -> &'static str {
 		match self {
 			ImportType::AnnexB => "annex-b",
+			ImportType::Hev1 => "hev1",
+			ImportType::Av1 => "av1",
+			ImportType::Ivf => "ivf",
 			ImportType::Cmaf => "cmaf",
+			ImportType::Adts => "adts",
+			ImportType::Opus => "opus",
 		}
 	}
 }
@@ -1,12 +1,18 @@
 mod client;
+mod hls;
 mod import;
+mod replay;
 mod server;
+mod subscribe;
 
 use std::path::PathBuf;
 
 use client::*;
+use hls::*;
 use import::*;
+use replay::*;
 use server::*;
+use subscribe::*;
 
 use clap::{Parser, Subcommand};
 use url::Url;
@@ -64,6 +70,67 @@ pub enum Command {
 		#[arg(long, value_enum, default_value_t = ImportType::Cmaf)]
 		format: ImportType,
 	},
+	/// Subscribe to a broadcast and write it to stdout.
+	Subscribe {
+		/// The MoQ client configuration.
+		#[command(flatten)]
+		config: moq_native::ClientConfig,
+
+		/// The URL of the MoQ server.
+		#[arg(long)]
+		url: Url,
+
+		/// The name of the broadcast to subscribe to.
+		#[arg(long)]
+		name: String,
+
+		/// The format of the output media.
+		#[arg(long, value_enum, default_value_t = ExportType::Fmp4)]
+		format: ExportType,
+	},
+	/// Subscribe to a broadcast and serve it as LL-HLS.
+	Hls {
+		/// The MoQ client configuration.
+		#[command(flatten)]
+		config: moq_native::ClientConfig,
+
+		/// The URL of the MoQ server.
+		#[arg(long)]
+		url: Url,
+
+		/// The name of the broadcast to subscribe to.
+		#[arg(long)]
+		name: String,
+
+		#[command(flatten)]
+		hls: HlsConfig,
+	},
+	/// Publish a relay recording or fMP4 file at its original timing.
+	Replay {
+		/// The MoQ client configuration.
+		#[command(flatten)]
+		config: moq_native::ClientConfig,
+
+		/// The URL of the MoQ server.
+		#[arg(long)]
+		url: Url,
+
+		/// The name of the broadcast to publish.
+		#[arg(long)]
+		name: String,
+
+		/// A recording directory written by moq-relay, or a fMP4 file.
+		#[arg(long)]
+		input: PathBuf,
+
+		/// The playback speed, where 1.0 is real-time and 0 is as fast as possible.
+		#[arg(long, default_value_t = 1.0)]
+		speed: f64,
+
+		/// Start over from the beginning once the end is reached.
+		#[arg(long = "loop")]
+		looping: bool,
+	},
 }
 
 #[tokio::main]
@@ -84,5 +151,20 @@ async fn main() -> anyhow::Result<()> {
 			name,
 			format,
 		} => client(config, url, name, format, &mut tokio::io::stdin()).await,
+		Command::Subscribe {
+			config,
+			url,
+			name,
+			format,
+		} => subscribe(config, url, name, format, &mut tokio::io::stdout()).await,
+		Command::Hls { config, url, name, hls } => self::hls(config, url, name, hls).await,
+		Command::Replay {
+			config,
+			url,
+			name,
+			input,
+			speed,
+			looping,
+		} => replay(config, url, name, input, speed, looping).await,
 	}
 }
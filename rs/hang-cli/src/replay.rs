@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use hang::moq_lite;
+use url::Url;
+
+pub async fn replay(
+	config: moq_native::ClientConfig,
+	url: Url,
+	name: String,
+	input: PathBuf,
+	speed: f64,
+	looping: bool,
+) -> anyhow::Result<()> {
+	let broadcast = moq_lite::Broadcast::produce();
+
+	// Validate the speed before connecting.
+	let mut replay = hang::replay::Replay::new(broadcast.producer.into(), &input);
+	replay.set_speed(speed)?;
+	replay.set_loop(looping);
+
+	let client = config.init()?;
+
+	tracing::info!(%url, %name, input = %input.display(), "connecting");
+	let session = client.connect(url).await?;
+
+	// Create an origin producer to publish to the broadcast.
+	let origin = moq_lite::Origin::produce();
+
+	// Establish the connection, not providing a subscriber.
+	let session = moq_lite::Session::connect(session, origin.consumer, None).await?;
+
+	origin.producer.publish_broadcast(&name, broadcast.consumer);
+
+	tokio::select! {
+		res = replay.run() => res,
+		res = session.closed() => res.map_err(Into::into),
+
+		_ = tokio::signal::ctrl_c() => {
+			session.close(moq_lite::Error::Cancel);
+
+			// Give it a chance to close.
+			tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+			Ok(())
+		},
+	}
+}
@@ -0,0 +1,66 @@
+use anyhow::Context;
+use clap::ValueEnum;
+use hang::moq_lite;
+use tokio::io::AsyncWrite;
+use url::Url;
+
+#[derive(ValueEnum, Clone)]
+pub enum ExportType {
+	/// Fragmented MP4 with a video and audio track, ex. to pipe into ffplay.
+	Fmp4,
+	/// H.264 Annex B with the SPS/PPS before each keyframe.
+	AnnexB,
+}
+
+pub async fn subscribe<T: AsyncWrite + Unpin>(
+	config: moq_native::ClientConfig,
+	url: Url,
+	name: String,
+	format: ExportType,
+	output: &mut T,
+) -> anyhow::Result<()> {
+	let client = config.init()?;
+
+	tracing::info!(%url, %name, "connecting");
+	let session = client.connect(url).await?;
+
+	// Establish the connection, not providing a publisher.
+	let origin = moq_lite::Origin::produce();
+	let session = moq_lite::Session::connect(session, None, Some(origin.producer)).await?;
+
+	let path: moq_lite::Path<'_> = name.as_str().into();
+	let mut origin = origin
+		.consumer
+		.consume_only(&[path])
+		.context("not allowed to consume broadcast")?;
+
+	tokio::select! {
+		res = async {
+			tracing::info!("waiting for broadcast to be online");
+
+			let broadcast = loop {
+				match origin.announced().await.context("origin closed")? {
+					(_, Some(broadcast)) => break broadcast,
+					(path, None) => tracing::warn!(broadcast = %path, "broadcast is offline, waiting..."),
+				}
+			};
+
+			// Wait until the first renditions are available.
+			let mut broadcast = hang::BroadcastConsumer::new(broadcast);
+			broadcast.next().await?.context("missing catalog")?;
+
+			match format {
+				ExportType::Fmp4 => hang::export::Fmp4::subscribe(&broadcast, broadcast.catalog()).run(output).await,
+				ExportType::AnnexB => hang::export::AnnexB::subscribe(&broadcast, broadcast.catalog())?.run(output).await,
+			}
+		} => res,
+		res = session.closed() => res.map_err(Into::into),
+		_ = tokio::signal::ctrl_c() => {
+			session.close(moq_lite::Error::Cancel);
+
+			// Give it a chance to close.
+			tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+			Ok(())
+		},
+	}
+}
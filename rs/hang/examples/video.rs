@@ -71,6 +71,7 @@ fn create_track(broadcast: &mut moq_lite::BroadcastProducer) -> hang::TrackProdu
 		framerate: Some(30.0),
 		display_ratio_width: None,
 		display_ratio_height: None,
+		color_space: None,
 		optimize_for_latency: None,
 	};
 
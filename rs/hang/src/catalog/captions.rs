@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use bytes::Bytes;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use serde_with::{hex::Hex, DisplayFromStr};
+
+use crate::Error;
+
+/// Information about the caption and subtitle tracks in the catalog.
+///
+/// Each caption frame is a keyframe, so a new subscriber starts with the latest cue.
+#[serde_with::serde_as]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Captions {
+	/// A map of track name to rendition configuration.
+	/// This is not an array so it will work with JSON Merge Patch.
+	pub renditions: HashMap<String, CaptionConfig>,
+
+	/// The priority of the caption tracks, relative to other tracks in the broadcast.
+	pub priority: u8,
+}
+
+/// The configuration needed to render a caption track.
+#[serde_with::serde_as]
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptionConfig {
+	// The format of each frame, ex. "wvtt" or "cea-608".
+	#[serde_as(as = "DisplayFromStr")]
+	pub format: CaptionFormat,
+
+	// The language as an ISO 639-2 code, ex. "eng".
+	#[serde(default)]
+	pub language: Option<String>,
+
+	// Some formats include a header, ex. the WebVTT file header.
+	#[serde(default)]
+	#[serde_as(as = "Option<Hex>")]
+	pub description: Option<Bytes>,
+}
+
+/// Supported caption formats.
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum CaptionFormat {
+	/// WebVTT cues as stored in MP4 (ISO/IEC 14496-30), one sample per frame.
+	#[display("wvtt")]
+	WebVtt,
+
+	/// TTML documents as stored in MP4 (ISO/IEC 14496-30), one sample per frame.
+	#[display("stpp")]
+	Ttml,
+
+	/// The `cc_data` triplets from CEA-708 (`cc_valid`, `cc_type`, `cc_data_1`, `cc_data_2`).
+	///
+	/// This includes any CEA-608 byte pairs, as carried in H.264 SEI messages.
+	#[display("cea-608")]
+	Cea608,
+
+	/// Unknown or unsupported format with original string
+	#[display("{_0}")]
+	Unknown(String),
+}
+
+impl FromStr for CaptionFormat {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"wvtt" => Self::WebVtt,
+			"stpp" => Self::Ttml,
+			"cea-608" => Self::Cea608,
+			_ => Self::Unknown(s.to_string()),
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_serde() {
+		let encoded = r#"{"format":"wvtt","language":"eng","description":"574542565454"}"#;
+		let decoded = CaptionConfig {
+			format: CaptionFormat::WebVtt,
+			language: Some("eng".to_string()),
+			description: Some(Bytes::from_static(b"WEBVTT")),
+		};
+
+		assert_eq!(serde_json::to_string(&decoded).unwrap(), encoded);
+		assert_eq!(serde_json::from_str::<CaptionConfig>(encoded).unwrap(), decoded);
+
+		let unknown: CaptionFormat = "tx3g".parse().unwrap();
+		assert_eq!(unknown, CaptionFormat::Unknown("tx3g".to_string()));
+		assert_eq!(unknown.to_string(), "tx3g");
+	}
+}
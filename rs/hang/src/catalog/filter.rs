@@ -0,0 +1,230 @@
+use crate::catalog::{AudioConfig, Catalog, CatalogConsumer, CatalogProducer, VideoConfig};
+use crate::feedback::Capabilities;
+use crate::Result;
+
+/// Limits used to derive a viewer-specific catalog from the broadcaster's catalog.
+///
+/// Renditions the viewer can't decode, or that exceed the configured limits, are removed.
+/// Renditions with unknown dimensions or bitrate are kept.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CatalogFilter {
+	/// The codecs the viewer reported it can (or can't) decode.
+	///
+	/// If the viewer didn't report any decodable codecs, only the unsupported codecs are removed.
+	pub capabilities: Option<Capabilities>,
+
+	/// Remove video renditions wider than this many pixels.
+	pub max_width: Option<u32>,
+
+	/// Remove video renditions taller than this many pixels.
+	pub max_height: Option<u32>,
+
+	/// Remove audio and video renditions above this bitrate, in bits per second.
+	pub max_bitrate: Option<u64>,
+}
+
+impl CatalogFilter {
+	/// Returns true if the viewer should be offered this video rendition.
+	pub fn video(&self, config: &VideoConfig) -> bool {
+		if let Some(capabilities) = &self.capabilities {
+			let video = &capabilities.video;
+			if !supported(&config.codec, &video.hardware, &video.software, &video.unsupported) {
+				return false;
+			}
+		}
+
+		within(config.coded_width, self.max_width)
+			&& within(config.coded_height, self.max_height)
+			&& within(config.bitrate, self.max_bitrate)
+	}
+
+	/// Returns true if the viewer should be offered this audio rendition.
+	pub fn audio(&self, config: &AudioConfig) -> bool {
+		if let Some(capabilities) = &self.capabilities {
+			let audio = &capabilities.audio;
+			if !supported(&config.codec, &audio.hardware, &audio.software, &audio.unsupported) {
+				return false;
+			}
+		}
+
+		within(config.bitrate, self.max_bitrate)
+	}
+
+	/// Republish each update from `input` to `output` after filtering, until the input is closed.
+	pub async fn run(&self, mut input: CatalogConsumer, mut output: CatalogProducer) -> Result<()> {
+		while let Some(catalog) = input.next().await? {
+			*output.lock() = catalog.filter(self);
+		}
+
+		output.close();
+		Ok(())
+	}
+}
+
+impl Catalog {
+	/// Return a copy of the catalog with only the renditions allowed by the filter.
+	///
+	/// The video or audio section is removed entirely if none of its renditions remain.
+	pub fn filter(&self, filter: &CatalogFilter) -> Catalog {
+		let mut catalog = self.clone();
+
+		if let Some(mut video) = catalog.video.take() {
+			video.renditions.retain(|_, config| filter.video(config));
+			catalog.video = (!video.renditions.is_empty()).then_some(video);
+		}
+
+		if let Some(mut audio) = catalog.audio.take() {
+			audio.renditions.retain(|_, config| filter.audio(config));
+			catalog.audio = (!audio.renditions.is_empty()).then_some(audio);
+		}
+
+		catalog
+	}
+}
+
+fn supported<T: PartialEq>(codec: &T, hardware: &[T], software: &[T], unsupported: &[T]) -> bool {
+	if unsupported.contains(codec) {
+		return false;
+	}
+
+	// An empty list means the viewer didn't check, not that nothing is supported.
+	(hardware.is_empty() && software.is_empty()) || hardware.contains(codec) || software.contains(codec)
+}
+
+fn within<T: PartialOrd>(value: Option<T>, max: Option<T>) -> bool {
+	match (value, max) {
+		(Some(value), Some(max)) => value <= max,
+		_ => true,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::catalog::{AudioCodec, AudioConfig, VideoConfig, AAC, H264};
+	use crate::feedback::{AudioCapabilities, VideoCapabilities};
+
+	fn video(profile: u8, height: u32, bitrate: u64) -> VideoConfig {
+		VideoConfig {
+			codec: H264 {
+				profile,
+				constraints: 0,
+				level: 0x1f,
+				inline: false,
+			}
+			.into(),
+			description: None,
+			coded_width: Some(height * 16 / 9),
+			coded_height: Some(height),
+			display_ratio_width: None,
+			display_ratio_height: None,
+			color_space: None,
+			bitrate: Some(bitrate),
+			framerate: None,
+			optimize_for_latency: None,
+		}
+	}
+
+	fn audio(codec: AudioCodec) -> AudioConfig {
+		AudioConfig {
+			codec,
+			sample_rate: 48_000,
+			channel_count: 2,
+			bitrate: Some(128_000),
+			description: None,
+		}
+	}
+
+	fn catalog() -> Catalog {
+		let mut catalog = Catalog::default();
+		catalog.insert_video("1080p".to_string(), video(0x64, 1080, 6_000_000));
+		catalog.insert_video("720p".to_string(), video(0x64, 720, 3_000_000));
+		catalog.insert_video("360p".to_string(), video(0x42, 360, 800_000));
+		catalog.insert_audio("opus".to_string(), audio(AudioCodec::Opus));
+		catalog.insert_audio("aac".to_string(), audio(AAC { profile: 2 }.into()));
+		catalog
+	}
+
+	fn names<T>(renditions: &std::collections::HashMap<String, T>) -> Vec<&str> {
+		let mut names: Vec<_> = renditions.keys().map(String::as_str).collect();
+		names.sort();
+		names
+	}
+
+	#[test]
+	fn test_no_filter() {
+		let catalog = catalog();
+		assert_eq!(catalog.filter(&CatalogFilter::default()), catalog);
+	}
+
+	#[test]
+	fn test_limits() {
+		let filter = CatalogFilter {
+			max_height: Some(720),
+			max_bitrate: Some(1_000_000),
+			..Default::default()
+		};
+
+		let filtered = catalog().filter(&filter);
+		assert_eq!(names(&filtered.video.unwrap().renditions), vec!["360p"]);
+		assert_eq!(names(&filtered.audio.unwrap().renditions), vec!["aac", "opus"]);
+
+		// Nothing left, so the section is removed.
+		let filter = CatalogFilter {
+			max_width: Some(320),
+			..Default::default()
+		};
+
+		let filtered = catalog().filter(&filter);
+		assert!(filtered.video.is_none());
+		assert!(filtered.audio.is_some());
+	}
+
+	#[test]
+	fn test_capabilities() {
+		let filter = CatalogFilter {
+			capabilities: Some(Capabilities {
+				video: VideoCapabilities {
+					hardware: vec![video(0x42, 360, 0).codec],
+					software: vec![video(0x64, 720, 0).codec],
+					unsupported: vec![],
+				},
+				audio: AudioCapabilities {
+					unsupported: vec![AudioCodec::Opus],
+					..Default::default()
+				},
+			}),
+			max_height: Some(720),
+			..Default::default()
+		};
+
+		let filtered = catalog().filter(&filter);
+		assert_eq!(names(&filtered.video.unwrap().renditions), vec!["360p", "720p"]);
+		assert_eq!(names(&filtered.audio.unwrap().renditions), vec!["aac"]);
+	}
+
+	#[tokio::test]
+	async fn test_run() {
+		let mut input = Catalog::default().produce();
+		let output = Catalog::default().produce();
+		let mut consumer = output.consumer;
+
+		let filter = CatalogFilter {
+			max_height: Some(720),
+			..Default::default()
+		};
+
+		let task = tokio::spawn({
+			let consumer = input.producer.consume();
+			async move { filter.run(consumer, output.producer).await }
+		});
+
+		*input.producer.lock() = catalog();
+		let filtered = consumer.next().await.unwrap().unwrap();
+		assert_eq!(names(&filtered.video.unwrap().renditions), vec!["360p", "720p"]);
+
+		input.producer.close();
+		task.await.unwrap().unwrap();
+		assert!(consumer.next().await.unwrap().is_none());
+	}
+}
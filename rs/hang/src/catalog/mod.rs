@@ -6,7 +6,9 @@
 //! tracks to discover and choose appropriate tracks for their capabilities.
 
 mod audio;
+mod captions;
 mod chat;
+mod filter;
 mod preview;
 mod root;
 mod track;
@@ -14,7 +16,9 @@ mod user;
 mod video;
 
 pub use audio::*;
+pub use captions::*;
 pub use chat::*;
+pub use filter::*;
 pub use preview::*;
 pub use root::*;
 pub use track::*;
@@ -5,7 +5,7 @@ use std::sync::{Arc, Mutex, MutexGuard};
 /// The catalog format is a JSON file that describes the tracks available in a broadcast.
 use serde::{Deserialize, Serialize};
 
-use crate::catalog::{Audio, AudioConfig, Chat, Track, User, Video, VideoConfig};
+use crate::catalog::{Audio, AudioConfig, CaptionConfig, Captions, Chat, Track, User, Video, VideoConfig};
 use crate::Result;
 use moq_lite::Produce;
 
@@ -29,6 +29,12 @@ pub struct Catalog {
 	#[serde(default)]
 	pub audio: Option<Audio>,
 
+	/// Caption and subtitle track information with multiple renditions.
+	///
+	/// Contains a map of caption track renditions, typically one per language.
+	#[serde(default)]
+	pub captions: Option<Captions>,
+
 	/// User metadata for the broadcaster
 	#[serde(default)]
 	pub user: Option<User>,
@@ -114,6 +120,13 @@ impl Catalog {
 		self.audio.as_mut().unwrap()
 	}
 
+	pub fn insert_captions(&mut self, name: String, config: CaptionConfig) -> &mut Captions {
+		let mut captions = self.captions.take().unwrap_or_default();
+		captions.renditions.insert(name, config);
+		self.captions = Some(captions);
+		self.captions.as_mut().unwrap()
+	}
+
 	pub fn remove_video(&mut self, name: &str) {
 		let mut video = self.video.take().unwrap_or_default();
 		video.renditions.remove(name);
@@ -133,6 +146,16 @@ impl Catalog {
 			false => self.audio = Some(audio),
 		}
 	}
+
+	pub fn remove_captions(&mut self, name: &str) {
+		let mut captions = self.captions.take().unwrap_or_default();
+		captions.renditions.remove(name);
+
+		match captions.renditions.is_empty() {
+			true => self.captions = None,
+			false => self.captions = Some(captions),
+		}
+	}
 }
 
 /// Produces a catalog track that describes the available media tracks.
@@ -320,6 +343,7 @@ mod test {
 				coded_height: Some(720),
 				display_ratio_width: None,
 				display_ratio_height: None,
+				color_space: None,
 				bitrate: Some(6_000_000),
 				framerate: Some(30.0),
 				optimize_for_latency: None,
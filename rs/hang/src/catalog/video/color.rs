@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+/// The color space of a video track, based on WebCodecs VideoColorSpaceInit.
+///
+/// Any unset fields are left to the decoder, which typically assumes BT.709 limited range.
+/// HDR content needs these fields, otherwise it will be rendered with the wrong colors.
+///
+/// Reference: <https://w3c.github.io/webcodecs/#videocolorspace>
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoColorSpace {
+	/// The color primaries, ex. "bt709" or "bt2020".
+	#[serde(default)]
+	pub primaries: Option<String>,
+
+	/// The transfer characteristics, ex. "bt709", "pq" or "hlg".
+	#[serde(default)]
+	pub transfer: Option<String>,
+
+	/// The matrix coefficients, ex. "bt709" or "bt2020-ncl".
+	#[serde(default)]
+	pub matrix: Option<String>,
+
+	/// Whether the video uses full range (true) or limited range (false).
+	#[serde(default)]
+	pub full_range: Option<bool>,
+}
+
+impl VideoColorSpace {
+	/// Convert the code points from ISO/IEC 23091-2, as used by `colr` boxes, H.264/H.265 VUI and AV1.
+	///
+	/// Code points without a WebCodecs equivalent, including "unspecified", are left unset.
+	pub fn from_cicp(primaries: u8, transfer: u8, matrix: u8, full_range: bool) -> Self {
+		let primaries = match primaries {
+			1 => Some("bt709"),
+			5 => Some("bt470bg"),
+			6 => Some("smpte170m"),
+			9 => Some("bt2020"),
+			12 => Some("smpte432"),
+			_ => None,
+		};
+
+		let transfer = match transfer {
+			// BT.2020 uses the same transfer function as BT.709, just with more precision.
+			1 | 14 | 15 => Some("bt709"),
+			6 => Some("smpte170m"),
+			8 => Some("linear"),
+			13 => Some("iec61966-2-1"),
+			16 => Some("pq"),
+			18 => Some("hlg"),
+			_ => None,
+		};
+
+		let matrix = match matrix {
+			0 => Some("rgb"),
+			1 => Some("bt709"),
+			5 => Some("bt470bg"),
+			6 => Some("smpte170m"),
+			9 => Some("bt2020-ncl"),
+			_ => None,
+		};
+
+		Self {
+			primaries: primaries.map(Into::into),
+			transfer: transfer.map(Into::into),
+			matrix: matrix.map(Into::into),
+			full_range: Some(full_range),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_from_cicp() {
+		let hdr = VideoColorSpace::from_cicp(9, 16, 9, false);
+		assert_eq!(hdr.primaries.as_deref(), Some("bt2020"));
+		assert_eq!(hdr.transfer.as_deref(), Some("pq"));
+		assert_eq!(hdr.matrix.as_deref(), Some("bt2020-ncl"));
+		assert_eq!(hdr.full_range, Some(false));
+
+		let unspecified = VideoColorSpace::from_cicp(2, 2, 2, true);
+		assert_eq!(
+			unspecified,
+			VideoColorSpace {
+				full_range: Some(true),
+				..Default::default()
+			}
+		);
+	}
+
+	#[test]
+	fn test_serde() {
+		let encoded = r#"{"primaries":"bt709","transfer":"iec61966-2-1","matrix":"rgb","fullRange":true}"#;
+		let decoded = VideoColorSpace::from_cicp(1, 13, 0, true);
+
+		assert_eq!(serde_json::to_string(&decoded).unwrap(), encoded);
+		assert_eq!(serde_json::from_str::<VideoColorSpace>(encoded).unwrap(), decoded);
+	}
+}
@@ -1,11 +1,13 @@
 mod av1;
 mod codec;
+mod color;
 mod h264;
 mod h265;
 mod vp9;
 
 pub use av1::*;
 pub use codec::*;
+pub use color::*;
 pub use h264::*;
 pub use h265::*;
 pub use vp9::*;
@@ -94,7 +96,12 @@ pub struct VideoConfig {
 	pub display_ratio_width: Option<u32>,
 	pub display_ratio_height: Option<u32>,
 
-	// TODO color space
+	/// The color space of the media, if known.
+	///
+	/// This is required to render HDR content correctly.
+	#[serde(default)]
+	pub color_space: Option<VideoColorSpace>,
+
 	/// The maximum bitrate of the video track, if known.
 	#[serde(default)]
 	pub bitrate: Option<u64>,
@@ -0,0 +1,218 @@
+use crate::catalog::{Catalog, VideoCodec, VideoConfig};
+use crate::{Frame, TrackConsumer};
+use anyhow::Context;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use mp4_atom::Atom;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+const START_CODE: &[u8] = &[0, 0, 0, 1];
+
+/// Exports an H.264 rendition as an Annex B byte stream, ex. to pipe into ffmpeg.
+///
+/// If the catalog includes an avcC description, the payload is length prefixed and the SPS/PPS are out-of-band.
+/// These are converted to start codes and the SPS/PPS are inserted before each keyframe.
+/// Otherwise, the payload already uses start codes and is written as-is.
+pub struct AnnexB {
+	track: TrackConsumer,
+
+	// The SPS and PPS with start codes, inserted before each keyframe.
+	parameter_sets: Bytes,
+
+	// The size of each NAL unit length prefix, or None if the payload already uses start codes.
+	length_size: Option<usize>,
+}
+
+impl AnnexB {
+	pub fn new(config: &VideoConfig, track: TrackConsumer) -> anyhow::Result<Self> {
+		anyhow::ensure!(
+			matches!(config.codec, VideoCodec::H264(_)),
+			"unsupported codec: {}",
+			config.codec
+		);
+
+		let Some(description) = &config.description else {
+			return Ok(Self {
+				track,
+				parameter_sets: Bytes::new(),
+				length_size: None,
+			});
+		};
+
+		let avcc = mp4_atom::Avcc::decode_body(&mut description.clone())?;
+
+		// ISO/IEC 14496-15 only allows 1, 2, or 4 byte NAL length prefixes.
+		anyhow::ensure!(
+			matches!(avcc.length_size, 1 | 2 | 4),
+			"invalid NAL length size: {}",
+			avcc.length_size
+		);
+
+		let mut parameter_sets = BytesMut::new();
+		let ext = avcc.ext.iter().flat_map(|ext| &ext.sequence_parameter_sets_ext);
+
+		for nal in avcc
+			.sequence_parameter_sets
+			.iter()
+			.chain(ext)
+			.chain(&avcc.picture_parameter_sets)
+		{
+			parameter_sets.put_slice(START_CODE);
+			parameter_sets.put_slice(nal);
+		}
+
+		Ok(Self {
+			track,
+			parameter_sets: parameter_sets.freeze(),
+			length_size: Some(avcc.length_size.into()),
+		})
+	}
+
+	/// Subscribe to the largest H.264 rendition in the catalog.
+	pub fn subscribe(broadcast: &moq_lite::BroadcastConsumer, catalog: &Catalog) -> anyhow::Result<Self> {
+		let video = catalog.video.as_ref().context("missing video")?;
+
+		let (name, config) = video
+			.renditions
+			.iter()
+			.filter(|(_, config)| matches!(config.codec, VideoCodec::H264(_)))
+			.max_by_key(|(name, config)| {
+				let area =
+					config.coded_width.unwrap_or_default() as u64 * config.coded_height.unwrap_or_default() as u64;
+				(area, config.bitrate, *name)
+			})
+			.context("missing H.264 rendition")?;
+
+		let track = broadcast.subscribe_track(&moq_lite::Track {
+			name: name.clone(),
+			priority: video.priority,
+		});
+
+		Self::new(config, track.into())
+	}
+
+	/// Write the Annex B stream to the output until the track has ended.
+	pub async fn run<W: AsyncWrite + Unpin>(mut self, output: &mut W) -> anyhow::Result<()> {
+		while let Some(frame) = self.track.read_frame().await? {
+			let data = self.convert(&frame)?;
+			output.write_all(&data).await?;
+			output.flush().await?;
+		}
+
+		Ok(())
+	}
+
+	// Convert a frame to Annex B, prepending the parameter sets to keyframes.
+	fn convert(&self, frame: &Frame) -> anyhow::Result<Bytes> {
+		let mut payload = frame.payload.clone();
+
+		let Some(length_size) = self.length_size else {
+			return Ok(payload.copy_to_bytes(payload.remaining()));
+		};
+
+		let mut data = BytesMut::new();
+		if frame.keyframe {
+			data.put_slice(&self.parameter_sets);
+		}
+
+		while payload.has_remaining() {
+			anyhow::ensure!(payload.remaining() >= length_size, "truncated NAL length");
+			let size = payload.get_uint(length_size) as usize;
+
+			anyhow::ensure!(payload.remaining() >= size, "truncated NAL unit");
+			data.put_slice(START_CODE);
+			data.put(payload.copy_to_bytes(size));
+		}
+
+		Ok(data.freeze())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::catalog::H264;
+	use crate::Timestamp;
+
+	const SPS: &[u8] = &[0x67, 0x42, 0xc0, 0x0d, 0xd9];
+	const PPS: &[u8] = &[0x68, 0xcb, 0x83];
+
+	fn config(description: Option<Bytes>) -> VideoConfig {
+		VideoConfig {
+			codec: H264 {
+				profile: 0x42,
+				constraints: 0xc0,
+				level: 0x0d,
+				inline: description.is_none(),
+			}
+			.into(),
+			description,
+			coded_width: Some(320),
+			coded_height: Some(240),
+			display_ratio_width: None,
+			display_ratio_height: None,
+			color_space: None,
+			bitrate: None,
+			framerate: None,
+			optimize_for_latency: None,
+		}
+	}
+
+	fn frame(keyframe: bool, payload: &'static [u8]) -> Frame {
+		Frame {
+			timestamp: Timestamp::ZERO,
+			keyframe,
+			payload: Bytes::from_static(payload).into(),
+		}
+	}
+
+	fn track() -> TrackConsumer {
+		moq_lite::Track::new("video").produce().consumer.into()
+	}
+
+	#[test]
+	fn test_avcc() {
+		let mut description = BytesMut::new();
+		mp4_atom::Avcc::new(SPS, PPS)
+			.unwrap()
+			.encode_body(&mut description)
+			.unwrap();
+
+		let annexb = AnnexB::new(&config(Some(description.freeze())), track()).unwrap();
+
+		let keyframe = annexb
+			.convert(&frame(true, &[0, 0, 0, 2, 0x65, 0x88, 0, 0, 0, 1, 0x06]))
+			.unwrap();
+
+		let mut expected = vec![0, 0, 0, 1];
+		expected.extend_from_slice(SPS);
+		expected.extend_from_slice(&[0, 0, 0, 1]);
+		expected.extend_from_slice(PPS);
+		expected.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88, 0, 0, 0, 1, 0x06]);
+		assert_eq!(keyframe, expected);
+
+		let delta = annexb.convert(&frame(false, &[0, 0, 0, 2, 0x41, 0x9a])).unwrap();
+		assert_eq!(delta, vec![0, 0, 0, 1, 0x41, 0x9a]);
+
+		assert!(annexb.convert(&frame(false, &[0, 0, 0, 3, 0x41])).is_err());
+	}
+
+	#[test]
+	fn test_length_size() {
+		let mut avcc = mp4_atom::Avcc::new(SPS, PPS).unwrap();
+		avcc.length_size = 3;
+
+		let mut description = BytesMut::new();
+		avcc.encode_body(&mut description).unwrap();
+
+		// The length size is checked up front instead of failing every frame.
+		assert!(AnnexB::new(&config(Some(description.freeze())), track()).is_err());
+	}
+
+	#[test]
+	fn test_inline() {
+		let annexb = AnnexB::new(&config(None), track()).unwrap();
+
+		let payload = &[0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x65, 0x88];
+		assert_eq!(annexb.convert(&frame(true, payload)).unwrap(), payload.to_vec());
+	}
+}
@@ -0,0 +1,605 @@
+use crate::catalog::{AudioCodec, AudioConfig, VideoCodec, VideoConfig};
+use crate::import::{after_start_code, find_start_code};
+use crate::{Frame, Timestamp};
+use anyhow::Context;
+use bytes::{Buf, Bytes, BytesMut};
+use mp4_atom::{Atom, Decode, Encode};
+
+// Every track is packaged on its own, so it always uses the same ID.
+const TRACK_ID: u32 = 1;
+
+// Timestamps are in microseconds, so we use the same timescale to avoid rounding.
+const TIMESCALE: u32 = 1_000_000;
+
+// ISO/IEC 14496-12 sample flags.
+const SAMPLE_SYNC: u32 = 0x0200_0000; // sample_depends_on = 2
+const SAMPLE_NON_SYNC: u32 = 0x0101_0000; // sample_depends_on = 1, sample_is_non_sync_sample = 1
+
+/// Packages a single hang track into CMAF, for protocols like HLS and DASH that want fMP4.
+///
+/// The [Self::init] segment is produced once, and then each [Self::fragment] contains a run of frames.
+pub struct Cmaf {
+	handler: mp4_atom::FourCC,
+	codec: mp4_atom::Codec,
+	width: u16,
+	height: u16,
+
+	// The payload uses Annex B start codes and needs to be converted to length prefixes.
+	annexb: bool,
+}
+
+impl Cmaf {
+	/// Create a packager for a video track.
+	///
+	/// The first keyframe is required for codecs that only include their parameter sets in-band.
+	pub fn video(config: &VideoConfig, keyframe: &Frame) -> anyhow::Result<Self> {
+		let width = config.coded_width.unwrap_or_default().try_into()?;
+		let height = config.coded_height.unwrap_or_default().try_into()?;
+
+		let visual = mp4_atom::Visual {
+			data_reference_index: 1,
+			width,
+			height,
+			..Default::default()
+		};
+
+		let mut annexb = false;
+
+		let codec = match &config.codec {
+			VideoCodec::H264(h264) => {
+				let avcc = match &config.description {
+					Some(description) => mp4_atom::Avcc::decode_body(&mut description.clone())?,
+					None => {
+						anyhow::ensure!(h264.inline, "missing H.264 description");
+						annexb = true;
+
+						let payload = flatten(keyframe);
+						let nals = annexb_nals(&payload)?;
+
+						// Only the NAL unit type is needed to find the parameter sets.
+						let find = |kind: u8| nals.iter().find(|nal| nal.first().map(|h| h & 0x1f) == Some(kind));
+						let sps = find(7).context("missing SPS in keyframe")?;
+						let pps = find(8).context("missing PPS in keyframe")?;
+
+						mp4_atom::Avcc::new(sps, pps)?
+					}
+				};
+
+				mp4_atom::Avc1 {
+					visual,
+					avcc,
+					..Default::default()
+				}
+				.into()
+			}
+			VideoCodec::H265(h265) => {
+				let description = config.description.as_ref().context("missing H.265 description")?;
+				let hvcc = mp4_atom::Hvcc::decode_body(&mut description.clone())?;
+
+				match h265.in_band {
+					true => mp4_atom::Hev1 {
+						visual,
+						hvcc,
+						..Default::default()
+					}
+					.into(),
+					false => mp4_atom::Hvc1 {
+						visual,
+						hvcc,
+						..Default::default()
+					}
+					.into(),
+				}
+			}
+			VideoCodec::AV1(av1) => mp4_atom::Av01 {
+				visual,
+				av1c: mp4_atom::Av1c {
+					seq_profile: av1.profile,
+					seq_level_idx_0: av1.level,
+					seq_tier_0: av1.tier == 'H',
+					high_bitdepth: av1.bitdepth > 8,
+					twelve_bit: av1.bitdepth == 12,
+					monochrome: av1.mono_chrome,
+					chroma_subsampling_x: av1.chroma_subsampling_x,
+					chroma_subsampling_y: av1.chroma_subsampling_y,
+					chroma_sample_position: av1.chroma_sample_position,
+					initial_presentation_delay: None,
+					config_obus: config.description.clone().unwrap_or_default().to_vec(),
+				},
+				..Default::default()
+			}
+			.into(),
+			VideoCodec::VP9(vp9) => mp4_atom::Vp09 {
+				visual,
+				vpcc: mp4_atom::VpcC {
+					profile: vp9.profile,
+					level: vp9.level,
+					bit_depth: vp9.bit_depth,
+					chroma_subsampling: vp9.chroma_subsampling,
+					video_full_range_flag: vp9.full_range,
+					color_primaries: vp9.color_primaries,
+					transfer_characteristics: vp9.transfer_characteristics,
+					matrix_coefficients: vp9.matrix_coefficients,
+					codec_initialization_data: Vec::new(),
+				},
+			}
+			.into(),
+			VideoCodec::VP8 => mp4_atom::Vp08 {
+				visual,
+				vpcc: mp4_atom::VpcC {
+					bit_depth: 8,
+					chroma_subsampling: 1,
+					..Default::default()
+				},
+			}
+			.into(),
+			VideoCodec::Unknown(codec) => anyhow::bail!("unsupported codec: {codec}"),
+		};
+
+		Ok(Self {
+			handler: b"vide".into(),
+			codec,
+			width,
+			height,
+			annexb,
+		})
+	}
+
+	/// Create a packager for an audio track.
+	pub fn audio(config: &AudioConfig) -> anyhow::Result<Self> {
+		let audio = mp4_atom::Audio {
+			data_reference_index: 1,
+			channel_count: config.channel_count.try_into()?,
+			sample_size: 16,
+			// NOTE: The sample rate is 16.16 fixed point, so anything above 65535 Hz doesn't fit.
+			sample_rate: u16::try_from(config.sample_rate).unwrap_or(0).into(),
+		};
+
+		let codec = match &config.codec {
+			AudioCodec::AAC(aac) => {
+				// Reuse the AudioSpecificConfig if provided, otherwise rebuild it from the catalog.
+				let dec_specific = match &config.description {
+					Some(description) => mp4_atom::esds::DecoderSpecific::decode(&mut description.clone())?,
+					None => mp4_atom::esds::DecoderSpecific {
+						profile: aac.profile,
+						freq_index: AAC_SAMPLE_RATES
+							.iter()
+							.position(|rate| *rate == config.sample_rate)
+							.context("unsupported AAC sample rate")? as u8,
+						chan_conf: config.channel_count.try_into()?,
+					},
+				};
+
+				mp4_atom::Mp4a {
+					audio,
+					esds: mp4_atom::Esds {
+						es_desc: mp4_atom::esds::EsDescriptor {
+							es_id: TRACK_ID as u16,
+							dec_config: mp4_atom::esds::DecoderConfig {
+								object_type_indication: 0x40, // MPEG-4 Audio
+								stream_type: 0x05,            // Audio
+								up_stream: 0,
+								max_bitrate: config.bitrate.unwrap_or_default().try_into()?,
+								avg_bitrate: config.bitrate.unwrap_or_default().try_into()?,
+								dec_specific,
+								..Default::default()
+							},
+							..Default::default()
+						},
+					},
+					btrt: None,
+					taic: None,
+				}
+				.into()
+			}
+			AudioCodec::Opus => {
+				let dops = match &config.description {
+					Some(head) => dops(head)?,
+					None => mp4_atom::Dops {
+						output_channel_count: config.channel_count.try_into()?,
+						pre_skip: 0,
+						input_sample_rate: config.sample_rate,
+						output_gain: 0,
+					},
+				};
+
+				mp4_atom::Opus { audio, dops }.into()
+			}
+			AudioCodec::Unknown(codec) => anyhow::bail!("unsupported codec: {codec}"),
+		};
+
+		Ok(Self {
+			handler: b"soun".into(),
+			codec,
+			width: 0,
+			height: 0,
+			annexb: false,
+		})
+	}
+
+	/// Encode the initialization segment (ftyp + moov).
+	pub fn init(&self) -> anyhow::Result<Bytes> {
+		init(vec![self.trak(TRACK_ID)])
+	}
+
+	// The track box for the moov, using the given track ID.
+	pub(super) fn trak(&self, track_id: u32) -> mp4_atom::Trak {
+		let video = self.handler == b"vide".into();
+
+		mp4_atom::Trak {
+			tkhd: mp4_atom::Tkhd {
+				track_id,
+				enabled: true,
+				volume: if video { 0.into() } else { 1.into() },
+				width: self.width.into(),
+				height: self.height.into(),
+				..Default::default()
+			},
+			mdia: mp4_atom::Mdia {
+				mdhd: mp4_atom::Mdhd {
+					timescale: TIMESCALE,
+					language: "und".to_string(),
+					..Default::default()
+				},
+				hdlr: mp4_atom::Hdlr {
+					handler: self.handler,
+					name: String::new(),
+				},
+				minf: mp4_atom::Minf {
+					vmhd: video.then(Default::default),
+					smhd: (!video).then(Default::default),
+					dinf: mp4_atom::Dinf {
+						dref: mp4_atom::Dref {
+							urls: vec![Default::default()],
+						},
+					},
+					stbl: mp4_atom::Stbl {
+						stsd: mp4_atom::Stsd {
+							codecs: vec![self.codec.clone()],
+						},
+						stco: Some(Default::default()),
+						..Default::default()
+					},
+				},
+			},
+			..Default::default()
+		}
+	}
+
+	/// Encode a fragment (moof + mdat) containing the given frames.
+	///
+	/// The duration of each frame is the difference to the next timestamp, and `end` is used for the last frame.
+	/// Frames must be in decode order and B-frames are not supported.
+	pub fn fragment(&self, sequence: u32, frames: &[Frame], end: Timestamp) -> anyhow::Result<Bytes> {
+		self.fragment_track(TRACK_ID, sequence, frames, end)
+	}
+
+	// The same as [Self::fragment], but using the given track ID.
+	pub(super) fn fragment_track(
+		&self,
+		track_id: u32,
+		sequence: u32,
+		frames: &[Frame],
+		end: Timestamp,
+	) -> anyhow::Result<Bytes> {
+		let first = frames.first().context("empty fragment")?;
+
+		let mut data = Vec::new();
+		let mut entries = Vec::with_capacity(frames.len());
+
+		for (i, frame) in frames.iter().enumerate() {
+			let next = frames.get(i + 1).map(|f| f.timestamp).unwrap_or(end);
+			let duration = next.as_micros().saturating_sub(frame.timestamp.as_micros());
+
+			let start = data.len();
+			let payload = flatten(frame);
+
+			match self.annexb {
+				true => {
+					for nal in annexb_nals(&payload)? {
+						data.extend_from_slice(&u32::try_from(nal.len())?.to_be_bytes());
+						data.extend_from_slice(nal);
+					}
+				}
+				false => data.extend_from_slice(&payload),
+			}
+
+			entries.push(mp4_atom::TrunEntry {
+				duration: Some(duration.try_into()?),
+				size: Some((data.len() - start).try_into()?),
+				flags: Some(match frame.keyframe {
+					true => SAMPLE_SYNC,
+					false => SAMPLE_NON_SYNC,
+				}),
+				cts: None,
+			});
+		}
+
+		let mut moof = mp4_atom::Moof {
+			mfhd: mp4_atom::Mfhd {
+				sequence_number: sequence,
+			},
+			traf: vec![mp4_atom::Traf {
+				tfhd: mp4_atom::Tfhd {
+					track_id,
+					..Default::default()
+				},
+				tfdt: Some(mp4_atom::Tfdt {
+					base_media_decode_time: first.timestamp.as_micros(),
+				}),
+				trun: vec![mp4_atom::Trun {
+					data_offset: Some(0),
+					entries,
+				}],
+				..Default::default()
+			}],
+		};
+
+		// The data offset is relative to the start of the moof, so we need to encode it twice.
+		// The size doesn't change because the offset is a fixed size field.
+		let mut buf = BytesMut::new();
+		moof.encode(&mut buf)?;
+		let offset = buf.len() + 8; // mdat header
+
+		moof.traf[0].trun[0].data_offset = Some(offset.try_into()?);
+
+		buf.clear();
+		moof.encode(&mut buf)?;
+		mp4_atom::Mdat { data }.encode(&mut buf)?;
+
+		Ok(buf.freeze())
+	}
+}
+
+// Encode the initialization segment (ftyp + moov) for the given tracks.
+pub(super) fn init(trak: Vec<mp4_atom::Trak>) -> anyhow::Result<Bytes> {
+	let ftyp = mp4_atom::Ftyp {
+		major_brand: b"iso6".into(),
+		minor_version: 0,
+		compatible_brands: vec![b"iso6".into(), b"cmfc".into(), b"mp41".into()],
+	};
+
+	let trex = trak
+		.iter()
+		.map(|trak| mp4_atom::Trex {
+			track_id: trak.tkhd.track_id,
+			default_sample_description_index: 1,
+			default_sample_duration: 0,
+			default_sample_size: 0,
+			default_sample_flags: 0,
+		})
+		.collect();
+
+	let next_track_id = trak.iter().map(|trak| trak.tkhd.track_id).max().unwrap_or_default() + 1;
+
+	let moov = mp4_atom::Moov {
+		mvhd: mp4_atom::Mvhd {
+			timescale: TIMESCALE,
+			next_track_id,
+			..Default::default()
+		},
+		mvex: Some(mp4_atom::Mvex { mehd: None, trex }),
+		trak,
+		..Default::default()
+	};
+
+	let mut buf = BytesMut::new();
+	ftyp.encode(&mut buf)?;
+	moov.encode(&mut buf)?;
+
+	Ok(buf.freeze())
+}
+
+// The sampling frequency index used by the AAC AudioSpecificConfig.
+const AAC_SAMPLE_RATES: [u32; 13] = [
+	96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+// Convert an OpusHead (RFC 7845), which is little-endian, into the equivalent dOps box.
+fn dops(head: &[u8]) -> anyhow::Result<mp4_atom::Dops> {
+	anyhow::ensure!(head.len() >= 19 && head.starts_with(b"OpusHead"), "invalid OpusHead");
+
+	Ok(mp4_atom::Dops {
+		output_channel_count: head[9],
+		pre_skip: u16::from_le_bytes([head[10], head[11]]),
+		input_sample_rate: u32::from_le_bytes([head[12], head[13], head[14], head[15]]),
+		output_gain: i16::from_le_bytes([head[16], head[17]]),
+	})
+}
+
+fn flatten(frame: &Frame) -> Bytes {
+	let mut payload = frame.payload.clone();
+	payload.copy_to_bytes(payload.remaining())
+}
+
+// Split a complete Annex B frame into NAL units, without the start codes.
+fn annexb_nals(mut data: &[u8]) -> anyhow::Result<Vec<&[u8]>> {
+	let mut nals = Vec::new();
+
+	while !data.is_empty() {
+		let start = after_start_code(data)?.context("missing start code")?;
+		data = &data[start..];
+
+		// The last NAL unit isn't followed by a start code.
+		let size = find_start_code(data).map(|(size, _)| size).unwrap_or(data.len());
+		nals.push(&data[..size]);
+		data = &data[size..];
+	}
+
+	Ok(nals)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::catalog::{AAC, H264};
+	use mp4_atom::{Decode, DecodeMaybe};
+
+	// A 320x240 baseline SPS and PPS.
+	const SPS: &[u8] = &[
+		0x67, 0x42, 0xc0, 0x0d, 0xd9, 0x01, 0x41, 0xfb, 0x01, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03,
+		0x03, 0xc0, 0xf1, 0x42, 0x99, 0x20,
+	];
+	const PPS: &[u8] = &[0x68, 0xcb, 0x83, 0xcb, 0x20];
+
+	fn frame(timestamp: u64, keyframe: bool, payload: &[u8]) -> Frame {
+		Frame {
+			timestamp: Timestamp::from_millis(timestamp).unwrap(),
+			keyframe,
+			payload: Bytes::copy_from_slice(payload).into(),
+		}
+	}
+
+	#[test]
+	fn test_annexb_nals() {
+		let data = [0, 0, 0, 1, 0x67, 0xaa, 0, 0, 1, 0x68, 0xbb, 0, 0, 0, 1, 0x65];
+		let nals = annexb_nals(&data).unwrap();
+		assert_eq!(nals, vec![&[0x67, 0xaa][..], &[0x68, 0xbb], &[0x65]]);
+
+		assert!(annexb_nals(&[0x65, 0x00]).is_err());
+	}
+
+	#[test]
+	fn test_avc3() {
+		let config = VideoConfig {
+			codec: H264 {
+				inline: true,
+				profile: 0x42,
+				constraints: 0xc0,
+				level: 0x0d,
+			}
+			.into(),
+			description: None,
+			coded_width: Some(320),
+			coded_height: Some(240),
+			display_ratio_width: None,
+			display_ratio_height: None,
+			color_space: None,
+			bitrate: None,
+			framerate: None,
+			optimize_for_latency: None,
+		};
+
+		let mut keyframe = vec![0, 0, 0, 1];
+		keyframe.extend_from_slice(SPS);
+		keyframe.extend_from_slice(&[0, 0, 0, 1]);
+		keyframe.extend_from_slice(PPS);
+		keyframe.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88]);
+
+		let frames = [
+			frame(1000, true, &keyframe),
+			frame(1033, false, &[0, 0, 0, 1, 0x41, 0x9a]),
+		];
+
+		let cmaf = Cmaf::video(&config, &frames[0]).unwrap();
+
+		let mut init = cmaf.init().unwrap();
+		mp4_atom::Ftyp::decode(&mut init).unwrap();
+		let moov = mp4_atom::Moov::decode(&mut init).unwrap();
+
+		match &moov.trak[0].mdia.minf.stbl.stsd.codecs[0] {
+			mp4_atom::Codec::Avc1(avc1) => {
+				assert_eq!(avc1.visual.width, 320);
+				assert_eq!(avc1.avcc.sequence_parameter_sets, vec![SPS.to_vec()]);
+				assert_eq!(avc1.avcc.picture_parameter_sets, vec![PPS.to_vec()]);
+			}
+			codec => panic!("unexpected codec: {codec:?}"),
+		}
+
+		let fragment = cmaf
+			.fragment(7, &frames, Timestamp::from_millis(1066).unwrap())
+			.unwrap();
+		let mut buf = fragment.clone();
+		let moof = mp4_atom::Moof::decode_maybe(&mut buf).unwrap().unwrap();
+		assert_eq!(moof.mfhd.sequence_number, 7);
+
+		let traf = &moof.traf[0];
+		assert_eq!(traf.tfdt.as_ref().unwrap().base_media_decode_time, 1_000_000);
+
+		let trun = &traf.trun[0];
+		assert_eq!(trun.entries[0].duration, Some(33_000));
+		assert_eq!(trun.entries[0].flags, Some(SAMPLE_SYNC));
+		assert_eq!(trun.entries[1].flags, Some(SAMPLE_NON_SYNC));
+
+		// The data offset points at the first sample, which is now length prefixed.
+		let offset = trun.data_offset.unwrap() as usize;
+		assert_eq!(&fragment[offset..offset + 4], &(SPS.len() as u32).to_be_bytes());
+		assert_eq!(&fragment[offset + 4..offset + 4 + SPS.len()], SPS);
+		assert_eq!(&fragment[fragment.len() - 6..], &[0, 0, 0, 2, 0x41, 0x9a]);
+	}
+
+	#[test]
+	fn test_aac() {
+		let config = AudioConfig {
+			codec: AAC { profile: 2 }.into(),
+			sample_rate: 48_000,
+			channel_count: 2,
+			bitrate: None,
+			description: None,
+		};
+
+		let cmaf = Cmaf::audio(&config).unwrap();
+		let mut init = cmaf.init().unwrap();
+		mp4_atom::Ftyp::decode(&mut init).unwrap();
+		let moov = mp4_atom::Moov::decode(&mut init).unwrap();
+
+		match &moov.trak[0].mdia.minf.stbl.stsd.codecs[0] {
+			mp4_atom::Codec::Mp4a(mp4a) => {
+				let specific = &mp4a.esds.es_desc.dec_config.dec_specific;
+				assert_eq!(specific.profile, 2);
+				assert_eq!(specific.freq_index, 3);
+				assert_eq!(specific.chan_conf, 2);
+			}
+			codec => panic!("unexpected codec: {codec:?}"),
+		}
+	}
+
+	#[test]
+	fn test_description() {
+		// AAC-LC at 44.1kHz mono, which differs from the catalog fields.
+		let aac = AudioConfig {
+			codec: AAC { profile: 2 }.into(),
+			sample_rate: 48_000,
+			channel_count: 2,
+			bitrate: None,
+			description: Some(Bytes::from_static(&[0x12, 0x08])),
+		};
+
+		let mut head = b"OpusHead".to_vec();
+		head.extend_from_slice(&[1, 2, 0x38, 0x01, 0x80, 0xbb, 0, 0, 0xff, 0xff, 0]);
+
+		let opus = AudioConfig {
+			codec: AudioCodec::Opus,
+			sample_rate: 48_000,
+			channel_count: 2,
+			bitrate: None,
+			description: Some(head.into()),
+		};
+
+		let codec = |config: &AudioConfig| {
+			let mut init = Cmaf::audio(config).unwrap().init().unwrap();
+			mp4_atom::Ftyp::decode(&mut init).unwrap();
+			let moov = mp4_atom::Moov::decode(&mut init).unwrap();
+			moov.trak[0].mdia.minf.stbl.stsd.codecs[0].clone()
+		};
+
+		match codec(&aac) {
+			mp4_atom::Codec::Mp4a(mp4a) => {
+				let specific = &mp4a.esds.es_desc.dec_config.dec_specific;
+				assert_eq!(specific.profile, 2);
+				assert_eq!(specific.freq_index, 4);
+				assert_eq!(specific.chan_conf, 1);
+			}
+			codec => panic!("unexpected codec: {codec:?}"),
+		}
+
+		match codec(&opus) {
+			mp4_atom::Codec::Opus(opus) => {
+				assert_eq!(opus.dops.pre_skip, 312);
+				assert_eq!(opus.dops.input_sample_rate, 48_000);
+				assert_eq!(opus.dops.output_gain, -1);
+			}
+			codec => panic!("unexpected codec: {codec:?}"),
+		}
+	}
+}
@@ -0,0 +1,360 @@
+use crate::catalog::{AudioConfig, Catalog, VideoConfig};
+use crate::{Frame, Timestamp, TrackConsumer};
+use futures::stream::{self, StreamExt};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::Cmaf;
+
+/// Exports hang tracks as a single fragmented MP4 stream, ex. to pipe into ffmpeg or ffplay.
+///
+/// The moov is written once every track has produced a keyframe, because some codecs only include their parameters in-band.
+/// Afterwards, each frame is written as its own moof + mdat, delayed until the next frame so its duration is known.
+/// Timestamps are preserved, so the output starts at the broadcast's timestamp rather than zero.
+#[derive(Default)]
+pub struct Fmp4 {
+	tracks: Vec<(Kind, TrackConsumer)>,
+}
+
+enum Kind {
+	Video(VideoConfig),
+	Audio(AudioConfig),
+}
+
+impl Fmp4 {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Subscribe to the largest video rendition and the highest bitrate audio rendition in the catalog.
+	pub fn subscribe(broadcast: &moq_lite::BroadcastConsumer, catalog: &Catalog) -> Self {
+		let mut fmp4 = Self::new();
+
+		if let Some(video) = &catalog.video {
+			let best = video.renditions.iter().max_by_key(|(name, config)| {
+				let area =
+					config.coded_width.unwrap_or_default() as u64 * config.coded_height.unwrap_or_default() as u64;
+				(area, config.bitrate, *name)
+			});
+
+			if let Some((name, config)) = best {
+				let track = broadcast.subscribe_track(&moq_lite::Track {
+					name: name.clone(),
+					priority: video.priority,
+				});
+				fmp4.add_video(config.clone(), track.into());
+			}
+		}
+
+		if let Some(audio) = &catalog.audio {
+			let best = audio
+				.renditions
+				.iter()
+				.max_by_key(|(name, config)| (config.bitrate, config.sample_rate, *name));
+
+			if let Some((name, config)) = best {
+				let track = broadcast.subscribe_track(&moq_lite::Track {
+					name: name.clone(),
+					priority: audio.priority,
+				});
+				fmp4.add_audio(config.clone(), track.into());
+			}
+		}
+
+		fmp4
+	}
+
+	/// Add a video track, which will be written in the order it was added.
+	pub fn add_video(&mut self, config: VideoConfig, track: TrackConsumer) {
+		self.tracks.push((Kind::Video(config), track));
+	}
+
+	/// Add an audio track, which will be written in the order it was added.
+	pub fn add_audio(&mut self, config: AudioConfig, track: TrackConsumer) {
+		self.tracks.push((Kind::Audio(config), track));
+	}
+
+	/// Write the fMP4 stream to the output until every track has ended.
+	pub async fn run<W: AsyncWrite + Unpin>(self, output: &mut W) -> anyhow::Result<()> {
+		let (kinds, consumers): (Vec<_>, Vec<_>) = self.tracks.into_iter().unzip();
+
+		// Read from every track at once, with a final None when each track ends.
+		let mut frames = stream::select_all(consumers.into_iter().enumerate().map(|(index, consumer)| {
+			stream::unfold(consumer, |mut consumer| async move {
+				let frame = consumer.read_frame().await.transpose()?;
+				Some((frame.map(Some), consumer))
+			})
+			.chain(stream::once(async { Ok(None) }))
+			.map(move |frame| (index, frame))
+			.boxed()
+		}));
+
+		let mut writer = Writer {
+			tracks: kinds.into_iter().map(Track::new).collect(),
+			initialized: false,
+			sequence: 1,
+		};
+
+		while let Some((index, frame)) = frames.next().await {
+			let data = writer.frame(index, frame?)?;
+
+			if !data.is_empty() {
+				output.write_all(&data).await?;
+				output.flush().await?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+// The state of each track while writing.
+struct Track {
+	kind: Kind,
+
+	// Created when the init segment is written, or None if the track had no frames.
+	cmaf: Option<Cmaf>,
+	track_id: u32,
+
+	// Frames waiting for the init segment, or for the next frame to compute the duration.
+	pending: Vec<Frame>,
+
+	// The duration of the previous frame, used to guess the duration of the last frame.
+	duration: Option<Timestamp>,
+
+	ended: bool,
+}
+
+impl Track {
+	fn new(kind: Kind) -> Self {
+		Self {
+			kind,
+			cmaf: None,
+			track_id: 0,
+			pending: Vec::new(),
+			duration: None,
+			ended: false,
+		}
+	}
+}
+
+struct Writer {
+	tracks: Vec<Track>,
+	initialized: bool,
+	sequence: u32,
+}
+
+impl Writer {
+	// Process a frame, or the end of a track, returning any data to write.
+	fn frame(&mut self, index: usize, frame: Option<Frame>) -> anyhow::Result<Vec<u8>> {
+		let mut data = Vec::new();
+
+		let track = &mut self.tracks[index];
+
+		match frame {
+			// Skip until the first keyframe.
+			Some(frame) if track.pending.is_empty() && track.cmaf.is_none() && !frame.keyframe => {}
+			Some(frame) => {
+				if self.initialized {
+					Self::flush(track, &mut self.sequence, Some(frame.timestamp), &mut data)?;
+				}
+
+				track.pending.push(frame);
+			}
+			None => {
+				if self.initialized {
+					Self::flush(track, &mut self.sequence, None, &mut data)?;
+				}
+
+				track.ended = true;
+			}
+		}
+
+		// Write the init segment once every track has a keyframe or has ended.
+		if !self.initialized && self.tracks.iter().all(|track| track.ended || !track.pending.is_empty()) {
+			data.extend_from_slice(&self.init()?);
+
+			// Write anything that was buffered, keeping the last frame unless the track has ended.
+			for track in &mut self.tracks {
+				let last = match track.ended {
+					true => None,
+					false => track.pending.pop(),
+				};
+
+				Self::flush(
+					track,
+					&mut self.sequence,
+					last.as_ref().map(|last| last.timestamp),
+					&mut data,
+				)?;
+				track.pending.extend(last);
+			}
+		}
+
+		Ok(data)
+	}
+
+	fn init(&mut self) -> anyhow::Result<Vec<u8>> {
+		let mut traks = Vec::new();
+
+		for track in &mut self.tracks {
+			let Some(first) = track.pending.first() else {
+				continue;
+			};
+
+			let cmaf = match &track.kind {
+				Kind::Video(config) => Cmaf::video(config, first)?,
+				Kind::Audio(config) => Cmaf::audio(config)?,
+			};
+
+			track.track_id = traks.len() as u32 + 1;
+			traks.push(cmaf.trak(track.track_id));
+			track.cmaf = Some(cmaf);
+		}
+
+		self.initialized = true;
+
+		Ok(super::cmaf::init(traks)?.to_vec())
+	}
+
+	// Write each pending frame as a fragment, using `end` as the timestamp after the last frame.
+	// If there's no end, assume the last frame is as long as the one before it.
+	fn flush(track: &mut Track, sequence: &mut u32, end: Option<Timestamp>, data: &mut Vec<u8>) -> anyhow::Result<()> {
+		let pending = std::mem::take(&mut track.pending);
+
+		// The track didn't have a keyframe in time for the init segment.
+		let Some(cmaf) = &track.cmaf else {
+			return Ok(());
+		};
+
+		for (i, frame) in pending.iter().enumerate() {
+			let next = match pending.get(i + 1).map(|next| next.timestamp).or(end) {
+				Some(next) => next,
+				None => frame.timestamp + track.duration.unwrap_or_default(),
+			};
+
+			if next > frame.timestamp {
+				track.duration = Some(next - frame.timestamp);
+			}
+
+			let fragment = cmaf.fragment_track(track.track_id, *sequence, std::slice::from_ref(frame), next)?;
+			data.extend_from_slice(&fragment);
+			*sequence += 1;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::catalog::{VideoCodec, AAC};
+	use bytes::Bytes;
+	use mp4_atom::{Any, DecodeMaybe};
+
+	fn frame(millis: u64, keyframe: bool) -> Frame {
+		Frame {
+			timestamp: Timestamp::from_millis(millis).unwrap(),
+			keyframe,
+			payload: Bytes::from_static(&[0xaa, 0xbb]).into(),
+		}
+	}
+
+	// Time is paused, so the sleep only completes once the writer is idle and waiting for more frames.
+	#[tokio::test(start_paused = true)]
+	async fn test_run() {
+		let video = moq_lite::Track::new("video").produce();
+		let audio = moq_lite::Track::new("audio").produce();
+
+		let mut fmp4 = Fmp4::new();
+		fmp4.add_video(
+			VideoConfig {
+				codec: VideoCodec::VP8,
+				description: None,
+				coded_width: Some(640),
+				coded_height: Some(480),
+				display_ratio_width: None,
+				display_ratio_height: None,
+				color_space: None,
+				bitrate: None,
+				framerate: None,
+				optimize_for_latency: None,
+			},
+			video.consumer.into(),
+		);
+		fmp4.add_audio(
+			AudioConfig {
+				codec: AAC { profile: 2 }.into(),
+				sample_rate: 48_000,
+				channel_count: 2,
+				bitrate: None,
+				description: None,
+			},
+			audio.consumer.into(),
+		);
+
+		let mut video = crate::TrackProducer::new(video.producer);
+		video.write(frame(1000, true)).unwrap();
+		video.write(frame(1033, false)).unwrap();
+
+		let mut audio = crate::TrackProducer::new(audio.producer);
+		audio.write(frame(1000, true)).unwrap();
+		audio.write(frame(1020, false)).unwrap();
+
+		let mut output = Vec::new();
+
+		// Closing a track skips any unread groups, so wait until they've been read.
+		// Dropping the producers also ends the current group.
+		let (res, _) = tokio::join!(fmp4.run(&mut output), async move {
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+			video.inner.clone().close();
+			audio.inner.clone().close();
+			drop((video, audio));
+		});
+		res.unwrap();
+
+		let mut buf = Bytes::from(output);
+		let mut durations = Vec::new();
+
+		match Any::decode_maybe(&mut buf).unwrap() {
+			Some(Any::Ftyp(_)) => {}
+			atom => panic!("expected ftyp: {atom:?}"),
+		}
+
+		match Any::decode_maybe(&mut buf).unwrap() {
+			Some(Any::Moov(moov)) => {
+				assert_eq!(moov.trak.len(), 2);
+				assert_eq!(moov.trak[0].mdia.hdlr.handler, b"vide".into());
+				assert_eq!(moov.trak[1].mdia.hdlr.handler, b"soun".into());
+			}
+			atom => panic!("expected moov: {atom:?}"),
+		}
+
+		while let Some(atom) = Any::decode_maybe(&mut buf).unwrap() {
+			match atom {
+				Any::Moof(moof) => {
+					let traf = &moof.traf[0];
+					durations.push((
+						traf.tfhd.track_id,
+						traf.tfdt.as_ref().unwrap().base_media_decode_time,
+						traf.trun[0].entries[0].duration.unwrap(),
+					));
+				}
+				Any::Mdat(mdat) => assert_eq!(mdat.data, vec![0xaa, 0xbb]),
+				atom => panic!("unexpected atom: {atom:?}"),
+			}
+		}
+
+		// The timestamps are preserved, and the last frame is as long as the one before it.
+		durations.sort();
+		assert_eq!(
+			durations,
+			vec![
+				(1, 1_000_000, 33_000),
+				(1, 1_033_000, 33_000),
+				(2, 1_000_000, 20_000),
+				(2, 1_020_000, 20_000),
+			]
+		);
+	}
+}
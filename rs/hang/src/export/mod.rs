@@ -0,0 +1,7 @@
+mod annexb;
+mod cmaf;
+mod fmp4;
+
+pub use annexb::*;
+pub use cmaf::*;
+pub use fmp4::*;
@@ -1,18 +1,34 @@
 use crate as hang;
 use anyhow::Context;
 use buf_list::BufList;
-use bytes::Buf;
+use bytes::{Buf, Bytes};
 use moq_lite as moq;
 
-/// AAC decoder, initialized via AudioSpecificConfig (variable length from ESDS box).
+// The number of samples in each AAC frame.
+const FRAME_SAMPLES: u64 = 1024;
+
+/// AAC decoder, supporting two modes:
+/// - [Self::initialize] with an AudioSpecificConfig (variable length from ESDS box), then [Self::decode] for raw frames.
+/// - [Self::decode_adts] for a stream of ADTS frames (ex. from ffmpeg), with timestamps derived from the sample count.
 pub struct Aac {
 	broadcast: hang::BroadcastProducer,
 	track: Option<hang::TrackProducer>,
+
+	// The current config, used to detect changes in the ADTS headers.
+	config: Option<hang::catalog::AudioConfig>,
+
+	// The number of samples decoded from ADTS frames, used for timestamps.
+	samples: u64,
 }
 
 impl Aac {
 	pub fn new(broadcast: hang::BroadcastProducer) -> Self {
-		Self { broadcast, track: None }
+		Self {
+			broadcast,
+			track: None,
+			config: None,
+			samples: 0,
+		}
 	}
 
 	pub fn initialize<T: Buf>(&mut self, buf: &mut T) -> anyhow::Result<()> {
@@ -91,11 +107,6 @@ impl Aac {
 			(object_type, sample_rate, channel_count)
 		};
 
-		let track = moq::Track {
-			name: self.broadcast.track_name("audio"),
-			priority: 2,
-		};
-
 		let config = hang::catalog::AudioConfig {
 			codec: hang::catalog::AAC { profile }.into(),
 			sample_rate,
@@ -104,15 +115,36 @@ impl Aac {
 			description: None,
 		};
 
+		self.init(config)
+	}
+
+	fn init(&mut self, config: hang::catalog::AudioConfig) -> anyhow::Result<()> {
+		if let Some(old) = &self.config {
+			if old == &config {
+				return Ok(());
+			}
+		}
+
+		if let Some(track) = self.track.take() {
+			tracing::debug!(name = ?track.info.name, "reinitializing track");
+			self.broadcast.catalog.lock().remove_audio(&track.info.name);
+		}
+
+		let track = moq::Track {
+			name: self.broadcast.track_name("audio"),
+			priority: 2,
+		};
+
 		tracing::debug!(name = ?track.name, ?config, "starting track");
 
 		let track = track.produce();
 		self.broadcast.insert_track(track.consumer);
 
 		let mut catalog = self.broadcast.catalog.lock();
-		let audio = catalog.insert_audio(track.producer.info.name.clone(), config);
+		let audio = catalog.insert_audio(track.producer.info.name.clone(), config.clone());
 		audio.priority = 2;
 
+		self.config = Some(config);
 		self.track = Some(track.producer.into());
 
 		Ok(())
@@ -138,6 +170,46 @@ impl Aac {
 		Ok(())
 	}
 
+	/// Decode a stream of ADTS frames, initializing the track from the first header.
+	///
+	/// Only complete frames are consumed; if the buffer is not fully consumed, more data is needed.
+	pub fn decode_adts<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T) -> anyhow::Result<()> {
+		while let Some(header) = AdtsHeader::parse(buf.as_ref())? {
+			let mut frame = buf.copy_to_bytes(header.frame_length);
+			frame.advance(header.header_length);
+
+			self.decode_adts_frame(&header, frame)?;
+		}
+
+		Ok(())
+	}
+
+	fn decode_adts_frame(&mut self, header: &AdtsHeader, mut frame: Bytes) -> anyhow::Result<()> {
+		// ADTS can't signal an explicit sample rate, so no extra bytes are needed.
+		let sample_rate = sample_rate_from_index(header.freq_index, &mut Bytes::new())?;
+
+		// Keep the timestamps continuous if the sample rate changes.
+		if let Some(old) = &self.config {
+			self.samples = self.samples * sample_rate as u64 / old.sample_rate as u64;
+		}
+
+		self.init(hang::catalog::AudioConfig {
+			codec: hang::catalog::AAC {
+				profile: header.object_type,
+			}
+			.into(),
+			sample_rate,
+			channel_count: channel_count_from_config(header.channel_config),
+			bitrate: None,
+			description: Some(Bytes::copy_from_slice(&header.audio_specific_config())),
+		})?;
+
+		let pts = hang::Timestamp::from_micros(self.samples * 1_000_000 / sample_rate as u64)?;
+		self.samples += FRAME_SAMPLES;
+
+		self.decode(&mut frame, pts)
+	}
+
 	pub fn is_initialized(&self) -> bool {
 		self.track.is_some()
 	}
@@ -152,6 +224,72 @@ impl Drop for Aac {
 	}
 }
 
+/// The fields of an ADTS header (ISO 14496-3, section 1.A.2.2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AdtsHeader {
+	object_type: u8,
+	freq_index: u8,
+	channel_config: u8,
+
+	// The size of the header, including the CRC if present.
+	header_length: usize,
+
+	// The size of the entire frame, including the header.
+	frame_length: usize,
+}
+
+impl AdtsHeader {
+	// Returns None if the entire frame is not available yet.
+	fn parse(buf: &[u8]) -> anyhow::Result<Option<Self>> {
+		if buf.len() < 7 {
+			return Ok(None);
+		}
+
+		anyhow::ensure!(buf[0] == 0xff && buf[1] & 0xf0 == 0xf0, "missing ADTS sync word");
+		anyhow::ensure!(buf[1] & 0x06 == 0, "invalid ADTS layer");
+
+		let protection_absent = buf[1] & 0x01 == 1;
+		let header_length = if protection_absent { 7 } else { 9 };
+
+		// The profile is the object type minus one.
+		let object_type = (buf[2] >> 6) + 1;
+		let freq_index = (buf[2] >> 2) & 0x0f;
+		anyhow::ensure!(freq_index != 15, "invalid ADTS sample rate index");
+
+		let channel_config = ((buf[2] & 0x01) << 2) | (buf[3] >> 6);
+
+		let frame_length = (((buf[3] & 0x03) as usize) << 11) | ((buf[4] as usize) << 3) | (buf[5] >> 5) as usize;
+		anyhow::ensure!(frame_length >= header_length, "invalid ADTS frame length");
+
+		// Multiple frames can't be split without parsing the raw data blocks.
+		let raw_data_blocks = buf[6] & 0x03;
+		anyhow::ensure!(
+			raw_data_blocks == 0,
+			"multiple AAC frames per ADTS frame are not supported"
+		);
+
+		if buf.len() < frame_length {
+			return Ok(None);
+		}
+
+		Ok(Some(Self {
+			object_type,
+			freq_index,
+			channel_config,
+			header_length,
+			frame_length,
+		}))
+	}
+
+	// Synthesize the 2 byte AudioSpecificConfig, as would be found in the ESDS box.
+	fn audio_specific_config(&self) -> [u8; 2] {
+		[
+			(self.object_type << 3) | (self.freq_index >> 1),
+			((self.freq_index & 0x01) << 7) | (self.channel_config << 3),
+		]
+	}
+}
+
 fn sample_rate_from_index<T: Buf>(freq_index: u8, buf: &mut T) -> anyhow::Result<u32> {
 	const SAMPLE_RATES: [u32; 13] = [
 		96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
@@ -179,3 +317,82 @@ fn channel_count_from_config(channel_config: u8) -> u32 {
 		2
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// An AAC-LC (profile 1), 44.1kHz (index 4), stereo ADTS frame without a CRC.
+	fn adts(payload: &[u8]) -> Vec<u8> {
+		let length = 7 + payload.len();
+
+		let mut frame = vec![
+			0xff,
+			0xf1,
+			(1 << 6) | (4 << 2),
+			(2 << 6) | (length >> 11) as u8,
+			(length >> 3) as u8,
+			((length & 0x07) << 5) as u8 | 0x1f,
+			0xfc,
+		];
+		frame.extend_from_slice(payload);
+		frame
+	}
+
+	#[test]
+	fn test_adts_header() {
+		let frame = adts(&[0; 100]);
+
+		assert_eq!(AdtsHeader::parse(&frame[..6]).unwrap(), None);
+		assert_eq!(AdtsHeader::parse(&frame[..50]).unwrap(), None);
+
+		let header = AdtsHeader::parse(&frame).unwrap().unwrap();
+		assert_eq!(header.object_type, 2);
+		assert_eq!(header.freq_index, 4);
+		assert_eq!(header.channel_config, 2);
+		assert_eq!(header.header_length, 7);
+		assert_eq!(header.frame_length, 107);
+		assert_eq!(header.audio_specific_config(), [0x12, 0x10]);
+
+		assert!(AdtsHeader::parse(&[0; 7]).is_err());
+	}
+
+	#[tokio::test]
+	async fn test_adts() {
+		let broadcast = moq_lite::Broadcast::produce();
+		let producer: hang::BroadcastProducer = broadcast.producer.clone().into();
+		let mut catalog = producer.catalog.consume();
+		let mut aac = Aac::new(producer);
+
+		let mut stream = adts(&[1; 100]);
+		stream.extend(adts(&[2; 200]));
+
+		// Feed a partial second frame.
+		let mut buf = bytes::BytesMut::from(&stream[..150]);
+		aac.decode_adts(&mut buf).unwrap();
+		assert_eq!(buf.len(), 150 - 107);
+		assert!(aac.is_initialized());
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		let config = catalog.audio.unwrap().renditions.remove("audio0").unwrap();
+		assert_eq!(config.codec.to_string(), "mp4a.40.2");
+		assert_eq!(config.sample_rate, 44100);
+		assert_eq!(config.channel_count, 2);
+		assert_eq!(config.description.unwrap().as_ref(), &[0x12, 0x10]);
+
+		let track = broadcast.consumer.subscribe_track(&moq_lite::Track::new("audio0"));
+		let mut track = hang::TrackConsumer::new(track);
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert_eq!(frame.timestamp.as_micros(), 0);
+		assert_eq!(frame.payload.num_bytes(), 100);
+
+		buf.extend_from_slice(&stream[150..]);
+		aac.decode_adts(&mut buf).unwrap();
+		assert!(buf.is_empty());
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert_eq!(frame.timestamp.as_micros(), 23_219);
+		assert_eq!(frame.payload.num_bytes(), 200);
+	}
+}
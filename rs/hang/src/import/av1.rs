@@ -0,0 +1,596 @@
+use crate as hang;
+use anyhow::Context;
+use buf_list::BufList;
+use bytes::{Buf, Bytes, BytesMut};
+use moq_lite as moq;
+use mp4_atom::Atom;
+
+use super::hev1::BitReader;
+
+/// A decoder for AV1 in the low overhead bitstream format, as produced by SVT-AV1 and libaom.
+///
+/// Each OBU must include a size field, and each temporal unit must start with a temporal delimiter.
+pub struct Av1 {
+	// The broadcast being produced.
+	// This `hang` variant includes a catalog.
+	broadcast: hang::BroadcastProducer,
+
+	// The track being produced.
+	track: Option<hang::TrackProducer>,
+
+	// Whether the track has been initialized.
+	// If it changes, then we'll reinitialize with a new track.
+	config: Option<hang::catalog::VideoConfig>,
+
+	// The latest sequence header, needed to parse frame headers.
+	sequence: Option<SequenceHeader>,
+
+	// The current temporal unit being built.
+	current: Frame,
+}
+
+impl Av1 {
+	pub fn new(broadcast: hang::BroadcastProducer) -> Self {
+		Self {
+			broadcast,
+			track: None,
+			config: None,
+			sequence: None,
+			current: Default::default(),
+		}
+	}
+
+	fn init(&mut self, sequence: &SequenceHeader, obu: &Bytes) -> anyhow::Result<()> {
+		// Signal the sequence header out-of-band too, as it would be in MP4.
+		let mut description = BytesMut::new();
+		mp4_atom::Av1c {
+			seq_profile: sequence.profile,
+			seq_level_idx_0: sequence.level,
+			seq_tier_0: sequence.tier,
+			high_bitdepth: sequence.high_bitdepth,
+			twelve_bit: sequence.twelve_bit,
+			monochrome: sequence.mono_chrome,
+			chroma_subsampling_x: sequence.chroma_subsampling_x,
+			chroma_subsampling_y: sequence.chroma_subsampling_y,
+			chroma_sample_position: sequence.chroma_sample_position,
+			initial_presentation_delay: None,
+			config_obus: obu.to_vec(),
+		}
+		.encode_body(&mut description)?;
+
+		let config = hang::catalog::VideoConfig {
+			coded_width: Some(sequence.width),
+			coded_height: Some(sequence.height),
+			codec: hang::catalog::AV1 {
+				profile: sequence.profile,
+				level: sequence.level,
+				tier: if sequence.tier { 'H' } else { 'M' },
+				bitdepth: sequence.bitdepth(),
+				mono_chrome: sequence.mono_chrome,
+				chroma_subsampling_x: sequence.chroma_subsampling_x,
+				chroma_subsampling_y: sequence.chroma_subsampling_y,
+				chroma_sample_position: sequence.chroma_sample_position,
+				color_primaries: sequence.color_primaries,
+				transfer_characteristics: sequence.transfer_characteristics,
+				matrix_coefficients: sequence.matrix_coefficients,
+				full_range: sequence.full_range,
+			}
+			.into(),
+			description: Some(description.freeze()),
+			framerate: sequence.framerate(),
+			color_space: Some(hang::catalog::VideoColorSpace::from_cicp(
+				sequence.color_primaries,
+				sequence.transfer_characteristics,
+				sequence.matrix_coefficients,
+				sequence.full_range,
+			)),
+			// TODO: populate these fields
+			bitrate: None,
+			display_ratio_width: None,
+			display_ratio_height: None,
+			optimize_for_latency: None,
+		};
+
+		if let Some(old) = &self.config {
+			if old == &config {
+				return Ok(());
+			}
+		}
+
+		if let Some(track) = &self.track.take() {
+			tracing::debug!(name = ?track.info.name, "reinitializing track");
+			self.broadcast.catalog.lock().remove_video(&track.info.name);
+		}
+
+		let track = moq::Track {
+			name: self.broadcast.track_name("video"),
+			priority: 2,
+		};
+
+		tracing::debug!(name = ?track.name, ?config, "starting track");
+
+		{
+			let mut catalog = self.broadcast.catalog.lock();
+			let video = catalog.insert_video(track.name.clone(), config.clone());
+			video.priority = 2;
+		}
+
+		let track = track.produce();
+		self.broadcast.insert_track(track.consumer);
+
+		self.config = Some(config);
+		self.track = Some(track.producer.into());
+
+		Ok(())
+	}
+
+	/// Decode as many complete OBUs as possible from the given buffer.
+	///
+	/// Unlike [Self::decode_frame], this method needs the temporal delimiter for the next frame.
+	/// This means it works for streaming media (ex. stdin) but adds a frame of latency.
+	pub fn decode_stream<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T, pts: hang::Timestamp) -> anyhow::Result<()> {
+		while let Some((header, size)) = obu_size(buf.as_ref())? {
+			let obu = buf.copy_to_bytes(size);
+			self.decode_obu(obu, header, pts)?;
+		}
+
+		Ok(())
+	}
+
+	/// Decode all data in the buffer, assuming the buffer contains (the rest of) a temporal unit.
+	///
+	/// Unlike [Self::decode_stream], this is called when we know the temporal unit boundaries.
+	/// This can avoid a frame of latency just waiting for the next temporal delimiter.
+	/// This can also be used when EOF is detected to flush the final frame.
+	pub fn decode_frame<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T, pts: hang::Timestamp) -> anyhow::Result<()> {
+		self.decode_stream(buf, pts)?;
+		anyhow::ensure!(!buf.has_remaining(), "incomplete OBU");
+
+		// Flush the frame if we read a frame header.
+		self.maybe_start_frame(pts)?;
+
+		Ok(())
+	}
+
+	// The header is the size of the OBU header and size field, before the payload.
+	fn decode_obu(&mut self, obu: Bytes, header: usize, pts: hang::Timestamp) -> anyhow::Result<()> {
+		let obu_type = (obu[0] >> 3) & 0b1111;
+		let payload = &obu[header..];
+
+		match obu_type {
+			OBU_SEQUENCE_HEADER => {
+				// Try to reinitialize the track if the sequence header has changed.
+				let sequence = SequenceHeader::parse(payload)?;
+				self.init(&sequence, &obu)?;
+				self.sequence = Some(sequence);
+			}
+			OBU_TEMPORAL_DELIMITER => {
+				self.maybe_start_frame(pts)?;
+
+				// Temporal delimiters are stripped, just like in MP4.
+				return Ok(());
+			}
+			OBU_FRAME_HEADER | OBU_FRAME => {
+				let sequence = self
+					.sequence
+					.as_ref()
+					.context("expected sequence header before any frames")?;
+
+				// The first frame in a temporal unit determines if it's a keyframe.
+				if !self.current.contains_frame {
+					self.current.contains_keyframe = sequence.is_keyframe(payload)?;
+				}
+
+				self.current.contains_frame = true;
+			}
+			OBU_PADDING => return Ok(()),
+			_ => {}
+		}
+
+		tracing::trace!(kind = obu_type, "parsed OBU");
+
+		self.current.chunks.push_chunk(obu);
+
+		Ok(())
+	}
+
+	fn maybe_start_frame(&mut self, pts: hang::Timestamp) -> anyhow::Result<()> {
+		// If we haven't seen any frames, we shouldn't flush yet.
+		if !self.current.contains_frame {
+			return Ok(());
+		}
+
+		let track = self
+			.track
+			.as_mut()
+			.context("expected sequence header before any frames")?;
+
+		let payload = std::mem::take(&mut self.current.chunks);
+		let frame = hang::Frame {
+			timestamp: pts,
+			keyframe: self.current.contains_keyframe,
+			payload,
+		};
+
+		track.write(frame)?;
+
+		self.current.contains_keyframe = false;
+		self.current.contains_frame = false;
+
+		Ok(())
+	}
+
+	pub fn is_initialized(&self) -> bool {
+		self.track.is_some()
+	}
+}
+
+impl Drop for Av1 {
+	fn drop(&mut self) {
+		if let Some(track) = &self.track {
+			tracing::debug!(name = ?track.info.name, "ending track");
+			self.broadcast.catalog.lock().remove_video(&track.info.name);
+		}
+	}
+}
+
+const OBU_SEQUENCE_HEADER: u8 = 1;
+const OBU_TEMPORAL_DELIMITER: u8 = 2;
+const OBU_FRAME_HEADER: u8 = 3;
+const OBU_FRAME: u8 = 6;
+const OBU_PADDING: u8 = 15;
+
+#[derive(Default)]
+struct Frame {
+	chunks: BufList,
+	contains_keyframe: bool,
+	contains_frame: bool,
+}
+
+// Returns the size of the OBU header and the size of the entire OBU, or None if more data is needed.
+fn obu_size(buf: &[u8]) -> anyhow::Result<Option<(usize, usize)>> {
+	let Some(&header) = buf.first() else {
+		return Ok(None);
+	};
+
+	anyhow::ensure!(header & 0x80 == 0, "forbidden bit is not zero");
+	anyhow::ensure!(header & 0x02 != 0, "OBU is missing a size field");
+
+	// Skip the extension header if present.
+	let mut offset = 1 + ((header >> 2) & 1) as usize;
+
+	// The size is encoded as leb128, up to 8 bytes.
+	let mut size = 0u64;
+	for i in 0..8 {
+		let Some(&byte) = buf.get(offset) else {
+			return Ok(None);
+		};
+
+		offset += 1;
+		size |= ((byte & 0x7f) as u64) << (i * 7);
+
+		if byte & 0x80 == 0 {
+			let total = offset + usize::try_from(size)?;
+			return Ok((buf.len() >= total).then_some((offset, total)));
+		}
+	}
+
+	anyhow::bail!("invalid OBU size")
+}
+
+/// The fields of an AV1 sequence header needed for the catalog (AV1 spec, section 5.5).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SequenceHeader {
+	profile: u8,
+	level: u8,
+	tier: bool,
+	high_bitdepth: bool,
+	twelve_bit: bool,
+	mono_chrome: bool,
+	chroma_subsampling_x: bool,
+	chroma_subsampling_y: bool,
+	chroma_sample_position: u8,
+	color_primaries: u8,
+	transfer_characteristics: u8,
+	matrix_coefficients: u8,
+	full_range: bool,
+
+	// Frame headers are implied when set.
+	reduced_still_picture_header: bool,
+
+	// The maximum frame size.
+	width: u32,
+	height: u32,
+
+	// The frame rate, as (numerator, denominator).
+	framerate: Option<(u64, u64)>,
+}
+
+impl SequenceHeader {
+	fn parse(payload: &[u8]) -> anyhow::Result<Self> {
+		let mut bits = BitReader::new(payload);
+
+		let profile = bits.read(3)? as u8;
+		let _still_picture = bits.read(1)?;
+		let reduced_still_picture_header = bits.read(1)? == 1;
+
+		// Only known if each picture has the same duration.
+		let mut framerate = None;
+
+		let (level, tier) = if reduced_still_picture_header {
+			(bits.read(5)? as u8, false)
+		} else {
+			let mut decoder_model_info_present = false;
+			let mut buffer_delay_length = 0;
+
+			// timing_info_present_flag
+			if bits.read(1)? == 1 {
+				let num_units_in_display_tick = bits.read(32)? as u64;
+				let time_scale = bits.read(32)? as u64;
+
+				// equal_picture_interval, num_ticks_per_picture_minus_1
+				if bits.read(1)? == 1 {
+					let ticks = bits.read_ue()? as u64 + 1;
+					framerate = Some((time_scale, num_units_in_display_tick * ticks));
+				}
+
+				decoder_model_info_present = bits.read(1)? == 1;
+				if decoder_model_info_present {
+					buffer_delay_length = bits.read(5)? as usize + 1;
+
+					// num_units_in_decoding_tick, buffer_removal_time_length_minus_1, frame_presentation_time_length_minus_1
+					bits.skip(32 + 5 + 5)?;
+				}
+			}
+
+			let initial_display_delay_present = bits.read(1)? == 1;
+			let operating_points = bits.read(5)? + 1;
+
+			// The codec string uses the first operating point.
+			let mut first = None;
+
+			for _ in 0..operating_points {
+				// operating_point_idc
+				bits.skip(12)?;
+
+				let level = bits.read(5)? as u8;
+				let tier = level > 7 && bits.read(1)? == 1;
+				first.get_or_insert((level, tier));
+
+				// decoder_buffer_delay, encoder_buffer_delay, low_delay_mode_flag
+				if decoder_model_info_present && bits.read(1)? == 1 {
+					bits.skip(2 * buffer_delay_length + 1)?;
+				}
+
+				// initial_display_delay_minus_1
+				if initial_display_delay_present && bits.read(1)? == 1 {
+					bits.skip(4)?;
+				}
+			}
+
+			first.context("no operating points")?
+		};
+
+		let width_bits = bits.read(4)? as usize + 1;
+		let height_bits = bits.read(4)? as usize + 1;
+		let width = bits.read(width_bits)? + 1;
+		let height = bits.read(height_bits)? + 1;
+
+		// frame_id_numbers_present_flag, delta_frame_id_length_minus_2, additional_frame_id_length_minus_1
+		if !reduced_still_picture_header && bits.read(1)? == 1 {
+			bits.skip(4 + 3)?;
+		}
+
+		// use_128x128_superblock, enable_filter_intra, enable_intra_edge_filter
+		bits.skip(3)?;
+
+		if !reduced_still_picture_header {
+			// enable_interintra_compound, enable_masked_compound, enable_warped_motion, enable_dual_filter
+			bits.skip(4)?;
+
+			let enable_order_hint = bits.read(1)? == 1;
+			if enable_order_hint {
+				// enable_jnt_comp, enable_ref_frame_mvs
+				bits.skip(2)?;
+			}
+
+			// seq_choose_screen_content_tools, otherwise seq_force_screen_content_tools
+			let force_screen_content_tools = if bits.read(1)? == 1 { 2 } else { bits.read(1)? };
+
+			// seq_choose_integer_mv, otherwise seq_force_integer_mv
+			if force_screen_content_tools > 0 && bits.read(1)? == 0 {
+				bits.skip(1)?;
+			}
+
+			// order_hint_bits_minus_1
+			if enable_order_hint {
+				bits.skip(3)?;
+			}
+		}
+
+		// enable_superres, enable_cdef, enable_restoration
+		bits.skip(3)?;
+
+		// color_config()
+		let high_bitdepth = bits.read(1)? == 1;
+		let twelve_bit = profile == 2 && high_bitdepth && bits.read(1)? == 1;
+		let mono_chrome = profile != 1 && bits.read(1)? == 1;
+
+		// Unspecified unless the color description is present.
+		let (color_primaries, transfer_characteristics, matrix_coefficients) = if bits.read(1)? == 1 {
+			(bits.read(8)? as u8, bits.read(8)? as u8, bits.read(8)? as u8)
+		} else {
+			(2, 2, 2)
+		};
+
+		let mut chroma_sample_position = 0;
+
+		let (full_range, chroma_subsampling_x, chroma_subsampling_y) = if mono_chrome {
+			(bits.read(1)? == 1, true, true)
+		} else if (color_primaries, transfer_characteristics, matrix_coefficients) == (1, 13, 0) {
+			// sRGB is always full range 4:4:4.
+			(true, false, false)
+		} else {
+			let full_range = bits.read(1)? == 1;
+
+			let (x, y) = match profile {
+				0 => (true, true),
+				1 => (false, false),
+				_ if twelve_bit => {
+					let x = bits.read(1)? == 1;
+					let y = x && bits.read(1)? == 1;
+					(x, y)
+				}
+				_ => (true, false),
+			};
+
+			if x && y {
+				chroma_sample_position = bits.read(2)? as u8;
+			}
+
+			(full_range, x, y)
+		};
+
+		Ok(Self {
+			profile,
+			level,
+			tier,
+			high_bitdepth,
+			twelve_bit,
+			mono_chrome,
+			chroma_subsampling_x,
+			chroma_subsampling_y,
+			chroma_sample_position,
+			color_primaries,
+			transfer_characteristics,
+			matrix_coefficients,
+			full_range,
+			reduced_still_picture_header,
+			width,
+			height,
+			framerate,
+		})
+	}
+
+	fn framerate(&self) -> Option<f64> {
+		let (numerator, denominator) = self.framerate?;
+		(numerator > 0 && denominator > 0).then(|| numerator as f64 / denominator as f64)
+	}
+
+	fn bitdepth(&self) -> u8 {
+		match (self.high_bitdepth, self.twelve_bit) {
+			(true, true) => 12,
+			(true, false) => 10,
+			_ => 8,
+		}
+	}
+
+	// Parse the start of a frame header to check if it's a key frame (AV1 spec, section 5.9.2).
+	fn is_keyframe(&self, payload: &[u8]) -> anyhow::Result<bool> {
+		if self.reduced_still_picture_header {
+			return Ok(true);
+		}
+
+		let mut bits = BitReader::new(payload);
+
+		// show_existing_frame, which repeats a previously decoded frame.
+		if bits.read(1)? == 1 {
+			return Ok(false);
+		}
+
+		// frame_type == KEY_FRAME
+		Ok(bits.read(2)? == 0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// A 1920x1080 10-bit Main profile sequence header OBU, from an av1C atom.
+	const SEQUENCE_HEADER: &[u8] = &[10, 11, 0, 0, 0, 74, 171, 191, 195, 119, 255, 231, 1];
+
+	const TEMPORAL_DELIMITER: &[u8] = &[0x12, 0x00];
+
+	// Frame OBUs, containing only the start of a (shown) frame header.
+	const KEY_FRAME: &[u8] = &[0x32, 0x01, 0x10];
+	const INTER_FRAME: &[u8] = &[0x32, 0x01, 0x30];
+
+	#[test]
+	fn test_obu_size() {
+		assert_eq!(obu_size(&[]).unwrap(), None);
+		assert_eq!(obu_size(&SEQUENCE_HEADER[..5]).unwrap(), None);
+		assert_eq!(obu_size(SEQUENCE_HEADER).unwrap(), Some((2, 13)));
+		assert_eq!(obu_size(TEMPORAL_DELIMITER).unwrap(), Some((2, 2)));
+
+		// A two byte leb128 size.
+		assert_eq!(obu_size(&[0x32, 0x80, 0x01]).unwrap(), None);
+		assert_eq!(obu_size(&[0x32, 0x80, 0x01, 0].repeat(64)).unwrap(), Some((3, 131)));
+
+		// Missing the size field.
+		assert!(obu_size(&[0x30, 0x10]).is_err());
+	}
+
+	#[test]
+	fn test_sequence_header() {
+		let sequence = SequenceHeader::parse(&SEQUENCE_HEADER[2..]).unwrap();
+		assert_eq!(sequence.profile, 0);
+		assert_eq!(sequence.level, 9);
+		assert!(!sequence.tier);
+		assert_eq!(sequence.bitdepth(), 10);
+		assert!(!sequence.mono_chrome);
+		assert!(sequence.chroma_subsampling_x);
+		assert!(sequence.chroma_subsampling_y);
+		assert_eq!(sequence.width, 1920);
+		assert_eq!(sequence.height, 1080);
+
+		assert!(sequence.is_keyframe(&KEY_FRAME[2..]).unwrap());
+		assert!(!sequence.is_keyframe(&INTER_FRAME[2..]).unwrap());
+	}
+
+	#[tokio::test]
+	async fn test_decode() {
+		let broadcast = moq_lite::Broadcast::produce();
+		let producer: hang::BroadcastProducer = broadcast.producer.clone().into();
+		let mut catalog = producer.catalog.consume();
+		let mut av1 = Av1::new(producer);
+
+		let mut buf = BytesMut::new();
+		for obu in [TEMPORAL_DELIMITER, SEQUENCE_HEADER, KEY_FRAME, TEMPORAL_DELIMITER] {
+			buf.extend_from_slice(obu);
+		}
+
+		// The key frame is flushed by the next temporal delimiter.
+		av1.decode_stream(&mut buf, hang::Timestamp::from_micros(0).unwrap())
+			.unwrap();
+		assert!(buf.is_empty());
+		assert!(av1.is_initialized());
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		let config = catalog.video.unwrap().renditions.remove("video0").unwrap();
+		assert_eq!(config.codec.to_string(), "av01.0.09M.10.0.110.02.02.02.0");
+		assert_eq!(config.coded_width, Some(1920));
+		assert_eq!(config.coded_height, Some(1080));
+
+		let description = config.description.unwrap();
+		assert_eq!(&description[..4], &[0x81, 0x09, 0x4c, 0x00]);
+		assert_eq!(&description[4..], SEQUENCE_HEADER);
+
+		let track = broadcast.consumer.subscribe_track(&moq_lite::Track::new("video0"));
+		let mut track = hang::TrackConsumer::new(track);
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.timestamp.as_micros(), 0);
+		assert_eq!(frame.payload.num_bytes(), SEQUENCE_HEADER.len() + KEY_FRAME.len());
+
+		buf.extend_from_slice(INTER_FRAME);
+		av1.decode_frame(&mut buf, hang::Timestamp::from_micros(33_000).unwrap())
+			.unwrap();
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(!frame.keyframe);
+		assert_eq!(frame.timestamp.as_micros(), 33_000);
+		assert_eq!(frame.payload.num_bytes(), INTER_FRAME.len());
+	}
+}
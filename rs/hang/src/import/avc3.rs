@@ -4,11 +4,16 @@ use buf_list::BufList;
 use bytes::{Buf, Bytes};
 use moq_lite as moq;
 
+use super::hev1::BitReader;
+use super::vui::Vui;
+
 // Prepend each NAL with a 4 byte start code.
 // Yes, it's one byte longer than the 3 byte start code, but it's easier to convert to MP4.
 const START_CODE: Bytes = Bytes::from_static(&[0, 0, 0, 1]);
 
 /// A decoder for H.264 with inline SPS/PPS.
+///
+/// Any CEA-608/708 captions in SEI messages are published to a separate caption track.
 pub struct Avc3 {
 	// The broadcast being produced.
 	// This `hang` variant includes a catalog.
@@ -21,6 +26,12 @@ pub struct Avc3 {
 	// If it changes, then we'll reinitialize with a new track.
 	config: Option<hang::catalog::VideoConfig>,
 
+	// The caption track, created when the first captions are found.
+	captions: Option<hang::TrackProducer>,
+
+	// The timestamp of the last caption keyframe, because captions are in decode order.
+	captions_keyframe: Option<hang::Timestamp>,
+
 	// The current frame being built.
 	current: Frame,
 }
@@ -31,11 +42,13 @@ impl Avc3 {
 			broadcast,
 			track: None,
 			config: None,
+			captions: None,
+			captions_keyframe: None,
 			current: Default::default(),
 		}
 	}
 
-	fn init(&mut self, sps: &h264_parser::Sps) -> anyhow::Result<()> {
+	fn init(&mut self, sps: &h264_parser::Sps, vui: &Vui) -> anyhow::Result<()> {
 		let constraint_flags: u8 = ((sps.constraint_set0_flag as u8) << 7)
 			| ((sps.constraint_set1_flag as u8) << 6)
 			| ((sps.constraint_set2_flag as u8) << 5)
@@ -54,8 +67,9 @@ impl Avc3 {
 			}
 			.into(),
 			description: None,
+			framerate: vui.framerate(),
+			color_space: vui.color_space(),
 			// TODO: populate these fields
-			framerate: None,
 			bitrate: None,
 			display_ratio_width: None,
 			display_ratio_height: None,
@@ -95,6 +109,32 @@ impl Avc3 {
 		Ok(())
 	}
 
+	fn init_captions(&mut self) -> hang::TrackProducer {
+		let track = moq::Track {
+			name: self.broadcast.track_name("captions"),
+			priority: 3,
+		};
+
+		let config = hang::catalog::CaptionConfig {
+			format: hang::catalog::CaptionFormat::Cea608,
+			language: None,
+			description: None,
+		};
+
+		tracing::debug!(name = ?track.name, ?config, "starting track");
+
+		{
+			let mut catalog = self.broadcast.catalog.lock();
+			let captions = catalog.insert_captions(track.name.clone(), config);
+			captions.priority = 3;
+		}
+
+		let track = track.produce();
+		self.broadcast.insert_track(track.consumer);
+
+		track.producer.into()
+	}
+
 	/// Decode as much data as possible from the given buffer.
 	///
 	/// Unlike [Self::decode_framed], this method needs the start code for the next frame.
@@ -151,10 +191,26 @@ impl Avc3 {
 				// Try to reinitialize the track if the SPS has changed.
 				let nal = h264_parser::nal::ebsp_to_rbsp(&nal[1..]);
 				let sps = h264_parser::Sps::parse(&nal)?;
-				self.init(&sps)?;
+
+				// The VUI is optional, so don't fail if we can't parse it.
+				let vui = parse_vui(&nal).unwrap_or_else(|err| {
+					tracing::warn!(%err, "failed to parse VUI");
+					Vui::default()
+				});
+
+				self.init(&sps, &vui)?;
+			}
+			Some(NalType::Sei) => {
+				self.maybe_start_frame(pts)?;
+
+				// Captions are optional, so don't fail if we can't parse them.
+				match super::sei::cc_data(&h264_parser::nal::ebsp_to_rbsp(&nal[1..])) {
+					Ok(cc_data) => self.current.captions.extend_from_slice(&cc_data),
+					Err(err) => tracing::warn!(%err, "failed to parse SEI"),
+				}
 			}
 			// TODO parse the SPS again and reinitialize the track if needed
-			Some(NalType::Aud) | Some(NalType::Pps) | Some(NalType::Sei) => {
+			Some(NalType::Aud) | Some(NalType::Pps) => {
 				self.maybe_start_frame(pts)?;
 			}
 			Some(NalType::IdrSlice) => {
@@ -206,6 +262,37 @@ impl Avc3 {
 		self.current.contains_idr = false;
 		self.current.contains_slice = false;
 
+		if !self.current.captions.is_empty() {
+			let payload = std::mem::take(&mut self.current.captions);
+			self.write_captions(payload.into(), pts)?;
+		}
+
+		Ok(())
+	}
+
+	fn write_captions(&mut self, payload: Bytes, pts: hang::Timestamp) -> anyhow::Result<()> {
+		// Each frame is a keyframe, unless B-frames would cause the timestamp to go backwards.
+		let keyframe = match self.captions_keyframe {
+			Some(prev) => pts >= prev,
+			None => true,
+		};
+
+		if keyframe {
+			self.captions_keyframe = Some(pts);
+		}
+
+		let frame = hang::Frame {
+			timestamp: pts,
+			keyframe,
+			payload: payload.into(),
+		};
+
+		if self.captions.is_none() {
+			self.captions = Some(self.init_captions());
+		}
+
+		self.captions.as_mut().unwrap().write(frame)?;
+
 		Ok(())
 	}
 
@@ -220,6 +307,11 @@ impl Drop for Avc3 {
 			tracing::debug!(name = ?track.info.name, "ending track");
 			self.broadcast.catalog.lock().remove_video(&track.info.name);
 		}
+
+		if let Some(track) = &self.captions {
+			tracing::debug!(name = ?track.info.name, "ending track");
+			self.broadcast.catalog.lock().remove_captions(&track.info.name);
+		}
 	}
 }
 
@@ -245,7 +337,121 @@ pub enum NalType {
 	DepthParameterSet = 16,
 }
 
-struct NalIterator<T: Buf + AsRef<[u8]>> {
+/// Parse the VUI from a SPS, after the NAL header and with emulation prevention bytes removed.
+///
+/// `h264_parser` doesn't expose the VUI, so we need to skip over the rest of the SPS (ITU-T H.264, section 7.3.2.1.1).
+pub(crate) fn parse_vui(rbsp: &[u8]) -> anyhow::Result<Vui> {
+	let mut bits = BitReader::new(rbsp);
+
+	let profile_idc = bits.read(8)?;
+
+	// constraint_set_flags, level_idc
+	bits.skip(16)?;
+
+	// seq_parameter_set_id
+	bits.read_ue()?;
+
+	if matches!(
+		profile_idc,
+		100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+	) {
+		let chroma_format_idc = bits.read_ue()?;
+		if chroma_format_idc == 3 {
+			// separate_colour_plane_flag
+			bits.skip(1)?;
+		}
+
+		// bit_depth_luma_minus8, bit_depth_chroma_minus8
+		bits.read_ue()?;
+		bits.read_ue()?;
+
+		// qpprime_y_zero_transform_bypass_flag
+		bits.skip(1)?;
+
+		// seq_scaling_matrix_present_flag
+		if bits.read(1)? == 1 {
+			let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+			for i in 0..lists {
+				// seq_scaling_list_present_flag
+				if bits.read(1)? == 1 {
+					skip_scaling_list(&mut bits, if i < 6 { 16 } else { 64 })?;
+				}
+			}
+		}
+	}
+
+	// log2_max_frame_num_minus4
+	bits.read_ue()?;
+
+	match bits.read_ue()? {
+		// log2_max_pic_order_cnt_lsb_minus4
+		0 => {
+			bits.read_ue()?;
+		}
+		1 => {
+			// delta_pic_order_always_zero_flag, offset_for_non_ref_pic, offset_for_top_to_bottom_field
+			bits.skip(1)?;
+			bits.read_se()?;
+			bits.read_se()?;
+
+			// offset_for_ref_frame
+			for _ in 0..bits.read_ue()? {
+				bits.read_se()?;
+			}
+		}
+		_ => {}
+	}
+
+	// max_num_ref_frames, gaps_in_frame_num_value_allowed_flag
+	bits.read_ue()?;
+	bits.skip(1)?;
+
+	// pic_width_in_mbs_minus1, pic_height_in_map_units_minus1
+	bits.read_ue()?;
+	bits.read_ue()?;
+
+	// frame_mbs_only_flag, otherwise mb_adaptive_frame_field_flag
+	if bits.read(1)? == 0 {
+		bits.skip(1)?;
+	}
+
+	// direct_8x8_inference_flag
+	bits.skip(1)?;
+
+	// frame_cropping_flag
+	if bits.read(1)? == 1 {
+		for _ in 0..4 {
+			bits.read_ue()?;
+		}
+	}
+
+	// vui_parameters_present_flag
+	match bits.read(1)? {
+		1 => Vui::parse_h264(&mut bits),
+		_ => Ok(Vui::default()),
+	}
+}
+
+// ITU-T H.264, section 7.3.2.1.1.1
+fn skip_scaling_list(bits: &mut BitReader, size: usize) -> anyhow::Result<()> {
+	let mut last = 8;
+	let mut next = 8;
+
+	for _ in 0..size {
+		if next != 0 {
+			let delta = bits.read_se()?;
+			next = (last + delta).rem_euclid(256);
+		}
+
+		if next != 0 {
+			last = next;
+		}
+	}
+
+	Ok(())
+}
+
+pub(crate) struct NalIterator<T: Buf + AsRef<[u8]>> {
 	buf: T,
 	start: Option<usize>,
 }
@@ -278,7 +484,7 @@ impl<T: Buf + AsRef<[u8]>> Iterator for NalIterator<T> {
 }
 
 // Return the size of the start code at the start of the buffer.
-fn after_start_code(b: &[u8]) -> anyhow::Result<Option<usize>> {
+pub(crate) fn after_start_code(b: &[u8]) -> anyhow::Result<Option<usize>> {
 	if b.len() < 3 {
 		return Ok(None);
 	}
@@ -297,7 +503,7 @@ fn after_start_code(b: &[u8]) -> anyhow::Result<Option<usize>> {
 }
 
 // Return the number of bytes until the next start code, and the size of that start code.
-fn find_start_code(mut b: &[u8]) -> Option<(usize, usize)> {
+pub(crate) fn find_start_code(mut b: &[u8]) -> Option<(usize, usize)> {
 	// Okay this is over-engineered because this was my interview question.
 	// We need to find either a 3 byte or 4 byte start code.
 	// 3-byte: 0 0 1
@@ -364,12 +570,74 @@ struct Frame {
 	chunks: BufList,
 	contains_idr: bool,
 	contains_slice: bool,
+
+	// The cc_data triplets found in any SEI messages.
+	captions: Vec<u8>,
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	// A 1920x1080 High profile SPS with BT.2020 PQ limited range at 29.97fps.
+	const SPS: &[u8] = &[
+		0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0x9a, 0x84, 0x88, 0x04, 0xa0, 0x00, 0x00,
+		0x7d, 0x20, 0x00, 0x1d, 0x4c, 0x10, 0x80,
+	];
+
+	#[test]
+	fn test_parse_vui() {
+		let rbsp = h264_parser::nal::ebsp_to_rbsp(&SPS[1..]);
+
+		let sps = h264_parser::Sps::parse(&rbsp).unwrap();
+		assert_eq!(sps.width, 1920);
+		assert_eq!(sps.height, 1080);
+
+		let vui = parse_vui(&rbsp).unwrap();
+		assert_eq!(vui.framerate(), Some(60_000.0 / 2002.0));
+
+		let color_space = vui.color_space().unwrap();
+		assert_eq!(color_space.primaries.as_deref(), Some("bt2020"));
+		assert_eq!(color_space.transfer.as_deref(), Some("pq"));
+		assert_eq!(color_space.matrix.as_deref(), Some("bt2020-ncl"));
+		assert_eq!(color_space.full_range, Some(false));
+	}
+
+	#[tokio::test]
+	async fn test_captions() {
+		let broadcast = moq_lite::Broadcast::produce();
+		let producer: hang::BroadcastProducer = broadcast.producer.clone().into();
+		let mut catalog = producer.catalog.consume();
+		let mut avc3 = Avc3::new(producer);
+
+		// A SEI with ATSC captions, then an IDR slice truncated after the header.
+		let sei: &[u8] = &[
+			0x06, 0x04, 0x0e, 0xb5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x03, 0x41, 0xff, 0xfc, 0x94, 0x2c, 0xff, 0x80,
+		];
+		let idr: &[u8] = &[0x65, 0x88, 0x80];
+
+		let mut buf = bytes::BytesMut::new();
+		for nal in [SPS, sei, idr] {
+			buf.extend_from_slice(&START_CODE);
+			buf.extend_from_slice(nal);
+		}
+
+		let pts = hang::Timestamp::from_millis(100).unwrap();
+		avc3.decode_frame(&mut buf, pts).unwrap();
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		let config = catalog.captions.unwrap().renditions.remove("captions1").unwrap();
+		assert_eq!(config.format, hang::catalog::CaptionFormat::Cea608);
+
+		let track = broadcast.consumer.subscribe_track(&moq_lite::Track::new("captions1"));
+		let mut track = hang::TrackConsumer::new(track);
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.timestamp, pts);
+		assert_eq!(frame.payload.num_bytes(), 3);
+	}
+
 	// Tests for after_start_code - validates and measures start code at buffer beginning
 
 	#[test]
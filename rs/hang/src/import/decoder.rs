@@ -1,6 +1,9 @@
 use bytes::Buf;
 
-use crate::{self as hang, import::Aac};
+use crate::{
+	self as hang,
+	import::{Aac, Av1, Hev1, Ivf, Opus},
+};
 
 use super::{Avc3, Fmp4};
 
@@ -8,9 +11,20 @@ use super::{Avc3, Fmp4};
 enum DecoderKind {
 	/// aka H264 with inline SPS/PPS
 	Avc3(Avc3),
+	/// aka H265 with inline VPS/SPS/PPS
+	Hev1(Hev1),
+	/// aka AV1 in the low overhead bitstream format
+	Av1(Av1),
+	/// An IVF file, containing AV1, VP8 or VP9
+	Ivf(Ivf),
 	// Boxed because it's a large struct and clippy complains about the size.
 	Fmp4(Box<Fmp4>),
 	Aac(Aac),
+	/// AAC with ADTS headers
+	#[from(skip)]
+	Adts(Aac),
+	/// Ogg-encapsulated Opus
+	Opus(Opus),
 }
 
 /// A generic interface for importing a stream of media into a hang broadcast.
@@ -38,8 +52,13 @@ impl Decoder {
 				tracing::warn!("'annex-b' format is deprecated, use 'avc3' instead");
 				Avc3::new(broadcast).into()
 			}
+			"hev1" => Hev1::new(broadcast).into(),
+			"av1" => Av1::new(broadcast).into(),
+			"ivf" => Ivf::new(broadcast).into(),
 			"fmp4" | "cmaf" => Box::new(Fmp4::new(broadcast)).into(),
 			"aac" => Aac::new(broadcast).into(),
+			"adts" => DecoderKind::Adts(Aac::new(broadcast)),
+			"opus" => Opus::new(broadcast).into(),
 			_ => return None,
 		};
 
@@ -60,8 +79,13 @@ impl Decoder {
 
 		match &mut self.decoder {
 			DecoderKind::Avc3(decoder) => decoder.decode_frame(buf, pts()?)?,
+			DecoderKind::Hev1(decoder) => decoder.decode_frame(buf, pts()?)?,
+			DecoderKind::Av1(decoder) => decoder.decode_frame(buf, pts()?)?,
+			DecoderKind::Ivf(decoder) => decoder.decode(buf)?,
 			DecoderKind::Fmp4(decoder) => decoder.decode(buf)?,
 			DecoderKind::Aac(decoder) => decoder.initialize(buf)?,
+			DecoderKind::Adts(decoder) => decoder.decode_adts(buf)?,
+			DecoderKind::Opus(decoder) => decoder.decode_ogg(buf)?,
 		}
 
 		anyhow::ensure!(!buf.has_remaining(), "buffer was not fully consumed");
@@ -92,8 +116,13 @@ impl Decoder {
 
 		match &mut self.decoder {
 			DecoderKind::Avc3(decoder) => decoder.decode_stream(buf, pts()?),
+			DecoderKind::Hev1(decoder) => decoder.decode_stream(buf, pts()?),
+			DecoderKind::Av1(decoder) => decoder.decode_stream(buf, pts()?),
+			DecoderKind::Ivf(decoder) => decoder.decode(buf),
 			DecoderKind::Fmp4(decoder) => decoder.decode(buf),
 			DecoderKind::Aac(decoder) => decoder.decode(buf, pts()?),
+			DecoderKind::Adts(decoder) => decoder.decode_adts(buf),
+			DecoderKind::Opus(decoder) => decoder.decode_ogg(buf),
 		}
 	}
 
@@ -124,8 +153,25 @@ impl Decoder {
 
 		match &mut self.decoder {
 			DecoderKind::Avc3(decoder) => decoder.decode_frame(buf, pts()?)?,
+			DecoderKind::Hev1(decoder) => decoder.decode_frame(buf, pts()?)?,
+			DecoderKind::Av1(decoder) => decoder.decode_frame(buf, pts()?)?,
+			DecoderKind::Ivf(decoder) => {
+				// The timestamps come from the IVF frame headers.
+				decoder.decode(buf)?;
+				anyhow::ensure!(!buf.has_remaining(), "incomplete IVF frame");
+			}
 			DecoderKind::Fmp4(decoder) => decoder.decode(buf)?,
 			DecoderKind::Aac(decoder) => decoder.decode(buf, pts()?)?,
+			DecoderKind::Adts(decoder) => {
+				// The timestamps come from the number of samples.
+				decoder.decode_adts(buf)?;
+				anyhow::ensure!(!buf.has_remaining(), "incomplete ADTS frame");
+			}
+			DecoderKind::Opus(decoder) => {
+				// The timestamps come from the Ogg granule positions.
+				decoder.decode_ogg(buf)?;
+				anyhow::ensure!(!buf.has_remaining(), "incomplete Ogg page");
+			}
 		}
 
 		Ok(())
@@ -135,8 +181,13 @@ impl Decoder {
 	pub fn is_initialized(&self) -> bool {
 		match &self.decoder {
 			DecoderKind::Avc3(decoder) => decoder.is_initialized(),
+			DecoderKind::Hev1(decoder) => decoder.is_initialized(),
+			DecoderKind::Av1(decoder) => decoder.is_initialized(),
+			DecoderKind::Ivf(decoder) => decoder.is_initialized(),
 			DecoderKind::Fmp4(decoder) => decoder.is_initialized(),
 			DecoderKind::Aac(decoder) => decoder.is_initialized(),
+			DecoderKind::Adts(decoder) => decoder.is_initialized(),
+			DecoderKind::Opus(decoder) => decoder.is_initialized(),
 		}
 	}
 }
@@ -1,5 +1,6 @@
 use crate::catalog::{
-	AudioCodec, AudioConfig, Catalog, CatalogProducer, VideoCodec, VideoConfig, AAC, AV1, H264, H265, VP9,
+	AudioCodec, AudioConfig, CaptionConfig, CaptionFormat, Catalog, CatalogProducer, VideoCodec, VideoColorSpace,
+	VideoConfig, AAC, AV1, H264, H265, VP9,
 };
 use crate::{self as hang, Timestamp};
 use anyhow::Context;
@@ -25,6 +26,10 @@ use std::collections::HashMap;
 /// **Audio:**
 /// - AAC (MP4A)
 /// - Opus
+///
+/// **Captions:**
+/// - WebVTT (WVTT)
+/// - TTML (STPP)
 pub struct Fmp4 {
 	// The broadcast being produced
 	// This `hang` variant includes a catalog.
@@ -157,7 +162,23 @@ impl Fmp4 {
 					self.broadcast.insert_track(track.consumer);
 					track.producer
 				}
-				b"sbtl" => anyhow::bail!("subtitle tracks are not supported"),
+				b"sbtl" | b"subt" | b"text" => {
+					let config = Self::init_captions(trak)?;
+
+					let track = moq::Track {
+						name: self.broadcast.track_name("captions"),
+						priority: 3,
+					};
+
+					tracing::debug!(name = ?track.name, ?config, "starting track");
+
+					let captions = catalog.insert_captions(track.name.clone(), config);
+					captions.priority = 3;
+
+					let track = track.produce();
+					self.broadcast.insert_track(track.consumer);
+					track.producer
+				}
 				handler => anyhow::bail!("unknown track type: {:?}", handler),
 			};
 
@@ -185,6 +206,13 @@ impl Fmp4 {
 				let mut description = BytesMut::new();
 				avcc.encode_body(&mut description)?;
 
+				// The SPS may contain the color space and frame rate.
+				let vui = avcc
+					.sequence_parameter_sets
+					.first()
+					.and_then(|sps| super::avc3::parse_vui(&h264_parser::nal::ebsp_to_rbsp(sps.get(1..)?)).ok())
+					.unwrap_or_default();
+
 				VideoConfig {
 					coded_width: Some(avc1.visual.width as _),
 					coded_height: Some(avc1.visual.height as _),
@@ -196,21 +224,27 @@ impl Fmp4 {
 					}
 					.into(),
 					description: Some(description.freeze()),
+					framerate: vui.framerate(),
+					bitrate: bitrate(avc1.btrt.as_ref()),
+					color_space: color_space(avc1.colr.as_ref()).or_else(|| vui.color_space()),
 					// TODO: populate these fields
-					framerate: None,
-					bitrate: None,
 					display_ratio_width: None,
 					display_ratio_height: None,
 					optimize_for_latency: None,
 				}
 			}
-			mp4_atom::Codec::Hev1(hev1) => Self::init_h265(true, &hev1.hvcc, &hev1.visual)?,
-			mp4_atom::Codec::Hvc1(hvc1) => Self::init_h265(false, &hvc1.hvcc, &hvc1.visual)?,
+			mp4_atom::Codec::Hev1(hev1) => {
+				Self::init_h265(true, &hev1.hvcc, &hev1.visual, hev1.colr.as_ref(), hev1.btrt.as_ref())?
+			}
+			mp4_atom::Codec::Hvc1(hvc1) => {
+				Self::init_h265(false, &hvc1.hvcc, &hvc1.visual, hvc1.colr.as_ref(), hvc1.btrt.as_ref())?
+			}
 			mp4_atom::Codec::Vp08(vp08) => VideoConfig {
 				codec: VideoCodec::VP8,
 				description: Default::default(),
 				coded_width: Some(vp08.visual.width as _),
 				coded_height: Some(vp08.visual.height as _),
+				color_space: None,
 				// TODO: populate these fields
 				framerate: None,
 				bitrate: None,
@@ -237,6 +271,12 @@ impl Fmp4 {
 					description: Default::default(),
 					coded_width: Some(vp09.visual.width as _),
 					coded_height: Some(vp09.visual.height as _),
+					color_space: Some(VideoColorSpace::from_cicp(
+						vpcc.color_primaries,
+						vpcc.transfer_characteristics,
+						vpcc.matrix_coefficients,
+						vpcc.video_full_range_flag,
+					)),
 					// TODO: populate these fields
 					display_ratio_width: None,
 					display_ratio_height: None,
@@ -248,32 +288,47 @@ impl Fmp4 {
 			mp4_atom::Codec::Av01(av01) => {
 				let av1c = &av01.av1c;
 
+				let mut codec = AV1 {
+					profile: av1c.seq_profile,
+					level: av1c.seq_level_idx_0,
+					tier: if av1c.seq_tier_0 { 'H' } else { 'M' },
+					bitdepth: match (av1c.high_bitdepth, av1c.twelve_bit) {
+						(true, true) => 12,
+						(true, false) => 10,
+						_ => 8,
+					},
+					mono_chrome: av1c.monochrome,
+					chroma_subsampling_x: av1c.chroma_subsampling_x,
+					chroma_subsampling_y: av1c.chroma_subsampling_y,
+					chroma_sample_position: av1c.chroma_sample_position,
+					..Default::default()
+				};
+
+				// The codec string includes the color information too.
+				if let Some(mp4_atom::Colr::Nclx {
+					colour_primaries,
+					transfer_characteristics,
+					matrix_coefficients,
+					full_range_flag,
+				}) = &av01.colr
+				{
+					codec.color_primaries = (*colour_primaries).try_into()?;
+					codec.transfer_characteristics = (*transfer_characteristics).try_into()?;
+					codec.matrix_coefficients = (*matrix_coefficients).try_into()?;
+					codec.full_range = *full_range_flag;
+				}
+
 				VideoConfig {
-					codec: AV1 {
-						profile: av1c.seq_profile,
-						level: av1c.seq_level_idx_0,
-						bitdepth: match (av1c.seq_tier_0, av1c.high_bitdepth) {
-							(true, true) => 12,
-							(true, false) => 10,
-							(false, true) => 10,
-							(false, false) => 8,
-						},
-						mono_chrome: av1c.monochrome,
-						chroma_subsampling_x: av1c.chroma_subsampling_x,
-						chroma_subsampling_y: av1c.chroma_subsampling_y,
-						chroma_sample_position: av1c.chroma_sample_position,
-						// TODO HDR stuff?
-						..Default::default()
-					}
-					.into(),
+					codec: codec.into(),
 					description: Default::default(),
 					coded_width: Some(av01.visual.width as _),
 					coded_height: Some(av01.visual.height as _),
+					color_space: color_space(av01.colr.as_ref()),
+					bitrate: bitrate(av01.btrt.as_ref()),
 					// TODO: populate these fields
 					display_ratio_width: None,
 					display_ratio_height: None,
 					optimize_for_latency: None,
-					bitrate: None,
 					framerate: None,
 				}
 			}
@@ -285,10 +340,31 @@ impl Fmp4 {
 	}
 
 	// There's two almost identical hvcc atoms in the wild.
-	fn init_h265(in_band: bool, hvcc: &mp4_atom::Hvcc, visual: &mp4_atom::Visual) -> anyhow::Result<VideoConfig> {
+	fn init_h265(
+		in_band: bool,
+		hvcc: &mp4_atom::Hvcc,
+		visual: &mp4_atom::Visual,
+		colr: Option<&mp4_atom::Colr>,
+		btrt: Option<&mp4_atom::Btrt>,
+	) -> anyhow::Result<VideoConfig> {
 		let mut description = BytesMut::new();
 		hvcc.encode_body(&mut description)?;
 
+		// The SPS may contain the color space and frame rate.
+		let vui = hvcc
+			.arrays
+			.iter()
+			.filter(|array| array.nal_unit_type == 33)
+			.flat_map(|array| array.nalus.first())
+			.find_map(|sps| super::hev1::Sps::parse(&h264_parser::nal::ebsp_to_rbsp(sps.get(2..)?)).ok())
+			.map(|sps| sps.vui)
+			.unwrap_or_default();
+
+		// Otherwise, the average frame rate is in units of frames per 256 seconds.
+		let framerate = vui
+			.framerate()
+			.or_else(|| (hvcc.avg_frame_rate > 0).then(|| hvcc.avg_frame_rate as f64 / 256.0));
+
 		Ok(VideoConfig {
 			codec: H265 {
 				in_band,
@@ -303,9 +379,10 @@ impl Fmp4 {
 			description: Some(description.freeze()),
 			coded_width: Some(visual.width as _),
 			coded_height: Some(visual.height as _),
+			bitrate: bitrate(btrt),
+			framerate,
+			color_space: color_space(colr).or_else(|| vui.color_space()),
 			// TODO: populate these fields
-			bitrate: None,
-			framerate: None,
 			display_ratio_width: None,
 			display_ratio_height: None,
 			optimize_for_latency: None,
@@ -359,6 +436,36 @@ impl Fmp4 {
 		Ok(config)
 	}
 
+	fn init_captions(trak: &Trak) -> anyhow::Result<CaptionConfig> {
+		let stsd = &trak.mdia.minf.stbl.stsd;
+
+		let codec = match stsd.codecs.len() {
+			0 => anyhow::bail!("missing codec"),
+			1 => &stsd.codecs[0],
+			_ => anyhow::bail!("multiple codecs"),
+		};
+
+		// mp4-atom doesn't parse these sample entries, so any configuration (ex. vttC) is unavailable.
+		let format = match codec {
+			mp4_atom::Codec::Unknown(fourcc) => match fourcc.as_ref() {
+				b"wvtt" => CaptionFormat::WebVtt,
+				b"stpp" => CaptionFormat::Ttml,
+				_ => anyhow::bail!("unknown codec: {:?}", fourcc),
+			},
+			unsupported => anyhow::bail!("unsupported codec: {:?}", unsupported),
+		};
+
+		// An undetermined language is the same as no language.
+		let language = &trak.mdia.mdhd.language;
+		let language = (!language.is_empty() && language != "und").then(|| language.clone());
+
+		Ok(CaptionConfig {
+			format,
+			language,
+			description: None,
+		})
+	}
+
 	// Extract all frames out of an mdat atom.
 	fn extract(&mut self, mdat: Mdat, header_size: usize) -> anyhow::Result<()> {
 		let mdat = Bytes::from(mdat.data);
@@ -394,6 +501,7 @@ impl Fmp4 {
 			let tfdt = traf.tfdt.as_ref().context("missing tfdt box")?;
 			let mut dts = tfdt.base_media_decode_time;
 			let timescale = trak.mdia.mdhd.timescale as u64;
+			let handler = trak.mdia.hdlr.handler;
 
 			let mut offset = traf.tfhd.base_data_offset.unwrap_or_default() as usize;
 
@@ -435,7 +543,7 @@ impl Fmp4 {
 						anyhow::bail!("invalid data offset");
 					}
 
-					let keyframe = if trak.mdia.hdlr.handler == b"vide".into() {
+					let keyframe = if handler == b"vide".into() {
 						// https://chromium.googlesource.com/chromium/src/media/+/master/formats/mp4/track_run_iterator.cc#177
 						let keyframe = (flags >> 24) & 0x3 == 0x2; // kSampleDependsOnNoOther
 						let non_sync = (flags >> 16) & 0x1 == 0x1; // kSampleIsNonSyncSample
@@ -450,12 +558,15 @@ impl Fmp4 {
 						} else {
 							false
 						}
-					} else {
+					} else if handler == b"soun".into() {
 						match self.last_keyframe.get(&track_id) {
 							// Force an audio keyframe at least every 10 seconds, but ideally at video keyframes
 							Some(prev) => timestamp - *prev > Timestamp::from_secs(10).unwrap(),
 							None => true,
 						}
+					} else {
+						// Each caption sample can be decoded independently.
+						true
 					};
 
 					if keyframe {
@@ -503,9 +614,38 @@ impl Drop for Fmp4 {
 		for track in self.tracks.values() {
 			tracing::debug!(name = ?track.info.name, "ending track");
 
-			// We're too lazy to keep track of if this track is for audio or video, so we just remove both.
+			// We're too lazy to keep track of the type of this track, so we just remove them all.
 			catalog.remove_video(&track.info.name);
 			catalog.remove_audio(&track.info.name);
+			catalog.remove_captions(&track.info.name);
 		}
 	}
 }
+
+// Convert a colr box into a color space, ignoring ICC profiles.
+fn color_space(colr: Option<&mp4_atom::Colr>) -> Option<VideoColorSpace> {
+	match colr? {
+		mp4_atom::Colr::Nclx {
+			colour_primaries,
+			transfer_characteristics,
+			matrix_coefficients,
+			full_range_flag,
+		} => Some(VideoColorSpace::from_cicp(
+			(*colour_primaries).try_into().ok()?,
+			(*transfer_characteristics).try_into().ok()?,
+			(*matrix_coefficients).try_into().ok()?,
+			*full_range_flag,
+		)),
+		_ => None,
+	}
+}
+
+// Use the maximum bitrate from the btrt box, falling back to the average.
+fn bitrate(btrt: Option<&mp4_atom::Btrt>) -> Option<u64> {
+	let btrt = btrt?;
+
+	match btrt.max_bitrate {
+		0 => (btrt.avg_bitrate > 0).then_some(btrt.avg_bitrate as u64),
+		max => Some(max as u64),
+	}
+}
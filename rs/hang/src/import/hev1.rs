@@ -0,0 +1,639 @@
+use crate as hang;
+use anyhow::Context;
+use buf_list::BufList;
+use bytes::{Buf, Bytes};
+use moq_lite as moq;
+
+use super::avc3::{after_start_code, NalIterator};
+use super::vui::Vui;
+
+// Prepend each NAL with a 4 byte start code, just like Avc3.
+const START_CODE: Bytes = Bytes::from_static(&[0, 0, 0, 1]);
+
+/// A decoder for H.265 with inline VPS/SPS/PPS.
+pub struct Hev1 {
+	// The broadcast being produced.
+	// This `hang` variant includes a catalog.
+	broadcast: hang::BroadcastProducer,
+
+	// The track being produced.
+	track: Option<hang::TrackProducer>,
+
+	// Whether the track has been initialized.
+	// If it changes, then we'll reinitialize with a new track.
+	config: Option<hang::catalog::VideoConfig>,
+
+	// The current frame being built.
+	current: Frame,
+}
+
+impl Hev1 {
+	pub fn new(broadcast: hang::BroadcastProducer) -> Self {
+		Self {
+			broadcast,
+			track: None,
+			config: None,
+			current: Default::default(),
+		}
+	}
+
+	fn init(&mut self, sps: &Sps) -> anyhow::Result<()> {
+		let config = hang::catalog::VideoConfig {
+			coded_width: Some(sps.width),
+			coded_height: Some(sps.height),
+			codec: hang::catalog::H265 {
+				in_band: true,
+				profile_space: sps.profile_space,
+				profile_idc: sps.profile_idc,
+				profile_compatibility_flags: sps.profile_compatibility_flags,
+				tier_flag: sps.tier_flag,
+				level_idc: sps.level_idc,
+				constraint_flags: sps.constraint_flags,
+			}
+			.into(),
+			description: None,
+			framerate: sps.vui.framerate(),
+			color_space: sps.vui.color_space(),
+			// TODO: populate these fields
+			bitrate: None,
+			display_ratio_width: None,
+			display_ratio_height: None,
+			optimize_for_latency: None,
+		};
+
+		if let Some(old) = &self.config {
+			if old == &config {
+				return Ok(());
+			}
+		}
+
+		if let Some(track) = &self.track.take() {
+			tracing::debug!(name = ?track.info.name, "reinitializing track");
+			self.broadcast.catalog.lock().remove_video(&track.info.name);
+		}
+
+		let track = moq::Track {
+			name: self.broadcast.track_name("video"),
+			priority: 2,
+		};
+
+		tracing::debug!(name = ?track.name, ?config, "starting track");
+
+		{
+			let mut catalog = self.broadcast.catalog.lock();
+			let video = catalog.insert_video(track.name.clone(), config.clone());
+			video.priority = 2;
+		}
+
+		let track = track.produce();
+		self.broadcast.insert_track(track.consumer);
+
+		self.config = Some(config);
+		self.track = Some(track.producer.into());
+
+		Ok(())
+	}
+
+	/// Decode as much data as possible from the given buffer.
+	///
+	/// Unlike [Self::decode_frame], this method needs the start code for the next frame.
+	/// This means it works for streaming media (ex. stdin) but adds a frame of latency.
+	pub fn decode_stream<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T, pts: hang::Timestamp) -> anyhow::Result<()> {
+		let nals = NalIterator::new(buf);
+
+		for nal in nals {
+			self.decode_nal(nal?, pts)?;
+		}
+
+		Ok(())
+	}
+
+	/// Decode all data in the buffer, assuming the buffer contains (the rest of) a frame.
+	///
+	/// Unlike [Self::decode_stream], this is called when we know NAL boundaries.
+	/// This can avoid a frame of latency just waiting for the next frame's start code.
+	/// This can also be used when EOF is detected to flush the final frame.
+	///
+	/// NOTE: The next decode will fail if it doesn't begin with a start code.
+	pub fn decode_frame<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T, pts: hang::Timestamp) -> anyhow::Result<()> {
+		// Decode any NALs at the start of the buffer.
+		self.decode_stream(buf, pts)?;
+
+		// Make sure there's a start code at the start of the buffer.
+		let start = after_start_code(buf.as_ref())?.context("missing start code")?;
+		buf.advance(start);
+
+		// Assume the rest of the buffer is a single NAL.
+		let nal = buf.copy_to_bytes(buf.remaining());
+		self.decode_nal(nal, pts)?;
+
+		// Flush the frame if we read a slice.
+		self.maybe_start_frame(pts)?;
+
+		Ok(())
+	}
+
+	fn decode_nal(&mut self, nal: Bytes, pts: hang::Timestamp) -> anyhow::Result<()> {
+		anyhow::ensure!(nal.len() >= 2, "NAL unit is too short");
+
+		let header = nal[0];
+		let forbidden_zero_bit = (header >> 7) & 1;
+		anyhow::ensure!(forbidden_zero_bit == 0, "forbidden zero bit is not zero");
+
+		let nal_unit_type = (header >> 1) & 0b111111;
+
+		match nal_unit_type {
+			NAL_SPS => {
+				self.maybe_start_frame(pts)?;
+
+				// Try to reinitialize the track if the SPS has changed.
+				let rbsp = h264_parser::nal::ebsp_to_rbsp(&nal[2..]);
+				let sps = Sps::parse(&rbsp)?;
+				self.init(&sps)?;
+			}
+			NAL_VPS | NAL_PPS | NAL_AUD | NAL_PREFIX_SEI => {
+				self.maybe_start_frame(pts)?;
+			}
+			// Video coding layer.
+			0..=31 => {
+				// first_slice_segment_in_pic_flag, means this is the first slice of a new picture.
+				if nal.get(2).context("NAL unit is too short")? & 0x80 != 0 {
+					self.maybe_start_frame(pts)?;
+				}
+
+				// Intra random access point (BLA, IDR, CRA), including the reserved types.
+				if (16..=23).contains(&nal_unit_type) {
+					self.current.contains_irap = true;
+				}
+
+				self.current.contains_slice = true;
+			}
+			_ => {}
+		}
+
+		tracing::trace!(kind = nal_unit_type, "parsed NAL");
+
+		self.current.chunks.push_chunk(START_CODE.clone());
+		self.current.chunks.push_chunk(nal);
+
+		Ok(())
+	}
+
+	fn maybe_start_frame(&mut self, pts: hang::Timestamp) -> anyhow::Result<()> {
+		// If we haven't seen any slices, we shouldn't flush yet.
+		if !self.current.contains_slice {
+			return Ok(());
+		}
+
+		let track = self.track.as_mut().context("expected SPS before any frames")?;
+
+		let payload = std::mem::take(&mut self.current.chunks);
+		let frame = hang::Frame {
+			timestamp: pts,
+			keyframe: self.current.contains_irap,
+			payload,
+		};
+
+		track.write(frame)?;
+
+		self.current.contains_irap = false;
+		self.current.contains_slice = false;
+
+		Ok(())
+	}
+
+	pub fn is_initialized(&self) -> bool {
+		self.track.is_some()
+	}
+}
+
+impl Drop for Hev1 {
+	fn drop(&mut self) {
+		if let Some(track) = &self.track {
+			tracing::debug!(name = ?track.info.name, "ending track");
+			self.broadcast.catalog.lock().remove_video(&track.info.name);
+		}
+	}
+}
+
+const NAL_VPS: u8 = 32;
+const NAL_SPS: u8 = 33;
+const NAL_PPS: u8 = 34;
+const NAL_AUD: u8 = 35;
+const NAL_PREFIX_SEI: u8 = 39;
+
+#[derive(Default)]
+struct Frame {
+	chunks: BufList,
+	contains_irap: bool,
+	contains_slice: bool,
+}
+
+/// The fields of a H.265 SPS needed for the catalog (ITU-T H.265, section 7.3.2.2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Sps {
+	profile_space: u8,
+	tier_flag: bool,
+	profile_idc: u8,
+	profile_compatibility_flags: [u8; 4],
+	constraint_flags: [u8; 6],
+	level_idc: u8,
+
+	// The display size, after applying the conformance window.
+	width: u32,
+	height: u32,
+
+	// The color space and frame rate, if signaled.
+	pub vui: Vui,
+}
+
+impl Sps {
+	// Parse the SPS payload, after the NAL header and with emulation prevention bytes removed.
+	pub fn parse(rbsp: &[u8]) -> anyhow::Result<Self> {
+		let mut bits = BitReader::new(rbsp);
+
+		let _vps_id = bits.read(4)?;
+		let max_sub_layers_minus1 = bits.read(3)? as usize;
+		let _temporal_id_nesting = bits.read(1)?;
+
+		// profile_tier_level(1, max_sub_layers_minus1)
+		let profile_space = bits.read(2)? as u8;
+		let tier_flag = bits.read(1)? == 1;
+		let profile_idc = bits.read(5)? as u8;
+
+		let mut profile_compatibility_flags = [0u8; 4];
+		for byte in &mut profile_compatibility_flags {
+			*byte = bits.read(8)? as u8;
+		}
+
+		let mut constraint_flags = [0u8; 6];
+		for byte in &mut constraint_flags {
+			*byte = bits.read(8)? as u8;
+		}
+
+		let level_idc = bits.read(8)? as u8;
+
+		let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1);
+		for _ in 0..max_sub_layers_minus1 {
+			let profile_present = bits.read(1)? == 1;
+			let level_present = bits.read(1)? == 1;
+			sub_layers.push((profile_present, level_present));
+		}
+
+		if max_sub_layers_minus1 > 0 {
+			for _ in max_sub_layers_minus1..8 {
+				bits.read(2)?; // reserved_zero_2bits
+			}
+		}
+
+		for (profile_present, level_present) in sub_layers {
+			if profile_present {
+				bits.skip(88)?;
+			}
+			if level_present {
+				bits.skip(8)?;
+			}
+		}
+
+		let _sps_id = bits.read_ue()?;
+		let chroma_format_idc = bits.read_ue()?;
+		if chroma_format_idc == 3 {
+			let _separate_colour_plane = bits.read(1)?;
+		}
+
+		let mut width = bits.read_ue()?;
+		let mut height = bits.read_ue()?;
+
+		if bits.read(1)? == 1 {
+			let left = bits.read_ue()?;
+			let right = bits.read_ue()?;
+			let top = bits.read_ue()?;
+			let bottom = bits.read_ue()?;
+
+			// The offsets are in units of chroma samples.
+			let (sub_width, sub_height) = match chroma_format_idc {
+				1 => (2, 2),
+				2 => (2, 1),
+				_ => (1, 1),
+			};
+
+			width = width
+				.checked_sub(sub_width * (left + right))
+				.context("invalid conformance window")?;
+			height = height
+				.checked_sub(sub_height * (top + bottom))
+				.context("invalid conformance window")?;
+		}
+
+		// The VUI is optional, so don't fail if we can't parse everything before it.
+		let vui = Self::parse_vui(&mut bits, max_sub_layers_minus1).unwrap_or_else(|err| {
+			tracing::warn!(%err, "failed to parse VUI");
+			Vui::default()
+		});
+
+		Ok(Self {
+			profile_space,
+			tier_flag,
+			profile_idc,
+			profile_compatibility_flags,
+			constraint_flags,
+			level_idc,
+			width,
+			height,
+			vui,
+		})
+	}
+
+	// Skip the rest of the SPS until the VUI, if present.
+	fn parse_vui(bits: &mut BitReader, max_sub_layers_minus1: usize) -> anyhow::Result<Vui> {
+		// bit_depth_luma_minus8, bit_depth_chroma_minus8
+		bits.read_ue()?;
+		bits.read_ue()?;
+
+		let log2_max_pic_order_cnt_lsb = bits.read_ue()? as usize + 4;
+
+		// sps_sub_layer_ordering_info_present_flag
+		let sub_layers = match bits.read(1)? {
+			1 => max_sub_layers_minus1 + 1,
+			_ => 1,
+		};
+
+		// max_dec_pic_buffering_minus1, max_num_reorder_pics, max_latency_increase_plus1
+		for _ in 0..sub_layers * 3 {
+			bits.read_ue()?;
+		}
+
+		// log2_min_luma_coding_block_size_minus3 through max_transform_hierarchy_depth_intra
+		for _ in 0..6 {
+			bits.read_ue()?;
+		}
+
+		// scaling_list_enabled_flag, sps_scaling_list_data_present_flag
+		if bits.read(1)? == 1 && bits.read(1)? == 1 {
+			skip_scaling_list_data(bits)?;
+		}
+
+		// amp_enabled_flag, sample_adaptive_offset_enabled_flag
+		bits.skip(2)?;
+
+		// pcm_enabled_flag
+		if bits.read(1)? == 1 {
+			// pcm_sample_bit_depth_luma_minus1, pcm_sample_bit_depth_chroma_minus1
+			bits.skip(8)?;
+			bits.read_ue()?;
+			bits.read_ue()?;
+			// pcm_loop_filter_disabled_flag
+			bits.skip(1)?;
+		}
+
+		let num_short_term_ref_pic_sets = bits.read_ue()?;
+		let mut num_delta_pocs = Vec::new();
+		for _ in 0..num_short_term_ref_pic_sets {
+			let count = skip_st_ref_pic_set(bits, &num_delta_pocs)?;
+			num_delta_pocs.push(count);
+		}
+
+		// long_term_ref_pics_present_flag
+		if bits.read(1)? == 1 {
+			// lt_ref_pic_poc_lsb_sps, used_by_curr_pic_lt_sps_flag
+			for _ in 0..bits.read_ue()? {
+				bits.skip(log2_max_pic_order_cnt_lsb + 1)?;
+			}
+		}
+
+		// sps_temporal_mvp_enabled_flag, strong_intra_smoothing_enabled_flag
+		bits.skip(2)?;
+
+		// vui_parameters_present_flag
+		match bits.read(1)? {
+			1 => Vui::parse_h265(bits),
+			_ => Ok(Vui::default()),
+		}
+	}
+}
+
+// ITU-T H.265, section 7.3.4
+fn skip_scaling_list_data(bits: &mut BitReader) -> anyhow::Result<()> {
+	for size_id in 0..4 {
+		let matrices = if size_id == 3 { 2 } else { 6 };
+
+		for _ in 0..matrices {
+			// scaling_list_pred_mode_flag
+			if bits.read(1)? == 0 {
+				// scaling_list_pred_matrix_id_delta
+				bits.read_ue()?;
+				continue;
+			}
+
+			// scaling_list_dc_coef_minus8
+			if size_id > 1 {
+				bits.read_se()?;
+			}
+
+			// scaling_list_delta_coef
+			for _ in 0..64.min(16 << (size_id * 2)) {
+				bits.read_se()?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+// ITU-T H.265, section 7.3.7, returning NumDeltaPocs for the set.
+fn skip_st_ref_pic_set(bits: &mut BitReader, previous: &[u32]) -> anyhow::Result<u32> {
+	// inter_ref_pic_set_prediction_flag, only present after the first set.
+	if let Some(reference) = previous.last() {
+		if bits.read(1)? == 1 {
+			// delta_rps_sign, abs_delta_rps_minus1
+			bits.skip(1)?;
+			bits.read_ue()?;
+
+			let mut count = 0;
+			for _ in 0..=*reference {
+				// used_by_curr_pic_flag, otherwise use_delta_flag
+				if bits.read(1)? == 1 || bits.read(1)? == 1 {
+					count += 1;
+				}
+			}
+
+			return Ok(count);
+		}
+	}
+
+	let num_negative_pics = bits.read_ue()?;
+	let num_positive_pics = bits.read_ue()?;
+	let count = num_negative_pics + num_positive_pics;
+	anyhow::ensure!(count <= 32, "too many reference pictures");
+
+	// delta_poc_minus1, used_by_curr_pic_flag
+	for _ in 0..count {
+		bits.read_ue()?;
+		bits.skip(1)?;
+	}
+
+	Ok(count)
+}
+
+// Reads big-endian bits and Exp-Golomb codes, as used by H.265 and AV1 headers.
+pub(crate) struct BitReader<'a> {
+	data: &'a [u8],
+	offset: usize,
+}
+
+impl<'a> BitReader<'a> {
+	pub fn new(data: &'a [u8]) -> Self {
+		Self { data, offset: 0 }
+	}
+
+	pub fn read(&mut self, count: usize) -> anyhow::Result<u32> {
+		let mut value = 0u32;
+
+		for _ in 0..count {
+			let byte = self.data.get(self.offset / 8).context("bitstream is too short")?;
+			let bit = (byte >> (7 - self.offset % 8)) & 1;
+			value = (value << 1) | bit as u32;
+			self.offset += 1;
+		}
+
+		Ok(value)
+	}
+
+	pub fn skip(&mut self, count: usize) -> anyhow::Result<()> {
+		anyhow::ensure!(self.offset + count <= self.data.len() * 8, "bitstream is too short");
+		self.offset += count;
+		Ok(())
+	}
+
+	pub fn read_ue(&mut self) -> anyhow::Result<u32> {
+		let mut zeros = 0;
+		while self.read(1)? == 0 {
+			zeros += 1;
+			anyhow::ensure!(zeros < 32, "invalid Exp-Golomb code");
+		}
+
+		Ok((1 << zeros) - 1 + self.read(zeros)?)
+	}
+
+	pub fn read_se(&mut self) -> anyhow::Result<i32> {
+		let value = self.read_ue()? as i64;
+
+		// Odd values are positive, even values are negative.
+		let value = match value % 2 {
+			1 => (value + 1) / 2,
+			_ => -(value / 2),
+		};
+
+		Ok(value as i32)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// A 1920x1080 Main profile SPS from x265, coded as 1920x1088 with a conformance window.
+	const SPS: &[u8] = &[
+		0x42, 0x01, 0x01, 0x21, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x78,
+		0xa0, 0x03, 0xc0, 0x80, 0x10, 0xe5, 0x96, 0x56, 0x69, 0x24, 0xca, 0xf0, 0x10, 0x10, 0x00, 0x00, 0x03, 0x00,
+		0x10, 0x00, 0x00, 0x03, 0x01, 0xe0, 0x80,
+	];
+
+	const VPS: &[u8] = &[
+		0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x21, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+		0x03, 0x00, 0x78, 0x95, 0x98, 0x09,
+	];
+
+	const PPS: &[u8] = &[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+
+	// Slice NALs with first_slice_segment_in_pic_flag set.
+	const IDR: &[u8] = &[0x26, 0x01, 0xaf, 0x00];
+	const TRAIL: &[u8] = &[0x02, 0x01, 0xd0, 0x00];
+
+	#[test]
+	fn test_sps() {
+		let rbsp = h264_parser::nal::ebsp_to_rbsp(&SPS[2..]);
+		let sps = Sps::parse(&rbsp).unwrap();
+
+		assert_eq!(
+			sps,
+			Sps {
+				profile_space: 0,
+				tier_flag: true,
+				profile_idc: 1,
+				profile_compatibility_flags: [0x60, 0, 0, 0],
+				constraint_flags: [0x90, 0, 0, 0, 0, 0],
+				level_idc: 120,
+				width: 1920,
+				height: 1080,
+				vui: sps.vui.clone(),
+			}
+		);
+
+		// x265 signals the frame rate but not the color space by default.
+		assert_eq!(sps.vui.framerate(), Some(30.0));
+		assert_eq!(sps.vui.color_space(), None);
+	}
+
+	#[tokio::test]
+	async fn test_decode() {
+		let broadcast = moq_lite::Broadcast::produce();
+		let producer: hang::BroadcastProducer = broadcast.producer.clone().into();
+		let mut catalog = producer.catalog.consume();
+		let mut hev1 = Hev1::new(producer);
+
+		let mut stream = Vec::new();
+		for nal in [VPS, SPS, PPS, IDR] {
+			stream.extend_from_slice(&[0, 0, 0, 1]);
+			stream.extend_from_slice(nal);
+		}
+
+		let mut buf = bytes::BytesMut::from(&stream[..]);
+		hev1.decode_stream(&mut buf, hang::Timestamp::from_micros(0).unwrap())
+			.unwrap();
+		assert!(hev1.is_initialized());
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		let config = catalog.video.unwrap().renditions.remove("video0").unwrap();
+		assert_eq!(
+			config.codec,
+			hang::catalog::H265 {
+				in_band: true,
+				profile_space: 0,
+				profile_idc: 1,
+				profile_compatibility_flags: [0x60, 0, 0, 0],
+				tier_flag: true,
+				level_idc: 120,
+				constraint_flags: [0x90, 0, 0, 0, 0, 0],
+			}
+			.into()
+		);
+		assert_eq!(config.coded_width, Some(1920));
+		assert_eq!(config.coded_height, Some(1080));
+
+		let track = broadcast.consumer.subscribe_track(&moq_lite::Track::new("video0"));
+		let mut track = hang::TrackConsumer::new(track);
+
+		// The IDR slice is still buffered, waiting for the next start code.
+		hev1.decode_frame(&mut buf, hang::Timestamp::from_micros(0).unwrap())
+			.unwrap();
+		assert!(buf.is_empty());
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.timestamp.as_micros(), 0);
+		assert_eq!(
+			frame.payload.num_bytes(),
+			VPS.len() + SPS.len() + PPS.len() + IDR.len() + 16
+		);
+
+		buf.extend_from_slice(&[0, 0, 0, 1]);
+		buf.extend_from_slice(TRAIL);
+		hev1.decode_frame(&mut buf, hang::Timestamp::from_micros(33_000).unwrap())
+			.unwrap();
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(!frame.keyframe);
+		assert_eq!(frame.timestamp.as_micros(), 33_000);
+	}
+}
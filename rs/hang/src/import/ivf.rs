@@ -0,0 +1,241 @@
+use crate as hang;
+use bytes::Buf;
+
+use super::{Av1, Vp8, Vp9};
+
+// The minimum size of the file header.
+const FILE_HEADER_SIZE: usize = 32;
+
+// The size of each frame header: a u32 size and a u64 timestamp.
+const FRAME_HEADER_SIZE: usize = 12;
+
+/// A decoder for IVF, the simple container produced by libaom, SVT-AV1 and libvpx.
+///
+/// The codec is detected from the file header; AV1, VP8 and VP9 are supported.
+/// Timestamps come from the frame headers, so no wall clock time is needed.
+pub struct Ivf {
+	// The broadcast being produced, used to create the codec decoder.
+	broadcast: hang::BroadcastProducer,
+
+	// The time base from the file header, as (numerator, denominator).
+	timebase: (u64, u64),
+
+	// The codec decoder, created after parsing the file header.
+	decoder: Option<IvfDecoder>,
+}
+
+enum IvfDecoder {
+	Av1(Av1),
+	Vp8(Vp8),
+	Vp9(Vp9),
+}
+
+impl Ivf {
+	pub fn new(broadcast: hang::BroadcastProducer) -> Self {
+		Self {
+			broadcast,
+			timebase: (1, 1),
+			decoder: None,
+		}
+	}
+
+	/// Decode as much data as possible from the given buffer.
+	///
+	/// The file header and each frame are only consumed once they're complete.
+	pub fn decode<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T) -> anyhow::Result<()> {
+		if self.decoder.is_none() && !self.decode_header(buf)? {
+			return Ok(());
+		}
+
+		while let Some(size) = frame_size(buf.as_ref()) {
+			let header = buf.as_ref();
+			let timestamp = u64::from_le_bytes(header[4..12].try_into().unwrap());
+
+			let (numerator, denominator) = self.timebase;
+			let micros = timestamp as u128 * numerator as u128 * 1_000_000 / denominator as u128;
+			let pts = hang::Timestamp::from_micros(micros.try_into()?)?;
+
+			buf.advance(FRAME_HEADER_SIZE);
+			let mut frame = buf.copy_to_bytes(size);
+
+			match self.decoder.as_mut().unwrap() {
+				IvfDecoder::Av1(decoder) => decoder.decode_frame(&mut frame, pts)?,
+				IvfDecoder::Vp8(decoder) => decoder.decode_frame(&mut frame, pts)?,
+				IvfDecoder::Vp9(decoder) => decoder.decode_frame(&mut frame, pts)?,
+			}
+		}
+
+		Ok(())
+	}
+
+	// Returns false if more data is needed.
+	fn decode_header<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T) -> anyhow::Result<bool> {
+		let header = buf.as_ref();
+		if header.len() < FILE_HEADER_SIZE {
+			return Ok(false);
+		}
+
+		anyhow::ensure!(&header[0..4] == b"DKIF", "invalid IVF signature");
+
+		let size = u16::from_le_bytes([header[6], header[7]]) as usize;
+		anyhow::ensure!(size >= FILE_HEADER_SIZE, "invalid IVF header size");
+
+		if header.len() < size {
+			return Ok(false);
+		}
+
+		let denominator = u32::from_le_bytes(header[16..20].try_into().unwrap());
+		let numerator = u32::from_le_bytes(header[20..24].try_into().unwrap());
+		anyhow::ensure!(numerator > 0 && denominator > 0, "invalid IVF time base");
+
+		let decoder = match &header[8..12] {
+			b"AV01" => IvfDecoder::Av1(Av1::new(self.broadcast.clone())),
+			b"VP80" => IvfDecoder::Vp8(Vp8::new(self.broadcast.clone())),
+			b"VP90" => IvfDecoder::Vp9(Vp9::new(self.broadcast.clone())),
+			fourcc => anyhow::bail!("unsupported IVF codec: {}", String::from_utf8_lossy(fourcc)),
+		};
+
+		tracing::debug!(numerator, denominator, "parsed IVF header");
+
+		self.timebase = (numerator as u64, denominator as u64);
+		self.decoder = Some(decoder);
+
+		buf.advance(size);
+
+		Ok(true)
+	}
+
+	pub fn is_initialized(&self) -> bool {
+		match &self.decoder {
+			Some(IvfDecoder::Av1(decoder)) => decoder.is_initialized(),
+			Some(IvfDecoder::Vp8(decoder)) => decoder.is_initialized(),
+			Some(IvfDecoder::Vp9(decoder)) => decoder.is_initialized(),
+			None => false,
+		}
+	}
+}
+
+// Returns the size of the next frame, or None if the frame is incomplete.
+fn frame_size(buf: &[u8]) -> Option<usize> {
+	let size = u32::from_le_bytes(buf.get(0..4)?.try_into().unwrap()) as usize;
+	(buf.len() >= FRAME_HEADER_SIZE + size).then_some(size)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Temporal units with a 1920x1080 10-bit sequence header and key frame, then an inter frame.
+	const KEY_FRAME: &[u8] = &[
+		0x12, 0x00, 10, 11, 0, 0, 0, 74, 171, 191, 195, 119, 255, 231, 1, 0x32, 0x01, 0x10,
+	];
+	const INTER_FRAME: &[u8] = &[0x12, 0x00, 0x32, 0x01, 0x30];
+
+	fn file_header(fourcc: &[u8; 4]) -> Vec<u8> {
+		let mut header = Vec::new();
+		header.extend_from_slice(b"DKIF");
+		header.extend_from_slice(&0u16.to_le_bytes());
+		header.extend_from_slice(&32u16.to_le_bytes());
+		header.extend_from_slice(fourcc);
+		header.extend_from_slice(&1920u16.to_le_bytes());
+		header.extend_from_slice(&1080u16.to_le_bytes());
+		header.extend_from_slice(&30u32.to_le_bytes());
+		header.extend_from_slice(&1u32.to_le_bytes());
+		header.extend_from_slice(&[0; 8]);
+		header
+	}
+
+	fn frame(timestamp: u64, payload: &[u8]) -> Vec<u8> {
+		let mut frame = Vec::new();
+		frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+		frame.extend_from_slice(&timestamp.to_le_bytes());
+		frame.extend_from_slice(payload);
+		frame
+	}
+
+	#[tokio::test]
+	async fn test_av1() {
+		let broadcast = moq_lite::Broadcast::produce();
+		let mut ivf = Ivf::new(broadcast.producer.clone().into());
+
+		let mut stream = file_header(b"AV01");
+		stream.extend(frame(0, KEY_FRAME));
+		stream.extend(frame(1, INTER_FRAME));
+
+		// Feed a partial header, then a partial frame.
+		let mut buf = bytes::BytesMut::from(&stream[..20]);
+		ivf.decode(&mut buf).unwrap();
+		assert_eq!(buf.len(), 20);
+		assert!(!ivf.is_initialized());
+
+		buf.extend_from_slice(&stream[20..50]);
+		ivf.decode(&mut buf).unwrap();
+		assert_eq!(buf.len(), 50 - 32);
+		assert!(!ivf.is_initialized());
+
+		buf.extend_from_slice(&stream[50..62]);
+		ivf.decode(&mut buf).unwrap();
+		assert!(ivf.is_initialized());
+
+		let track = broadcast.consumer.subscribe_track(&moq_lite::Track::new("video0"));
+		let mut track = hang::TrackConsumer::new(track);
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.timestamp.as_micros(), 0);
+
+		buf.extend_from_slice(&stream[62..]);
+		ivf.decode(&mut buf).unwrap();
+		assert!(buf.is_empty());
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(!frame.keyframe);
+		assert_eq!(frame.timestamp.as_micros(), 33_333);
+	}
+
+	#[tokio::test]
+	async fn test_vp9() {
+		// A 1280x720 key frame, then an inter frame, both truncated after the header.
+		const KEY_FRAME: &[u8] = &[0x82, 0x49, 0x83, 0x42, 0x40, 0x4f, 0xf0, 0x2c, 0xf0];
+		const INTER_FRAME: &[u8] = &[0x86, 0x00, 0x40];
+
+		let broadcast = moq_lite::Broadcast::produce();
+		let producer: hang::BroadcastProducer = broadcast.producer.clone().into();
+		let mut catalog = producer.catalog.consume();
+		let mut ivf = Ivf::new(producer);
+
+		let mut buf = bytes::BytesMut::from(&file_header(b"VP90")[..]);
+		buf.extend(frame(0, KEY_FRAME));
+		buf.extend(frame(1, INTER_FRAME));
+
+		ivf.decode(&mut buf).unwrap();
+		assert!(buf.is_empty());
+		assert!(ivf.is_initialized());
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		let config = catalog.video.unwrap().renditions.remove("video0").unwrap();
+		assert_eq!(config.codec.to_string(), "vp09.00.31.08");
+		assert_eq!(config.coded_width, Some(1280));
+		assert_eq!(config.coded_height, Some(720));
+
+		let track = broadcast.consumer.subscribe_track(&moq_lite::Track::new("video0"));
+		let mut track = hang::TrackConsumer::new(track);
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(frame.keyframe);
+		assert_eq!(frame.payload.num_bytes(), KEY_FRAME.len());
+
+		let frame = track.read_frame().await.unwrap().unwrap();
+		assert!(!frame.keyframe);
+		assert_eq!(frame.timestamp.as_micros(), 33_333);
+	}
+
+	#[test]
+	fn test_unsupported() {
+		let broadcast = moq_lite::Broadcast::produce();
+		let mut ivf = Ivf::new(broadcast.producer.into());
+
+		let mut buf = bytes::BytesMut::from(&file_header(b"H264")[..]);
+		assert!(ivf.decode(&mut buf).is_err());
+	}
+}
@@ -1,9 +1,23 @@
 mod aac;
+mod av1;
 mod avc3;
 mod decoder;
 mod fmp4;
+mod hev1;
+mod ivf;
+mod opus;
+mod sei;
+mod vp8;
+mod vp9;
+mod vui;
 
 pub use aac::*;
+pub use av1::*;
 pub use avc3::*;
 pub use decoder::*;
 pub use fmp4::*;
+pub use hev1::*;
+pub use ivf::*;
+pub use opus::*;
+pub use vp8::*;
+pub use vp9::*;
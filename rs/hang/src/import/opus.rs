@@ -0,0 +1,394 @@
+use crate as hang;
+use anyhow::Context;
+use buf_list::BufList;
+use bytes::{Buf, Bytes, BytesMut};
+use moq_lite as moq;
+
+// Opus always decodes at 48kHz, regardless of the input sample rate in the header.
+const SAMPLE_RATE: u64 = 48_000;
+
+// The size of an Ogg page header, excluding the segment table.
+const OGG_HEADER: usize = 27;
+
+/// Opus decoder, initialized via the OpusHead identification header (RFC 7845).
+///
+/// Supports two modes:
+/// - [Self::decode_ogg] for Ogg-encapsulated Opus (ex. from ffmpeg), with timestamps derived from granule positions.
+/// - [Self::initialize] and [Self::decode] for raw packets with explicit timestamps.
+pub struct Opus {
+	broadcast: hang::BroadcastProducer,
+	track: Option<hang::TrackProducer>,
+
+	// The number of samples to discard at the start of the stream, from the OpusHead.
+	pre_skip: u64,
+
+	// The Ogg logical stream we're reading, if any.
+	serial: Option<u32>,
+
+	// A packet that continues onto the next page.
+	partial: BytesMut,
+
+	// Packets that have been completed on the current page, waiting for the granule position.
+	pending: Vec<Bytes>,
+
+	// Skip the OpusTags packet after the OpusHead.
+	tags: bool,
+}
+
+impl Opus {
+	pub fn new(broadcast: hang::BroadcastProducer) -> Self {
+		Self {
+			broadcast,
+			track: None,
+			pre_skip: 0,
+			serial: None,
+			partial: BytesMut::new(),
+			pending: Vec::new(),
+			tags: false,
+		}
+	}
+
+	/// Initialize the track with an OpusHead packet, which is also used as the decoder description.
+	pub fn initialize<T: Buf>(&mut self, buf: &mut T) -> anyhow::Result<()> {
+		let head = buf.copy_to_bytes(buf.remaining());
+
+		anyhow::ensure!(head.len() >= 19, "OpusHead must be at least 19 bytes");
+		anyhow::ensure!(&head[0..8] == b"OpusHead", "missing OpusHead magic");
+
+		let version = head[8];
+		anyhow::ensure!(version >> 4 == 0, "unsupported OpusHead version: {version}");
+
+		let channel_count = head[9] as u32;
+		anyhow::ensure!(channel_count > 0, "OpusHead has no channels");
+
+		let pre_skip = u16::from_le_bytes([head[10], head[11]]);
+		let input_rate = u32::from_le_bytes([head[12], head[13], head[14], head[15]]);
+
+		let track = moq::Track {
+			name: self.broadcast.track_name("audio"),
+			priority: 2,
+		};
+
+		let config = hang::catalog::AudioConfig {
+			codec: hang::catalog::AudioCodec::Opus,
+			sample_rate: SAMPLE_RATE as u32,
+			channel_count,
+			bitrate: None,
+			description: Some(head),
+		};
+
+		tracing::debug!(name = ?track.name, ?config, input_rate, "starting track");
+
+		let track = track.produce();
+		self.broadcast.insert_track(track.consumer);
+
+		let mut catalog = self.broadcast.catalog.lock();
+		let audio = catalog.insert_audio(track.producer.info.name.clone(), config);
+		audio.priority = 2;
+
+		self.track = Some(track.producer.into());
+		self.pre_skip = pre_skip as u64;
+
+		Ok(())
+	}
+
+	/// Decode a single raw Opus packet with the given timestamp.
+	pub fn decode<T: Buf>(&mut self, buf: &mut T, pts: hang::Timestamp) -> anyhow::Result<()> {
+		let track = self.track.as_mut().context("not initialized")?;
+
+		// Create a BufList at chunk boundaries, potentially avoiding allocations.
+		let mut payload = BufList::new();
+		while !buf.chunk().is_empty() {
+			payload.push_chunk(buf.copy_to_bytes(buf.chunk().len()));
+		}
+
+		let frame = hang::Frame {
+			timestamp: pts,
+			keyframe: true,
+			payload,
+		};
+
+		track.write(frame)?;
+
+		Ok(())
+	}
+
+	/// Decode a stream of Ogg pages, initializing the track from the OpusHead.
+	///
+	/// Only complete pages are consumed; if the buffer is not fully consumed, more data is needed.
+	pub fn decode_ogg<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T) -> anyhow::Result<()> {
+		while let Some(size) = ogg_page_size(buf.as_ref())? {
+			let page = buf.copy_to_bytes(size);
+			self.decode_page(page)?;
+		}
+
+		Ok(())
+	}
+
+	fn decode_page(&mut self, page: Bytes) -> anyhow::Result<()> {
+		let flags = page[5];
+		let granule = i64::from_le_bytes(page[6..14].try_into().unwrap());
+		let serial = u32::from_le_bytes(page[14..18].try_into().unwrap());
+		let segments = page[26] as usize;
+		let lacing = &page[OGG_HEADER..OGG_HEADER + segments];
+
+		// Only read the first logical stream, ignoring any others (ex. video).
+		if *self.serial.get_or_insert(serial) != serial {
+			return Ok(());
+		}
+
+		// Discard a partial packet if the page isn't a continuation, ex. after data loss.
+		if flags & 0x01 == 0 && !self.partial.is_empty() {
+			tracing::warn!("discarding incomplete Ogg packet");
+			self.partial.clear();
+		}
+
+		let mut offset = OGG_HEADER + segments;
+		for &size in lacing {
+			let size = size as usize;
+			self.partial.extend_from_slice(&page[offset..offset + size]);
+			offset += size;
+
+			// A lacing value less than 255 terminates the packet.
+			if size < 255 {
+				let packet = self.partial.split().freeze();
+				self.decode_packet(packet)?;
+			}
+		}
+
+		// A granule position of -1 means no packet finished on this page.
+		if granule >= 0 {
+			self.flush(granule as u64)?;
+		}
+
+		Ok(())
+	}
+
+	fn decode_packet(&mut self, packet: Bytes) -> anyhow::Result<()> {
+		if !self.is_initialized() {
+			return self.initialize(&mut packet.clone());
+		}
+
+		if !self.tags {
+			self.tags = true;
+			if packet.starts_with(b"OpusTags") {
+				return Ok(());
+			}
+		}
+
+		self.pending.push(packet);
+		Ok(())
+	}
+
+	fn flush(&mut self, granule: u64) -> anyhow::Result<()> {
+		let pending = std::mem::take(&mut self.pending);
+		let timestamps = packet_timestamps(&pending, granule, self.pre_skip)?;
+
+		for (packet, pts) in pending.into_iter().zip(timestamps) {
+			self.decode(&mut packet.clone(), pts)?;
+		}
+
+		Ok(())
+	}
+
+	pub fn is_initialized(&self) -> bool {
+		self.track.is_some()
+	}
+}
+
+impl Drop for Opus {
+	fn drop(&mut self) {
+		if let Some(track) = self.track.take() {
+			tracing::debug!(name = ?track.info.name, "ending track");
+			self.broadcast.catalog.lock().remove_audio(&track.info.name);
+		}
+	}
+}
+
+// Returns the size of the Ogg page at the start of the buffer, or None if more data is needed.
+fn ogg_page_size(buf: &[u8]) -> anyhow::Result<Option<usize>> {
+	if buf.len() < OGG_HEADER {
+		return Ok(None);
+	}
+
+	anyhow::ensure!(&buf[0..4] == b"OggS", "missing Ogg capture pattern");
+	anyhow::ensure!(buf[4] == 0, "unsupported Ogg version: {}", buf[4]);
+
+	let segments = buf[26] as usize;
+	if buf.len() < OGG_HEADER + segments {
+		return Ok(None);
+	}
+
+	let body: usize = buf[OGG_HEADER..OGG_HEADER + segments]
+		.iter()
+		.map(|&size| size as usize)
+		.sum();
+	let size = OGG_HEADER + segments + body;
+
+	Ok((buf.len() >= size).then_some(size))
+}
+
+// The granule position is the number of samples at the end of the last packet on the page.
+// Work backwards to find the timestamp of each packet, removing the pre-skip.
+fn packet_timestamps(packets: &[Bytes], granule: u64, pre_skip: u64) -> anyhow::Result<Vec<hang::Timestamp>> {
+	let mut durations = Vec::with_capacity(packets.len());
+	for packet in packets {
+		durations.push(packet_samples(packet)?);
+	}
+
+	let mut position = granule.saturating_sub(durations.iter().sum());
+	let mut timestamps = Vec::with_capacity(packets.len());
+
+	for duration in durations {
+		let samples = position.saturating_sub(pre_skip);
+		timestamps.push(hang::Timestamp::from_micros(samples * 1_000_000 / SAMPLE_RATE)?);
+		position += duration;
+	}
+
+	Ok(timestamps)
+}
+
+// Returns the duration of an Opus packet in 48kHz samples, using the TOC byte (RFC 6716, section 3.1).
+fn packet_samples(packet: &[u8]) -> anyhow::Result<u64> {
+	let toc = *packet.first().context("empty Opus packet")?;
+	let config = toc >> 3;
+
+	// The frame size in units of 2.5ms (120 samples).
+	let frame = match config {
+		// SILK: 10, 20, 40, 60ms
+		0..=11 => [4, 8, 16, 24][config as usize % 4],
+		// Hybrid: 10, 20ms
+		12..=15 => [4, 8][config as usize % 2],
+		// CELT: 2.5, 5, 10, 20ms
+		_ => [1, 2, 4, 8][config as usize % 4],
+	};
+
+	let frames = match toc & 0x03 {
+		0 => 1,
+		1 | 2 => 2,
+		_ => (*packet.get(1).context("missing Opus frame count")? & 0x3f) as u64,
+	};
+
+	Ok(frame * 120 * frames)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn head(channels: u8, pre_skip: u16) -> Vec<u8> {
+		let mut head = b"OpusHead".to_vec();
+		head.push(1);
+		head.push(channels);
+		head.extend_from_slice(&pre_skip.to_le_bytes());
+		head.extend_from_slice(&44_100u32.to_le_bytes());
+		head.extend_from_slice(&0i16.to_le_bytes());
+		head.push(0);
+		head
+	}
+
+	// Build an Ogg page containing complete packets.
+	fn page(granule: i64, packets: &[&[u8]]) -> Vec<u8> {
+		let mut lacing = Vec::new();
+		let mut body = Vec::new();
+
+		for packet in packets {
+			let mut size = packet.len();
+			while size >= 255 {
+				lacing.push(255);
+				size -= 255;
+			}
+			lacing.push(size as u8);
+			body.extend_from_slice(packet);
+		}
+
+		let mut page = b"OggS".to_vec();
+		page.push(0);
+		page.push(0);
+		page.extend_from_slice(&granule.to_le_bytes());
+		page.extend_from_slice(&1u32.to_le_bytes());
+		page.extend_from_slice(&0u32.to_le_bytes());
+		page.extend_from_slice(&0u32.to_le_bytes()); // The CRC isn't checked.
+		page.push(lacing.len() as u8);
+		page.extend_from_slice(&lacing);
+		page.extend_from_slice(&body);
+		page
+	}
+
+	#[test]
+	fn test_packet_samples() {
+		// CELT 20ms, one frame.
+		assert_eq!(packet_samples(&[0xf8]).unwrap(), 960);
+		// SILK 60ms, two frames.
+		assert_eq!(packet_samples(&[(3 << 3) | 1]).unwrap(), 5760);
+		// CELT 2.5ms, arbitrary number of frames.
+		assert_eq!(packet_samples(&[(16 << 3) | 3, 4]).unwrap(), 480);
+		assert!(packet_samples(&[]).is_err());
+	}
+
+	#[test]
+	fn test_packet_timestamps() {
+		let packet = Bytes::from_static(&[0xf8]);
+		let packets = [packet.clone(), packet.clone(), packet];
+
+		let micros = |granule, pre_skip| -> Vec<u64> {
+			packet_timestamps(&packets, granule, pre_skip)
+				.unwrap()
+				.iter()
+				.map(|ts| ts.as_micros())
+				.collect()
+		};
+
+		assert_eq!(micros(2880, 0), vec![0, 20_000, 40_000]);
+		assert_eq!(micros(4800, 960), vec![20_000, 40_000, 60_000]);
+
+		// Samples within the pre-skip are clamped to zero.
+		assert_eq!(micros(2880, 1200), vec![0, 0, 15_000]);
+	}
+
+	#[tokio::test]
+	async fn test_ogg() {
+		let broadcast = moq_lite::Broadcast::produce();
+		let producer: hang::BroadcastProducer = broadcast.producer.clone().into();
+		let mut catalog = producer.catalog.consume();
+		let mut opus = Opus::new(producer);
+
+		// A 20ms CELT packet, large enough to span multiple lacing values.
+		let mut packet = vec![0xf8];
+		packet.resize(300, 0xaa);
+
+		let mut headers = page(0, &[&head(2, 312)]);
+		headers.extend(page(0, &[b"OpusTags\0\0\0\0\0\0\0\0"]));
+
+		// Feed a partial page first.
+		let mut buf = bytes::BytesMut::from(&headers[..60]);
+		opus.decode_ogg(&mut buf).unwrap();
+		assert!(opus.is_initialized());
+		assert!(!buf.is_empty());
+
+		buf.extend_from_slice(&headers[60..]);
+		opus.decode_ogg(&mut buf).unwrap();
+		assert!(buf.is_empty());
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		let audio = catalog.audio.unwrap();
+		let config = audio.renditions.get("audio0").unwrap();
+		assert_eq!(config.codec, hang::catalog::AudioCodec::Opus);
+		assert_eq!(config.sample_rate, 48_000);
+		assert_eq!(config.channel_count, 2);
+		assert_eq!(config.description.as_deref(), Some(&head(2, 312)[..]));
+
+		let track = broadcast.consumer.subscribe_track(&moq_lite::Track::new("audio0"));
+		let mut track = hang::TrackConsumer::new(track);
+
+		// The pre-skip is removed from the timestamps.
+		for (index, expected) in [0, 20_000, 40_000].into_iter().enumerate() {
+			let mut buf = bytes::BytesMut::from(&page(312 + 960 * (index as i64 + 1), &[&packet])[..]);
+			opus.decode_ogg(&mut buf).unwrap();
+
+			let frame = track.read_frame().await.unwrap().unwrap();
+			assert_eq!(frame.timestamp.as_micros(), expected);
+			assert_eq!(frame.payload.num_bytes(), 300);
+		}
+	}
+}
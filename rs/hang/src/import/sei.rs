@@ -0,0 +1,91 @@
+use anyhow::Context;
+
+// user_data_registered_itu_t_t35
+const PAYLOAD_TYPE_T35: u32 = 4;
+
+// The ITU-T T.35 prefix used by ATSC A/53 for closed captions: USA, ATSC and "GA94".
+const ATSC_PREFIX: &[u8] = &[0xb5, 0x00, 0x31, b'G', b'A', b'9', b'4'];
+
+// user_data_type_code for cc_data()
+const CC_DATA: u8 = 0x03;
+
+/// Extract the valid `cc_data` triplets from a SEI, after the NAL header and with emulation prevention bytes removed.
+///
+/// Captions are carried in registered user data as specified by ATSC A/53 Part 4 and CEA-708.
+/// Each triplet is (`marker_bits`, `cc_valid`, `cc_type`), `cc_data_1`, `cc_data_2`.
+pub(crate) fn cc_data(mut rbsp: &[u8]) -> anyhow::Result<Vec<u8>> {
+	let mut triplets = Vec::new();
+
+	// Loop until we reach the rbsp_trailing_bits.
+	while rbsp.len() > 1 || rbsp.first().is_some_and(|b| *b != 0x80) {
+		let payload_type = read_value(&mut rbsp)?;
+		let payload_size = read_value(&mut rbsp)? as usize;
+
+		let payload = rbsp.get(..payload_size).context("SEI payload is too short")?;
+		rbsp = &rbsp[payload_size..];
+
+		if payload_type != PAYLOAD_TYPE_T35 {
+			continue;
+		}
+
+		let Some(payload) = payload.strip_prefix(ATSC_PREFIX) else {
+			continue;
+		};
+
+		let &[CC_DATA, flags, _em_data, ref data @ ..] = payload else {
+			continue;
+		};
+
+		// process_cc_data_flag
+		if flags & 0x40 == 0 {
+			continue;
+		}
+
+		let cc_count = (flags & 0x1f) as usize;
+		let data = data.get(..cc_count * 3).context("cc_data is too short")?;
+
+		for triplet in data.chunks_exact(3) {
+			// cc_valid
+			if triplet[0] & 0x04 != 0 {
+				triplets.extend_from_slice(triplet);
+			}
+		}
+	}
+
+	Ok(triplets)
+}
+
+// The payload type and size are both coded as a run of 0xFF bytes followed by the remainder.
+fn read_value(rbsp: &mut &[u8]) -> anyhow::Result<u32> {
+	let mut value = 0;
+
+	loop {
+		let (byte, rest) = rbsp.split_first().context("SEI message is too short")?;
+		*rbsp = rest;
+
+		value += *byte as u32;
+		if *byte != 0xff {
+			return Ok(value);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_cc_data() {
+		let rbsp = &[
+			// An unregistered user data message, which is skipped.
+			0x05, 0x02, 0xaa, 0xbb, // ATSC captions with two triplets, the second one invalid.
+			0x04, 0x11, 0xb5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x03, 0x42, 0xff, 0xfc, 0x94, 0x2c, 0xf8, 0x80, 0x80,
+			0xff, // rbsp_trailing_bits
+			0x80,
+		];
+
+		assert_eq!(cc_data(rbsp).unwrap(), vec![0xfc, 0x94, 0x2c]);
+		assert!(cc_data(&rbsp[..10]).is_err());
+		assert!(cc_data(&[0x80]).unwrap().is_empty());
+	}
+}
@@ -0,0 +1,171 @@
+use crate as hang;
+use anyhow::Context;
+use buf_list::BufList;
+use bytes::Buf;
+use moq_lite as moq;
+
+/// A decoder for VP8 frames, which must be delimited by a container like IVF.
+///
+/// The resolution is parsed from each key frame, reinitializing the track if it changes.
+pub struct Vp8 {
+	// The broadcast being produced.
+	// This `hang` variant includes a catalog.
+	broadcast: hang::BroadcastProducer,
+
+	// The track being produced.
+	track: Option<hang::TrackProducer>,
+
+	// Whether the track has been initialized.
+	// If it changes, then we'll reinitialize with a new track.
+	config: Option<hang::catalog::VideoConfig>,
+}
+
+impl Vp8 {
+	pub fn new(broadcast: hang::BroadcastProducer) -> Self {
+		Self {
+			broadcast,
+			track: None,
+			config: None,
+		}
+	}
+
+	fn init(&mut self, header: &KeyFrameHeader) -> anyhow::Result<()> {
+		let config = hang::catalog::VideoConfig {
+			coded_width: Some(header.width),
+			coded_height: Some(header.height),
+			codec: hang::catalog::VideoCodec::VP8,
+			description: None,
+			// TODO: populate these fields
+			framerate: None,
+			bitrate: None,
+			display_ratio_width: None,
+			display_ratio_height: None,
+			color_space: None,
+			optimize_for_latency: None,
+		};
+
+		if let Some(old) = &self.config {
+			if old == &config {
+				return Ok(());
+			}
+		}
+
+		if let Some(track) = &self.track.take() {
+			tracing::debug!(name = ?track.info.name, "reinitializing track");
+			self.broadcast.catalog.lock().remove_video(&track.info.name);
+		}
+
+		let track = moq::Track {
+			name: self.broadcast.track_name("video"),
+			priority: 2,
+		};
+
+		tracing::debug!(name = ?track.name, ?config, "starting track");
+
+		{
+			let mut catalog = self.broadcast.catalog.lock();
+			let video = catalog.insert_video(track.name.clone(), config.clone());
+			video.priority = 2;
+		}
+
+		let track = track.produce();
+		self.broadcast.insert_track(track.consumer);
+
+		self.config = Some(config);
+		self.track = Some(track.producer.into());
+
+		Ok(())
+	}
+
+	/// Decode a single, complete frame from the buffer.
+	pub fn decode_frame<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T, pts: hang::Timestamp) -> anyhow::Result<()> {
+		let keyframe = match KeyFrameHeader::parse(buf.as_ref())? {
+			Some(header) => {
+				// Try to reinitialize the track if the resolution has changed.
+				self.init(&header)?;
+				true
+			}
+			None => false,
+		};
+
+		let track = self.track.as_mut().context("expected key frame before any frames")?;
+
+		let mut payload = BufList::new();
+		while buf.has_remaining() {
+			payload.push_chunk(buf.copy_to_bytes(buf.chunk().len()));
+		}
+
+		let frame = hang::Frame {
+			timestamp: pts,
+			keyframe,
+			payload,
+		};
+
+		track.write(frame)?;
+
+		Ok(())
+	}
+
+	pub fn is_initialized(&self) -> bool {
+		self.track.is_some()
+	}
+}
+
+impl Drop for Vp8 {
+	fn drop(&mut self) {
+		if let Some(track) = &self.track {
+			tracing::debug!(name = ?track.info.name, "ending track");
+			self.broadcast.catalog.lock().remove_video(&track.info.name);
+		}
+	}
+}
+
+/// The fields of a VP8 key frame header needed for the catalog (RFC 6386, section 9.1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyFrameHeader {
+	width: u32,
+	height: u32,
+}
+
+impl KeyFrameHeader {
+	// Returns None if this is not a key frame.
+	fn parse(frame: &[u8]) -> anyhow::Result<Option<Self>> {
+		anyhow::ensure!(frame.len() >= 3, "VP8 frame is too short");
+
+		// The frame tag starts with an inverse key frame flag.
+		if frame[0] & 1 == 1 {
+			return Ok(None);
+		}
+
+		anyhow::ensure!(frame.len() >= 10, "VP8 key frame is too short");
+		anyhow::ensure!(frame[3..6] == [0x9d, 0x01, 0x2a], "invalid VP8 start code");
+
+		// The top two bits are the upscaling mode, which is not part of the coded size.
+		let width = u16::from_le_bytes([frame[6], frame[7]]) & 0x3fff;
+		let height = u16::from_le_bytes([frame[8], frame[9]]) & 0x3fff;
+
+		Ok(Some(Self {
+			width: width as u32,
+			height: height as u32,
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// A 640x360 key frame, truncated after the dimensions.
+	const KEY_FRAME: &[u8] = &[0x50, 0x2e, 0x00, 0x9d, 0x01, 0x2a, 0x80, 0x02, 0x68, 0x01];
+	const INTER_FRAME: &[u8] = &[0x31, 0x05, 0x00];
+
+	#[test]
+	fn test_key_frame_header() {
+		let header = KeyFrameHeader::parse(KEY_FRAME).unwrap().unwrap();
+		assert_eq!(header.width, 640);
+		assert_eq!(header.height, 360);
+
+		assert_eq!(KeyFrameHeader::parse(INTER_FRAME).unwrap(), None);
+		assert!(KeyFrameHeader::parse(&KEY_FRAME[..8]).is_err());
+	}
+}
@@ -0,0 +1,322 @@
+use crate as hang;
+use anyhow::Context;
+use buf_list::BufList;
+use bytes::Buf;
+use moq_lite as moq;
+
+use super::hev1::BitReader;
+
+/// A decoder for VP9 frames, which must be delimited by a container like IVF.
+///
+/// The profile, bit depth, color space and resolution are parsed from each key frame.
+/// The track is reinitialized if any of them change.
+pub struct Vp9 {
+	// The broadcast being produced.
+	// This `hang` variant includes a catalog.
+	broadcast: hang::BroadcastProducer,
+
+	// The track being produced.
+	track: Option<hang::TrackProducer>,
+
+	// Whether the track has been initialized.
+	// If it changes, then we'll reinitialize with a new track.
+	config: Option<hang::catalog::VideoConfig>,
+}
+
+impl Vp9 {
+	pub fn new(broadcast: hang::BroadcastProducer) -> Self {
+		Self {
+			broadcast,
+			track: None,
+			config: None,
+		}
+	}
+
+	fn init(&mut self, header: &KeyFrameHeader) -> anyhow::Result<()> {
+		let (color_primaries, transfer_characteristics, matrix_coefficients) = header.color_description();
+
+		let config = hang::catalog::VideoConfig {
+			coded_width: Some(header.width),
+			coded_height: Some(header.height),
+			codec: hang::catalog::VP9 {
+				profile: header.profile,
+				level: header.level(),
+				bit_depth: header.bit_depth,
+				chroma_subsampling: header.chroma_subsampling(),
+				color_primaries,
+				transfer_characteristics,
+				matrix_coefficients,
+				full_range: header.full_range,
+			}
+			.into(),
+			description: None,
+			color_space: Some(hang::catalog::VideoColorSpace::from_cicp(
+				color_primaries,
+				transfer_characteristics,
+				matrix_coefficients,
+				header.full_range,
+			)),
+			// TODO: populate these fields
+			framerate: None,
+			bitrate: None,
+			display_ratio_width: None,
+			display_ratio_height: None,
+			optimize_for_latency: None,
+		};
+
+		if let Some(old) = &self.config {
+			if old == &config {
+				return Ok(());
+			}
+		}
+
+		if let Some(track) = &self.track.take() {
+			tracing::debug!(name = ?track.info.name, "reinitializing track");
+			self.broadcast.catalog.lock().remove_video(&track.info.name);
+		}
+
+		let track = moq::Track {
+			name: self.broadcast.track_name("video"),
+			priority: 2,
+		};
+
+		tracing::debug!(name = ?track.name, ?config, "starting track");
+
+		{
+			let mut catalog = self.broadcast.catalog.lock();
+			let video = catalog.insert_video(track.name.clone(), config.clone());
+			video.priority = 2;
+		}
+
+		let track = track.produce();
+		self.broadcast.insert_track(track.consumer);
+
+		self.config = Some(config);
+		self.track = Some(track.producer.into());
+
+		Ok(())
+	}
+
+	/// Decode a single, complete frame (or superframe) from the buffer.
+	pub fn decode_frame<T: Buf + AsRef<[u8]>>(&mut self, buf: &mut T, pts: hang::Timestamp) -> anyhow::Result<()> {
+		// A superframe starts with the first frame, so we only need to parse that one.
+		let keyframe = match KeyFrameHeader::parse(buf.as_ref())? {
+			Some(header) => {
+				// Try to reinitialize the track if the configuration has changed.
+				self.init(&header)?;
+				true
+			}
+			None => false,
+		};
+
+		let track = self.track.as_mut().context("expected key frame before any frames")?;
+
+		let mut payload = BufList::new();
+		while buf.has_remaining() {
+			payload.push_chunk(buf.copy_to_bytes(buf.chunk().len()));
+		}
+
+		let frame = hang::Frame {
+			timestamp: pts,
+			keyframe,
+			payload,
+		};
+
+		track.write(frame)?;
+
+		Ok(())
+	}
+
+	pub fn is_initialized(&self) -> bool {
+		self.track.is_some()
+	}
+}
+
+impl Drop for Vp9 {
+	fn drop(&mut self) {
+		if let Some(track) = &self.track {
+			tracing::debug!(name = ?track.info.name, "ending track");
+			self.broadcast.catalog.lock().remove_video(&track.info.name);
+		}
+	}
+}
+
+// The sRGB color space, which is always 4:4:4.
+const CS_RGB: u8 = 7;
+
+/// The fields of a VP9 key frame header needed for the catalog (VP9 spec, section 6.2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyFrameHeader {
+	profile: u8,
+	bit_depth: u8,
+	color_space: u8,
+	full_range: bool,
+	subsampling_x: bool,
+	subsampling_y: bool,
+	width: u32,
+	height: u32,
+}
+
+impl KeyFrameHeader {
+	// Returns None if this is not a key frame.
+	fn parse(frame: &[u8]) -> anyhow::Result<Option<Self>> {
+		let mut bits = BitReader::new(frame);
+
+		anyhow::ensure!(bits.read(2)? == 2, "invalid VP9 frame marker");
+
+		let profile_low = bits.read(1)?;
+		let profile = (bits.read(1)? << 1 | profile_low) as u8;
+		if profile == 3 {
+			// reserved_zero
+			bits.skip(1)?;
+		}
+
+		// show_existing_frame, which repeats a previously decoded frame.
+		if bits.read(1)? == 1 {
+			return Ok(None);
+		}
+
+		// frame_type == KEY_FRAME
+		if bits.read(1)? != 0 {
+			return Ok(None);
+		}
+
+		// show_frame, error_resilient_mode
+		bits.skip(2)?;
+
+		anyhow::ensure!(bits.read(24)? == 0x498342, "invalid VP9 sync code");
+
+		// color_config()
+		let bit_depth = match profile {
+			0 | 1 => 8,
+			_ if bits.read(1)? == 1 => 12,
+			_ => 10,
+		};
+
+		let color_space = bits.read(3)? as u8;
+
+		let (full_range, subsampling_x, subsampling_y) = if color_space == CS_RGB {
+			if profile == 1 || profile == 3 {
+				// reserved_zero
+				bits.skip(1)?;
+			}
+
+			(true, false, false)
+		} else {
+			let full_range = bits.read(1)? == 1;
+
+			if profile == 1 || profile == 3 {
+				let x = bits.read(1)? == 1;
+				let y = bits.read(1)? == 1;
+
+				// reserved_zero
+				bits.skip(1)?;
+
+				(full_range, x, y)
+			} else {
+				(full_range, true, true)
+			}
+		};
+
+		// frame_size()
+		let width = bits.read(16)? + 1;
+		let height = bits.read(16)? + 1;
+
+		Ok(Some(Self {
+			profile,
+			bit_depth,
+			color_space,
+			full_range,
+			subsampling_x,
+			subsampling_y,
+			width,
+			height,
+		}))
+	}
+
+	// The chroma subsampling as used by the codec string, assuming 4:2:0 is colocated with luma.
+	fn chroma_subsampling(&self) -> u8 {
+		match (self.subsampling_x, self.subsampling_y) {
+			(true, true) => 1,
+			(true, false) => 2,
+			_ => 3,
+		}
+	}
+
+	// The bitstream only signals a color space, so map it to (primaries, transfer, matrix) as in ISO/IEC 23091-2.
+	fn color_description(&self) -> (u8, u8, u8) {
+		match self.color_space {
+			// CS_BT_601, CS_SMPTE_170
+			1 | 3 => (6, 6, 6),
+			// CS_BT_709
+			2 => (1, 1, 1),
+			// CS_SMPTE_240
+			4 => (7, 7, 7),
+			// CS_BT_2020, although the transfer (ex. PQ or HLG) is unknown.
+			5 => (9, 2, 9),
+			CS_RGB => (1, 13, 0),
+			// CS_UNKNOWN, CS_RESERVED
+			_ => (2, 2, 2),
+		}
+	}
+
+	// The level isn't signaled, so pick the lowest level that supports the picture size (VP9 spec, Annex A).
+	fn level(&self) -> u8 {
+		const LEVELS: &[(u64, u8)] = &[
+			(36_864, 10),
+			(73_728, 11),
+			(122_880, 20),
+			(245_760, 21),
+			(552_960, 30),
+			(983_040, 31),
+			(2_228_224, 40),
+			(8_912_896, 50),
+			(35_651_584, 60),
+		];
+
+		let size = self.width as u64 * self.height as u64;
+
+		LEVELS
+			.iter()
+			.find(|(max, _)| size <= *max)
+			.map(|(_, level)| *level)
+			.unwrap_or(62)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// A 1280x720 profile 0 key frame with BT.709 limited range, truncated after the frame size.
+	const KEY_FRAME: &[u8] = &[0x82, 0x49, 0x83, 0x42, 0x40, 0x4f, 0xf0, 0x2c, 0xf0];
+
+	// A 1920x1080 profile 2 key frame with 10-bit BT.2020 limited range.
+	const KEY_FRAME_HDR: &[u8] = &[0x92, 0x49, 0x83, 0x42, 0x50, 0x3b, 0xf8, 0x21, 0xb8];
+
+	const INTER_FRAME: &[u8] = &[0x86, 0x00, 0x40];
+
+	#[test]
+	fn test_key_frame_header() {
+		let header = KeyFrameHeader::parse(KEY_FRAME).unwrap().unwrap();
+		assert_eq!(header.profile, 0);
+		assert_eq!(header.bit_depth, 8);
+		assert_eq!(header.color_space, 2);
+		assert!(!header.full_range);
+		assert_eq!(header.chroma_subsampling(), 1);
+		assert_eq!(header.width, 1280);
+		assert_eq!(header.height, 720);
+		assert_eq!(header.level(), 31);
+
+		let header = KeyFrameHeader::parse(KEY_FRAME_HDR).unwrap().unwrap();
+		assert_eq!(header.profile, 2);
+		assert_eq!(header.bit_depth, 10);
+		assert_eq!(header.color_description(), (9, 2, 9));
+		assert_eq!(header.width, 1920);
+		assert_eq!(header.height, 1080);
+		assert_eq!(header.level(), 40);
+
+		assert_eq!(KeyFrameHeader::parse(INTER_FRAME).unwrap(), None);
+		assert!(KeyFrameHeader::parse(&[0x02]).is_err());
+	}
+}
@@ -0,0 +1,108 @@
+use crate as hang;
+
+use super::hev1::BitReader;
+
+/// The fields of the video usability information (VUI) needed for the catalog.
+///
+/// H.264 (Annex E.1.1) and H.265 (Annex E.2.1) share the same layout until the timing information.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct Vui {
+	// Only set when video_signal_type_present_flag is set.
+	full_range: Option<bool>,
+
+	// The colour primaries, transfer characteristics and matrix coefficients.
+	color: Option<(u8, u8, u8)>,
+
+	// The frame rate, as (numerator, denominator).
+	framerate: Option<(u64, u64)>,
+}
+
+impl Vui {
+	pub fn parse_h264(bits: &mut BitReader) -> anyhow::Result<Self> {
+		let mut vui = Self::parse_common(bits)?;
+
+		// timing_info_present_flag
+		if bits.read(1)? == 1 {
+			let num_units_in_tick = bits.read(32)? as u64;
+			let time_scale = bits.read(32)? as u64;
+
+			// Each tick is a field, so there's two per frame.
+			vui.framerate = Some((time_scale, num_units_in_tick * 2));
+		}
+
+		Ok(vui)
+	}
+
+	pub fn parse_h265(bits: &mut BitReader) -> anyhow::Result<Self> {
+		let mut vui = Self::parse_common(bits)?;
+
+		// neutral_chroma_indication_flag, field_seq_flag, frame_field_info_present_flag
+		bits.skip(3)?;
+
+		// default_display_window_flag
+		if bits.read(1)? == 1 {
+			for _ in 0..4 {
+				bits.read_ue()?;
+			}
+		}
+
+		// vui_timing_info_present_flag
+		if bits.read(1)? == 1 {
+			let num_units_in_tick = bits.read(32)? as u64;
+			let time_scale = bits.read(32)? as u64;
+			vui.framerate = Some((time_scale, num_units_in_tick));
+		}
+
+		Ok(vui)
+	}
+
+	fn parse_common(bits: &mut BitReader) -> anyhow::Result<Self> {
+		let mut vui = Self::default();
+
+		// aspect_ratio_info_present_flag, aspect_ratio_idc == EXTENDED_SAR
+		if bits.read(1)? == 1 && bits.read(8)? == 255 {
+			// sar_width, sar_height
+			bits.skip(32)?;
+		}
+
+		// overscan_info_present_flag, overscan_appropriate_flag
+		if bits.read(1)? == 1 {
+			bits.skip(1)?;
+		}
+
+		// video_signal_type_present_flag
+		if bits.read(1)? == 1 {
+			// video_format
+			bits.skip(3)?;
+
+			vui.full_range = Some(bits.read(1)? == 1);
+
+			// colour_description_present_flag
+			if bits.read(1)? == 1 {
+				vui.color = Some((bits.read(8)? as u8, bits.read(8)? as u8, bits.read(8)? as u8));
+			}
+		}
+
+		// chroma_loc_info_present_flag
+		if bits.read(1)? == 1 {
+			bits.read_ue()?;
+			bits.read_ue()?;
+		}
+
+		Ok(vui)
+	}
+
+	pub fn color_space(&self) -> Option<hang::catalog::VideoColorSpace> {
+		let full_range = self.full_range?;
+		let (primaries, transfer, matrix) = self.color.unwrap_or((2, 2, 2));
+
+		Some(hang::catalog::VideoColorSpace::from_cicp(
+			primaries, transfer, matrix, full_range,
+		))
+	}
+
+	pub fn framerate(&self) -> Option<f64> {
+		let (numerator, denominator) = self.framerate?;
+		(numerator > 0 && denominator > 0).then(|| numerator as f64 / denominator as f64)
+	}
+}
@@ -12,13 +12,18 @@
 //! - **Codec support**: Integration with common audio/video codecs
 //! - **Container**: A simple timestamped container format.
 //! - **CMAF Import**: Convert a fMP4 file into a hang broadcast.
+//! - **CMAF Export**: Package a hang track into fMP4 segments, ex. for HLS.
+//! - **Stream Export**: Write a hang broadcast as a single fMP4 or H.264 Annex B stream, ex. for ffmpeg.
+//! - **Replay**: Republish a relay recording or fMP4 file at its original timing.
 //!
 mod error;
 
 pub mod catalog;
+pub mod export;
 pub mod feedback;
 pub mod import;
 pub mod model;
+pub mod replay;
 
 // export the moq-lite version in use
 pub use moq_lite;
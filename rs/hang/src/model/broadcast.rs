@@ -1,9 +1,12 @@
 use std::{
+	collections::{HashMap, VecDeque},
 	ops::{Deref, DerefMut},
 	sync::{atomic, Arc},
 };
 
-use crate::catalog::{Catalog, CatalogProducer};
+use crate::catalog::{AudioConfig, CaptionConfig, Catalog, CatalogConsumer, CatalogProducer, VideoConfig};
+use crate::model::TrackConsumer;
+use crate::{Error, Result};
 
 #[derive(Clone)]
 pub struct BroadcastProducer {
@@ -58,4 +61,346 @@ impl From<BroadcastProducer> for moq_lite::BroadcastProducer {
 	}
 }
 
-// TODO BroadcastConsumer
+/// A change to the renditions in a broadcast, as reported by [BroadcastConsumer::next].
+///
+/// A rendition with a new configuration is reported as removed and then added again.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BroadcastEvent {
+	VideoAdded(String, VideoConfig),
+	VideoRemoved(String),
+	AudioAdded(String, AudioConfig),
+	AudioRemoved(String),
+	CaptionsAdded(String, CaptionConfig),
+	CaptionsRemoved(String),
+}
+
+/// A consumer for a hang broadcast, discovering tracks via the catalog.
+///
+/// Call [Self::next] to receive the renditions as they're added or removed.
+/// The latest catalog is then available via the typed accessors, which are used to subscribe to each rendition.
+#[derive(Clone)]
+pub struct BroadcastConsumer {
+	pub inner: moq_lite::BroadcastConsumer,
+	pub catalog: CatalogConsumer,
+
+	// The latest catalog, used to compute the changes.
+	current: Catalog,
+
+	// Changes that have not been returned yet.
+	events: VecDeque<BroadcastEvent>,
+}
+
+impl BroadcastConsumer {
+	pub fn new(inner: moq_lite::BroadcastConsumer) -> Self {
+		let catalog = CatalogConsumer::new(inner.subscribe_track(&Catalog::default_track()));
+
+		Self {
+			inner,
+			catalog,
+			current: Catalog::default(),
+			events: VecDeque::new(),
+		}
+	}
+
+	/// Wait for the next rendition to be added or removed.
+	///
+	/// Returns `None` when the catalog track has ended.
+	pub async fn next(&mut self) -> Result<Option<BroadcastEvent>> {
+		loop {
+			if let Some(event) = self.events.pop_front() {
+				return Ok(Some(event));
+			}
+
+			match self.catalog.next().await? {
+				Some(catalog) => self.update(catalog),
+				None => return Ok(None),
+			}
+		}
+	}
+
+	// Queue an event for each rendition that changed, in a consistent order.
+	fn update(&mut self, catalog: Catalog) {
+		let old_video = self.current.video.as_ref().map(|video| &video.renditions);
+		let new_video = catalog.video.as_ref().map(|video| &video.renditions);
+		let old_audio = self.current.audio.as_ref().map(|audio| &audio.renditions);
+		let new_audio = catalog.audio.as_ref().map(|audio| &audio.renditions);
+		let old_captions = self.current.captions.as_ref().map(|captions| &captions.renditions);
+		let new_captions = catalog.captions.as_ref().map(|captions| &captions.renditions);
+
+		for name in diff(old_video, new_video) {
+			self.events.push_back(BroadcastEvent::VideoRemoved(name));
+		}
+
+		for name in diff(old_audio, new_audio) {
+			self.events.push_back(BroadcastEvent::AudioRemoved(name));
+		}
+
+		for name in diff(old_captions, new_captions) {
+			self.events.push_back(BroadcastEvent::CaptionsRemoved(name));
+		}
+
+		for name in diff(new_video, old_video) {
+			let config = new_video.and_then(|renditions| renditions.get(&name)).unwrap().clone();
+			self.events.push_back(BroadcastEvent::VideoAdded(name, config));
+		}
+
+		for name in diff(new_audio, old_audio) {
+			let config = new_audio.and_then(|renditions| renditions.get(&name)).unwrap().clone();
+			self.events.push_back(BroadcastEvent::AudioAdded(name, config));
+		}
+
+		for name in diff(new_captions, old_captions) {
+			let config = new_captions
+				.and_then(|renditions| renditions.get(&name))
+				.unwrap()
+				.clone();
+			self.events.push_back(BroadcastEvent::CaptionsAdded(name, config));
+		}
+
+		self.current = catalog;
+	}
+
+	/// The latest catalog, as of the last call to [Self::next].
+	pub fn catalog(&self) -> &Catalog {
+		&self.current
+	}
+
+	/// The current video renditions, sorted by name.
+	pub fn videos(&self) -> Vec<(&str, &VideoConfig)> {
+		let mut renditions: Vec<_> = self
+			.current
+			.video
+			.iter()
+			.flat_map(|video| &video.renditions)
+			.map(|(name, config)| (name.as_str(), config))
+			.collect();
+		renditions.sort_by_key(|(name, _)| *name);
+		renditions
+	}
+
+	/// The current audio renditions, sorted by name.
+	pub fn audios(&self) -> Vec<(&str, &AudioConfig)> {
+		let mut renditions: Vec<_> = self
+			.current
+			.audio
+			.iter()
+			.flat_map(|audio| &audio.renditions)
+			.map(|(name, config)| (name.as_str(), config))
+			.collect();
+		renditions.sort_by_key(|(name, _)| *name);
+		renditions
+	}
+
+	/// The current caption renditions, sorted by name.
+	pub fn captions(&self) -> Vec<(&str, &CaptionConfig)> {
+		let mut renditions: Vec<_> = self
+			.current
+			.captions
+			.iter()
+			.flat_map(|captions| &captions.renditions)
+			.map(|(name, config)| (name.as_str(), config))
+			.collect();
+		renditions.sort_by_key(|(name, _)| *name);
+		renditions
+	}
+
+	/// Return the configuration of the video rendition with the given name.
+	pub fn video(&self, name: &str) -> Option<&VideoConfig> {
+		self.current.video.as_ref()?.renditions.get(name)
+	}
+
+	/// Return the configuration of the audio rendition with the given name.
+	pub fn audio(&self, name: &str) -> Option<&AudioConfig> {
+		self.current.audio.as_ref()?.renditions.get(name)
+	}
+
+	/// Return the configuration of the caption rendition with the given name.
+	pub fn caption(&self, name: &str) -> Option<&CaptionConfig> {
+		self.current.captions.as_ref()?.renditions.get(name)
+	}
+
+	/// Subscribe to the video rendition with the given name, using the priority from the catalog.
+	pub fn subscribe_video(&self, name: &str) -> Result<TrackConsumer> {
+		let video = self.current.video.as_ref().ok_or(Error::MissingTrack)?;
+		if !video.renditions.contains_key(name) {
+			return Err(Error::MissingTrack);
+		}
+
+		Ok(self.subscribe(name, video.priority))
+	}
+
+	/// Subscribe to the audio rendition with the given name, using the priority from the catalog.
+	pub fn subscribe_audio(&self, name: &str) -> Result<TrackConsumer> {
+		let audio = self.current.audio.as_ref().ok_or(Error::MissingTrack)?;
+		if !audio.renditions.contains_key(name) {
+			return Err(Error::MissingTrack);
+		}
+
+		Ok(self.subscribe(name, audio.priority))
+	}
+
+	/// Subscribe to the caption rendition with the given name, using the priority from the catalog.
+	pub fn subscribe_captions(&self, name: &str) -> Result<TrackConsumer> {
+		let captions = self.current.captions.as_ref().ok_or(Error::MissingTrack)?;
+		if !captions.renditions.contains_key(name) {
+			return Err(Error::MissingTrack);
+		}
+
+		Ok(self.subscribe(name, captions.priority))
+	}
+
+	fn subscribe(&self, name: &str, priority: u8) -> TrackConsumer {
+		let track = moq_lite::Track {
+			name: name.to_string(),
+			priority,
+		};
+
+		self.inner.subscribe_track(&track).into()
+	}
+}
+
+impl Deref for BroadcastConsumer {
+	type Target = moq_lite::BroadcastConsumer;
+
+	fn deref(&self) -> &Self::Target {
+		&self.inner
+	}
+}
+
+impl From<moq_lite::BroadcastConsumer> for BroadcastConsumer {
+	fn from(inner: moq_lite::BroadcastConsumer) -> Self {
+		Self::new(inner)
+	}
+}
+
+impl From<BroadcastConsumer> for moq_lite::BroadcastConsumer {
+	fn from(consumer: BroadcastConsumer) -> Self {
+		consumer.inner
+	}
+}
+
+// Return the sorted names in `a` that are missing or different in `b`.
+fn diff<T: PartialEq>(a: Option<&HashMap<String, T>>, b: Option<&HashMap<String, T>>) -> Vec<String> {
+	let mut names: Vec<_> = a
+		.into_iter()
+		.flatten()
+		.filter(|(name, config)| b.and_then(|b| b.get(*name)) != Some(*config))
+		.map(|(name, _)| name.clone())
+		.collect();
+	names.sort();
+	names
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::catalog::{AudioCodec, CaptionFormat, H264};
+
+	fn video(height: u32) -> VideoConfig {
+		VideoConfig {
+			codec: H264 {
+				profile: 0x64,
+				constraints: 0,
+				level: 0x1f,
+				inline: false,
+			}
+			.into(),
+			description: None,
+			coded_width: Some(height * 16 / 9),
+			coded_height: Some(height),
+			display_ratio_width: None,
+			display_ratio_height: None,
+			color_space: None,
+			bitrate: None,
+			framerate: None,
+			optimize_for_latency: None,
+		}
+	}
+
+	fn audio() -> AudioConfig {
+		AudioConfig {
+			codec: AudioCodec::Opus,
+			sample_rate: 48_000,
+			channel_count: 2,
+			bitrate: None,
+			description: None,
+		}
+	}
+
+	fn captions() -> CaptionConfig {
+		CaptionConfig {
+			format: CaptionFormat::Cea608,
+			language: None,
+			description: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_events() {
+		let broadcast = moq_lite::Broadcast::produce();
+		let mut producer: BroadcastProducer = broadcast.producer.into();
+		let mut consumer = BroadcastConsumer::new(broadcast.consumer);
+
+		{
+			let mut catalog = producer.catalog.lock();
+			catalog.insert_video("720p".to_string(), video(720));
+			catalog.insert_video("360p".to_string(), video(360));
+			catalog.insert_audio("opus".to_string(), audio());
+			catalog.insert_captions("captions".to_string(), captions());
+		}
+
+		assert_eq!(
+			consumer.next().await.unwrap(),
+			Some(BroadcastEvent::VideoAdded("360p".to_string(), video(360)))
+		);
+		assert_eq!(
+			consumer.next().await.unwrap(),
+			Some(BroadcastEvent::VideoAdded("720p".to_string(), video(720)))
+		);
+		assert_eq!(
+			consumer.next().await.unwrap(),
+			Some(BroadcastEvent::AudioAdded("opus".to_string(), audio()))
+		);
+		assert_eq!(
+			consumer.next().await.unwrap(),
+			Some(BroadcastEvent::CaptionsAdded("captions".to_string(), captions()))
+		);
+
+		assert_eq!(consumer.videos().len(), 2);
+		assert_eq!(consumer.audio("opus"), Some(&audio()));
+		assert!(consumer.subscribe_video("720p").is_ok());
+		assert!(matches!(consumer.subscribe_audio("aac"), Err(Error::MissingTrack)));
+		assert_eq!(consumer.captions(), vec![("captions", &captions())]);
+		assert!(consumer.subscribe_captions("captions").is_ok());
+
+		// A changed configuration is removed and then added again.
+		{
+			let mut catalog = producer.catalog.lock();
+			catalog.remove_audio("opus");
+			catalog.remove_captions("captions");
+			catalog.insert_video("720p".to_string(), video(1080));
+		}
+
+		assert_eq!(
+			consumer.next().await.unwrap(),
+			Some(BroadcastEvent::VideoRemoved("720p".to_string()))
+		);
+		assert_eq!(
+			consumer.next().await.unwrap(),
+			Some(BroadcastEvent::AudioRemoved("opus".to_string()))
+		);
+		assert_eq!(
+			consumer.next().await.unwrap(),
+			Some(BroadcastEvent::CaptionsRemoved("captions".to_string()))
+		);
+		assert_eq!(
+			consumer.next().await.unwrap(),
+			Some(BroadcastEvent::VideoAdded("720p".to_string(), video(1080)))
+		);
+		assert!(consumer.audios().is_empty());
+		assert!(consumer.captions().is_empty());
+
+		producer.catalog.clone().close();
+		assert_eq!(consumer.next().await.unwrap(), None);
+	}
+}
@@ -22,6 +22,7 @@ pub struct Frame {
 	///
 	/// Keyframes are used as group boundaries and entry points for new subscribers.
 	/// It's necessary to periodically encode keyframes to support new subscribers.
+	/// Caption frames are independent, so they're normally all keyframes.
 	pub keyframe: bool,
 
 	/// The encoded media data for this frame, split into chunks.
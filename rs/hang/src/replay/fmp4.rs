@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use bytes::{Bytes, BytesMut};
+use mp4_atom::{Decode, Encode};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+
+use super::Clock;
+use crate::{self as hang, import, Timestamp};
+
+/// The timing information for a track, from the moov.
+struct Timing {
+	timescale: u64,
+	default_duration: u32,
+}
+
+/// Feed a fMP4 file into the importer, one fragment at a time.
+pub(super) async fn run(
+	broadcast: hang::BroadcastProducer,
+	path: &Path,
+	clock: Clock,
+	looping: bool,
+) -> anyhow::Result<()> {
+	let mut importer = import::Fmp4::new(broadcast);
+	let mut timing = HashMap::new();
+
+	// The first decode time and the latest end time in the file, in microseconds.
+	let mut first = None;
+	let mut end = 0;
+
+	// Added to each decode time so timestamps keep increasing when looping, in microseconds.
+	let mut offset = 0;
+
+	loop {
+		let file = tokio::fs::File::open(path)
+			.await
+			.with_context(|| format!("failed to open {}", path.display()))?;
+		let mut file = BufReader::new(file);
+
+		while let Some((kind, mut atom)) = read_atom(&mut file).await? {
+			match &kind {
+				// Only initialize once, even when looping.
+				b"ftyp" | b"styp" | b"moov" if importer.is_initialized() => continue,
+				b"moov" => {
+					let moov = mp4_atom::Moov::decode(&mut atom.clone())?;
+					timing = self::timing(&moov);
+				}
+				b"moof" => {
+					let mut moof = mp4_atom::Moof::decode(&mut atom.clone())?;
+					let (start, stop) = span(&moof, &timing)?;
+
+					let first = *first.get_or_insert(start);
+					end = end.max(stop);
+
+					if offset > 0 {
+						atom = shift(&mut moof, atom.len(), offset, &timing)?;
+					}
+
+					let elapsed = (start + offset).saturating_sub(first);
+					clock.wait(Timestamp::from_micros(elapsed)?).await;
+				}
+				_ => {}
+			}
+
+			importer.decode(&mut atom)?;
+		}
+
+		if !looping {
+			return Ok(());
+		}
+
+		let first = first.context("no fragments to loop")?;
+		offset += end.saturating_sub(first);
+	}
+}
+
+fn timing(moov: &mp4_atom::Moov) -> HashMap<u32, Timing> {
+	let mut timing = HashMap::new();
+
+	for trak in &moov.trak {
+		let track_id = trak.tkhd.track_id;
+		let default_duration = moov
+			.mvex
+			.as_ref()
+			.and_then(|mvex| mvex.trex.iter().find(|trex| trex.track_id == track_id))
+			.map(|trex| trex.default_sample_duration)
+			.unwrap_or_default();
+
+		timing.insert(
+			track_id,
+			Timing {
+				timescale: trak.mdia.mdhd.timescale as u64,
+				default_duration,
+			},
+		);
+	}
+
+	timing
+}
+
+// Return the earliest decode time and the latest end time in the fragment, in microseconds.
+fn span(moof: &mp4_atom::Moof, timing: &HashMap<u32, Timing>) -> anyhow::Result<(u64, u64)> {
+	let mut start = None;
+	let mut end = 0;
+
+	for traf in &moof.traf {
+		let timing = timing.get(&traf.tfhd.track_id).context("unknown track")?;
+		let dts = traf.tfdt.as_ref().context("missing tfdt box")?.base_media_decode_time;
+
+		let duration: u64 = traf
+			.trun
+			.iter()
+			.flat_map(|trun| &trun.entries)
+			.map(|entry| {
+				entry
+					.duration
+					.unwrap_or(traf.tfhd.default_sample_duration.unwrap_or(timing.default_duration)) as u64
+			})
+			.sum();
+
+		let micros = |ts: u64| (ts as u128 * 1_000_000 / timing.timescale as u128) as u64;
+		start = Some(start.unwrap_or(u64::MAX).min(micros(dts)));
+		end = end.max(micros(dts + duration));
+	}
+
+	Ok((start.context("empty moof box")?, end))
+}
+
+// Add the offset to each decode time, returning the encoded moof.
+fn shift(moof: &mut mp4_atom::Moof, size: usize, offset: u64, timing: &HashMap<u32, Timing>) -> anyhow::Result<Bytes> {
+	for traf in &mut moof.traf {
+		let timing = timing.get(&traf.tfhd.track_id).context("unknown track")?;
+		let tfdt = traf.tfdt.as_mut().context("missing tfdt box")?;
+		tfdt.base_media_decode_time += (offset as u128 * timing.timescale as u128 / 1_000_000) as u64;
+	}
+
+	let mut after = BytesMut::new();
+	moof.encode(&mut after)?;
+
+	// The data offsets are relative to the start of the moof, so they need to account for any change in size.
+	// NOTE: mp4-atom always encodes a 64-bit tfdt, so the size changes for version 0.
+	let delta = after.len() as i32 - size as i32;
+	if delta != 0 {
+		for trun in moof.traf.iter_mut().flat_map(|traf| traf.trun.iter_mut()) {
+			if let Some(data_offset) = trun.data_offset.as_mut() {
+				*data_offset += delta;
+			}
+		}
+
+		after.clear();
+		moof.encode(&mut after)?;
+	}
+
+	Ok(after.freeze())
+}
+
+// Read the next top-level atom, including its header.
+async fn read_atom<R: AsyncRead + Unpin>(input: &mut R) -> anyhow::Result<Option<([u8; 4], Bytes)>> {
+	let mut header = [0u8; 8];
+	match input.read_exact(&mut header).await {
+		Ok(_) => {}
+		Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+		Err(err) => return Err(err.into()),
+	}
+
+	let kind: [u8; 4] = header[4..8].try_into().unwrap();
+	let mut atom = BytesMut::from(&header[..]);
+
+	match u32::from_be_bytes(header[0..4].try_into().unwrap()) {
+		// The atom extends to the end of the file.
+		0 => {
+			let mut rest = Vec::new();
+			input.read_to_end(&mut rest).await?;
+			atom.extend_from_slice(&rest);
+		}
+		// A 64-bit size follows the header.
+		1 => {
+			let size = input.read_u64().await?;
+			atom.extend_from_slice(&size.to_be_bytes());
+
+			let size = size.checked_sub(16).context("invalid atom size")?;
+			let mut body = vec![0u8; size as usize];
+			input.read_exact(&mut body).await?;
+			atom.extend_from_slice(&body);
+		}
+		size => {
+			let size = size.checked_sub(8).context("invalid atom size")?;
+			let mut body = vec![0u8; size as usize];
+			input.read_exact(&mut body).await?;
+			atom.extend_from_slice(&body);
+		}
+	}
+
+	Ok(Some((kind, atom.freeze())))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::catalog::{AudioConfig, AAC};
+	use crate::export::Cmaf;
+
+	fn cmaf() -> Cmaf {
+		let config = AudioConfig {
+			codec: AAC { profile: 2 }.into(),
+			sample_rate: 48_000,
+			channel_count: 2,
+			bitrate: None,
+			description: None,
+		};
+
+		Cmaf::audio(&config).unwrap()
+	}
+
+	fn frame(micros: u64) -> hang::Frame {
+		hang::Frame {
+			timestamp: Timestamp::from_micros(micros).unwrap(),
+			keyframe: true,
+			payload: Bytes::from_static(b"aac").into(),
+		}
+	}
+
+	// The importer doesn't close tracks, so read a fixed number of frames.
+	async fn read(broadcast: &moq_lite::BroadcastConsumer, count: usize) -> Vec<u64> {
+		let track = broadcast.subscribe_track(&moq_lite::Track::new("audio0"));
+		let mut track = hang::TrackConsumer::new(track);
+
+		let mut timestamps = Vec::new();
+		for _ in 0..count {
+			let frame = track.read_frame().await.unwrap().unwrap();
+			assert_eq!(frame.payload.num_bytes(), 3);
+			timestamps.push(frame.timestamp.as_micros());
+		}
+
+		timestamps
+	}
+
+	#[tokio::test]
+	async fn test_replay() {
+		let cmaf = cmaf();
+
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("audio.mp4");
+
+		let mut file = cmaf.init().unwrap().to_vec();
+		file.extend_from_slice(
+			&cmaf
+				.fragment(1, &[frame(0), frame(20_000)], frame(40_000).timestamp)
+				.unwrap(),
+		);
+		std::fs::write(&path, file).unwrap();
+
+		let broadcast = moq_lite::Broadcast::produce();
+		let mut replay = super::super::Replay::new(broadcast.producer.clone().into(), &path);
+		replay.set_speed(0.0).unwrap();
+		replay.run().await.unwrap();
+
+		assert_eq!(read(&broadcast.consumer, 2).await, vec![0, 20_000]);
+	}
+
+	#[tokio::test]
+	async fn test_shift() {
+		let cmaf = cmaf();
+
+		let mut init = cmaf.init().unwrap();
+		mp4_atom::Ftyp::decode(&mut init).unwrap();
+		let moov = mp4_atom::Moov::decode(&mut init).unwrap();
+		let timing = timing(&moov);
+
+		let fragment = cmaf
+			.fragment(1, &[frame(0), frame(20_000)], frame(40_000).timestamp)
+			.unwrap();
+
+		let mut buf = fragment.clone();
+		let mut moof = mp4_atom::Moof::decode(&mut buf).unwrap();
+		let size = fragment.len() - buf.len();
+		assert_eq!(span(&moof, &timing).unwrap(), (0, 40_000));
+
+		let shifted = shift(&mut moof, size, 40_000, &timing).unwrap();
+
+		let broadcast = moq_lite::Broadcast::produce();
+		let mut importer = import::Fmp4::new(broadcast.producer.clone().into());
+		importer.decode(&mut cmaf.init().unwrap()).unwrap();
+		importer.decode(&mut shifted.clone()).unwrap();
+		importer.decode(&mut buf).unwrap();
+		drop(importer);
+
+		assert_eq!(read(&broadcast.consumer, 2).await, vec![40_000, 60_000]);
+	}
+}
@@ -0,0 +1,113 @@
+//! Replay recorded media into a broadcast.
+//!
+//! Two sources are supported:
+//! - A directory written by the moq-relay recorder, either a broadcast or a single segment.
+//! - A fragmented MP4 file.
+//!
+//! Frames are published at their original timing by default, or faster/slower with [Replay::set_speed].
+
+mod fmp4;
+mod recording;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::{self as hang, Timestamp};
+
+/// Republishes a recording or fMP4 file as a hang broadcast.
+pub struct Replay {
+	broadcast: hang::BroadcastProducer,
+	path: PathBuf,
+	speed: f64,
+	looping: bool,
+}
+
+impl Replay {
+	/// Create a new replay from the given path, which is either a recording directory or a fMP4 file.
+	pub fn new(broadcast: hang::BroadcastProducer, path: impl Into<PathBuf>) -> Self {
+		Self {
+			broadcast,
+			path: path.into(),
+			speed: 1.0,
+			looping: false,
+		}
+	}
+
+	/// Set the playback speed, where 1.0 is real-time and 0.0 is as fast as possible.
+	///
+	/// Returns an error if the speed is negative, infinite or NaN.
+	pub fn set_speed(&mut self, speed: f64) -> anyhow::Result<()> {
+		anyhow::ensure!(speed.is_finite() && speed >= 0.0, "invalid speed: {speed}");
+		self.speed = speed;
+		Ok(())
+	}
+
+	/// Start again from the beginning once the end is reached, forever.
+	///
+	/// Timestamps keep increasing across each loop.
+	pub fn set_loop(&mut self, looping: bool) {
+		self.looping = looping;
+	}
+
+	/// Publish the media until the end is reached, or forever if looping.
+	pub async fn run(self) -> anyhow::Result<()> {
+		let clock = Clock::new(self.speed);
+
+		if tokio::fs::metadata(&self.path).await?.is_dir() {
+			recording::Recording::new(self.broadcast, &self.path)
+				.await?
+				.run(clock, self.looping)
+				.await
+		} else {
+			fmp4::run(self.broadcast, &self.path, clock, self.looping).await
+		}
+	}
+}
+
+/// Converts media timestamps into wall clock deadlines.
+#[derive(Clone, Copy)]
+struct Clock {
+	start: tokio::time::Instant,
+	speed: f64,
+}
+
+impl Clock {
+	fn new(speed: f64) -> Self {
+		Self {
+			start: tokio::time::Instant::now(),
+			speed,
+		}
+	}
+
+	/// Sleep until the given amount of media has elapsed since the start.
+	async fn wait(&self, elapsed: Timestamp) {
+		if self.speed <= 0.0 {
+			return;
+		}
+
+		// A tiny speed could overflow, in which case the deadline is never reached.
+		let Ok(elapsed) = Duration::try_from_secs_f64(Duration::from(elapsed).as_secs_f64() / self.speed) else {
+			return std::future::pending().await;
+		};
+
+		tokio::time::sleep_until(self.start + elapsed).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::moq_lite;
+
+	#[test]
+	fn test_speed() {
+		let mut replay = Replay::new(moq_lite::Broadcast::produce().producer.into(), "unused");
+
+		assert!(replay.set_speed(0.0).is_ok());
+		assert!(replay.set_speed(2.5).is_ok());
+		assert!(replay.set_speed(-1.0).is_err());
+		assert!(replay.set_speed(f64::NAN).is_err());
+		assert!(replay.set_speed(f64::INFINITY).is_err());
+		assert_eq!(replay.speed, 2.5);
+	}
+}
@@ -0,0 +1,450 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use bytes::Bytes;
+use moq_lite::coding::Decode;
+use moq_lite::lite;
+use tokio::io::{AsyncReadExt, BufReader};
+
+use super::Clock;
+use crate::catalog::Catalog;
+use crate::{self as hang, Timestamp};
+
+/// The magic at the start of each `.moq` file.
+const MAGIC: &[u8; 8] = b"moqrec01";
+
+/// The number of finished groups remembered per track, so late frames can be dropped.
+const RECENT_GROUPS: usize = 32;
+
+/// The gap in microseconds inserted when the timestamps jump backwards, ex. when looping.
+const REBASE_GAP: u64 = 20_000;
+
+/// The subset of `meta.json` needed for replay.
+#[derive(serde::Deserialize)]
+struct Meta {
+	tracks: HashMap<String, u32>,
+}
+
+/// Replays a directory written by the moq-relay recorder.
+pub(super) struct Recording {
+	broadcast: hang::BroadcastProducer,
+	segments: Vec<PathBuf>,
+
+	// The output track for each rendition, created on first use.
+	tracks: HashMap<String, Track>,
+
+	// The last catalog that was published.
+	catalog: Option<Catalog>,
+
+	// Added to each recorded timestamp to produce the output timestamp, in microseconds.
+	offset: i64,
+
+	// The largest recorded timestamp in the previous segment.
+	last: Option<u64>,
+
+	// The largest output timestamp so far.
+	end: u64,
+}
+
+impl Recording {
+	/// Open either a broadcast directory containing segments, or a single segment directory.
+	pub async fn new(broadcast: hang::BroadcastProducer, path: &Path) -> anyhow::Result<Self> {
+		let segments = if tokio::fs::try_exists(path.join("meta.json")).await? {
+			vec![path.to_path_buf()]
+		} else {
+			let mut segments = Vec::new();
+
+			let mut entries = tokio::fs::read_dir(path).await?;
+			while let Some(entry) = entries.next_entry().await? {
+				let segment = entry.path();
+				if tokio::fs::try_exists(segment.join("meta.json")).await? {
+					segments.push(segment);
+				}
+			}
+
+			// Segments are named after their start time in milliseconds.
+			segments.sort_by_key(|segment| {
+				let name = segment.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+				(name.parse::<u64>().unwrap_or(u64::MAX), name.to_string())
+			});
+
+			segments
+		};
+
+		anyhow::ensure!(!segments.is_empty(), "no recording found in {}", path.display());
+
+		Ok(Self {
+			broadcast,
+			segments,
+			tracks: HashMap::new(),
+			catalog: None,
+			offset: 0,
+			last: None,
+			end: 0,
+		})
+	}
+
+	pub async fn run(mut self, clock: Clock, looping: bool) -> anyhow::Result<()> {
+		loop {
+			for segment in self.segments.clone() {
+				self.segment(&segment, clock)
+					.await
+					.with_context(|| format!("failed to replay {}", segment.display()))?;
+			}
+
+			if !looping {
+				for (_, track) in self.tracks.drain() {
+					track.producer.inner.close();
+				}
+
+				return Ok(());
+			}
+
+			// Group sequences start over, so forget them.
+			for track in self.tracks.values_mut() {
+				track.reset();
+			}
+		}
+	}
+
+	async fn segment(&mut self, dir: &Path, clock: Clock) -> anyhow::Result<()> {
+		let meta: Meta = serde_json::from_slice(&tokio::fs::read(dir.join("meta.json")).await?)?;
+
+		// Use the latest catalog in the segment.
+		let id = meta
+			.tracks
+			.get(Catalog::DEFAULT_NAME)
+			.context("missing catalog track")?;
+		let mut reader = Reader::open(&dir.join(format!("{id}.moq"))).await?;
+
+		let mut latest = None;
+		while let Some(record) = reader.next().await? {
+			latest = Some(record.payload);
+		}
+
+		let catalog = Catalog::from_slice(&latest.context("empty catalog track")?)?;
+		self.publish(catalog);
+
+		let renditions: Vec<String> = self.tracks.keys().cloned().collect();
+
+		let mut readers = Vec::new();
+		let mut base = None;
+
+		for name in renditions {
+			let Some(id) = meta.tracks.get(&name) else {
+				continue;
+			};
+
+			let mut reader = Reader::open(&dir.join(format!("{id}.moq"))).await?;
+			if let Some(record) = reader.peek().await? {
+				let micros = u64::decode(&mut record.payload.clone(), lite::Version::Draft02)?;
+				base = Some(base.unwrap_or(micros).min(micros));
+			}
+
+			readers.push((name, reader));
+		}
+
+		let Some(base) = base else {
+			// No media in this segment.
+			return Ok(());
+		};
+
+		// Keep the original timing unless it went backwards, ex. the publisher reconnected or we looped.
+		match self.last {
+			Some(last) if base >= last => {}
+			Some(_) => self.offset = (self.end + REBASE_GAP) as i64 - base as i64,
+			None => self.offset = -(base as i64),
+		}
+
+		let offset = self.offset;
+
+		let tasks = readers.into_iter().map(|(name, reader)| {
+			let track = self.tracks.remove(&name).expect("missing track");
+			async move {
+				let mut track = track;
+				let mut reader = reader;
+				let res = track.replay(&mut reader, clock, offset).await;
+				(name, track, res)
+			}
+		});
+
+		let mut last = None;
+		let mut result = Ok(());
+
+		for (name, track, res) in futures::future::join_all(tasks).await {
+			self.tracks.insert(name, track);
+
+			match res {
+				Ok(Some(max)) => last = Some(last.unwrap_or(max).max(max)),
+				Ok(None) => {}
+				Err(err) => result = Err(err),
+			}
+		}
+
+		if let Some(last) = last {
+			self.last = Some(last);
+			self.end = self.end.max(last.saturating_add_signed(offset));
+		}
+
+		result
+	}
+
+	// Publish the catalog, creating a track for any new renditions.
+	fn publish(&mut self, catalog: Catalog) {
+		if self.catalog.as_ref() == Some(&catalog) {
+			return;
+		}
+
+		let mut renditions = Vec::new();
+		if let Some(video) = &catalog.video {
+			renditions.extend(video.renditions.keys().map(|name| (name.clone(), video.priority)));
+		}
+		if let Some(audio) = &catalog.audio {
+			renditions.extend(audio.renditions.keys().map(|name| (name.clone(), audio.priority)));
+		}
+
+		for (name, priority) in renditions {
+			if !self.tracks.contains_key(&name) {
+				let track = moq_lite::Track {
+					name: name.clone(),
+					priority,
+				};
+				let producer = self.broadcast.create_track(track);
+				self.tracks.insert(name, Track::new(producer.into()));
+			}
+		}
+
+		// Only the media tracks are replayed, so skip chat and the preview.
+		let mut current = self.broadcast.catalog.lock();
+		current.video = catalog.video.clone();
+		current.audio = catalog.audio.clone();
+		current.user = catalog.user.clone();
+		drop(current);
+
+		self.catalog = Some(catalog);
+	}
+}
+
+/// An output track and the state needed to rebuild its groups.
+struct Track {
+	producer: hang::TrackProducer,
+
+	// The recorded group sequence currently being written.
+	group: Option<u64>,
+
+	// Recently finished groups, used to drop frames that arrive after a newer group started.
+	recent: VecDeque<u64>,
+
+	// Skip frames until the next group, after failing to write a keyframe.
+	skip: bool,
+}
+
+impl Track {
+	fn new(producer: hang::TrackProducer) -> Self {
+		Self {
+			producer,
+			group: None,
+			recent: VecDeque::new(),
+			skip: false,
+		}
+	}
+
+	fn reset(&mut self) {
+		self.group = None;
+		self.recent.clear();
+	}
+
+	// Write every frame in the file, returning the largest recorded timestamp.
+	async fn replay(&mut self, reader: &mut Reader, clock: Clock, offset: i64) -> anyhow::Result<Option<u64>> {
+		let mut max = None;
+
+		while let Some(record) = reader.next().await? {
+			let mut payload = record.payload;
+			let micros = u64::decode(&mut payload, lite::Version::Draft02)?;
+
+			let keyframe = match self.group {
+				Some(group) if group == record.group => false,
+				Some(group) if record.group < group && self.recent.contains(&record.group) => {
+					tracing::debug!(group = record.group, "dropping late frame");
+					continue;
+				}
+				_ => true,
+			};
+
+			if keyframe {
+				if let Some(group) = self.group.replace(record.group) {
+					self.recent.push_back(group);
+					if self.recent.len() > RECENT_GROUPS {
+						self.recent.pop_front();
+					}
+				}
+				self.skip = false;
+			} else if self.skip {
+				continue;
+			}
+
+			let timestamp = Timestamp::from_micros(micros.saturating_add_signed(offset))?;
+			clock.wait(timestamp).await;
+
+			max = Some(max.unwrap_or(micros).max(micros));
+
+			let frame = hang::Frame {
+				timestamp,
+				keyframe,
+				payload: payload.into(),
+			};
+
+			if let Err(err) = self.producer.write(frame) {
+				tracing::warn!(%err, group = record.group, "skipping group");
+				self.skip = true;
+			}
+		}
+
+		Ok(max)
+	}
+}
+
+/// A frame read from a `.moq` file.
+struct Record {
+	group: u64,
+	payload: Bytes,
+}
+
+/// Reads the records in a `.moq` file.
+struct Reader {
+	file: BufReader<tokio::fs::File>,
+	peeked: Option<Record>,
+}
+
+impl Reader {
+	async fn open(path: &Path) -> anyhow::Result<Self> {
+		let file = tokio::fs::File::open(path)
+			.await
+			.with_context(|| format!("failed to open {}", path.display()))?;
+		let mut file = BufReader::new(file);
+
+		let mut magic = [0u8; 8];
+		file.read_exact(&mut magic).await?;
+		anyhow::ensure!(&magic == MAGIC, "invalid recording: {}", path.display());
+
+		Ok(Self { file, peeked: None })
+	}
+
+	async fn peek(&mut self) -> anyhow::Result<Option<&Record>> {
+		if self.peeked.is_none() {
+			self.peeked = self.read().await?;
+		}
+
+		Ok(self.peeked.as_ref())
+	}
+
+	async fn next(&mut self) -> anyhow::Result<Option<Record>> {
+		match self.peeked.take() {
+			Some(record) => Ok(Some(record)),
+			None => self.read().await,
+		}
+	}
+
+	async fn read(&mut self) -> anyhow::Result<Option<Record>> {
+		// [group u64][received u64][size u32]
+		let mut header = [0u8; 20];
+
+		match self.file.read_exact(&mut header).await {
+			Ok(_) => {}
+			Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+			Err(err) => return Err(err.into()),
+		}
+
+		let group = u64::from_be_bytes(header[0..8].try_into().unwrap());
+		let size = u32::from_be_bytes(header[16..20].try_into().unwrap());
+
+		let mut payload = vec![0u8; size as usize];
+		match self.file.read_exact(&mut payload).await {
+			Ok(_) => {}
+			Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+				// The recorder was interrupted mid-write.
+				tracing::warn!("truncated recording");
+				return Ok(None);
+			}
+			Err(err) => return Err(err.into()),
+		}
+
+		Ok(Some(Record {
+			group,
+			payload: payload.into(),
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bytes::BufMut;
+	use moq_lite::coding::Encode;
+
+	fn record(buf: &mut Vec<u8>, group: u64, timestamp: u64, payload: &[u8]) {
+		let mut frame = Vec::new();
+		timestamp.encode(&mut frame, lite::Version::Draft02);
+		frame.extend_from_slice(payload);
+
+		buf.put_u64(group);
+		buf.put_u64(0);
+		buf.put_u32(frame.len() as u32);
+		buf.extend_from_slice(&frame);
+	}
+
+	#[tokio::test]
+	async fn test_replay() {
+		let dir = tempfile::tempdir().unwrap();
+		let segment = dir.path().join("1000");
+		std::fs::create_dir(&segment).unwrap();
+
+		let meta = r#"{"broadcast":"demo","started":1000,"tracks":{"catalog.json":0,"video0":1}}"#;
+		std::fs::write(segment.join("meta.json"), meta).unwrap();
+
+		let catalog = r#"{"video":{"renditions":{"video0":{"codec":"avc1.64001f"}},"priority":1}}"#;
+		let mut data = MAGIC.to_vec();
+		data.put_u64(0);
+		data.put_u64(0);
+		data.put_u32(catalog.len() as u32);
+		data.extend_from_slice(catalog.as_bytes());
+		std::fs::write(segment.join("0.moq"), data).unwrap();
+
+		// Two groups, with a late frame from the first group after the second started.
+		let mut data = MAGIC.to_vec();
+		record(&mut data, 5, 1_000_000, b"a");
+		record(&mut data, 5, 1_033_000, b"b");
+		record(&mut data, 6, 1_066_000, b"c");
+		record(&mut data, 5, 1_050_000, b"late");
+		record(&mut data, 6, 1_100_000, b"d");
+		std::fs::write(segment.join("1.moq"), data).unwrap();
+
+		let broadcast = moq_lite::Broadcast::produce();
+		let producer: hang::BroadcastProducer = broadcast.producer.into();
+		let mut catalog = producer.catalog.consume();
+
+		let replay = super::super::Replay::new(producer.clone(), dir.path());
+		let replay = tokio::spawn(replay.run());
+
+		let catalog = catalog.next().await.unwrap().unwrap();
+		assert!(catalog.video.unwrap().renditions.contains_key("video0"));
+
+		let track = broadcast.consumer.subscribe_track(&moq_lite::Track::new("video0"));
+		let mut track = hang::TrackConsumer::new(track);
+		track.set_latency(std::time::Duration::from_secs(1));
+
+		let mut frames = Vec::new();
+		while let Some(frame) = track.read_frame().await.unwrap() {
+			frames.push((frame.timestamp.as_micros(), frame.keyframe));
+		}
+
+		// Timestamps start at zero and the late frame is dropped.
+		assert_eq!(
+			frames,
+			vec![(0, true), (33_000, false), (66_000, true), (100_000, false)]
+		);
+
+		replay.await.unwrap().unwrap();
+	}
+}
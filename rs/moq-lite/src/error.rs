@@ -79,6 +79,10 @@ pub enum Error {
 
 	#[error("invalid role")]
 	InvalidRole,
+
+	/// A configured limit was exceeded, such as the number of subscriptions.
+	#[error("limit exceeded")]
+	LimitExceeded,
 }
 
 impl Error {
@@ -104,6 +108,7 @@ impl Error {
 			Self::TooLarge => 18,
 			Self::TooManyParameters => 19,
 			Self::InvalidRole => 20,
+			Self::LimitExceeded => 21,
 			Self::App(app) => *app + 64,
 		}
 	}
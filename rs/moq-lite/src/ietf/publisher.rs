@@ -79,7 +79,7 @@ impl<S: web_transport_trait::Session> Publisher<S> {
 
 		tracing::info!(id = %request_id, broadcast = %absolute, %track, "subscribed started");
 
-		let broadcast = match self.origin.consume_broadcast(&msg.track_namespace) {
+		let broadcast = match self.origin.request_broadcast(&msg.track_namespace) {
 			Some(consumer) => consumer,
 			None => {
 				self.control.send(ietf::SubscribeError {
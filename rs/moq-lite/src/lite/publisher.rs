@@ -150,7 +150,7 @@ impl<S: web_transport_trait::Session> Publisher<S> {
 
 		tracing::info!(%id, broadcast = %absolute, %track, "subscribed started");
 
-		let broadcast = self.origin.consume_broadcast(&subscribe.broadcast);
+		let broadcast = self.origin.request_broadcast(&subscribe.broadcast);
 		let priority = self.priority.clone();
 		let version = self.version;
 
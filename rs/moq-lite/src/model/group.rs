@@ -186,6 +186,11 @@ impl GroupConsumer {
 		Ok(Some(frame))
 	}
 
+	/// Returns true if the group was closed cleanly, so no more frames will be appended.
+	pub fn is_closed(&self) -> bool {
+		matches!(self.state.borrow().closed, Some(Ok(())))
+	}
+
 	/// Return a reader for the next frame.
 	pub async fn next_frame(&mut self) -> Result<Option<FrameConsumer>> {
 		// Just in case someone called read_frame, cancelled it, then called next_frame.
@@ -5,7 +5,7 @@ use std::{
 use tokio::sync::mpsc;
 use web_async::Lock;
 
-use super::BroadcastConsumer;
+use super::{Broadcast, BroadcastConsumer, BroadcastProducer};
 use crate::{AsPath, Path, PathOwned, Produce};
 
 static NEXT_CONSUMER_ID: AtomicU64 = AtomicU64::new(0);
@@ -248,9 +248,18 @@ impl OriginNode {
 	}
 }
 
+// Sends requests for broadcasts that haven't been announced to the [OriginDynamic] handler.
+struct DynamicNotify {
+	root: PathOwned,
+	tx: mpsc::UnboundedSender<OriginRequest>,
+}
+
 #[derive(Clone)]
 struct OriginNodes {
 	nodes: Vec<(PathOwned, Lock<OriginNode>)>,
+
+	// Shared by every producer and consumer of the same origin.
+	dynamic: Lock<Option<DynamicNotify>>,
 }
 
 impl OriginNodes {
@@ -278,7 +287,10 @@ impl OriginNodes {
 		if roots.is_empty() {
 			None
 		} else {
-			Some(Self { nodes: roots })
+			Some(Self {
+				nodes: roots,
+				dynamic: self.dynamic.clone(),
+			})
 		}
 	}
 
@@ -305,7 +317,10 @@ impl OriginNodes {
 		if roots.is_empty() {
 			None
 		} else {
-			Some(Self { nodes: roots })
+			Some(Self {
+				nodes: roots,
+				dynamic: self.dynamic.clone(),
+			})
 		}
 	}
 
@@ -327,6 +342,7 @@ impl Default for OriginNodes {
 	fn default() -> Self {
 		Self {
 			nodes: vec![("".into(), Lock::new(OriginNode::new(None)))],
+			dynamic: Default::default(),
 		}
 	}
 }
@@ -334,6 +350,23 @@ impl Default for OriginNodes {
 /// A broadcast path and its associated consumer, or None if closed.
 pub type OriginAnnounce = (PathOwned, Option<BroadcastConsumer>);
 
+/// A broadcast path that was requested but not announced, and the producer used to serve it.
+pub type OriginRequest = (PathOwned, BroadcastProducer);
+
+/// Receives requests for broadcasts that haven't been announced, created by [OriginProducer::dynamic].
+pub struct OriginDynamic {
+	requests: mpsc::UnboundedReceiver<OriginRequest>,
+}
+
+impl OriginDynamic {
+	/// Returns the next requested broadcast, relative to the root of the producer.
+	///
+	/// Dropping the [BroadcastProducer] without serving it will cancel any subscriptions.
+	pub async fn requested_broadcast(&mut self) -> Option<OriginRequest> {
+		self.requests.recv().await
+	}
+}
+
 pub struct Origin {}
 
 impl Origin {
@@ -421,6 +454,20 @@ impl OriginProducer {
 		})
 	}
 
+	/// Serve broadcasts on demand when they're requested via [OriginConsumer::request_broadcast] but not announced.
+	///
+	/// Only the most recent handler for an origin receives requests, and only those within this producer's root.
+	pub fn dynamic(&self) -> OriginDynamic {
+		let (tx, rx) = mpsc::unbounded_channel();
+
+		*self.nodes.dynamic.lock() = Some(DynamicNotify {
+			root: self.root.clone(),
+			tx,
+		});
+
+		OriginDynamic { requests: rx }
+	}
+
 	/// Returns the root that is automatically stripped from all paths.
 	pub fn root(&self) -> &Path<'_> {
 		&self.root
@@ -505,6 +552,30 @@ impl OriginConsumer {
 		state.consume_broadcast(&rest)
 	}
 
+	/// Get a specific broadcast by path, requesting it from the [OriginDynamic] handler if it hasn't been announced.
+	///
+	/// Returns None if the path is not allowed, or if it hasn't been announced and there's no handler.
+	pub fn request_broadcast(&self, path: impl AsPath) -> Option<BroadcastConsumer> {
+		let path = path.as_path();
+
+		// Make sure we're allowed to consume the path.
+		let (root, rest) = self.nodes.get(&path)?;
+		if let Some(broadcast) = root.lock().consume_broadcast(&rest) {
+			return Some(broadcast);
+		}
+
+		let dynamic = self.nodes.dynamic.lock();
+		let dynamic = dynamic.as_ref()?;
+
+		let full = self.root.join(&path);
+		let relative = full.strip_prefix(&dynamic.root)?.to_owned();
+
+		let broadcast = Broadcast::produce();
+		dynamic.tx.send((relative, broadcast.producer)).ok()?;
+
+		Some(broadcast.consumer)
+	}
+
 	/// Returns a new OriginConsumer that only consumes broadcasts matching one of the prefixes.
 	///
 	/// Returns None if there are no legal prefixes (would always return None).
@@ -1026,6 +1097,44 @@ mod tests {
 		assert_eq!(foo_producer.root().as_str(), "foo");
 	}
 
+	#[tokio::test]
+	async fn test_request_broadcast() {
+		let origin = Origin::produce();
+		let consumer = origin.consumer.consume_only(&["allowed".into()]).unwrap();
+
+		// Without a handler, only announced broadcasts are returned.
+		assert!(consumer.request_broadcast("allowed/test").is_none());
+
+		let mut dynamic = origin.producer.with_root("allowed").unwrap().dynamic();
+
+		// Announced broadcasts are returned without a request.
+		let announced = Broadcast::produce();
+		origin
+			.producer
+			.publish_broadcast("allowed/announced", announced.consumer.clone());
+		let result = consumer.request_broadcast("allowed/announced").unwrap();
+		assert!(result.is_clone(&announced.consumer));
+
+		// Otherwise the handler receives the path relative to its root.
+		let requested = consumer.request_broadcast("allowed/test").unwrap();
+		let (path, mut producer) = dynamic.requested_broadcast().now_or_never().unwrap().unwrap();
+		assert_eq!(path.as_str(), "test");
+
+		// Subscriptions are served by the handler.
+		let _track = requested.subscribe_track(&crate::Track::new("video"));
+		assert_eq!(producer.assert_request().info.name, "video");
+
+		// Requests outside of the allowed prefixes are rejected.
+		assert!(consumer.request_broadcast("notallowed/test").is_none());
+
+		// The handler only receives requests within its root.
+		assert!(origin.consumer.request_broadcast("other/test").is_none());
+
+		// Requests fail once the handler is dropped.
+		drop(dynamic);
+		assert!(consumer.request_broadcast("allowed/test").is_none());
+	}
+
 	#[tokio::test]
 	async fn test_consume_broadcast_with_permissions() {
 		let origin = Origin::produce();
@@ -1,6 +1,6 @@
 use crate::crypto;
 use anyhow::Context;
-use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
 use rustls::RootCertStore;
 use std::path::PathBuf;
 use std::{fs, io, net, sync::Arc, time};
@@ -28,6 +28,28 @@ pub struct ClientTls {
 		action = clap::ArgAction::SetTrue
 	)]
 	pub disable_verify: Option<bool>,
+
+	/// Present the certificate chain at this path to the server, encoded as PEM.
+	///
+	/// This is used for mutual TLS, for example to authenticate relays to each other.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	#[arg(
+		id = "client-tls-cert",
+		long = "client-tls-cert",
+		env = "MOQ_CLIENT_TLS_CERT",
+		requires = "client-tls-key"
+	)]
+	pub cert: Option<PathBuf>,
+
+	/// The private key for the client certificate, encoded as PEM.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	#[arg(
+		id = "client-tls-key",
+		long = "client-tls-key",
+		env = "MOQ_CLIENT_TLS_KEY",
+		requires = "client-tls-cert"
+	)]
+	pub key: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, clap::Parser, serde::Serialize, serde::Deserialize)]
@@ -104,10 +126,21 @@ impl Client {
 		}
 
 		// Create the TLS configuration we'll use as a client (relay -> relay)
-		let mut tls = rustls::ClientConfig::builder_with_provider(provider.clone())
+		let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
 			.with_protocol_versions(&[&rustls::version::TLS13])?
-			.with_root_certificates(roots)
-			.with_no_client_auth();
+			.with_root_certificates(roots);
+
+		// Optionally present a client certificate for mutual TLS.
+		let mut tls = match (&config.tls.cert, &config.tls.key) {
+			(Some(cert), Some(key)) => {
+				let (chain, key) = Self::load_cert(cert, key)?;
+				builder
+					.with_client_auth_cert(chain, key)
+					.context("invalid client certificate")?
+			}
+			(None, None) => builder.with_no_client_auth(),
+			_ => anyhow::bail!("must provide both client cert and key"),
+		};
 
 		// Allow disabling TLS verification altogether.
 		if config.tls.disable_verify.unwrap_or_default() {
@@ -138,6 +171,32 @@ impl Client {
 		Ok(Self { quic, tls, transport })
 	}
 
+	fn load_cert(
+		chain: &PathBuf,
+		key: &PathBuf,
+	) -> anyhow::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+		let chain = fs::File::open(chain).context("failed to open client cert file")?;
+		let mut chain = io::BufReader::new(chain);
+
+		let chain: Vec<CertificateDer> = rustls_pemfile::certs(&mut chain)
+			.collect::<Result<_, _>>()
+			.context("failed to read client certs")?;
+
+		anyhow::ensure!(!chain.is_empty(), "could not find client certificate");
+
+		let key = fs::File::open(key).context("failed to open client key file")?;
+		let mut key = io::BufReader::new(key);
+
+		let key = rustls_pemfile::private_key(&mut key)?.context("missing client private key")?;
+
+		Ok((chain, key))
+	}
+
+	/// Returns true if a client certificate is presented to servers for mutual TLS.
+	pub fn has_client_cert(&self) -> bool {
+		self.tls.client_auth_cert_resolver.has_certs()
+	}
+
 	pub async fn connect(&self, mut url: Url) -> anyhow::Result<web_transport_quinn::Session> {
 		let mut config = self.tls.clone();
 
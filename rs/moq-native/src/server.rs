@@ -4,8 +4,9 @@ use std::{net, sync::Arc, time::Duration};
 use crate::crypto;
 use anyhow::Context;
 use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
-use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
 use rustls::sign::CertifiedKey;
+use rustls::RootCertStore;
 use std::fs;
 use std::io::{self, Cursor, Read};
 use url::Url;
@@ -56,6 +57,13 @@ pub struct ServerTlsConfig {
 	)]
 	#[serde(default, skip_serializing_if = "Vec::is_empty")]
 	pub generate: Vec<String>,
+
+	/// Request a client certificate and verify it against the roots at these paths, encoded as PEM.
+	///
+	/// Clients without a certificate are still accepted; use [Request::peer_certificates] to check.
+	#[arg(long = "tls-client-root", id = "tls-client-root", env = "MOQ_SERVER_TLS_CLIENT_ROOT")]
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub client_root: Vec<PathBuf>,
 }
 
 #[derive(clap::Args, Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
@@ -115,10 +123,34 @@ impl Server {
 
 		let fingerprints = serve.fingerprints();
 
-		let mut tls = rustls::ServerConfig::builder_with_provider(provider)
-			.with_protocol_versions(&[&rustls::version::TLS13])?
-			.with_no_client_auth()
-			.with_cert_resolver(Arc::new(serve));
+		let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+			.with_protocol_versions(&[&rustls::version::TLS13])?;
+
+		// Optionally verify client certificates, used for mutual TLS between relays.
+		let builder = if config.tls.client_root.is_empty() {
+			builder.with_no_client_auth()
+		} else {
+			let mut roots = RootCertStore::empty();
+
+			for root in &config.tls.client_root {
+				let root = fs::File::open(root).context("failed to open client root cert file")?;
+				let mut root = io::BufReader::new(root);
+
+				for cert in rustls_pemfile::certs(&mut root) {
+					let cert = cert.context("failed to read client root cert")?;
+					roots.add(cert).context("failed to add client root cert")?;
+				}
+			}
+
+			let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+				.allow_unauthenticated()
+				.build()
+				.context("failed to build client verifier")?;
+
+			builder.with_client_cert_verifier(verifier)
+		};
+
+		let mut tls = builder.with_cert_resolver(Arc::new(serve));
 
 		tls.alpn_protocols = vec![
 			web_transport_quinn::ALPN.as_bytes().to_vec(),
@@ -210,10 +242,12 @@ impl Server {
 		match alpn.as_str() {
 			web_transport_quinn::ALPN => {
 				// Wait for the CONNECT request.
+				let remote = conn.remote_address();
+				let certs = peer_certificates(&conn);
 				let request = web_transport_quinn::Request::accept(conn)
 					.await
 					.context("failed to receive WebTransport request")?;
-				Ok(Request::WebTransport(request))
+				Ok(Request::WebTransport(WebTransportRequest { request, remote, certs }))
 			}
 			moq_lite::lite::ALPN | moq_lite::ietf::ALPN => Ok(Request::Quic(QuicRequest::accept(conn))),
 			_ => anyhow::bail!("unsupported ALPN: {alpn}"),
@@ -230,7 +264,7 @@ impl Server {
 }
 
 pub enum Request {
-	WebTransport(web_transport_quinn::Request),
+	WebTransport(WebTransportRequest),
 	Quic(QuicRequest),
 }
 
@@ -264,11 +298,71 @@ impl Request {
 			Request::Quic(request) => request.url(),
 		}
 	}
+
+	/// Returns the IP address and port of the client.
+	pub fn remote_address(&self) -> net::SocketAddr {
+		match self {
+			Request::WebTransport(request) => request.remote_address(),
+			Request::Quic(request) => request.remote_address(),
+		}
+	}
+
+	/// Returns the client's certificate chain, if it presented one.
+	///
+	/// The chain has already been verified against [ServerTlsConfig::client_root].
+	pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+		match self {
+			Request::WebTransport(request) => request.peer_certificates(),
+			Request::Quic(request) => request.peer_certificates(),
+		}
+	}
+}
+
+// Returns the verified client certificate chain, if any.
+fn peer_certificates(conn: &quinn::Connection) -> Option<Vec<CertificateDer<'static>>> {
+	conn.peer_identity()?
+		.downcast::<Vec<CertificateDer<'static>>>()
+		.ok()
+		.map(|certs| *certs)
+}
+
+pub struct WebTransportRequest {
+	request: web_transport_quinn::Request,
+	remote: net::SocketAddr,
+	certs: Option<Vec<CertificateDer<'static>>>,
+}
+
+impl WebTransportRequest {
+	/// Accept the session, returning a 200 OK.
+	pub async fn ok(self) -> Result<web_transport_quinn::Session, ServerError> {
+		self.request.ok().await
+	}
+
+	/// Returns the URL provided by the client.
+	pub fn url(&self) -> &Url {
+		self.request.url()
+	}
+
+	/// Returns the IP address and port of the client.
+	pub fn remote_address(&self) -> net::SocketAddr {
+		self.remote
+	}
+
+	/// Returns the client's certificate chain, if it presented one.
+	pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+		self.certs.as_deref()
+	}
+
+	/// Reject the session, returning your favorite HTTP status code.
+	pub async fn close(self, status: http::StatusCode) -> Result<(), ServerError> {
+		self.request.close(status).await
+	}
 }
 
 pub struct QuicRequest {
 	connection: quinn::Connection,
 	url: Url,
+	certs: Option<Vec<CertificateDer<'static>>>,
 }
 
 impl QuicRequest {
@@ -277,7 +371,8 @@ impl QuicRequest {
 		let url: Url = format!("moql://{}", connection.remote_address())
 			.parse()
 			.expect("URL is valid");
-		Self { connection, url }
+		let certs = peer_certificates(&connection);
+		Self { connection, url, certs }
 	}
 
 	/// Accept the session, returning a 200 OK if using WebTransport.
@@ -290,6 +385,16 @@ impl QuicRequest {
 		&self.url
 	}
 
+	/// Returns the IP address and port of the client.
+	pub fn remote_address(&self) -> net::SocketAddr {
+		self.connection.remote_address()
+	}
+
+	/// Returns the client's certificate chain, if it presented one.
+	pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+		self.certs.as_deref()
+	}
+
 	/// Reject the session with a status code.
 	///
 	/// The status code number will be used as the error code.
@@ -0,0 +1,602 @@
+use std::{
+	net,
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::SystemTime,
+};
+
+use bytes::{BufMut, Bytes};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	io::{AsyncWrite, AsyncWriteExt},
+	sync::mpsc,
+	time::Instant,
+};
+
+use crate::{AuthToken, Hook, Usage};
+
+// The maximum number of events waiting to be written, after which new events are dropped.
+const QUEUE_SIZE: usize = 4096;
+
+/// Structured audit events, written as JSON lines.
+#[derive(clap::Args, Clone, Debug, Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+	/// Append audit events to the given file as JSON lines, or `-` for stdout.
+	/// Events are not recorded by default.
+	#[arg(id = "audit-log", long = "audit-log", env = "MOQ_AUDIT_LOG")]
+	pub log: Option<PathBuf>,
+}
+
+impl AuditConfig {
+	pub fn init(self) -> anyhow::Result<Audit> {
+		Audit::new(self)
+	}
+}
+
+/// An event written to the audit log.
+///
+/// Each event is tagged with `event`, ex. `{"event":"publish_start","broadcast":"demo",...}`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+	/// The session was authorized, with the paths it may use.
+	SessionStart {
+		ip: net::IpAddr,
+		cluster: bool,
+		publish: Vec<String>,
+		subscribe: Vec<String>,
+	},
+	/// The session was closed, with the bytes transferred over its lifetime.
+	SessionStop {
+		duration_ms: u64,
+		bytes_sent: u64,
+		bytes_received: u64,
+	},
+	/// The session announced a broadcast.
+	PublishStart { broadcast: String },
+	/// The session's broadcast was unannounced or the session was closed, with the groups and bytes it served.
+	PublishStop {
+		broadcast: String,
+		duration_ms: u64,
+		groups: u64,
+		bytes: u64,
+	},
+	/// The session subscribed to a track.
+	SubscribeStart { broadcast: String, track: String },
+	/// The session's subscription ended, with the groups and bytes delivered.
+	SubscribeStop {
+		broadcast: String,
+		track: String,
+		duration_ms: u64,
+		groups: u64,
+		bytes: u64,
+	},
+}
+
+// The fields shared by every event in a session.
+#[derive(Serialize)]
+struct Entry<'a> {
+	timestamp: String,
+	session: &'a str,
+	root: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	subject: Option<&'a str>,
+	#[serde(flatten)]
+	event: AuditEvent,
+}
+
+/// Writes audit events to the configured sink, shared between all connections.
+///
+/// Does nothing when disabled.
+#[derive(Clone, Default)]
+pub struct Audit {
+	sink: Option<Sink>,
+}
+
+// A bounded queue of encoded events, counting any that didn't fit.
+#[derive(Clone)]
+struct Sink {
+	queue: mpsc::Sender<String>,
+	dropped: Arc<AtomicU64>,
+}
+
+impl Sink {
+	fn send(&self, line: String) {
+		match self.queue.try_send(line) {
+			Ok(()) => {}
+			// Never block a session on a slow writer; the count is written to the log instead.
+			Err(mpsc::error::TrySendError::Full(_)) => {
+				self.dropped.fetch_add(1, Ordering::Relaxed);
+			}
+			// Only if the writer task has exited.
+			Err(mpsc::error::TrySendError::Closed(_)) => {}
+		}
+	}
+}
+
+impl Audit {
+	pub fn new(config: AuditConfig) -> anyhow::Result<Self> {
+		let output: Box<dyn AsyncWrite + Unpin + Send> = match config.log {
+			None => return Ok(Self::default()),
+			Some(path) if path.as_os_str() == "-" => Box::new(tokio::io::stdout()),
+			Some(path) => {
+				let file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+				Box::new(tokio::fs::File::from_std(file))
+			}
+		};
+
+		let (queue, rx) = mpsc::channel(QUEUE_SIZE);
+		let sink = Sink {
+			queue,
+			dropped: Default::default(),
+		};
+		tokio::spawn(Self::run(rx, sink.dropped.clone(), output));
+
+		Ok(Self { sink: Some(sink) })
+	}
+
+	/// Start auditing a session, emitting `session_start` now and `session_stop` when dropped.
+	pub fn session(&self, ip: net::IpAddr, token: &AuthToken) -> SessionAudit {
+		let log = AuditLog {
+			sink: self.sink.clone(),
+			session: Arc::new(Session {
+				// Random so it's unique across restarts and cluster nodes.
+				id: format!("{:032x}", rand::random::<u128>()),
+				root: token.root.to_string(),
+				subject: token.subject.clone(),
+			}),
+		};
+
+		log.emit(AuditEvent::SessionStart {
+			ip: ip.to_canonical(),
+			cluster: token.cluster,
+			publish: token.publish.iter().map(|path| path.to_string()).collect(),
+			subscribe: token.subscribe.iter().map(|path| path.to_string()).collect(),
+		});
+
+		SessionAudit {
+			log,
+			started: Instant::now(),
+			counters: Default::default(),
+		}
+	}
+
+	async fn run(
+		mut rx: mpsc::Receiver<String>,
+		dropped: Arc<AtomicU64>,
+		mut output: Box<dyn AsyncWrite + Unpin + Send>,
+	) {
+		while let Some(line) = rx.recv().await {
+			let mut buf = line.into_bytes();
+
+			// Batch any other queued events into the same write.
+			while let Ok(line) = rx.try_recv() {
+				buf.put_slice(line.as_bytes());
+			}
+
+			// Note any events that were dropped because the queue was full.
+			let count = dropped.swap(0, Ordering::Relaxed);
+			if count > 0 {
+				tracing::warn!(%count, "audit log queue is full, dropped events");
+				buf.put_slice(dropped_line(count).as_bytes());
+			}
+
+			let res = async {
+				output.write_all(&buf).await?;
+				output.flush().await
+			};
+
+			if let Err(err) = res.await {
+				tracing::warn!(%err, "failed to write audit log");
+			}
+		}
+	}
+}
+
+// An event without a session, written when events were dropped.
+fn dropped_line(count: u64) -> String {
+	let entry = serde_json::json!({
+		"timestamp": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+		"event": "dropped",
+		"count": count,
+	});
+
+	format!("{entry}\n")
+}
+
+// The identity of a session, included in every event.
+struct Session {
+	id: String,
+	root: String,
+	subject: Option<String>,
+}
+
+#[derive(Clone)]
+struct AuditLog {
+	sink: Option<Sink>,
+	session: Arc<Session>,
+}
+
+impl AuditLog {
+	fn emit(&self, event: AuditEvent) {
+		let Some(sink) = &self.sink else {
+			return;
+		};
+
+		let entry = Entry {
+			timestamp: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+			session: &self.session.id,
+			root: &self.session.root,
+			subject: self.session.subject.as_deref(),
+			event,
+		};
+
+		let mut line = serde_json::to_string(&entry).expect("failed to encode audit event");
+		line.push('\n');
+
+		sink.send(line);
+	}
+
+	fn enabled(&self) -> bool {
+		self.sink.is_some()
+	}
+}
+
+impl Hook for AuditLog {
+	fn publish(&self, broadcast: &str) -> Result<(), moq_lite::Error> {
+		self.emit(AuditEvent::PublishStart {
+			broadcast: broadcast.to_string(),
+		});
+		Ok(())
+	}
+
+	fn unpublish(&self, broadcast: &str, usage: Usage) {
+		self.emit(AuditEvent::PublishStop {
+			broadcast: broadcast.to_string(),
+			duration_ms: usage.duration.as_millis() as u64,
+			groups: usage.groups,
+			bytes: usage.bytes,
+		});
+	}
+
+	fn subscribe(&self, broadcast: &str, track: &str) -> Result<(), moq_lite::Error> {
+		self.emit(AuditEvent::SubscribeStart {
+			broadcast: broadcast.to_string(),
+			track: track.to_string(),
+		});
+		Ok(())
+	}
+
+	fn unsubscribe(&self, broadcast: &str, track: &str, usage: Usage) {
+		self.emit(AuditEvent::SubscribeStop {
+			broadcast: broadcast.to_string(),
+			track: track.to_string(),
+			duration_ms: usage.duration.as_millis() as u64,
+			groups: usage.groups,
+			bytes: usage.bytes,
+		});
+	}
+}
+
+#[derive(Default)]
+struct Counters {
+	sent: AtomicU64,
+	received: AtomicU64,
+}
+
+/// The audit trail for a single session, emitting `session_stop` on drop.
+pub struct SessionAudit {
+	log: AuditLog,
+	started: Instant,
+	counters: Arc<Counters>,
+}
+
+impl SessionAudit {
+	/// The random ID included in every event for this session.
+	pub fn id(&self) -> &str {
+		&self.log.session.id
+	}
+
+	/// Emit the publish and subscribe events via [Intercept], or None if the audit log is disabled.
+	pub fn hook(&self) -> Option<Arc<dyn Hook>> {
+		self.log.enabled().then(|| Arc::new(self.log.clone()) as Arc<dyn Hook>)
+	}
+
+	/// Count the bytes sent and received by the session, reported in `session_stop`.
+	pub fn meter<S: web_transport_trait::Session>(&self, session: S) -> Meter<S> {
+		Meter {
+			inner: session,
+			counters: self.counters.clone(),
+		}
+	}
+}
+
+impl Drop for SessionAudit {
+	fn drop(&mut self) {
+		self.log.emit(AuditEvent::SessionStop {
+			duration_ms: self.started.elapsed().as_millis() as u64,
+			bytes_sent: self.counters.sent.load(Ordering::Relaxed),
+			bytes_received: self.counters.received.load(Ordering::Relaxed),
+		});
+	}
+}
+
+/// Counts the bytes sent and received by a session, including datagrams.
+#[derive(Clone)]
+pub struct Meter<S> {
+	inner: S,
+	counters: Arc<Counters>,
+}
+
+impl<S> Meter<S> {
+	fn send<T>(&self, inner: T) -> MeterSend<T> {
+		MeterSend {
+			inner,
+			counters: self.counters.clone(),
+		}
+	}
+
+	fn recv<T>(&self, inner: T) -> MeterRecv<T> {
+		MeterRecv {
+			inner,
+			counters: self.counters.clone(),
+		}
+	}
+}
+
+impl<S: web_transport_trait::Session> web_transport_trait::Session for Meter<S> {
+	type SendStream = MeterSend<S::SendStream>;
+	type RecvStream = MeterRecv<S::RecvStream>;
+	type Error = S::Error;
+
+	async fn accept_uni(&self) -> Result<Self::RecvStream, Self::Error> {
+		let recv = self.inner.accept_uni().await?;
+		Ok(self.recv(recv))
+	}
+
+	async fn accept_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), Self::Error> {
+		let (send, recv) = self.inner.accept_bi().await?;
+		Ok((self.send(send), self.recv(recv)))
+	}
+
+	async fn open_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), Self::Error> {
+		let (send, recv) = self.inner.open_bi().await?;
+		Ok((self.send(send), self.recv(recv)))
+	}
+
+	async fn open_uni(&self) -> Result<Self::SendStream, Self::Error> {
+		let send = self.inner.open_uni().await?;
+		Ok(self.send(send))
+	}
+
+	fn send_datagram(&self, payload: Bytes) -> Result<(), Self::Error> {
+		let size = payload.len() as u64;
+		self.inner.send_datagram(payload)?;
+		self.counters.sent.fetch_add(size, Ordering::Relaxed);
+		Ok(())
+	}
+
+	async fn recv_datagram(&self) -> Result<Bytes, Self::Error> {
+		let payload = self.inner.recv_datagram().await?;
+		self.counters
+			.received
+			.fetch_add(payload.len() as u64, Ordering::Relaxed);
+		Ok(payload)
+	}
+
+	fn max_datagram_size(&self) -> usize {
+		self.inner.max_datagram_size()
+	}
+
+	fn close(&self, code: u32, reason: &str) {
+		self.inner.close(code, reason)
+	}
+
+	async fn closed(&self) -> Self::Error {
+		self.inner.closed().await
+	}
+}
+
+pub struct MeterSend<T> {
+	inner: T,
+	counters: Arc<Counters>,
+}
+
+impl<T: web_transport_trait::SendStream> web_transport_trait::SendStream for MeterSend<T> {
+	type Error = T::Error;
+
+	async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+		let size = self.inner.write(buf).await?;
+		self.counters.sent.fetch_add(size as u64, Ordering::Relaxed);
+		Ok(size)
+	}
+
+	fn set_priority(&mut self, order: u8) {
+		self.inner.set_priority(order)
+	}
+
+	fn finish(&mut self) -> Result<(), Self::Error> {
+		self.inner.finish()
+	}
+
+	fn reset(&mut self, code: u32) {
+		self.inner.reset(code)
+	}
+
+	async fn closed(&mut self) -> Result<(), Self::Error> {
+		self.inner.closed().await
+	}
+}
+
+pub struct MeterRecv<T> {
+	inner: T,
+	counters: Arc<Counters>,
+}
+
+impl<T: web_transport_trait::RecvStream> web_transport_trait::RecvStream for MeterRecv<T> {
+	type Error = T::Error;
+
+	async fn read(&mut self, dst: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+		let size = self.inner.read(dst).await?;
+		if let Some(size) = size {
+			self.counters.received.fetch_add(size as u64, Ordering::Relaxed);
+		}
+		Ok(size)
+	}
+
+	async fn read_chunk(&mut self, max: usize) -> Result<Option<Bytes>, Self::Error> {
+		// Delegate so we don't lose any zero-copy optimizations.
+		let chunk = self.inner.read_chunk(max).await?;
+		if let Some(chunk) = &chunk {
+			self.counters.received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+		}
+		Ok(chunk)
+	}
+
+	fn stop(&mut self, code: u32) {
+		self.inner.stop(code)
+	}
+
+	async fn closed(&mut self) -> Result<(), Self::Error> {
+		self.inner.closed().await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Intercept;
+	use moq_lite::{Broadcast, Origin};
+
+	fn audit(size: usize) -> (Audit, mpsc::Receiver<String>) {
+		let (queue, rx) = mpsc::channel(size);
+		let sink = Sink {
+			queue,
+			dropped: Default::default(),
+		};
+
+		(Audit { sink: Some(sink) }, rx)
+	}
+
+	fn token() -> AuthToken {
+		AuthToken {
+			root: moq_lite::Path::new("demo").to_owned(),
+			subject: Some("alice".to_string()),
+			subscribe: vec![moq_lite::Path::new("").to_owned()],
+			publish: vec![moq_lite::Path::new("").to_owned()],
+			cluster: false,
+		}
+	}
+
+	async fn next(rx: &mut mpsc::Receiver<String>) -> serde_json::Value {
+		let line = tokio::time::timeout(std::time::Duration::from_secs(1), rx.recv())
+			.await
+			.expect("timed out")
+			.expect("closed");
+		assert!(line.ends_with('\n'));
+		serde_json::from_str(&line).unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_events() {
+		let (audit, mut rx) = audit(QUEUE_SIZE);
+
+		let session = audit.session("::ffff:127.0.0.1".parse().unwrap(), &token());
+		let id = session.id().to_string();
+
+		let event = next(&mut rx).await;
+		assert_eq!(event["event"], "session_start");
+		assert_eq!(event["session"], id.as_str());
+		assert_eq!(event["root"], "demo");
+		assert_eq!(event["subject"], "alice");
+		assert_eq!(event["ip"], "127.0.0.1");
+		assert!(event["timestamp"].as_str().unwrap().ends_with('Z'));
+
+		// Publish a broadcast through the audited origin.
+		let origin = Origin::produce();
+		let root = origin.producer.with_root("demo").unwrap();
+		let mut intercept = Intercept::new([session.hook()]);
+		let publisher = intercept.publisher(Some(root.clone())).unwrap();
+
+		let mut broadcast = Broadcast::produce();
+		publisher.publish_broadcast("room", broadcast.consumer.clone());
+
+		let event = next(&mut rx).await;
+		assert_eq!(event["event"], "publish_start");
+		assert_eq!(event["broadcast"], "demo/room");
+		assert_eq!(event["session"], id.as_str());
+
+		// Subscribe to a track through the audited origin.
+		let mut subscriber = intercept.subscriber(Some(root.consume())).unwrap();
+		let (path, proxy) = subscriber.announced().await.unwrap();
+		assert_eq!(path.as_str(), "room");
+
+		let mut track = proxy.unwrap().subscribe_track(&moq_lite::Track::new("video"));
+
+		let event = next(&mut rx).await;
+		assert_eq!(event["event"], "subscribe_start");
+		assert_eq!(event["broadcast"], "demo/room");
+		assert_eq!(event["track"], "video");
+
+		// Serve the track upstream and write a group.
+		let mut upstream = broadcast.producer.requested_track().await.unwrap();
+		upstream.write_frame(Bytes::from_static(b"hello"));
+
+		let mut group = track.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+
+		drop(group);
+		drop(track);
+
+		let event = next(&mut rx).await;
+		assert_eq!(event["event"], "subscribe_stop");
+		assert_eq!(event["track"], "video");
+		assert_eq!(event["groups"], 1);
+		assert_eq!(event["bytes"], 5);
+
+		// Unannounce the broadcast.
+		broadcast.producer.close();
+		drop(broadcast);
+		drop(upstream);
+
+		let event = next(&mut rx).await;
+		assert_eq!(event["event"], "publish_stop");
+		assert_eq!(event["broadcast"], "demo/room");
+		assert_eq!(event["groups"], 1);
+		assert_eq!(event["bytes"], 5);
+
+		drop(intercept);
+		drop(session);
+
+		let event = next(&mut rx).await;
+		assert_eq!(event["event"], "session_stop");
+		assert_eq!(event["session"], id.as_str());
+		assert_eq!(event["bytes_sent"], 0);
+	}
+
+	#[tokio::test]
+	async fn test_dropped() {
+		let (audit, mut rx) = audit(1);
+
+		// The queue only fits the first event.
+		let session = audit.session("127.0.0.1".parse().unwrap(), &token());
+		drop(session);
+		drop(audit.session("127.0.0.1".parse().unwrap(), &token()));
+
+		let sink = audit.sink.as_ref().unwrap();
+		assert_eq!(sink.dropped.load(Ordering::Relaxed), 3);
+
+		let event = next(&mut rx).await;
+		assert_eq!(event["event"], "session_start");
+		assert!(rx.try_recv().is_err());
+
+		let line = dropped_line(3);
+		let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+		assert_eq!(event["event"], "dropped");
+		assert_eq!(event["count"], 3);
+	}
+}
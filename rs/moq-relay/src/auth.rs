@@ -1,8 +1,14 @@
-use std::sync::Arc;
+use std::{net, sync::Arc};
+
+use anyhow::Context;
 
 use axum::http;
 use moq_lite::{AsPath, Path, PathOwned};
+use moq_native::rustls::pki_types::{CertificateDer, ServerName};
 use serde::{Deserialize, Serialize};
+use url::{Host, Url};
+
+use crate::{Webhook, WebhookConfig};
 
 #[derive(thiserror::Error, Debug, Clone)]
 pub enum AuthError {
@@ -17,17 +23,29 @@ pub enum AuthError {
 
 	#[error("the path does not match the root")]
 	IncorrectRoot,
+
+	#[error("access was denied by the webhook")]
+	Denied,
+
+	#[error("the webhook is unavailable")]
+	WebhookFailed,
+
+	#[error("the client certificate is not for a cluster node")]
+	UnknownNode,
 }
 
 impl From<AuthError> for http::StatusCode {
-	fn from(_: AuthError) -> Self {
-		http::StatusCode::UNAUTHORIZED
+	fn from(err: AuthError) -> Self {
+		match err {
+			AuthError::WebhookFailed => http::StatusCode::SERVICE_UNAVAILABLE,
+			_ => http::StatusCode::UNAUTHORIZED,
+		}
 	}
 }
 
 impl axum::response::IntoResponse for AuthError {
 	fn into_response(self) -> axum::response::Response {
-		http::StatusCode::UNAUTHORIZED.into_response()
+		http::StatusCode::from(self).into_response()
 	}
 }
 
@@ -44,6 +62,18 @@ pub struct AuthConfig {
 	/// If a user provides a token, then they can only access the prefix only if it is specified in the token.
 	#[arg(long = "auth-public", env = "MOQ_AUTH_PUBLIC")]
 	pub public: Option<String>,
+
+	/// The hostnames of cluster nodes that may authenticate with a client certificate.
+	/// The certificate must be valid for one of these names; wildcard certificates are supported.
+	/// The --cluster-root, --cluster-node, and --cluster-peer hostnames are always included.
+	#[arg(long = "auth-cluster-name", env = "MOQ_AUTH_CLUSTER_NAME", value_delimiter = ',')]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub cluster: Vec<String>,
+
+	/// Optionally authorize clients with an external HTTP endpoint.
+	#[command(flatten)]
+	#[serde(default)]
+	pub webhook: WebhookConfig,
 }
 
 impl AuthConfig {
@@ -55,6 +85,7 @@ impl AuthConfig {
 #[derive(Debug)]
 pub struct AuthToken {
 	pub root: PathOwned,
+	pub subject: Option<String>,
 	pub subscribe: Vec<PathOwned>,
 	pub publish: Vec<PathOwned>,
 	pub cluster: bool,
@@ -64,6 +95,8 @@ pub struct AuthToken {
 pub struct Auth {
 	key: Option<Arc<moq_token::Key>>,
 	public: Option<PathOwned>,
+	webhook: Option<Arc<Webhook>>,
+	cluster: Arc<Vec<ServerName<'static>>>,
 }
 
 impl Auth {
@@ -74,16 +107,58 @@ impl Auth {
 		};
 
 		let public = config.public;
+		let webhook = Webhook::new(config.webhook)?;
 
 		match (&key, &public) {
-			(None, None) => anyhow::bail!("no root key or public path configured"),
+			(None, None) if webhook.is_none() => anyhow::bail!("no root key, public path, or webhook configured"),
 			(Some(_), Some(public)) if public.is_empty() => anyhow::bail!("root key but fully public access"),
 			_ => (),
 		}
 
+		let cluster = config
+			.cluster
+			.iter()
+			.map(|name| Self::server_name(name))
+			.collect::<anyhow::Result<_>>()?;
+
 		Ok(Self {
 			key: key.map(Arc::new),
 			public: public.map(|p| p.as_path().to_owned()),
+			webhook: webhook.map(Arc::new),
+			cluster: Arc::new(cluster),
+		})
+	}
+
+	// Parse a cluster hostname, which may include a port.
+	fn server_name(name: &str) -> anyhow::Result<ServerName<'static>> {
+		let url = Url::parse(&format!("https://{name}/")).context("invalid cluster name")?;
+		Ok(match url.host().context("missing cluster hostname")? {
+			Host::Domain(domain) => ServerName::try_from(domain.to_string()).context("invalid cluster hostname")?,
+			Host::Ipv4(ip) => ServerName::IpAddress(net::IpAddr::V4(ip).into()),
+			Host::Ipv6(ip) => ServerName::IpAddress(net::IpAddr::V6(ip).into()),
+		})
+	}
+
+	// Grant full access to another cluster node, which was authenticated with a client certificate.
+	// The chain was verified against --tls-client-root, but the certificate must also be for a known node.
+	pub fn verify_cluster(&self, path: &str, certs: &[CertificateDer]) -> Result<AuthToken, AuthError> {
+		let cert = certs.first().ok_or(AuthError::UnknownNode)?;
+		let cert = webpki::EndEntityCert::try_from(cert).map_err(|_| AuthError::UnknownNode)?;
+
+		if !self
+			.cluster
+			.iter()
+			.any(|name| cert.verify_is_valid_for_subject_name(name).is_ok())
+		{
+			return Err(AuthError::UnknownNode);
+		}
+
+		Ok(AuthToken {
+			root: Path::new(path).to_owned(),
+			subject: None,
+			subscribe: vec!["".as_path().to_owned()],
+			publish: vec!["".as_path().to_owned()],
+			cluster: true,
 		})
 	}
 
@@ -109,6 +184,43 @@ impl Auth {
 			return Err(AuthError::ExpectedToken);
 		};
 
+		Self::scope(path, claims)
+	}
+
+	// Authorize the client using the webhook if configured, otherwise the same as [Self::verify].
+	// Tokens signed by the root key, ex. for other cluster nodes, and anonymous clients within the public path are verified locally.
+	pub async fn authorize(
+		&self,
+		path: &str,
+		token: Option<&str>,
+		address: net::IpAddr,
+	) -> Result<AuthToken, AuthError> {
+		let webhook = match &self.webhook {
+			Some(webhook) => webhook,
+			None => return self.verify(path, token),
+		};
+
+		if let (Some(key), Some(token)) = (&self.key, token) {
+			if let Ok(claims) = key.decode(token) {
+				return Self::scope(path, claims);
+			}
+		}
+
+		if token.is_none() && self.public.is_some() {
+			if let Ok(token) = self.verify(path, None) {
+				return Ok(token);
+			}
+		}
+
+		// The webhook grants prefixes relative to the requested path.
+		let mut claims = webhook.authorize(path, token, address).await?;
+		claims.root = Path::new(path).to_string();
+
+		Self::scope(path, claims)
+	}
+
+	// Reduce the claims to the given path.
+	fn scope(path: &str, claims: moq_token::Claims) -> Result<AuthToken, AuthError> {
 		// Get the path from the URL, removing any leading or trailing slashes.
 		// We will automatically add a trailing slash when joining the path with the subscribe/publish roots.
 		let root = Path::new(path);
@@ -148,6 +260,7 @@ impl Auth {
 
 		Ok(AuthToken {
 			root: root.to_owned(),
+			subject: claims.subject,
 			subscribe,
 			publish,
 			cluster: claims.cluster,
@@ -174,6 +287,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: None,
 			public: Some("anon".to_string()),
+			..Default::default()
 		})?;
 
 		// Should succeed for anonymous path
@@ -191,12 +305,179 @@ mod tests {
 		Ok(())
 	}
 
+	// Generate a CA that signs cluster certificates, as used with --tls-client-root.
+	fn create_test_ca() -> anyhow::Result<rcgen::CertifiedIssuer<'static, rcgen::KeyPair>> {
+		let mut params = rcgen::CertificateParams::new(Vec::new())?;
+		params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+		Ok(rcgen::CertifiedIssuer::self_signed(
+			params,
+			rcgen::KeyPair::generate()?,
+		)?)
+	}
+
+	fn create_test_cert(
+		ca: &rcgen::CertifiedIssuer<'static, rcgen::KeyPair>,
+		name: &str,
+	) -> anyhow::Result<(rcgen::Certificate, rcgen::KeyPair)> {
+		let key = rcgen::KeyPair::generate()?;
+		let cert = rcgen::CertificateParams::new(vec![name.to_string()])?.signed_by(&key, ca)?;
+		Ok((cert, key))
+	}
+
+	#[test]
+	fn test_cluster_certificate() -> anyhow::Result<()> {
+		let auth = Auth::new(AuthConfig {
+			public: Some("anon".to_string()),
+			cluster: vec!["node1.example.com:4443".to_string(), "10.0.0.1".to_string()],
+			..Default::default()
+		})?;
+
+		let ca = create_test_ca()?;
+
+		// A certificate for a cluster node grants full access without a token.
+		let (cert, _) = create_test_cert(&ca, "node1.example.com")?;
+		let token = auth.verify_cluster("/demo", &[cert.der().clone()])?;
+		assert!(token.cluster);
+		assert_eq!(token.root, "demo".as_path());
+		assert_eq!(token.subscribe, vec!["".as_path()]);
+		assert_eq!(token.publish, vec!["".as_path()]);
+
+		// Wildcard and IP address certificates are matched too.
+		let (cert, _) = create_test_cert(&ca, "*.example.com")?;
+		assert!(auth.verify_cluster("/demo", &[cert.der().clone()]).is_ok());
+
+		let (cert, _) = create_test_cert(&ca, "10.0.0.1")?;
+		assert!(auth.verify_cluster("/demo", &[cert.der().clone()]).is_ok());
+
+		// Any other certificate signed by the same root is rejected.
+		let (cert, _) = create_test_cert(&ca, "node2.example.com")?;
+		let err = auth.verify_cluster("/demo", &[cert.der().clone()]).unwrap_err();
+		assert!(matches!(err, AuthError::UnknownNode));
+
+		assert!(auth.verify_cluster("/demo", &[]).is_err());
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_cluster_mtls() -> anyhow::Result<()> {
+		// Enabling every feature also enables ring, so rustls can't pick a default provider on its own.
+		let _ = moq_native::rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+		let dir = tempfile::tempdir()?;
+
+		// Write a certificate and key to disk, returning the client TLS config.
+		let write =
+			|name: &str, cert: &rcgen::Certificate, key: &rcgen::KeyPair| -> anyhow::Result<moq_native::ClientTls> {
+				let cert_path = dir.path().join(format!("{name}.pem"));
+				let key_path = dir.path().join(format!("{name}.key"));
+				std::fs::write(&cert_path, cert.pem())?;
+				std::fs::write(&key_path, key.serialize_pem())?;
+
+				Ok(moq_native::ClientTls {
+					disable_verify: Some(true),
+					cert: Some(cert_path),
+					key: Some(key_path),
+					..Default::default()
+				})
+			};
+
+		let ca = create_test_ca()?;
+		let root = dir.path().join("root.pem");
+		std::fs::write(&root, ca.pem())?;
+
+		let mut server = moq_native::ServerConfig {
+			bind: Some("127.0.0.1:0".parse()?),
+			tls: moq_native::ServerTlsConfig {
+				generate: vec!["localhost".to_string()],
+				client_root: vec![root],
+				..Default::default()
+			},
+		}
+		.init()?;
+
+		let url = Url::parse(&format!("https://127.0.0.1:{}/demo", server.local_addr()?.port()))?;
+		let client = |tls: moq_native::ClientTls| {
+			moq_native::ClientConfig {
+				bind: "127.0.0.1:0".parse().unwrap(),
+				tls,
+			}
+			.init()
+		};
+
+		let auth = Auth::new(AuthConfig {
+			public: Some("anon".to_string()),
+			cluster: vec!["node1.example.com".to_string()],
+			..Default::default()
+		})?;
+
+		// A node presenting a certificate signed by the client root.
+		let (cert, key) = create_test_cert(&ca, "node1.example.com")?;
+		let node = client(write("node1", &cert, &key)?)?;
+		assert!(node.has_client_cert());
+
+		let (session, token) = tokio::join!(node.connect(url.clone()), async {
+			let request = server.accept().await.expect("server closed");
+			let certs = request.peer_certificates().expect("missing client certificate");
+			assert_eq!(certs.first(), Some(cert.der()));
+
+			let token = auth.verify_cluster(request.url().path(), certs);
+			request.ok().await.expect("failed to accept");
+			token
+		});
+		session?;
+		assert!(token?.cluster);
+
+		// A certificate signed by the client root, but for another name.
+		let (cert, key) = create_test_cert(&ca, "node2.example.com")?;
+		let other = client(write("node2", &cert, &key)?)?;
+
+		let (session, token) = tokio::join!(other.connect(url.clone()), async {
+			let request = server.accept().await.expect("server closed");
+			let certs = request.peer_certificates().expect("missing client certificate");
+			let token = auth.verify_cluster(request.url().path(), certs);
+			request.ok().await.expect("failed to accept");
+			token
+		});
+		session?;
+		assert!(token.is_err());
+
+		// A client without a certificate is still accepted, but must use a token.
+		let anon = client(moq_native::ClientTls {
+			disable_verify: Some(true),
+			..Default::default()
+		})?;
+		assert!(!anon.has_client_cert());
+
+		let (session, certs) = tokio::join!(anon.connect(url.clone()), async {
+			let request = server.accept().await.expect("server closed");
+			let certs = request.peer_certificates().map(|certs| certs.to_vec());
+			request.ok().await.expect("failed to accept");
+			certs
+		});
+		session?;
+		assert!(certs.is_none());
+
+		// A certificate signed by another root fails the handshake.
+		let rogue = create_test_ca()?;
+		let (cert, key) = create_test_cert(&rogue, "node1.example.com")?;
+		let rogue = client(write("rogue", &cert, &key)?)?;
+
+		tokio::select! {
+			res = rogue.connect(url) => assert!(res.is_err()),
+			_ = server.accept() => panic!("accepted an untrusted certificate"),
+		}
+
+		Ok(())
+	}
+
 	#[test]
 	fn test_anonymous_access_fully_public() -> anyhow::Result<()> {
 		// Test fully public access (public = "")
 		let auth = Auth::new(AuthConfig {
 			key: None,
 			public: Some("".to_string()),
+			..Default::default()
 		})?;
 
 		// Should succeed for any path
@@ -214,6 +495,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: None,
 			public: Some("anon".to_string()),
+			..Default::default()
 		})?;
 
 		// Should fail for non-anonymous path
@@ -229,6 +511,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Should fail when no token and no public path
@@ -243,6 +526,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: None,
 			public: Some("anon".to_string()),
+			..Default::default()
 		})?;
 
 		// Should fail when token provided but no key configured
@@ -258,6 +542,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Create a token with basic permissions
@@ -284,6 +569,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Create a token for room/123
@@ -308,6 +594,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Create a token with specific pub/sub restrictions
@@ -334,6 +621,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Create a read-only token (no publish permissions)
@@ -358,6 +646,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Create a write-only token (no subscribe permissions)
@@ -382,6 +671,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Create a token with root at room/123 and unrestricted pub/sub
@@ -411,6 +701,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Token allows publishing only to alice/*
@@ -440,6 +731,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Token allows subscribing only to bob/*
@@ -468,6 +760,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Token allows publishing to alice/* and subscribing to bob/*
@@ -506,6 +799,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Token with nested publish/subscribe paths
@@ -543,6 +837,7 @@ mod tests {
 		let auth = Auth::new(AuthConfig {
 			key: Some(key_file.path().to_string_lossy().to_string()),
 			public: None,
+			..Default::default()
 		})?;
 
 		// Read-only token
@@ -1,11 +1,23 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+	collections::{HashMap, HashSet},
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
+};
 
 use anyhow::Context;
-use moq_lite::{Broadcast, BroadcastConsumer, BroadcastProducer, Origin, OriginConsumer, OriginProducer};
+use moq_lite::{
+	Broadcast, BroadcastConsumer, BroadcastProducer, Origin, OriginConsumer, OriginDynamic, OriginProducer,
+};
+use rand::Rng;
+use tokio::time::Instant;
 use tracing::Instrument;
 use url::Url;
 
-use crate::AuthToken;
+use crate::{AuthToken, Pull, Rewrite, Router, Rtt};
 
 #[serde_with::serde_as]
 #[derive(clap::Args, Clone, Debug, serde::Serialize, serde::Deserialize, Default)]
@@ -23,6 +35,8 @@ pub struct ClusterConfig {
 	pub root: Option<String>,
 
 	/// Use the token in this file when connecting to other nodes.
+	///
+	/// Deprecated: use a client certificate (--client-tls-cert) with --tls-client-root on the other nodes instead.
 	#[arg(id = "cluster-token", long = "cluster-token", env = "MOQ_CLUSTER_TOKEN")]
 	pub token: Option<PathBuf>,
 
@@ -49,6 +63,119 @@ pub struct ClusterConfig {
 		env = "MOQ_CLUSTER_PREFIX"
 	)]
 	pub prefix: String,
+
+	/// Connect directly to these nodes instead of using a root node.
+	/// Nodes gossip the peers they are connected to, so only a subset of the cluster needs to be listed.
+	#[arg(
+		id = "cluster-peer",
+		long = "cluster-peer",
+		env = "MOQ_CLUSTER_PEER",
+		value_delimiter = ','
+	)]
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub peers: Vec<String>,
+
+	/// Discover peers using the DNS SRV records for this name instead of using a root node.
+	/// ex. `_moq._udp.example.com`
+	#[arg(id = "cluster-dns", long = "cluster-dns", env = "MOQ_CLUSTER_DNS")]
+	pub dns: Option<String>,
+
+	/// The delay before the first reconnect attempt to a remote node, doubling after each failure.
+	/// Defaults to 1s.
+	#[arg(id = "cluster-backoff-initial", long = "cluster-backoff-initial", env = "MOQ_CLUSTER_BACKOFF_INITIAL", value_parser = humantime::parse_duration)]
+	#[serde(with = "humantime_serde")]
+	pub backoff_initial: Option<Duration>,
+
+	/// The maximum delay between reconnect attempts to a remote node.
+	/// Defaults to 1m.
+	#[arg(id = "cluster-backoff-max", long = "cluster-backoff-max", env = "MOQ_CLUSTER_BACKOFF_MAX", value_parser = humantime::parse_duration)]
+	#[serde(with = "humantime_serde")]
+	pub backoff_max: Option<Duration>,
+
+	/// Reset the backoff once a connection to a remote node has been up for this long.
+	/// Defaults to 30s.
+	#[arg(id = "cluster-backoff-reset", long = "cluster-backoff-reset", env = "MOQ_CLUSTER_BACKOFF_RESET", value_parser = humantime::parse_duration)]
+	#[serde(with = "humantime_serde")]
+	pub backoff_reset: Option<Duration>,
+
+	/// Give up on a remote node after failing to connect for this long.
+	/// Defaults to retrying forever.
+	#[arg(id = "cluster-backoff-timeout", long = "cluster-backoff-timeout", env = "MOQ_CLUSTER_BACKOFF_TIMEOUT", value_parser = humantime::parse_duration)]
+	#[serde(with = "humantime_serde")]
+	pub backoff_timeout: Option<Duration>,
+}
+
+impl ClusterConfig {
+	/// Returns true if nodes discover each other without a root node.
+	pub fn is_mesh(&self) -> bool {
+		!self.peers.is_empty() || self.dns.is_some()
+	}
+
+	/// Returns the configured hostnames of cluster nodes, including our own.
+	pub fn nodes(&self) -> impl Iterator<Item = &String> {
+		self.root.iter().chain(self.node.iter()).chain(self.peers.iter())
+	}
+}
+
+// How often to refresh the DNS SRV records.
+const DNS_REFRESH: Duration = Duration::from_secs(30);
+
+/// Exponential backoff with jitter, used when reconnecting to remote nodes.
+struct Backoff {
+	initial: Duration,
+	max: Duration,
+	current: Duration,
+}
+
+impl Backoff {
+	fn new(config: &ClusterConfig) -> Self {
+		let initial = config.backoff_initial.unwrap_or(Duration::from_secs(1));
+		let max = config.backoff_max.unwrap_or(Duration::from_secs(60)).max(initial);
+
+		Self {
+			initial,
+			max,
+			current: initial,
+		}
+	}
+
+	// Returns the delay before the next attempt, somewhere between 50% and 100% of the current backoff.
+	// The jitter avoids every node reconnecting at the same time after an outage.
+	fn next(&mut self) -> Duration {
+		let delay = self.current;
+		self.current = (self.current * 2).min(self.max);
+
+		let half = delay / 2;
+		half + half.mul_f64(rand::rng().random::<f64>())
+	}
+
+	fn reset(&mut self) {
+		self.current = self.initial;
+	}
+}
+
+/// The health of the connection to a remote node.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct RemoteHealth {
+	/// True if there is currently an established session.
+	pub connected: bool,
+
+	/// The total number of sessions established.
+	pub sessions: u64,
+
+	/// The number of consecutive failed attempts.
+	pub failures: u64,
+
+	/// The most recent error, if any.
+	pub error: Option<String>,
+
+	/// The delay before the next reconnect attempt.
+	#[serde(with = "humantime_serde")]
+	pub backoff: Option<Duration>,
+
+	/// The most recent round-trip time, used to route subscriptions.
+	#[serde(with = "humantime_serde")]
+	pub rtt: Option<Duration>,
 }
 
 #[derive(Clone)]
@@ -65,19 +192,42 @@ pub struct Cluster {
 	// Broadcasts announced by remote servers (cluster).
 	pub secondary: Arc<moq_lite::Produce<OriginProducer, OriginConsumer>>,
 
-	// Broadcasts announced by local clients and remote servers.
+	// Broadcasts announced by local clients and remote servers, under their public names.
 	pub combined: Arc<moq_lite::Produce<OriginProducer, OriginConsumer>>,
+
+	// The health of each remote node, keyed by hostname.
+	remotes: Arc<Mutex<HashMap<String, RemoteHealth>>>,
+
+	// Merges broadcasts from remote nodes into the secondary origin.
+	router: Router,
+
+	// Used to give each incoming cluster session a unique name in the router.
+	incoming: Arc<AtomicU64>,
+
+	// Renames broadcasts in the combined origin.
+	rewrite: Rewrite,
+
+	// Fetches broadcasts that aren't announced from an upstream server.
+	pull: Option<Pull>,
 }
 
 impl Cluster {
-	pub fn new(config: ClusterConfig, client: moq_native::Client) -> Self {
+	pub fn new(config: ClusterConfig, client: moq_native::Client, rewrite: Rewrite, pull: Option<Pull>) -> Self {
+		let secondary = Origin::produce();
+		let router = Router::new(secondary.producer.clone());
+
 		Cluster {
 			config,
 			client,
 			noop: Broadcast::produce(),
 			primary: Arc::new(Origin::produce()),
-			secondary: Arc::new(Origin::produce()),
+			secondary: Arc::new(secondary),
 			combined: Arc::new(Origin::produce()),
+			remotes: Default::default(),
+			router,
+			incoming: Default::default(),
+			rewrite,
+			pull,
 		}
 	}
 
@@ -96,26 +246,58 @@ impl Cluster {
 		subscribe_origin.consume_only(&token.subscribe)
 	}
 
-	pub fn publisher(&self, token: &AuthToken) -> Option<OriginProducer> {
-		// If this is a cluster node, then add its broadcasts to the secondary origin.
-		// That way we won't publish them to other cluster nodes.
-		let publish_origin = match token.cluster {
-			true => &self.secondary,
-			false => &self.primary,
-		};
+	// For a given auth token, return the origin that the session should publish to.
+	//
+	// The RTT of the session is used to route subscriptions if it's another cluster node.
+	pub fn publisher(&self, token: &AuthToken, rtt: Rtt) -> Option<ClusterPublisher> {
+		if !token.cluster {
+			let origin = self.primary.producer.with_root(&token.root)?;
+			return Some(ClusterPublisher {
+				origin: origin.publish_only(&token.publish)?,
+				route: None,
+			});
+		}
 
-		let publish_origin = publish_origin.producer.with_root(&token.root)?;
-		publish_origin.publish_only(&token.publish)
+		// Cluster nodes that connect to us go through the router just like the nodes we connect to.
+		// Their broadcasts end up in the secondary origin, so we won't publish them to other cluster nodes.
+		let remote = Origin::produce();
+		let origin = remote.producer.with_root(&token.root)?.publish_only(&token.publish)?;
+
+		let node = format!("incoming-{}", self.incoming.fetch_add(1, Ordering::Relaxed));
+		let router = self.router.clone();
+		let task = tokio::spawn(async move { router.run_remote(&node, remote.consumer, rtt).await }.in_current_span());
+
+		Some(ClusterPublisher {
+			origin,
+			route: Some(task.abort_handle()),
+		})
 	}
 
-	pub fn get(&self, broadcast: &str) -> Option<BroadcastConsumer> {
-		self.primary
-			.consumer
-			.consume_broadcast(broadcast)
-			.or_else(|| self.secondary.consumer.consume_broadcast(broadcast))
+	/// Returns a snapshot of the health of each remote node.
+	pub fn remotes(&self) -> HashMap<String, RemoteHealth> {
+		self.remotes.lock().unwrap().clone()
+	}
+
+	fn update_remote(&self, node: &str, f: impl FnOnce(&mut RemoteHealth)) {
+		f(self.remotes.lock().unwrap().entry(node.to_string()).or_default())
+	}
+
+	fn remove_remote(&self, node: &str) {
+		self.remotes.lock().unwrap().remove(node);
 	}
 
 	pub async fn run(self) -> anyhow::Result<()> {
+		// Pull broadcasts that sessions request but aren't announced.
+		if let Some(pull) = self.pull.clone() {
+			let dynamic = self.combined.producer.dynamic();
+			tokio::spawn(self.clone().run_pull(pull, dynamic).in_current_span());
+		}
+
+		if self.config.is_mesh() {
+			tracing::info!(peers = ?self.config.peers, dns = ?self.config.dns, "running as mesh");
+			return self.run_mesh().await;
+		}
+
 		let root = match self.config.root.clone() {
 			// If we're using a root node, then we have to connect to it.
 			Some(connect) if Some(&connect) != self.config.node.as_ref() => connect,
@@ -141,18 +323,11 @@ impl Cluster {
 			origins.publish_broadcast(myself, self.noop.consumer.clone());
 		}
 
-		// If the token is provided, read it from the disk and use it in the query parameter.
-		// TODO put this in an AUTH header once WebTransport supports it.
-		let token = match &self.config.token {
-			Some(path) => std::fs::read_to_string(path).context("failed to read token")?,
-			None => "".to_string(),
-		};
-
-		let noop = self.noop.consumer.clone();
+		let token = self.token()?;
 
 		// Despite returning a Result, we should NEVER return an Ok
 		tokio::select! {
-			res = self.clone().run_remote(&root, token.clone(), noop) => {
+			res = self.clone().run_remote(&root, token.clone()) => {
 				res.context("failed to connect to root")?;
 				anyhow::bail!("connection to root closed");
 			}
@@ -167,7 +342,163 @@ impl Cluster {
 		}
 	}
 
+	// If the token is provided, read it from the disk and use it in the query parameter.
+	// Prefer a client certificate (--client-tls-cert) instead, so the token doesn't end up in URLs.
+	fn token(&self) -> anyhow::Result<Option<String>> {
+		if self.client.has_client_cert() {
+			if self.config.token.is_some() {
+				tracing::warn!("ignoring --cluster-token because a client certificate is configured");
+			}
+
+			return Ok(None);
+		}
+
+		Ok(match &self.config.token {
+			Some(path) => {
+				let token = std::fs::read_to_string(path).context("failed to read token")?;
+				Some(token.trim().to_string())
+			}
+			None => None,
+		})
+	}
+
+	async fn run_mesh(self) -> anyhow::Result<()> {
+		let myself = match self.config.node.as_ref() {
+			Some(myself) => myself.clone(),
+			None => anyhow::bail!("--cluster-node is required when using --cluster-peer or --cluster-dns"),
+		};
+
+		// Announce ourselves to every peer that connects to us.
+		// Unlike root mode, this goes into the primary origin so it's forwarded to other cluster nodes.
+		let origins = self
+			.primary
+			.producer
+			.with_root(&self.config.prefix)
+			.context("no authorized origins")?;
+
+		tracing::info!(%myself, "announcing as peer");
+		origins.publish_broadcast(&myself, self.noop.consumer.clone());
+
+		// Discover peers announced by other nodes.
+		let discovered = self
+			.secondary
+			.producer
+			.with_root(&self.config.prefix)
+			.context("no authorized origins")?
+			.consume();
+
+		let token = self.token()?;
+
+		tokio::select! {
+			res = self.clone().run_peers(discovered, token) => {
+				res.context("failed to connect to peers")?;
+				anyhow::bail!("connection to peers closed");
+			}
+			res = self.run_combined() => {
+				res.context("failed to run combined")?;
+				anyhow::bail!("combined connection closed");
+			}
+		}
+	}
+
+	// Maintain a connection to every peer, whether configured, resolved via DNS, or gossiped.
+	async fn run_peers(self, mut discovered: OriginConsumer, token: Option<String>) -> anyhow::Result<()> {
+		let resolver = match &self.config.dns {
+			Some(_) => Some(
+				hickory_resolver::TokioResolver::builder_tokio()
+					.context("failed to create DNS resolver")?
+					.build(),
+			),
+			None => None,
+		};
+
+		let mut resolved = HashSet::new();
+		let mut announced = HashSet::new();
+		let mut active: HashMap<String, tokio::task::AbortHandle> = HashMap::new();
+
+		let mut refresh = tokio::time::interval(DNS_REFRESH);
+
+		loop {
+			tokio::select! {
+				Some((node, origin)) = discovered.announced() => {
+					match origin {
+						Some(_) => announced.insert(node.to_string()),
+						None => announced.remove(node.as_str()),
+					};
+				}
+				_ = refresh.tick(), if resolver.is_some() => {
+					let resolver = resolver.as_ref().unwrap();
+					let name = self.config.dns.as_ref().unwrap();
+
+					match Self::resolve(resolver, name).await {
+						Ok(nodes) => resolved = nodes,
+						// Keep using the previous records if the lookup fails.
+						Err(err) => tracing::warn!(%err, %name, "failed to resolve peers"),
+					}
+				}
+				else => return Ok(()),
+			}
+
+			let peers: HashSet<&String> = self
+				.config
+				.peers
+				.iter()
+				.chain(resolved.iter())
+				.chain(announced.iter())
+				.filter(|node| Some(*node) != self.config.node.as_ref())
+				.collect();
+
+			active.retain(|node, handle| {
+				if peers.contains(node) {
+					return true;
+				}
+
+				tracing::info!(%node, "peer removed");
+				handle.abort();
+				self.remove_remote(node);
+				false
+			});
+
+			for node in peers {
+				if active.contains_key(node) {
+					continue;
+				}
+
+				tracing::info!(%node, "discovered peer");
+
+				let this = self.clone();
+				let token = token.clone();
+				let node2 = node.clone();
+
+				let handle = tokio::spawn(
+					async move {
+						match this.run_remote(node2.as_str(), token).await {
+							Ok(()) => tracing::info!(%node2, "peer closed"),
+							Err(err) => tracing::warn!(%err, %node2, "peer error"),
+						}
+					}
+					.in_current_span(),
+				);
+
+				active.insert(node.clone(), handle.abort_handle());
+			}
+		}
+	}
+
+	async fn resolve(resolver: &hickory_resolver::TokioResolver, name: &str) -> anyhow::Result<HashSet<String>> {
+		let records = resolver.srv_lookup(name).await?;
+
+		Ok(records
+			.iter()
+			.map(|srv| {
+				let target = srv.target().to_utf8();
+				format!("{}:{}", target.trim_end_matches('.'), srv.port())
+			})
+			.collect())
+	}
+
 	// Shovel broadcasts from the primary and secondary origins into the combined origin.
+	// Each broadcast is published under the names given by the rewrite rules, so sessions are authorized against them.
 	async fn run_combined(self) -> anyhow::Result<()> {
 		let mut primary = self.primary.consumer.consume();
 		let mut secondary = self.secondary.consumer.consume();
@@ -181,12 +512,24 @@ impl Cluster {
 			};
 
 			if let Some(broadcast) = broadcast {
-				self.combined.producer.publish_broadcast(&name, broadcast);
+				for name in self.rewrite.names(&name) {
+					self.combined.producer.publish_broadcast(&name, broadcast.clone());
+				}
 			}
 		}
 	}
 
-	async fn run_remotes(self, mut origins: OriginConsumer, token: String) -> anyhow::Result<()> {
+	async fn run_pull(self, pull: Pull, mut dynamic: OriginDynamic) {
+		while let Some((name, broadcast)) = dynamic.requested_broadcast().await {
+			// Sessions request the public name, but the upstream announces the original.
+			let originals = self.rewrite.originals(&name);
+			let pull = pull.clone();
+			let origin = self.combined.producer.clone();
+			tokio::spawn(async move { pull.serve(&origin, name, &originals, broadcast).await }.in_current_span());
+		}
+	}
+
+	async fn run_remotes(self, mut origins: OriginConsumer, token: Option<String>) -> anyhow::Result<()> {
 		// Cancel tasks when the origin is closed.
 		let mut active: HashMap<String, tokio::task::AbortHandle> = HashMap::new();
 
@@ -199,14 +542,12 @@ impl Cluster {
 				continue;
 			}
 
-			let origin = match origin {
-				Some(origin) => origin,
-				None => {
-					tracing::info!(%node, "origin cancelled");
-					active.remove(node.as_str()).unwrap().abort();
-					continue;
-				}
-			};
+			if origin.is_none() {
+				tracing::info!(%node, "origin cancelled");
+				active.remove(node.as_str()).unwrap().abort();
+				self.remove_remote(node.as_str());
+				continue;
+			}
 
 			tracing::info!(%node, "discovered origin");
 
@@ -216,7 +557,7 @@ impl Cluster {
 
 			let handle = tokio::spawn(
 				async move {
-					match this.run_remote(node2.as_str(), token, origin).await {
+					match this.run_remote(node2.as_str(), token).await {
 						Ok(()) => tracing::info!(%node2, "origin closed"),
 						Err(err) => tracing::warn!(%err, %node2, "origin error"),
 					}
@@ -231,37 +572,58 @@ impl Cluster {
 	}
 
 	#[tracing::instrument("remote", skip_all, err, fields(%node))]
-	async fn run_remote(mut self, node: &str, token: String, origin: BroadcastConsumer) -> anyhow::Result<()> {
-		let url = Url::parse(&format!("https://{node}/?jwt={token}"))?;
-		let mut backoff = 1;
+	async fn run_remote(mut self, node: &str, token: Option<String>) -> anyhow::Result<()> {
+		let mut url = Url::parse(&format!("https://{node}/"))?;
+		if let Some(token) = token {
+			url.query_pairs_mut().append_pair("jwt", &token);
+		}
+
+		let reset = self.config.backoff_reset.unwrap_or(Duration::from_secs(30));
+		let mut backoff = Backoff::new(&self.config);
+
+		// When we started failing to connect, used to give up after the timeout.
+		let mut failing = Instant::now();
 
 		loop {
-			let res = tokio::select! {
-				biased;
-				_ = origin.closed() => break,
-				res = self.run_remote_once(&url) => res,
+			let mut connected = None;
+			let res = self.run_remote_once(node, &url, &mut connected).await;
+
+			// Reset the backoff if the session was up long enough to be considered stable.
+			if let Some(connected) = connected {
+				if connected.elapsed() >= reset {
+					backoff.reset();
+					failing = Instant::now();
+				}
+			}
+
+			let err = match res {
+				Ok(()) => anyhow::anyhow!("session closed"),
+				Err(err) => err,
 			};
 
-			if let Err(err) = res {
-				backoff *= 2;
-				tracing::error!(%err, "remote error");
+			if let Some(timeout) = self.config.backoff_timeout {
+				if failing.elapsed() >= timeout {
+					self.update_remote(node, |health| health.backoff = None);
+					anyhow::bail!("remote connection keeps failing, giving up: {err}");
+				}
 			}
 
-			let timeout = tokio::time::Duration::from_secs(backoff);
-			if timeout > tokio::time::Duration::from_secs(300) {
-				// 5 minutes of backoff is enough, just give up.
-				// TODO Reset the backoff if the connect is successful for some period of time.
-				anyhow::bail!("remote connection keep failing, giving up");
-			}
+			let delay = backoff.next();
+			self.update_remote(node, |health| {
+				health.connected = false;
+				health.failures += 1;
+				health.error = Some(err.to_string());
+				health.backoff = Some(delay);
+			});
 
-			tokio::time::sleep(timeout).await;
+			tracing::warn!(%err, ?delay, "remote error, reconnecting");
+			tokio::time::sleep(delay).await;
 		}
-
-		Ok(())
 	}
 
-	async fn run_remote_once(&mut self, url: &Url) -> anyhow::Result<()> {
-		tracing::info!(%url, "connecting to remote");
+	async fn run_remote_once(&mut self, node: &str, url: &Url, connected: &mut Option<Instant>) -> anyhow::Result<()> {
+		// NOTE: The URL is not logged because it may contain the token.
+		tracing::info!(%node, "connecting to remote");
 
 		// Connect to the remote node.
 		let conn = self
@@ -270,13 +632,169 @@ impl Cluster {
 			.await
 			.context("failed to connect to remote")?;
 
+		// Used to measure the RTT, since the session takes ownership of the connection.
+		let quic = conn.clone();
+
+		// The remote broadcasts are merged into the secondary origin by the router.
+		let remote = Origin::produce();
+
 		let publish = Some(self.primary.consumer.consume());
-		let subscribe = Some(self.secondary.producer.clone());
+		let subscribe = Some(remote.producer);
 
 		let session = moq_lite::Session::connect(conn, publish, subscribe)
 			.await
 			.context("failed to establish session")?;
 
-		session.closed().await.map_err(Into::into)
+		// In mesh mode, gossip that we're connected to this peer so other nodes can discover it.
+		// The announcement is removed when the connection is closed.
+		let _gossip = match self.config.is_mesh() {
+			true => {
+				let gossip = Broadcast::produce();
+				self.primary
+					.producer
+					.with_root(&self.config.prefix)
+					.context("no authorized origins")?
+					.publish_broadcast(node, gossip.consumer);
+				Some(gossip.producer)
+			}
+			false => None,
+		};
+
+		*connected = Some(Instant::now());
+		self.update_remote(node, |health| {
+			health.connected = true;
+			health.sessions += 1;
+			health.failures = 0;
+			health.backoff = None;
+		});
+		tracing::info!("connected to remote");
+
+		let rtt = Rtt::default();
+		rtt.set(quic.rtt());
+
+		tokio::select! {
+			res = session.closed() => res.map_err(Into::into),
+			_ = self.router.run_remote(node, remote.consumer, rtt.clone()) => Ok(()),
+			_ = self.run_rtt(node, &quic, &rtt) => Ok(()),
+		}
+	}
+
+	// Periodically sample the RTT of the connection.
+	async fn run_rtt(&self, node: &str, quic: &moq_native::web_transport_quinn::Session, rtt: &Rtt) {
+		let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+		loop {
+			interval.tick().await;
+
+			let sample = quic.rtt();
+			rtt.set(sample);
+			self.update_remote(node, |health| health.rtt = Some(sample));
+		}
+	}
+}
+
+/// The origin a session publishes to.
+///
+/// Broadcasts from another cluster node are routed until this is dropped.
+pub struct ClusterPublisher {
+	pub origin: OriginProducer,
+	route: Option<tokio::task::AbortHandle>,
+}
+
+impl Drop for ClusterPublisher {
+	fn drop(&mut self) {
+		if let Some(route) = &self.route {
+			route.abort();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use moq_lite::{AsPath, Track};
+
+	fn cluster() -> Cluster {
+		let client = moq_native::ClientConfig::default().init().unwrap();
+		let rewrite = crate::RewriteConfig::default().init().unwrap();
+		Cluster::new(ClusterConfig::default(), client, rewrite, None)
+	}
+
+	fn token(cluster: bool) -> AuthToken {
+		AuthToken {
+			root: "".as_path().to_owned(),
+			subject: None,
+			subscribe: vec!["".as_path().to_owned()],
+			publish: vec!["".as_path().to_owned()],
+			cluster,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_incoming_cluster() {
+		let cluster = cluster();
+		let mut secondary = cluster.secondary.consumer.consume();
+
+		let near = Rtt::default();
+		near.set(Duration::from_millis(10));
+
+		// Two cluster nodes connect to us and publish the same broadcast, the nearest one second.
+		let far = cluster.publisher(&token(true), Rtt::unknown()).unwrap();
+		let near = cluster.publisher(&token(true), near).unwrap();
+
+		let mut a = Broadcast::produce();
+		let mut b = Broadcast::produce();
+		far.origin.publish_broadcast("demo", a.consumer.clone());
+
+		// The router announces a single proxy in the secondary origin.
+		let (path, proxy) = secondary.announced().await.unwrap();
+		assert_eq!(path, "demo".as_path());
+		let proxy = proxy.unwrap();
+
+		near.origin.publish_broadcast("demo", b.consumer.clone());
+		tokio::task::yield_now().await;
+		assert!(secondary.try_announced().is_none());
+
+		// Subscriptions go to the nearest node, even though it connected last.
+		let _track = proxy.subscribe_track(&Track::new("video"));
+		b.producer.requested_track().await.unwrap();
+		assert!(futures::FutureExt::now_or_never(a.producer.requested_track()).is_none());
+
+		// The broadcast is unannounced once both sessions are gone.
+		drop(near);
+		drop(far);
+		let (path, proxy) = secondary.announced().await.unwrap();
+		assert_eq!(path, "demo".as_path());
+		assert!(proxy.is_none());
+
+		// Other sessions still publish directly to the primary origin.
+		let mut primary = cluster.primary.consumer.consume();
+		let user = cluster.publisher(&token(false), Rtt::unknown()).unwrap();
+		user.origin.publish_broadcast("user", a.consumer.clone());
+		let (path, _) = primary.announced().await.unwrap();
+		assert_eq!(path, "user".as_path());
+	}
+
+	#[test]
+	fn test_backoff() {
+		let config = ClusterConfig {
+			backoff_initial: Some(Duration::from_secs(1)),
+			backoff_max: Some(Duration::from_secs(4)),
+			..Default::default()
+		};
+
+		let mut backoff = Backoff::new(&config);
+
+		for expected in [1, 2, 4, 4] {
+			let expected = Duration::from_secs(expected);
+			let delay = backoff.next();
+			assert!(
+				delay >= expected / 2 && delay <= expected,
+				"{delay:?} not within {expected:?}"
+			);
+		}
+
+		backoff.reset();
+		assert!(backoff.next() <= Duration::from_secs(1));
 	}
 }
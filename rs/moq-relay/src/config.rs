@@ -1,7 +1,7 @@
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
-use crate::{AuthConfig, ClusterConfig, WebConfig};
+use crate::{AuditConfig, AuthConfig, ClusterConfig, LimitsConfig, PullConfig, RecordConfig, RewriteConfig, WebConfig};
 
 #[derive(Parser, Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -30,6 +30,31 @@ pub struct Config {
 	#[serde(default)]
 	pub auth: AuthConfig,
 
+	/// Per-session limits.
+	#[command(flatten)]
+	#[serde(default)]
+	pub limits: LimitsConfig,
+
+	/// Pull-through configuration.
+	#[command(flatten)]
+	#[serde(default)]
+	pub pull: PullConfig,
+
+	/// Broadcast alias and rename rules.
+	#[command(flatten)]
+	#[serde(default)]
+	pub rewrite: RewriteConfig,
+
+	/// Audit log configuration.
+	#[command(flatten)]
+	#[serde(default)]
+	pub audit: AuditConfig,
+
+	/// Recording configuration.
+	#[command(flatten)]
+	#[serde(default)]
+	pub record: RecordConfig,
+
 	/// Optionally run a TCP HTTP/WebSocket server.
 	#[command(flatten)]
 	#[serde(default)]
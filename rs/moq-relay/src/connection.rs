@@ -1,4 +1,6 @@
-use crate::{Auth, Cluster};
+use std::time::Duration;
+
+use crate::{Audit, Auth, Cluster, Intercept, Limits, Rtt};
 
 use moq_native::Request;
 
@@ -7,6 +9,8 @@ pub struct Connection {
 	pub request: Request,
 	pub cluster: Cluster,
 	pub auth: Auth,
+	pub limits: Limits,
+	pub audit: Audit,
 }
 
 impl Connection {
@@ -25,8 +29,17 @@ impl Connection {
 			}
 			Request::Quic(_conn) => ("", None),
 		};
-		// Verify the URL before accepting the connection.
-		let token = match self.auth.verify(path, token.as_deref()) {
+		// A verified client certificate means the client is another cluster node, so no JWT is needed.
+		let token = match self.request.peer_certificates() {
+			Some(certs) => self.auth.verify_cluster(path, certs),
+			None => {
+				// Verify the URL before accepting the connection.
+				let address = self.request.remote_address().ip();
+				self.auth.authorize(path, token.as_deref(), address).await
+			}
+		};
+
+		let token = match token {
 			Ok(token) => token,
 			Err(err) => {
 				let _ = self.request.close(err.clone().into()).await;
@@ -34,7 +47,21 @@ impl Connection {
 			}
 		};
 
-		let publish = self.cluster.publisher(&token);
+		// Make sure the client hasn't exceeded the session limits.
+		let ip = self.request.remote_address().ip();
+		let limits = match self.limits.session(ip, &token) {
+			Ok(limits) => limits,
+			Err(err) => {
+				let _ = self.request.close(err.clone().into()).await;
+				return Err(err.into());
+			}
+		};
+
+		// Used to route subscriptions if this is another cluster node.
+		let rtt = Rtt::unknown();
+
+		let publisher = self.cluster.publisher(&token, rtt.clone());
+		let publish = publisher.as_ref().map(|publisher| publisher.origin.clone());
 		let subscribe = self.cluster.subscriber(&token);
 
 		match (&publish, &subscribe) {
@@ -50,15 +77,41 @@ impl Connection {
 			_ => anyhow::bail!("invalid session; no allowed paths"),
 		}
 
+		// Record the session in the audit log, if enabled.
+		let audit = self.audit.session(ip, &token);
+
+		// Enforce the per-session limits and audit the origins, sharing a single proxy.
+		let mut intercept = Intercept::new([limits.hook(), audit.hook()]);
+		let publish = intercept.publisher(publish);
+		let subscribe = intercept.subscriber(subscribe);
+
 		// Accept the connection.
 		let session = self.request.ok().await?;
+		let quic = session.clone();
+		let session = audit.meter(limits.throttle(session));
 
 		// NOTE: subscribe and publish seem backwards because of how relays work.
 		// We publish the tracks the client is allowed to subscribe to.
 		// We subscribe to the tracks the client is allowed to publish.
 		let session = moq_lite::Session::accept(session, subscribe, publish).await?;
 
-		// Wait until the session is closed.
-		session.closed().await.map_err(Into::into)
+		// Periodically sample the RTT of the connection.
+		let sample = async {
+			let mut interval = tokio::time::interval(Duration::from_secs(1));
+			loop {
+				interval.tick().await;
+				rtt.set(quic.rtt());
+			}
+		};
+
+		// Wait until the session is closed, or close it if a limit is exceeded.
+		tokio::select! {
+			res = session.closed() => res.map_err(Into::into),
+			err = limits.closed() => {
+				session.close(moq_lite::Error::LimitExceeded);
+				Err(err.into())
+			}
+			_ = sample => Ok(()),
+		}
 	}
 }
@@ -0,0 +1,391 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use moq_lite::{GroupConsumer, PathOwned, TrackConsumer};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::watch, time::Instant};
+
+#[derive(clap::Args, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct FetchConfig {
+	/// The number of recent groups to keep for each track fetched over HTTP.
+	/// Defaults to 32.
+	#[arg(long = "web-fetch-groups", id = "web-fetch-groups", env = "MOQ_WEB_FETCH_GROUPS")]
+	pub groups: Option<usize>,
+
+	/// Stop caching a track after it hasn't been fetched for this long.
+	/// Defaults to 30s.
+	#[arg(
+		long = "web-fetch-idle",
+		id = "web-fetch-idle",
+		env = "MOQ_WEB_FETCH_IDLE",
+		value_parser = humantime::parse_duration
+	)]
+	#[serde(with = "humantime_serde")]
+	pub idle: Option<Duration>,
+
+	/// How long a fetch waits for a future group before giving up.
+	/// Defaults to 30s.
+	#[arg(
+		long = "web-fetch-timeout",
+		id = "web-fetch-timeout",
+		env = "MOQ_WEB_FETCH_TIMEOUT",
+		value_parser = humantime::parse_duration
+	)]
+	#[serde(with = "humantime_serde")]
+	pub timeout: Option<Duration>,
+}
+
+#[derive(Default)]
+struct CacheState {
+	// Sorted by sequence number.
+	groups: VecDeque<GroupConsumer>,
+	closed: Option<moq_lite::Result<()>>,
+	used: Option<Instant>,
+}
+
+impl CacheState {
+	fn insert(&mut self, group: GroupConsumer, capacity: usize) {
+		let sequence = group.info.sequence;
+		let index = self.groups.partition_point(|g| g.info.sequence < sequence);
+
+		// Ignore duplicates.
+		if self.groups.get(index).is_some_and(|g| g.info.sequence == sequence) {
+			return;
+		}
+
+		self.groups.insert(index, group);
+
+		while self.groups.len() > capacity {
+			self.groups.pop_front();
+		}
+	}
+
+	fn latest(&self) -> Option<u64> {
+		self.groups.back().map(|g| g.info.sequence)
+	}
+}
+
+// A broadcast path and track name.
+type CacheKey = (PathOwned, String);
+
+/// A range of groups to fetch, parsed from the query parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchRange {
+	/// The latest group, waiting for one if necessary.
+	Latest,
+
+	/// The latest N groups that are cached, waiting for the first group if necessary.
+	LatestN(usize),
+
+	/// A specific group, waiting for it if it's in the future.
+	Group(u64),
+
+	/// The first group after the given sequence, waiting for it if necessary.
+	After(u64),
+
+	/// All cached groups between the two sequences, inclusive, waiting for the first group if necessary.
+	Range(Option<u64>, Option<u64>),
+}
+
+/// Keeps a window of recent groups for tracks fetched over HTTP.
+///
+/// MoQ subscriptions only deliver the latest group, so HTTP clients that poll would otherwise miss groups.
+/// A track is subscribed on the first fetch and is cached until it hasn't been fetched for a while.
+#[derive(Clone)]
+pub struct FetchCache {
+	capacity: usize,
+	idle: Duration,
+	timeout: Duration,
+	tracks: Arc<Mutex<HashMap<CacheKey, watch::Sender<CacheState>>>>,
+}
+
+impl FetchCache {
+	pub fn new(config: FetchConfig) -> Self {
+		Self {
+			capacity: config.groups.unwrap_or(32).max(1),
+			idle: config.idle.unwrap_or(Duration::from_secs(30)),
+			timeout: config.timeout.unwrap_or(Duration::from_secs(30)),
+			tracks: Default::default(),
+		}
+	}
+
+	/// Return the requested groups, subscribing to the track with the provided function if it's not cached.
+	///
+	/// Returns an empty list if the groups are not available, or an error if the track failed.
+	/// Returns [moq_lite::Error::Timeout] if a future group didn't arrive in time.
+	pub async fn fetch(
+		&self,
+		broadcast: PathOwned,
+		track: String,
+		range: FetchRange,
+		subscribe: impl FnOnce() -> TrackConsumer,
+	) -> moq_lite::Result<Vec<GroupConsumer>> {
+		let mut state = self.entry(broadcast, track, subscribe).subscribe();
+
+		// Wait until the requested group is available, or the track is closed.
+		let wait = async {
+			match range {
+				// A new cache is empty until the first group arrives, so don't return an empty list right away.
+				FetchRange::Latest | FetchRange::LatestN(_) | FetchRange::Range(..) => {
+					state.wait_for(|s| s.latest().is_some() || s.closed.is_some()).await
+				}
+				FetchRange::Group(sequence) => {
+					state
+						.wait_for(|s| s.latest() >= Some(sequence) || s.closed.is_some())
+						.await
+				}
+				FetchRange::After(sequence) => {
+					state
+						.wait_for(|s| s.latest() > Some(sequence) || s.closed.is_some())
+						.await
+				}
+			}
+		};
+
+		let state = tokio::time::timeout(self.timeout, wait)
+			.await
+			.map_err(|_| moq_lite::Error::Timeout)?;
+
+		// The sender is only dropped when the cache is evicted, which can't happen while we're waiting.
+		let state = state.map_err(|_| moq_lite::Error::Cancel)?;
+
+		let groups: Vec<_> = match range {
+			FetchRange::Latest => state.groups.back().cloned().into_iter().collect(),
+			FetchRange::LatestN(count) => state.groups.iter().rev().take(count).rev().cloned().collect(),
+			FetchRange::Group(sequence) => state
+				.groups
+				.iter()
+				.find(|g| g.info.sequence == sequence)
+				.cloned()
+				.into_iter()
+				.collect(),
+			FetchRange::After(sequence) => state
+				.groups
+				.iter()
+				.find(|g| g.info.sequence > sequence)
+				.cloned()
+				.into_iter()
+				.collect(),
+			FetchRange::Range(from, to) => state
+				.groups
+				.iter()
+				.filter(|g| from.is_none_or(|from| g.info.sequence >= from))
+				.filter(|g| to.is_none_or(|to| g.info.sequence <= to))
+				.cloned()
+				.collect(),
+		};
+
+		match &state.closed {
+			Some(Err(err)) if groups.is_empty() => Err(err.clone()),
+			_ => Ok(groups),
+		}
+	}
+
+	fn entry(
+		&self,
+		broadcast: PathOwned,
+		track: String,
+		subscribe: impl FnOnce() -> TrackConsumer,
+	) -> watch::Sender<CacheState> {
+		let mut tracks = self.tracks.lock().unwrap();
+		let key = (broadcast, track);
+
+		if let Some(state) = tracks.get(&key) {
+			state.send_if_modified(|s| {
+				s.used = Some(Instant::now());
+				false
+			});
+			return state.clone();
+		}
+
+		let state = watch::Sender::new(CacheState {
+			used: Some(Instant::now()),
+			..Default::default()
+		});
+		tracks.insert(key.clone(), state.clone());
+
+		tokio::spawn(self.clone().run(key, subscribe(), state.clone()));
+
+		state
+	}
+
+	async fn run(self, key: CacheKey, mut track: TrackConsumer, state: watch::Sender<CacheState>) {
+		tracing::debug!(broadcast = %key.0, track = %key.1, "caching track");
+
+		loop {
+			let used = state.borrow().used.unwrap_or_else(Instant::now);
+
+			tokio::select! {
+				res = track.next_group() => match res {
+					Ok(Some(group)) => state.send_modify(|s| s.insert(group, self.capacity)),
+					Ok(None) => {
+						state.send_modify(|s| s.closed = Some(Ok(())));
+						break;
+					}
+					Err(err) => {
+						state.send_modify(|s| s.closed = Some(Err(err)));
+						break;
+					}
+				},
+				// Stop caching once nobody is waiting and nobody has fetched recently.
+				_ = tokio::time::sleep_until(used + self.idle) => {
+					if state.receiver_count() == 0 && state.borrow().used.unwrap_or(used).elapsed() >= self.idle {
+						break;
+					}
+				}
+			}
+		}
+
+		// Keep closed tracks around for a little while so clients don't immediately resubscribe.
+		if state.borrow().closed.is_some() {
+			tokio::time::sleep(self.idle).await;
+		}
+
+		let mut tracks = self.tracks.lock().unwrap();
+		if tracks.get(&key).is_some_and(|s| s.same_channel(&state)) {
+			tracks.remove(&key);
+		}
+
+		tracing::debug!(broadcast = %key.0, track = %key.1, "stopped caching track");
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use moq_lite::Track;
+
+	fn cache() -> FetchCache {
+		FetchCache::new(FetchConfig {
+			groups: Some(3),
+			idle: None,
+			timeout: Some(Duration::from_secs(10)),
+		})
+	}
+
+	async fn sequences(cache: &FetchCache, track: &TrackConsumer, range: FetchRange) -> moq_lite::Result<Vec<u64>> {
+		let groups = cache
+			.fetch("test".into(), "video".into(), range, || track.clone())
+			.await?;
+		Ok(groups.iter().map(|g| g.info.sequence).collect())
+	}
+
+	#[tokio::test]
+	async fn test_ranges() {
+		let cache = cache();
+		let track = Track::new("video").produce();
+		let (mut track, consumer) = (track.producer, track.consumer);
+
+		// The first fetch subscribes and waits for a group.
+		let fetch = sequences(&cache, &consumer, FetchRange::Latest);
+		tokio::pin!(fetch);
+		assert!(futures::poll!(&mut fetch).is_pending());
+
+		track.append_group();
+		assert_eq!(fetch.await.unwrap(), vec![0]);
+
+		for _ in 0..4 {
+			track.append_group();
+			tokio::task::yield_now().await;
+		}
+
+		// Only the last 3 groups are kept.
+		assert_eq!(
+			sequences(&cache, &consumer, FetchRange::LatestN(10)).await.unwrap(),
+			vec![2, 3, 4]
+		);
+		assert_eq!(
+			sequences(&cache, &consumer, FetchRange::LatestN(2)).await.unwrap(),
+			vec![3, 4]
+		);
+		assert_eq!(
+			sequences(&cache, &consumer, FetchRange::Group(3)).await.unwrap(),
+			vec![3]
+		);
+		assert_eq!(
+			sequences(&cache, &consumer, FetchRange::Group(0)).await.unwrap(),
+			Vec::<u64>::new()
+		);
+		assert_eq!(
+			sequences(&cache, &consumer, FetchRange::After(2)).await.unwrap(),
+			vec![3]
+		);
+		assert_eq!(
+			sequences(&cache, &consumer, FetchRange::Range(Some(3), None))
+				.await
+				.unwrap(),
+			vec![3, 4]
+		);
+		assert_eq!(
+			sequences(&cache, &consumer, FetchRange::Range(None, Some(2)))
+				.await
+				.unwrap(),
+			vec![2]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_long_poll() {
+		let cache = cache();
+		let track = Track::new("video").produce();
+		let (mut track, consumer) = (track.producer, track.consumer);
+		track.append_group();
+
+		assert_eq!(sequences(&cache, &consumer, FetchRange::Latest).await.unwrap(), vec![0]);
+
+		// Wait for the next group.
+		let fetch = sequences(&cache, &consumer, FetchRange::After(0));
+		tokio::pin!(fetch);
+		assert!(futures::poll!(&mut fetch).is_pending());
+
+		track.append_group();
+		assert_eq!(fetch.await.unwrap(), vec![1]);
+
+		// The track ends while waiting for a future group.
+		let fetch = sequences(&cache, &consumer, FetchRange::Group(5));
+		tokio::pin!(fetch);
+		assert!(futures::poll!(&mut fetch).is_pending());
+
+		track.close();
+		assert_eq!(fetch.await.unwrap(), Vec::<u64>::new());
+	}
+
+	#[tokio::test]
+	async fn test_first_group() {
+		// Every range waits for the first group of a new cache.
+		for range in [FetchRange::LatestN(2), FetchRange::Range(None, None)] {
+			let cache = cache();
+			let track = Track::new("video").produce();
+			let (mut track, consumer) = (track.producer, track.consumer);
+
+			let fetch = sequences(&cache, &consumer, range);
+			tokio::pin!(fetch);
+			assert!(futures::poll!(&mut fetch).is_pending());
+
+			track.append_group();
+			assert_eq!(fetch.await.unwrap(), vec![0]);
+		}
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_timeout() {
+		let cache = cache();
+		let track = Track::new("video").produce();
+		let (mut track, consumer) = (track.producer, track.consumer);
+		track.append_group();
+
+		// Nothing arrives before the deadline.
+		let res = sequences(&cache, &consumer, FetchRange::After(0)).await;
+		assert!(matches!(res, Err(moq_lite::Error::Timeout)));
+
+		// Cached groups don't wait at all.
+		assert_eq!(
+			sequences(&cache, &consumer, FetchRange::Group(0)).await.unwrap(),
+			vec![0]
+		);
+	}
+}
@@ -0,0 +1,545 @@
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use moq_lite::{
+	Broadcast, BroadcastConsumer, BroadcastProducer, GroupConsumer, Origin, OriginConsumer, OriginDynamic,
+	OriginProducer, PathOwned, TrackConsumer, TrackProducer,
+};
+use tokio::time::Instant;
+
+/// The usage of a broadcast or subscription, reported when it ends.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+	pub duration: Duration,
+
+	/// The number of groups forwarded.
+	pub groups: u64,
+
+	/// The number of payload bytes forwarded, excluding any framing.
+	pub bytes: u64,
+}
+
+/// Callbacks for the broadcasts and tracks that pass through a session, used by [Intercept].
+///
+/// Every accepted `publish` or `subscribe` is followed by exactly one `unpublish` or `unsubscribe`.
+pub trait Hook: Send + Sync {
+	/// The session announced a broadcast, returning an error to ignore it.
+	fn publish(&self, _broadcast: &str) -> Result<(), moq_lite::Error> {
+		Ok(())
+	}
+
+	/// The session's broadcast was unannounced, with the usage across all of its tracks.
+	fn unpublish(&self, _broadcast: &str, _usage: Usage) {}
+
+	/// The session subscribed to a track, returning an error to reject it.
+	fn subscribe(&self, _broadcast: &str, _track: &str) -> Result<(), moq_lite::Error> {
+		Ok(())
+	}
+
+	/// The session's subscription ended.
+	fn unsubscribe(&self, _broadcast: &str, _track: &str, _usage: Usage) {}
+}
+
+type Hooks = Arc<Vec<Arc<dyn Hook>>>;
+
+/// Proxies the origins of a session so each broadcast and subscription can be observed or rejected.
+///
+/// This is shared by the limits and the audit log, so there's a single proxy per session regardless of the number of hooks.
+/// The origins are passed through untouched when there are no hooks.
+pub struct Intercept {
+	hooks: Hooks,
+
+	// Background tasks that proxy the origins, aborted on drop.
+	tasks: Vec<tokio::task::AbortHandle>,
+}
+
+impl Intercept {
+	/// Create an interceptor, skipping any hooks that are `None`.
+	pub fn new(hooks: impl IntoIterator<Item = Option<Arc<dyn Hook>>>) -> Self {
+		Self {
+			hooks: Arc::new(hooks.into_iter().flatten().collect()),
+			tasks: Vec::new(),
+		}
+	}
+
+	/// Intercept the broadcasts announced by the session.
+	pub fn publisher(&mut self, origin: Option<OriginProducer>) -> Option<OriginProducer> {
+		let origin = match origin {
+			Some(origin) if !self.hooks.is_empty() => origin,
+			origin => return origin,
+		};
+
+		// The session publishes to a private origin with the same permissions.
+		// We then copy announcements to the real origin.
+		let allowed: Vec<_> = origin.allowed().cloned().collect();
+		let local = Origin::produce()
+			.producer
+			.with_root(origin.root())?
+			.publish_only(&allowed)?;
+
+		let task = tokio::spawn(run_publisher(self.hooks.clone(), local.consume(), origin));
+		self.tasks.push(task.abort_handle());
+
+		Some(local)
+	}
+
+	/// Intercept the tracks requested by the session.
+	pub fn subscriber(&mut self, origin: Option<OriginConsumer>) -> Option<OriginConsumer> {
+		let origin = match origin {
+			Some(origin) if !self.hooks.is_empty() => origin,
+			origin => return origin,
+		};
+
+		// Each broadcast is proxied through a private origin so we can see the requested tracks.
+		let allowed: Vec<_> = origin.allowed().cloned().collect();
+		let local = Origin::produce()
+			.producer
+			.with_root(origin.root())?
+			.publish_only(&allowed)?;
+		let consumer = local.consume();
+
+		// Forward requests for broadcasts that aren't announced, ex. so they can be pulled from an upstream.
+		let dynamic = local.dynamic();
+
+		let task = tokio::spawn(run_subscriber(self.hooks.clone(), origin, local, dynamic));
+		self.tasks.push(task.abort_handle());
+
+		Some(consumer)
+	}
+}
+
+impl Drop for Intercept {
+	fn drop(&mut self) {
+		for task in self.tasks.drain(..) {
+			task.abort();
+		}
+	}
+}
+
+#[derive(Default)]
+struct Counters {
+	groups: AtomicU64,
+	bytes: AtomicU64,
+}
+
+// An accepted broadcast or subscription, calling the matching hooks on drop.
+struct Active {
+	hooks: Hooks,
+	broadcast: String,
+	track: Option<String>,
+	started: Instant,
+	counters: Arc<Counters>,
+}
+
+impl Active {
+	fn publish(hooks: &Hooks, broadcast: String) -> Result<Self, moq_lite::Error> {
+		for (i, hook) in hooks.iter().enumerate() {
+			if let Err(err) = hook.publish(&broadcast) {
+				// Undo any hooks that already accepted it.
+				for hook in &hooks[..i] {
+					hook.unpublish(&broadcast, Usage::default());
+				}
+
+				return Err(err);
+			}
+		}
+
+		Ok(Self::new(hooks, broadcast, None))
+	}
+
+	fn subscribe(hooks: &Hooks, broadcast: String, track: String) -> Result<Self, moq_lite::Error> {
+		for (i, hook) in hooks.iter().enumerate() {
+			if let Err(err) = hook.subscribe(&broadcast, &track) {
+				for hook in &hooks[..i] {
+					hook.unsubscribe(&broadcast, &track, Usage::default());
+				}
+
+				return Err(err);
+			}
+		}
+
+		Ok(Self::new(hooks, broadcast, Some(track)))
+	}
+
+	fn new(hooks: &Hooks, broadcast: String, track: Option<String>) -> Self {
+		Self {
+			hooks: hooks.clone(),
+			broadcast,
+			track,
+			started: Instant::now(),
+			counters: Default::default(),
+		}
+	}
+}
+
+impl Drop for Active {
+	fn drop(&mut self) {
+		let usage = Usage {
+			duration: self.started.elapsed(),
+			groups: self.counters.groups.load(Ordering::Relaxed),
+			bytes: self.counters.bytes.load(Ordering::Relaxed),
+		};
+
+		for hook in self.hooks.iter() {
+			match &self.track {
+				Some(track) => hook.unsubscribe(&self.broadcast, track, usage),
+				None => hook.unpublish(&self.broadcast, usage),
+			}
+		}
+	}
+}
+
+async fn run_publisher(hooks: Hooks, mut local: OriginConsumer, remote: OriginProducer) {
+	// The hooks are called when the entry is removed, so they're in order even if the proxy is still running.
+	let mut active: HashMap<PathOwned, (Active, tokio::task::AbortHandle)> = HashMap::new();
+
+	while let Some((path, broadcast)) = local.announced().await {
+		if let Some((_, proxy)) = active.remove(&path) {
+			proxy.abort();
+		}
+
+		let broadcast = match broadcast {
+			Some(broadcast) => broadcast,
+			None => continue,
+		};
+
+		let absolute = remote.absolute(&path).to_string();
+		let publishing = match Active::publish(&hooks, absolute) {
+			Ok(publishing) => publishing,
+			Err(err) => {
+				tracing::debug!(broadcast = %remote.absolute(&path), %err, "broadcast rejected");
+				continue;
+			}
+		};
+
+		// Proxy the broadcast so we can count every track that is served.
+		let proxy = Broadcast::produce();
+		remote.publish_broadcast(&path, proxy.consumer);
+
+		let counters = publishing.counters.clone();
+		let task = tokio::spawn(run_proxy(proxy.producer, broadcast, move |track, broadcast| {
+			let upstream = broadcast.subscribe_track(&track.info);
+			run_track(track, upstream, counters.clone())
+		}));
+
+		active.insert(path, (publishing, task.abort_handle()));
+	}
+}
+
+async fn run_subscriber(hooks: Hooks, mut remote: OriginConsumer, local: OriginProducer, mut dynamic: OriginDynamic) {
+	// Abort the proxy when the broadcast is unannounced.
+	let mut active: HashMap<PathOwned, tokio::task::AbortHandle> = HashMap::new();
+
+	// Requested broadcasts are served until the session is closed.
+	let mut requested = tokio::task::JoinSet::new();
+
+	loop {
+		tokio::select! {
+			announced = remote.announced() => {
+				let (path, broadcast) = match announced {
+					Some(announced) => announced,
+					None => break,
+				};
+
+				if let Some(proxy) = active.remove(&path) {
+					proxy.abort();
+				}
+
+				let broadcast = match broadcast {
+					Some(broadcast) => broadcast,
+					None => continue,
+				};
+
+				let proxy = Broadcast::produce();
+				local.publish_broadcast(&path, proxy.consumer);
+
+				let absolute = remote.absolute(&path).to_string();
+				let task = tokio::spawn(run_subscribe(hooks.clone(), absolute, proxy.producer, broadcast));
+				active.insert(path, task.abort_handle());
+			}
+			Some((path, proxy)) = dynamic.requested_broadcast() => {
+				// Dropping the proxy cancels the subscriptions if the broadcast can't be found.
+				if let Some(broadcast) = remote.request_broadcast(&path) {
+					let absolute = remote.absolute(&path).to_string();
+					requested.spawn(run_subscribe(hooks.clone(), absolute, proxy, broadcast));
+				}
+			}
+			Some(_) = requested.join_next() => {}
+		}
+	}
+}
+
+// Serve the session's subscriptions from the broadcast, only subscribing upstream if the hooks accept them.
+async fn run_subscribe(hooks: Hooks, absolute: String, proxy: BroadcastProducer, broadcast: BroadcastConsumer) {
+	run_proxy(proxy, broadcast, move |track, broadcast| {
+		let subscribing = Active::subscribe(&hooks, absolute.clone(), track.info.name.clone())
+			.map(|subscribing| (broadcast.subscribe_track(&track.info), subscribing));
+
+		async move {
+			match subscribing {
+				Ok((upstream, subscribing)) => {
+					run_track(track, upstream, subscribing.counters.clone()).await;
+					drop(subscribing);
+				}
+				Err(err) => track.abort(err),
+			}
+		}
+	})
+	.await
+}
+
+// Serve each requested track from the upstream broadcast, until the broadcast is closed.
+async fn run_proxy<F, Fut>(mut proxy: BroadcastProducer, broadcast: BroadcastConsumer, serve: F)
+where
+	F: Fn(TrackProducer, &BroadcastConsumer) -> Fut,
+	Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+	// Abort the tracks when the broadcast is closed or unannounced.
+	let mut tasks = tokio::task::JoinSet::new();
+
+	loop {
+		let track = tokio::select! {
+			Some(track) = proxy.requested_track() => track,
+			_ = broadcast.closed() => return,
+			else => return,
+		};
+
+		// Clean up any finished tracks.
+		while tasks.try_join_next().is_some() {}
+
+		tasks.spawn(serve(track, &broadcast));
+	}
+}
+
+// Forward each group as-is, counting the frames in the background.
+async fn run_track(mut track: TrackProducer, mut upstream: TrackConsumer, counters: Arc<Counters>) {
+	let unused = track.unused();
+	tokio::pin!(unused);
+
+	let mut groups = FuturesUnordered::new();
+
+	loop {
+		tokio::select! {
+			res = upstream.next_group() => match res {
+				Ok(Some(group)) => {
+					counters.groups.fetch_add(1, Ordering::Relaxed);
+					groups.push(count_group(group.clone(), counters.clone()));
+					track.insert_group(group);
+				}
+				Ok(None) => break,
+				Err(err) => return track.abort(err),
+			},
+			Some(()) = groups.next() => {},
+			_ = &mut unused => return,
+		}
+	}
+
+	track.close();
+
+	// Finish counting the groups that are still in flight.
+	tokio::select! {
+		_ = async { while groups.next().await.is_some() {} } => {},
+		_ = unused => {},
+	}
+}
+
+async fn count_group(mut group: GroupConsumer, counters: Arc<Counters>) {
+	while let Ok(Some(frame)) = group.next_frame().await {
+		counters.bytes.fetch_add(frame.info.size, Ordering::Relaxed);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Mutex;
+
+	use super::*;
+
+	// Records every call, rejecting anything named "reject".
+	#[derive(Default)]
+	struct Recorder {
+		calls: Mutex<Vec<String>>,
+	}
+
+	impl Hook for Recorder {
+		fn publish(&self, broadcast: &str) -> Result<(), moq_lite::Error> {
+			self.calls.lock().unwrap().push(format!("publish {broadcast}"));
+			match broadcast.ends_with("reject") {
+				true => Err(moq_lite::Error::LimitExceeded),
+				false => Ok(()),
+			}
+		}
+
+		fn unpublish(&self, broadcast: &str, usage: Usage) {
+			let calls = &mut self.calls.lock().unwrap();
+			calls.push(format!("unpublish {broadcast} {} {}", usage.groups, usage.bytes));
+		}
+
+		fn subscribe(&self, broadcast: &str, track: &str) -> Result<(), moq_lite::Error> {
+			self.calls
+				.lock()
+				.unwrap()
+				.push(format!("subscribe {broadcast} {track}"));
+			match track == "reject" {
+				true => Err(moq_lite::Error::LimitExceeded),
+				false => Ok(()),
+			}
+		}
+
+		fn unsubscribe(&self, broadcast: &str, track: &str, usage: Usage) {
+			let calls = &mut self.calls.lock().unwrap();
+			calls.push(format!(
+				"unsubscribe {broadcast} {track} {} {}",
+				usage.groups, usage.bytes
+			));
+		}
+	}
+
+	impl Recorder {
+		fn take(&self) -> Vec<String> {
+			std::mem::take(&mut self.calls.lock().unwrap())
+		}
+	}
+
+	#[test]
+	fn test_passthrough() {
+		let origin = Origin::produce();
+		let mut intercept = Intercept::new([None]);
+
+		// Without hooks, the origins are returned as-is.
+		let publisher = intercept.publisher(Some(origin.producer.clone())).unwrap();
+		assert_eq!(publisher.root(), origin.producer.root());
+		assert!(intercept.tasks.is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_publisher() {
+		let hook = Arc::new(Recorder::default());
+		let mut intercept = Intercept::new([Some(hook.clone() as Arc<dyn Hook>)]);
+
+		let origin = Origin::produce();
+		let mut consumer = origin.consumer.consume();
+		let local = intercept.publisher(Some(origin.producer)).unwrap();
+
+		let mut rejected = Broadcast::produce();
+		local.publish_broadcast("reject", rejected.consumer.clone());
+
+		let mut broadcast = Broadcast::produce();
+		local.publish_broadcast("room", broadcast.consumer.clone());
+
+		// Only the accepted broadcast is announced.
+		let (path, proxy) = consumer.announced().await.unwrap();
+		assert_eq!(path.as_str(), "room");
+		assert_eq!(hook.take(), vec!["publish reject", "publish room"]);
+
+		// Serve a track so the usage is counted.
+		let mut track = proxy.unwrap().subscribe_track(&moq_lite::Track::new("video"));
+		let mut upstream = broadcast.producer.requested_track().await.unwrap();
+		let mut group = upstream.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"hello"));
+		group.write_frame(bytes::Bytes::from_static(b"world!"));
+		group.close();
+
+		let mut group = track.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "world!");
+
+		broadcast.producer.close();
+		drop(broadcast);
+		drop(upstream);
+		rejected.producer.close();
+
+		let (path, proxy) = consumer.announced().await.unwrap();
+		assert_eq!(path.as_str(), "room");
+		assert!(proxy.is_none());
+		assert_eq!(hook.take(), vec!["unpublish room 1 11"]);
+	}
+
+	#[tokio::test]
+	async fn test_subscriber() {
+		let hook = Arc::new(Recorder::default());
+		let mut intercept = Intercept::new([Some(hook.clone() as Arc<dyn Hook>)]);
+
+		let origin = Origin::produce();
+		let mut broadcast = Broadcast::produce();
+		origin.producer.publish_broadcast("room", broadcast.consumer.clone());
+
+		let mut local = intercept.subscriber(Some(origin.consumer)).unwrap();
+		let (_, proxy) = local.announced().await.unwrap();
+		let proxy = proxy.unwrap();
+
+		// A rejected subscription is aborted with the hook's error.
+		let mut rejected = proxy.subscribe_track(&moq_lite::Track::new("reject"));
+		assert!(matches!(
+			rejected.next_group().await,
+			Err(moq_lite::Error::LimitExceeded)
+		));
+
+		let mut track = proxy.subscribe_track(&moq_lite::Track::new("video"));
+		let mut upstream = broadcast.producer.requested_track().await.unwrap();
+		assert_eq!(upstream.info.name, "video");
+		upstream.write_frame(bytes::Bytes::from_static(b"hello"));
+
+		let mut group = track.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+
+		// The subscription ends when the track is no longer used.
+		drop(group);
+		drop(track);
+		drop(rejected);
+
+		tokio::time::timeout(Duration::from_secs(1), async {
+			while !hook
+				.calls
+				.lock()
+				.unwrap()
+				.iter()
+				.any(|call| call.starts_with("unsubscribe"))
+			{
+				tokio::task::yield_now().await;
+			}
+		})
+		.await
+		.expect("timed out");
+
+		assert_eq!(
+			hook.take(),
+			vec![
+				"subscribe room reject",
+				"subscribe room video",
+				"unsubscribe room video 1 5"
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_subscriber_requested() {
+		let hook = Arc::new(Recorder::default());
+		let mut intercept = Intercept::new([Some(hook.clone() as Arc<dyn Hook>)]);
+
+		let origin = Origin::produce();
+		let mut dynamic = origin.producer.dynamic();
+		let local = intercept.subscriber(origin.consumer.with_root("live")).unwrap();
+
+		// A broadcast that isn't announced is requested from the upstream origin.
+		let proxy = local.request_broadcast("room").unwrap();
+		let (path, mut broadcast) = dynamic.requested_broadcast().await.unwrap();
+		assert_eq!(path.as_str(), "live/room");
+
+		// The subscriptions are still passed through the hooks.
+		let mut track = proxy.subscribe_track(&moq_lite::Track::new("video"));
+		let mut upstream = broadcast.requested_track().await.unwrap();
+		upstream.write_frame(bytes::Bytes::from_static(b"hello"));
+
+		let mut group = track.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+		assert_eq!(hook.take(), vec!["subscribe live/room video"]);
+	}
+}
@@ -0,0 +1,448 @@
+use std::{
+	collections::HashMap,
+	net,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use axum::http;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{AuthToken, Hook, Throttle, Usage};
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum LimitError {
+	#[error("too many sessions from ip={0}")]
+	SessionsPerIp(net::IpAddr),
+
+	#[error("too many sessions for subject={0}")]
+	SessionsPerSubject(String),
+
+	#[error("too many broadcasts, max={0}")]
+	Broadcasts(usize),
+}
+
+impl From<LimitError> for http::StatusCode {
+	fn from(_: LimitError) -> Self {
+		http::StatusCode::TOO_MANY_REQUESTS
+	}
+}
+
+impl axum::response::IntoResponse for LimitError {
+	fn into_response(self) -> axum::response::Response {
+		http::StatusCode::TOO_MANY_REQUESTS.into_response()
+	}
+}
+
+/// Limits applied to each (non-cluster) session.
+///
+/// Everything is unlimited by default.
+#[derive(clap::Args, Clone, Debug, Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+	/// The maximum number of concurrent sessions from a single IP address.
+	#[arg(
+		id = "limit-sessions-per-ip",
+		long = "limit-sessions-per-ip",
+		env = "MOQ_LIMIT_SESSIONS_PER_IP"
+	)]
+	pub sessions_per_ip: Option<usize>,
+
+	/// The maximum number of concurrent sessions using a token with the same subject (`sub` claim).
+	/// Tokens without a subject are not limited.
+	#[arg(
+		id = "limit-sessions-per-subject",
+		long = "limit-sessions-per-subject",
+		env = "MOQ_LIMIT_SESSIONS_PER_SUBJECT"
+	)]
+	pub sessions_per_subject: Option<usize>,
+
+	/// The maximum number of broadcasts that a session can announce at the same time.
+	/// The session is closed with a limit exceeded error if it announces any more.
+	#[arg(id = "limit-broadcasts", long = "limit-broadcasts", env = "MOQ_LIMIT_BROADCASTS")]
+	pub broadcasts: Option<usize>,
+
+	/// The maximum number of tracks that a session can subscribe to at the same time.
+	/// Any additional subscriptions are rejected.
+	#[arg(
+		id = "limit-subscriptions",
+		long = "limit-subscriptions",
+		env = "MOQ_LIMIT_SUBSCRIPTIONS"
+	)]
+	pub subscriptions: Option<usize>,
+
+	/// The maximum egress bitrate of a session, in bits per second.
+	/// Must be greater than zero.
+	#[arg(id = "limit-egress", long = "limit-egress", env = "MOQ_LIMIT_EGRESS")]
+	pub egress: Option<u64>,
+}
+
+impl LimitsConfig {
+	pub fn init(self) -> anyhow::Result<Limits> {
+		anyhow::ensure!(self.egress != Some(0), "egress limit must be greater than zero");
+		Ok(Limits::new(self))
+	}
+}
+
+#[derive(Default)]
+struct LimitsState {
+	ip: HashMap<net::IpAddr, usize>,
+	subject: HashMap<String, usize>,
+}
+
+/// Tracks the number of active sessions, shared between all connections.
+#[derive(Clone)]
+pub struct Limits {
+	config: Arc<LimitsConfig>,
+	state: Arc<Mutex<LimitsState>>,
+}
+
+impl Limits {
+	pub fn new(config: LimitsConfig) -> Self {
+		Self {
+			config: Arc::new(config),
+			state: Default::default(),
+		}
+	}
+
+	/// Reserve a session for the given client, returning an error if it would exceed a limit.
+	///
+	/// Cluster nodes are exempt from all limits.
+	/// The session is released when the returned [SessionLimits] is dropped.
+	pub fn session(&self, ip: net::IpAddr, token: &AuthToken) -> Result<SessionLimits, LimitError> {
+		if token.cluster {
+			return Ok(SessionLimits::unlimited());
+		}
+
+		// Treat IPv4-mapped addresses the same as IPv4 addresses.
+		let ip = ip.to_canonical();
+		let subject = token.subject.clone();
+
+		let mut state = self.state.lock().unwrap();
+
+		if let Some(max) = self.config.sessions_per_ip {
+			if state.ip.get(&ip).copied().unwrap_or(0) >= max {
+				return Err(LimitError::SessionsPerIp(ip));
+			}
+		}
+
+		if let (Some(max), Some(subject)) = (self.config.sessions_per_subject, &subject) {
+			if state.subject.get(subject).copied().unwrap_or(0) >= max {
+				return Err(LimitError::SessionsPerSubject(subject.clone()));
+			}
+		}
+
+		*state.ip.entry(ip).or_default() += 1;
+		if let Some(subject) = &subject {
+			*state.subject.entry(subject.clone()).or_default() += 1;
+		}
+
+		Ok(SessionLimits {
+			session: Some((self.clone(), ip, subject)),
+			config: self.config.clone(),
+			exceeded: Default::default(),
+		})
+	}
+
+	fn release(&self, ip: net::IpAddr, subject: Option<String>) {
+		let mut state = self.state.lock().unwrap();
+
+		if let Some(count) = state.ip.get_mut(&ip) {
+			*count -= 1;
+			if *count == 0 {
+				state.ip.remove(&ip);
+			}
+		}
+
+		if let Some(subject) = subject {
+			if let Some(count) = state.subject.get_mut(&subject) {
+				*count -= 1;
+				if *count == 0 {
+					state.subject.remove(&subject);
+				}
+			}
+		}
+	}
+}
+
+/// The limits for a single session, released on drop.
+pub struct SessionLimits {
+	// The reservation for this session, or None if the session is exempt.
+	session: Option<(Limits, net::IpAddr, Option<String>)>,
+	config: Arc<LimitsConfig>,
+
+	// Set when a limit is exceeded and the session should be closed.
+	exceeded: watch::Sender<Option<LimitError>>,
+}
+
+impl SessionLimits {
+	fn unlimited() -> Self {
+		Self {
+			session: None,
+			config: Default::default(),
+			exceeded: Default::default(),
+		}
+	}
+
+	/// Wait until the session exceeds a limit that requires it to be closed.
+	pub async fn closed(&self) -> LimitError {
+		let mut exceeded = self.exceeded.subscribe();
+
+		// NOTE: The sender is owned by self, so this never returns an error.
+		let err = exceeded.wait_for(Option::is_some).await.expect("sender dropped");
+		err.clone().unwrap()
+	}
+
+	/// Enforce the broadcast and subscription limits via [Intercept], or None if they're unlimited.
+	pub fn hook(&self) -> Option<Arc<dyn Hook>> {
+		if self.config.broadcasts.is_none() && self.config.subscriptions.is_none() {
+			return None;
+		}
+
+		Some(Arc::new(LimitsHook {
+			broadcasts: self.config.broadcasts,
+			subscriptions: self.config.subscriptions,
+			publishing: Default::default(),
+			subscribing: Default::default(),
+			exceeded: self.exceeded.clone(),
+		}))
+	}
+
+	/// Enforce the egress bitrate limit on the session.
+	pub fn throttle<S: web_transport_trait::Session>(&self, session: S) -> Throttle<S> {
+		Throttle::new(session, self.config.egress)
+	}
+}
+
+impl Drop for SessionLimits {
+	fn drop(&mut self) {
+		if let Some((limits, ip, subject)) = self.session.take() {
+			limits.release(ip, subject);
+		}
+	}
+}
+
+// Counts the active broadcasts and subscriptions of a session.
+struct LimitsHook {
+	broadcasts: Option<usize>,
+	subscriptions: Option<usize>,
+
+	publishing: AtomicUsize,
+	subscribing: AtomicUsize,
+
+	exceeded: watch::Sender<Option<LimitError>>,
+}
+
+impl Hook for LimitsHook {
+	fn publish(&self, broadcast: &str) -> Result<(), moq_lite::Error> {
+		let Some(max) = self.broadcasts else {
+			return Ok(());
+		};
+
+		if !acquire(&self.publishing, max) {
+			tracing::warn!(%broadcast, %max, "broadcast limit exceeded");
+			self.exceeded.send_replace(Some(LimitError::Broadcasts(max)));
+			return Err(moq_lite::Error::LimitExceeded);
+		}
+
+		Ok(())
+	}
+
+	fn unpublish(&self, _broadcast: &str, _usage: Usage) {
+		if self.broadcasts.is_some() {
+			self.publishing.fetch_sub(1, Ordering::Relaxed);
+		}
+	}
+
+	fn subscribe(&self, broadcast: &str, track: &str) -> Result<(), moq_lite::Error> {
+		let Some(max) = self.subscriptions else {
+			return Ok(());
+		};
+
+		if !acquire(&self.subscribing, max) {
+			tracing::warn!(%broadcast, %track, %max, "subscription limit exceeded");
+			return Err(moq_lite::Error::LimitExceeded);
+		}
+
+		Ok(())
+	}
+
+	fn unsubscribe(&self, _broadcast: &str, _track: &str, _usage: Usage) {
+		if self.subscriptions.is_some() {
+			self.subscribing.fetch_sub(1, Ordering::Relaxed);
+		}
+	}
+}
+
+// Increment the counter unless it has reached the maximum.
+fn acquire(count: &AtomicUsize, max: usize) -> bool {
+	count
+		.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+			(count < max).then_some(count + 1)
+		})
+		.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Intercept;
+	use moq_lite::{Broadcast, Origin};
+
+	fn token(subject: Option<&str>, cluster: bool) -> AuthToken {
+		AuthToken {
+			root: "".into(),
+			subject: subject.map(|s| s.to_string()),
+			subscribe: vec!["".into()],
+			publish: vec!["".into()],
+			cluster,
+		}
+	}
+
+	#[test]
+	fn test_sessions_per_ip() {
+		let limits = Limits::new(LimitsConfig {
+			sessions_per_ip: Some(2),
+			..Default::default()
+		});
+
+		let ip: net::IpAddr = "10.0.0.1".parse().unwrap();
+		let other: net::IpAddr = "10.0.0.2".parse().unwrap();
+
+		let a = limits.session(ip, &token(None, false)).unwrap();
+		let _b = limits.session(ip, &token(None, false)).unwrap();
+		assert!(matches!(
+			limits.session(ip, &token(None, false)),
+			Err(LimitError::SessionsPerIp(_))
+		));
+
+		// A different IP is not affected.
+		let _c = limits.session(other, &token(None, false)).unwrap();
+
+		// Releasing a session frees up a slot.
+		drop(a);
+		let _d = limits.session(ip, &token(None, false)).unwrap();
+	}
+
+	#[test]
+	fn test_sessions_per_ip_mapped() {
+		let limits = Limits::new(LimitsConfig {
+			sessions_per_ip: Some(1),
+			..Default::default()
+		});
+
+		let v4: net::IpAddr = "10.0.0.1".parse().unwrap();
+		let v6: net::IpAddr = "::ffff:10.0.0.1".parse().unwrap();
+
+		let _a = limits.session(v4, &token(None, false)).unwrap();
+		assert!(limits.session(v6, &token(None, false)).is_err());
+	}
+
+	#[test]
+	fn test_sessions_per_subject() {
+		let limits = Limits::new(LimitsConfig {
+			sessions_per_subject: Some(1),
+			..Default::default()
+		});
+
+		let ip: net::IpAddr = "10.0.0.1".parse().unwrap();
+
+		let _a = limits.session(ip, &token(Some("alice"), false)).unwrap();
+		assert!(matches!(
+			limits.session(ip, &token(Some("alice"), false)),
+			Err(LimitError::SessionsPerSubject(_))
+		));
+
+		// Other subjects and tokens without a subject are not affected.
+		let _b = limits.session(ip, &token(Some("bob"), false)).unwrap();
+		let _c = limits.session(ip, &token(None, false)).unwrap();
+		let _d = limits.session(ip, &token(None, false)).unwrap();
+	}
+
+	#[test]
+	fn test_cluster_exempt() {
+		let limits = Limits::new(LimitsConfig {
+			sessions_per_ip: Some(0),
+			..Default::default()
+		});
+
+		let ip: net::IpAddr = "10.0.0.1".parse().unwrap();
+		assert!(limits.session(ip, &token(None, false)).is_err());
+		assert!(limits.session(ip, &token(None, true)).is_ok());
+	}
+
+	#[test]
+	fn test_egress_zero() {
+		let config = LimitsConfig {
+			egress: Some(0),
+			..Default::default()
+		};
+		assert!(config.init().is_err());
+	}
+
+	#[tokio::test]
+	async fn test_broadcast_limit() {
+		let limits = Limits::new(LimitsConfig {
+			broadcasts: Some(1),
+			..Default::default()
+		});
+
+		let ip: net::IpAddr = "10.0.0.1".parse().unwrap();
+		let session = limits.session(ip, &token(None, false)).unwrap();
+		let mut intercept = Intercept::new([session.hook()]);
+
+		let origin = Origin::produce();
+		let mut consumer = origin.consumer.consume();
+		let local = intercept.publisher(Some(origin.producer)).unwrap();
+
+		let first = Broadcast::produce();
+		let second = Broadcast::produce();
+		local.publish_broadcast("first", first.consumer.clone());
+		local.publish_broadcast("second", second.consumer.clone());
+
+		let (path, active) = consumer.announced().await.unwrap();
+		assert_eq!(path, "first".into());
+		assert!(active.is_some());
+
+		// The second broadcast closes the session.
+		assert!(matches!(session.closed().await, LimitError::Broadcasts(1)));
+		assert!(consumer.try_announced().is_none());
+	}
+
+	#[tokio::test]
+	async fn test_subscription_limit() {
+		let limits = Limits::new(LimitsConfig {
+			subscriptions: Some(1),
+			..Default::default()
+		});
+
+		let ip: net::IpAddr = "10.0.0.1".parse().unwrap();
+		let session = limits.session(ip, &token(None, false)).unwrap();
+		let mut intercept = Intercept::new([session.hook()]);
+
+		let origin = Origin::produce();
+		let mut broadcast = Broadcast::produce();
+		origin.producer.publish_broadcast("test", broadcast.consumer.clone());
+
+		let mut local = intercept.subscriber(Some(origin.consumer)).unwrap();
+		let (_, proxy) = local.announced().await.unwrap();
+		let proxy = proxy.unwrap();
+
+		let mut first = proxy.subscribe_track(&moq_lite::Track::new("first"));
+		let mut second = proxy.subscribe_track(&moq_lite::Track::new("second"));
+
+		// The first subscription is forwarded upstream.
+		let mut track = broadcast.producer.requested_track().await.unwrap();
+		assert_eq!(track.info.name, "first");
+		track.write_frame(bytes::Bytes::from_static(b"hello"));
+
+		let mut group = first.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+
+		// The second subscription is rejected.
+		assert!(matches!(second.next_group().await, Err(moq_lite::Error::LimitExceeded)));
+	}
+}
@@ -1,34 +1,79 @@
+mod audit;
 mod auth;
 mod cluster;
 mod config;
 mod connection;
+mod fetch;
+mod intercept;
+mod limits;
+mod pull;
+mod record;
+mod rewrite;
+mod router;
+mod throttle;
 mod web;
+mod webhook;
 
+pub use audit::*;
 pub use auth::*;
 pub use cluster::*;
 pub use config::*;
 pub use connection::*;
+pub use fetch::*;
+pub use intercept::*;
+pub use limits::*;
+pub use pull::*;
+pub use record::*;
+pub use rewrite::*;
+pub use router::*;
+pub use throttle::*;
 pub use web::*;
+pub use webhook::*;
+
+use anyhow::Context;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-	let config = Config::load()?;
+	let mut config = Config::load()?;
+
+	// Cluster nodes may authenticate with a client certificate for any of the configured hostnames.
+	config.auth.cluster.extend(config.cluster.nodes().cloned());
+
+	// Never fail open for the cluster prefix, otherwise anyone could announce a node while the webhook is down.
+	config.auth.webhook.protected.push(config.cluster.prefix.clone());
 
 	let addr = config.server.bind.unwrap_or("[::]:443".parse().unwrap());
 	let mut server = config.server.init()?;
 	let client = config.client.init()?;
 	let auth = config.auth.init()?;
+	let limits = config.limits.init().context("invalid limits")?;
+	let audit = config.audit.init().context("failed to open audit log")?;
+	let rewrite = config.rewrite.init().context("invalid rewrite rule")?;
 	let fingerprints = server.fingerprints().to_vec();
 
-	let cluster = Cluster::new(config.cluster, client);
+	let pull = config.pull.init(client.clone());
+	let cluster = Cluster::new(config.cluster, client, rewrite, pull);
 	let cloned = cluster.clone();
 	tokio::spawn(async move { cloned.run().await.expect("cluster failed") });
 
+	// Record broadcasts published to this node, so each broadcast is only recorded once per cluster.
+	if let Some(prefix) = config.record.prefix.clone() {
+		let origin = cluster
+			.primary
+			.consumer
+			.consume_only(&[prefix.as_str().into()])
+			.context("invalid record prefix")?;
+		tokio::spawn(Recorder::new(config.record).run(origin));
+	}
+
 	// Create a web server too.
 	let web = Web::new(
 		WebState {
 			auth: auth.clone(),
 			cluster: cluster.clone(),
+			limits: limits.clone(),
+			audit: audit.clone(),
+			fetch: FetchCache::new(config.web.fetch.clone()),
 			fingerprints,
 			conn_id: Default::default(),
 		},
@@ -52,6 +97,8 @@ async fn main() -> anyhow::Result<()> {
 			request,
 			cluster: cluster.clone(),
 			auth: auth.clone(),
+			limits: limits.clone(),
+			audit: audit.clone(),
 		};
 
 		conn_id += 1;
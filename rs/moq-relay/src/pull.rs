@@ -0,0 +1,470 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex, Weak},
+	time::Duration,
+};
+
+use anyhow::Context;
+use futures::FutureExt;
+use moq_lite::{
+	Broadcast, BroadcastConsumer, BroadcastProducer, Origin, OriginConsumer, OriginProducer, PathOwned, TrackProducer,
+};
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+use url::Url;
+
+#[derive(clap::Args, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct PullConfig {
+	/// Fetch broadcasts that aren't announced to the cluster from this upstream MoQ server.
+	///
+	/// The connection is made on demand and shared by all pulled broadcasts.
+	#[arg(long = "pull-upstream", id = "pull-upstream", env = "MOQ_PULL_UPSTREAM")]
+	pub upstream: Option<Url>,
+
+	/// How long to wait for the upstream to announce a requested broadcast.
+	/// Defaults to 5s.
+	#[arg(
+		long = "pull-timeout",
+		id = "pull-timeout",
+		env = "MOQ_PULL_TIMEOUT",
+		value_parser = humantime::parse_duration
+	)]
+	#[serde(with = "humantime_serde")]
+	pub timeout: Option<Duration>,
+
+	/// Keep a pulled broadcast (and the upstream connection) open for this long after its last subscription ends.
+	/// Defaults to 30s.
+	#[arg(
+		long = "pull-idle",
+		id = "pull-idle",
+		env = "MOQ_PULL_IDLE",
+		value_parser = humantime::parse_duration
+	)]
+	#[serde(with = "humantime_serde")]
+	pub idle: Option<Duration>,
+}
+
+impl PullConfig {
+	pub fn init(self, client: moq_native::Client) -> Option<Pull> {
+		let url = self.upstream?;
+
+		Some(Pull {
+			url,
+			client,
+			timeout: self.timeout.unwrap_or(Duration::from_secs(5)),
+			idle: self.idle.unwrap_or(Duration::from_secs(30)),
+			upstream: Default::default(),
+			pulling: Default::default(),
+		})
+	}
+}
+
+// A session with the upstream, closed when the last pulled broadcast is unused.
+struct Upstream {
+	// Broadcasts announced by the upstream.
+	origin: OriginConsumer,
+
+	// Runs the session.
+	task: tokio::task::AbortHandle,
+}
+
+impl Drop for Upstream {
+	fn drop(&mut self) {
+		self.task.abort();
+	}
+}
+
+/// Pulls broadcasts from an upstream server on demand.
+#[derive(Clone)]
+pub struct Pull {
+	url: Url,
+	client: moq_native::Client,
+	timeout: Duration,
+	idle: Duration,
+
+	// The current session, if any broadcasts are still being pulled.
+	upstream: Arc<tokio::sync::Mutex<Weak<Upstream>>>,
+
+	// The broadcasts currently being pulled, shared by concurrent requests for the same name.
+	pulling: Arc<Mutex<HashMap<PathOwned, BroadcastConsumer>>>,
+}
+
+impl Pull {
+	/// Serve a requested broadcast from the upstream, connecting if needed.
+	///
+	/// The broadcast may be announced by the upstream under any of the paths, ex. the original name of an alias.
+	/// Concurrent requests for the same name share a single pull, which is published to the origin once found so later sessions reuse it.
+	/// Any subscriptions fail with [moq_lite::Error::NotFound] if the upstream is unavailable or doesn't announce it in time.
+	pub async fn serve(
+		&self,
+		origin: &OriginProducer,
+		name: PathOwned,
+		paths: &[PathOwned],
+		request: BroadcastProducer,
+	) {
+		let pulled = {
+			let mut pulling = self.pulling.lock().unwrap();
+
+			match pulling.get(&name) {
+				Some(pulled) if pulled.closed().now_or_never().is_none() => pulled.clone(),
+				_ => {
+					let pulled = Broadcast::produce();
+					pulling.insert(name.clone(), pulled.consumer.clone());
+
+					let task = self.clone().pull(origin.clone(), name, paths.to_vec(), pulled.producer);
+					tokio::spawn(task.in_current_span());
+
+					pulled.consumer
+				}
+			}
+		};
+
+		Self::forward(pulled, request).await
+	}
+
+	// Pull the broadcast from the upstream and publish it to the origin until it's idle.
+	async fn pull(self, origin: OriginProducer, name: PathOwned, paths: Vec<PathOwned>, proxy: BroadcastProducer) {
+		let pulled = proxy.consume();
+
+		match self.find(&paths).await {
+			Some((upstream, path, broadcast)) => {
+				tracing::info!(broadcast = %path, "pulling broadcast");
+				origin.publish_broadcast(&name, pulled.clone());
+				Self::run_proxy(upstream, broadcast, proxy, self.idle).await;
+			}
+			None => Self::reject(proxy),
+		}
+
+		// The next request will start a new pull.
+		let mut pulling = self.pulling.lock().unwrap();
+		if pulling.get(&name).is_some_and(|current| current.is_clone(&pulled)) {
+			pulling.remove(&name);
+		}
+	}
+
+	// Connect to the upstream and wait for it to announce the broadcast under any of the paths.
+	async fn find(&self, paths: &[PathOwned]) -> Option<(Arc<Upstream>, PathOwned, BroadcastConsumer)> {
+		if paths.is_empty() {
+			return None;
+		}
+
+		let upstream = match self.connect().await {
+			Ok(upstream) => upstream,
+			Err(err) => {
+				tracing::warn!(%err, "failed to connect to pull upstream");
+				return None;
+			}
+		};
+
+		let (path, broadcast) = Self::announced(&upstream.origin, paths, self.timeout).await?;
+		Some((upstream, path, broadcast))
+	}
+
+	// Serve a single request from the shared pull, failing any remaining subscriptions if the pull ends.
+	async fn forward(pulled: BroadcastConsumer, mut request: BroadcastProducer) {
+		let unused = request.unused();
+		tokio::pin!(unused);
+
+		loop {
+			tokio::select! {
+				Some(track) = request.requested_track() => {
+					tokio::spawn(Self::run_track(pulled.clone(), track));
+				}
+				_ = &mut unused => return,
+				_ = pulled.closed() => break,
+			}
+		}
+
+		Self::reject(request);
+	}
+
+	// Fail any subscriptions that are waiting for a broadcast the upstream doesn't have.
+	fn reject(mut broadcast: BroadcastProducer) {
+		broadcast.close();
+
+		while let Some(track) = broadcast.requested_track().now_or_never().flatten() {
+			track.abort(moq_lite::Error::NotFound);
+		}
+	}
+
+	async fn connect(&self) -> anyhow::Result<Arc<Upstream>> {
+		// Hold the lock while connecting so concurrent requests share the session.
+		let mut current = self.upstream.lock().await;
+
+		if let Some(upstream) = current.upgrade() {
+			if !upstream.task.is_finished() {
+				return Ok(upstream);
+			}
+		}
+
+		tracing::info!(url = %self.url, "connecting to pull upstream");
+
+		let conn = self
+			.client
+			.connect(self.url.clone())
+			.await
+			.context("failed to connect to upstream")?;
+
+		let origin = Origin::produce();
+		let session = moq_lite::Session::connect(conn, None, origin.producer)
+			.await
+			.context("failed to establish session")?;
+
+		let task = tokio::spawn(async move {
+			match session.closed().await {
+				Ok(()) => tracing::info!("pull upstream closed"),
+				Err(err) => tracing::warn!(%err, "pull upstream error"),
+			}
+		});
+
+		let upstream = Arc::new(Upstream {
+			origin: origin.consumer,
+			task: task.abort_handle(),
+		});
+		*current = Arc::downgrade(&upstream);
+
+		Ok(upstream)
+	}
+
+	// Wait until the upstream announces the broadcast under any of the paths.
+	async fn announced(
+		origin: &OriginConsumer,
+		paths: &[PathOwned],
+		timeout: Duration,
+	) -> Option<(PathOwned, BroadcastConsumer)> {
+		let prefixes: Vec<_> = paths.iter().map(|path| path.borrow()).collect();
+		let mut announced = origin.consume_only(&prefixes)?;
+
+		let wait = async {
+			while let Some((name, broadcast)) = announced.announced().await {
+				if paths.contains(&name) {
+					if let Some(broadcast) = broadcast {
+						return Some((name, broadcast));
+					}
+				}
+			}
+
+			None
+		};
+
+		tokio::time::timeout(timeout, wait).await.ok().flatten()
+	}
+
+	// Serve the broadcast through the proxy, keeping the session open until no tracks have been subscribed for a while.
+	//
+	// The proxy is published to the origin, so it's only unused once its tracks are.
+	async fn run_proxy(
+		upstream: Arc<Upstream>,
+		broadcast: BroadcastConsumer,
+		mut proxy: BroadcastProducer,
+		idle: Duration,
+	) {
+		let mut tracks = tokio::task::JoinSet::new();
+
+		loop {
+			let empty = tracks.is_empty();
+			let idle = async move {
+				match empty {
+					true => tokio::time::sleep(idle).await,
+					false => std::future::pending().await,
+				}
+			};
+
+			tokio::select! {
+				Some(track) = proxy.requested_track() => {
+					tracks.spawn(Self::run_track(broadcast.clone(), track));
+				}
+				Some(_) = tracks.join_next() => {}
+				_ = idle => break,
+				_ = broadcast.closed() => break,
+			}
+		}
+
+		// Let the tracks finish on their own; the upstream ends them when the broadcast is closed.
+		tracks.detach_all();
+
+		// Unannounce the proxy and release the session.
+		drop(proxy);
+		drop(upstream);
+	}
+
+	async fn run_track(broadcast: BroadcastConsumer, mut track: TrackProducer) {
+		let mut upstream = broadcast.subscribe_track(&track.info);
+
+		loop {
+			tokio::select! {
+				res = upstream.next_group() => match res {
+					Ok(Some(group)) => {
+						track.insert_group(group);
+					}
+					Ok(None) => return track.close(),
+					Err(err) => return track.abort(err),
+				},
+				_ = track.unused() => return,
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use moq_lite::{Broadcast, Path, Track};
+
+	fn upstream(origin: OriginConsumer) -> Arc<Upstream> {
+		let task = tokio::spawn(std::future::pending::<()>());
+
+		Arc::new(Upstream {
+			origin,
+			task: task.abort_handle(),
+		})
+	}
+
+	fn paths(paths: &[&str]) -> Vec<PathOwned> {
+		paths.iter().map(|path| Path::new(path).to_owned()).collect()
+	}
+
+	#[tokio::test]
+	async fn test_announced() {
+		let origin = Origin::produce();
+		let timeout = Duration::from_millis(100);
+
+		// Not announced yet.
+		assert!(Pull::announced(&origin.consumer, &paths(&["demo"]), timeout)
+			.await
+			.is_none());
+
+		// Announced after the request.
+		let broadcast = Broadcast::produce();
+		let paths = paths(&["demo", "studio/main"]);
+		let wait = Pull::announced(&origin.consumer, &paths, timeout);
+		origin
+			.producer
+			.publish_broadcast("demo/other", broadcast.consumer.clone());
+		origin
+			.producer
+			.publish_broadcast("studio/main", broadcast.consumer.clone());
+
+		let (path, found) = wait.await.unwrap();
+		assert_eq!(path.as_str(), "studio/main");
+		assert!(found.is_clone(&broadcast.consumer));
+	}
+
+	#[tokio::test]
+	async fn test_proxy() {
+		let origin = Origin::produce();
+		let mut broadcast = Broadcast::produce();
+		origin.producer.publish_broadcast("demo", broadcast.consumer.clone());
+
+		let upstream = upstream(origin.consumer.consume());
+		let weak = Arc::downgrade(&upstream);
+
+		let (_, pulled) = Pull::announced(&upstream.origin, &paths(&["demo"]), Duration::from_secs(1))
+			.await
+			.unwrap();
+
+		let proxy = Broadcast::produce();
+		tokio::spawn(Pull::run_proxy(
+			upstream,
+			pulled,
+			proxy.producer,
+			Duration::from_millis(50),
+		));
+		let proxy = proxy.consumer;
+
+		// The subscription is forwarded to the upstream broadcast.
+		let mut track = proxy.subscribe_track(&Track::new("video"));
+		let mut requested = broadcast.producer.requested_track().await.unwrap();
+
+		let mut group = requested.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"frame"));
+		group.close();
+
+		let mut group = track.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "frame");
+
+		// The session is kept while the track is used, even though the proxy is still referenced.
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		assert!(weak.upgrade().is_some());
+
+		drop(group);
+		drop(track);
+
+		tokio::time::timeout(Duration::from_secs(1), async {
+			while weak.upgrade().is_some() {
+				tokio::time::sleep(Duration::from_millis(10)).await;
+			}
+		})
+		.await
+		.expect("session was not released");
+	}
+
+	#[tokio::test]
+	async fn test_serve_shared() {
+		let upstream_origin = Origin::produce();
+		let mut broadcast = Broadcast::produce();
+		upstream_origin
+			.producer
+			.publish_broadcast("demo", broadcast.consumer.clone());
+
+		let config = PullConfig {
+			upstream: Some("https://localhost".parse().unwrap()),
+			..Default::default()
+		};
+		let pull = config
+			.init(moq_native::ClientConfig::default().init().unwrap())
+			.unwrap();
+
+		// Skip connecting, since the upstream session is already open.
+		let session = upstream(upstream_origin.consumer.consume());
+		*pull.upstream.lock().await = Arc::downgrade(&session);
+
+		let origin = Origin::produce();
+		let name = Path::new("demo").to_owned();
+
+		// Two sessions request the broadcast at the same time.
+		let first = Broadcast::produce();
+		let second = Broadcast::produce();
+		for request in [first.producer, second.producer] {
+			let pull = pull.clone();
+			let origin = origin.producer.clone();
+			let name = name.clone();
+			tokio::spawn(async move { pull.serve(&origin, name.clone(), &[name], request).await });
+		}
+
+		let mut first = first.consumer.subscribe_track(&Track::new("video"));
+		let mut second = second.consumer.subscribe_track(&Track::new("video"));
+
+		// Only a single subscription is made to the upstream.
+		let mut requested = broadcast.producer.requested_track().await.unwrap();
+		assert!(
+			tokio::time::timeout(Duration::from_millis(50), broadcast.producer.requested_track())
+				.await
+				.is_err()
+		);
+
+		let mut group = requested.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"frame"));
+		group.close();
+
+		for track in [&mut first, &mut second] {
+			let mut group = track.next_group().await.unwrap().unwrap();
+			assert_eq!(group.read_frame().await.unwrap().unwrap(), "frame");
+		}
+
+		// The pulled broadcast is published, so later sessions don't need to request it.
+		assert!(origin.consumer.consume_broadcast("demo").is_some());
+		assert_eq!(pull.pulling.lock().unwrap().len(), 1);
+	}
+
+	#[tokio::test]
+	async fn test_reject() {
+		let broadcast = Broadcast::produce();
+		let mut track = broadcast.consumer.subscribe_track(&Track::new("video"));
+
+		// Subscriptions fail when the broadcast can't be pulled.
+		Pull::reject(broadcast.producer);
+		assert!(matches!(track.next_group().await, Err(moq_lite::Error::NotFound)));
+	}
+}
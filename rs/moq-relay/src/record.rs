@@ -0,0 +1,715 @@
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use futures::{stream::FuturesUnordered, StreamExt};
+use moq_lite::{BroadcastConsumer, GroupConsumer, OriginConsumer, PathOwned, Track, TrackConsumer};
+use serde::{Deserialize, Serialize};
+use tokio::{
+	io::{AsyncWriteExt, BufWriter},
+	sync::{mpsc, watch},
+	time::Instant,
+};
+
+/// The magic bytes at the start of each recorded track file.
+pub const RECORD_MAGIC: &[u8; 8] = b"moqrec01";
+
+// The track used to discover the other tracks in a hang broadcast.
+const CATALOG_TRACK: &str = hang::catalog::Catalog::DEFAULT_NAME;
+
+// Keep the recording open for this long after a broadcast is unannounced, in case the publisher reconnects.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Keep writing the groups that were open during a rotation to the previous segment for this long.
+const ROTATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Write the index in chunks of this size, after flushing the data it points to.
+const INDEX_BUFFER: usize = 4096;
+
+#[derive(clap::Args, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RecordConfig {
+	/// Record all broadcasts published to this relay that start with this prefix.
+	/// Use an empty string to record everything; recording is disabled if not set.
+	#[arg(id = "record-prefix", long = "record-prefix", env = "MOQ_RECORD_PREFIX")]
+	pub prefix: Option<String>,
+
+	/// The directory to write recordings to.
+	/// Defaults to `./recordings`.
+	#[arg(id = "record-dir", long = "record-dir", env = "MOQ_RECORD_DIR")]
+	pub dir: Option<PathBuf>,
+
+	/// Start a new segment after this long.
+	/// Defaults to 1h.
+	#[arg(
+		id = "record-rotate-time",
+		long = "record-rotate-time",
+		env = "MOQ_RECORD_ROTATE_TIME",
+		value_parser = humantime::parse_duration
+	)]
+	#[serde(with = "humantime_serde")]
+	pub rotate_time: Option<Duration>,
+
+	/// Start a new segment after this many bytes have been written.
+	/// Defaults to 1GiB.
+	#[arg(
+		id = "record-rotate-size",
+		long = "record-rotate-size",
+		env = "MOQ_RECORD_ROTATE_SIZE"
+	)]
+	pub rotate_size: Option<u64>,
+
+	/// Additional tracks to record for each broadcast.
+	/// The `catalog.json` track and any tracks listed in it are always recorded.
+	#[arg(
+		id = "record-track",
+		long = "record-track",
+		env = "MOQ_RECORD_TRACK",
+		value_delimiter = ','
+	)]
+	pub tracks: Vec<String>,
+}
+
+/// The contents of `meta.json` in each segment directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecordMeta {
+	/// The path of the broadcast.
+	pub broadcast: String,
+
+	/// When the segment was started, in milliseconds since the Unix epoch.
+	pub started: u64,
+
+	/// The ID of each track, used for the `<id>.moq` and `<id>.idx` file names.
+	pub tracks: HashMap<String, u32>,
+}
+
+// A frame received from a track.
+struct Record {
+	track: String,
+	group: u64,
+	payload: Bytes,
+	received: Instant,
+
+	// Incremented each time the publisher reconnects, since the group sequences start over.
+	epoch: u64,
+}
+
+struct TrackFile {
+	data: BufWriter<tokio::fs::File>,
+	index: tokio::fs::File,
+	offset: u64,
+
+	// The largest group written to this file.
+	group: u64,
+
+	// Index entries that are waiting for the data to be flushed.
+	pending: Vec<u8>,
+}
+
+impl TrackFile {
+	// Flush the data before the index, so the index never points past the data.
+	async fn flush(&mut self) -> std::io::Result<()> {
+		self.data.flush().await?;
+		self.index.write_all(&self.pending).await?;
+		self.index.flush().await?;
+		self.pending.clear();
+
+		Ok(())
+	}
+}
+
+struct Segment {
+	dir: PathBuf,
+	meta: RecordMeta,
+	started: Instant,
+	size: u64,
+	epoch: u64,
+	tracks: HashMap<String, TrackFile>,
+}
+
+impl Segment {
+	async fn open(dir: PathBuf, broadcast: &str, epoch: u64) -> std::io::Result<Self> {
+		let mut started = SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_millis() as u64;
+
+		tokio::fs::create_dir_all(&dir).await?;
+
+		// Never reuse a directory, even if we rotate twice in the same millisecond.
+		let dir = loop {
+			let path = dir.join(started.to_string());
+			match tokio::fs::create_dir(&path).await {
+				Ok(()) => break path,
+				Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => started += 1,
+				Err(err) => return Err(err),
+			}
+		};
+
+		tracing::info!(dir = %dir.display(), "recording segment");
+
+		let segment = Self {
+			dir,
+			meta: RecordMeta {
+				broadcast: broadcast.to_string(),
+				started,
+				tracks: HashMap::new(),
+			},
+			started: Instant::now(),
+			size: 0,
+			epoch,
+			tracks: HashMap::new(),
+		};
+		segment.write_meta().await?;
+
+		Ok(segment)
+	}
+
+	async fn write_meta(&self) -> std::io::Result<()> {
+		// Write to a temporary file first so a crash never leaves a partial meta.json behind.
+		let tmp = self.dir.join("meta.json.tmp");
+		tokio::fs::write(&tmp, serde_json::to_vec_pretty(&self.meta)?).await?;
+		tokio::fs::rename(&tmp, self.dir.join("meta.json")).await
+	}
+
+	async fn track(&mut self, name: &str) -> std::io::Result<&mut TrackFile> {
+		if !self.tracks.contains_key(name) {
+			let id = self.meta.tracks.len() as u32;
+
+			let mut data = BufWriter::new(tokio::fs::File::create(self.dir.join(format!("{id}.moq"))).await?);
+			let index = tokio::fs::File::create(self.dir.join(format!("{id}.idx"))).await?;
+			data.write_all(RECORD_MAGIC).await?;
+
+			self.meta.tracks.insert(name.to_string(), id);
+			self.write_meta().await?;
+
+			self.tracks.insert(
+				name.to_string(),
+				TrackFile {
+					data,
+					index,
+					offset: RECORD_MAGIC.len() as u64,
+					group: 0,
+					pending: Vec::with_capacity(INDEX_BUFFER),
+				},
+			);
+		}
+
+		Ok(self.tracks.get_mut(name).unwrap())
+	}
+
+	async fn write(&mut self, record: &Record) -> std::io::Result<()> {
+		let length = u32::try_from(record.payload.len())
+			.map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "frame too large"))?;
+
+		let received = record.received.saturating_duration_since(self.started).as_micros() as u64;
+		let track = self.track(&record.track).await?;
+
+		// Index every frame, since frames from different groups may be interleaved.
+		track.pending.extend_from_slice(&record.group.to_be_bytes());
+		track.pending.extend_from_slice(&received.to_be_bytes());
+		track.pending.extend_from_slice(&track.offset.to_be_bytes());
+
+		track.data.write_u64(record.group).await?;
+		track.data.write_u64(received).await?;
+		track.data.write_u32(length).await?;
+		track.data.write_all(&record.payload).await?;
+
+		let size = 20 + record.payload.len() as u64;
+		track.offset += size;
+		track.group = track.group.max(record.group);
+
+		if track.pending.len() >= INDEX_BUFFER {
+			track.flush().await?;
+		}
+
+		self.size += size;
+
+		Ok(())
+	}
+
+	async fn close(mut self) -> std::io::Result<()> {
+		for track in self.tracks.values_mut() {
+			track.flush().await?;
+		}
+
+		tracing::info!(dir = %self.dir.display(), size = self.size, "finished segment");
+
+		Ok(())
+	}
+}
+
+/// Writes the recording of a single broadcast, rotating segments by time and size.
+struct Writer {
+	dir: PathBuf,
+	broadcast: String,
+	rotate_time: Duration,
+	rotate_size: u64,
+	segment: Option<Segment>,
+
+	// The segment before a rotation, which receives the rest of any groups that were still open.
+	previous: Option<Segment>,
+}
+
+impl Writer {
+	fn new(config: &RecordConfig, broadcast: &str) -> Self {
+		let root = config.dir.clone().unwrap_or_else(|| PathBuf::from("recordings"));
+
+		Self {
+			dir: root.join(broadcast),
+			broadcast: broadcast.to_string(),
+			rotate_time: config.rotate_time.unwrap_or(Duration::from_secs(3600)),
+			rotate_size: config.rotate_size.unwrap_or(1024 * 1024 * 1024),
+			segment: None,
+			previous: None,
+		}
+	}
+
+	async fn write(&mut self, record: &Record) -> std::io::Result<()> {
+		// Start a new segment when the publisher reconnects, so group sequences only increase within a segment.
+		if self
+			.segment
+			.as_ref()
+			.is_some_and(|segment| segment.epoch != record.epoch)
+		{
+			self.close().await?;
+		}
+
+		if self
+			.segment
+			.as_ref()
+			.is_some_and(|segment| segment.started.elapsed() >= ROTATE_TIMEOUT)
+		{
+			if let Some(previous) = self.previous.take() {
+				previous.close().await?;
+			}
+		}
+
+		// Finish any group that was open during the rotation in the previous segment, so a group is never split.
+		if let Some(previous) = &mut self.previous {
+			if previous
+				.tracks
+				.get(&record.track)
+				.is_some_and(|track| record.group <= track.group)
+			{
+				return previous.write(record).await;
+			}
+		}
+
+		if let Some(segment) = &self.segment {
+			// Only rotate at the start of a group, so each track in a segment starts with a keyframe.
+			let due = segment.size >= self.rotate_size || segment.started.elapsed() >= self.rotate_time;
+			let boundary = segment
+				.tracks
+				.get(&record.track)
+				.is_none_or(|track| record.group > track.group);
+
+			if due && boundary {
+				if let Some(previous) = self.previous.take() {
+					previous.close().await?;
+				}
+
+				self.previous = self.segment.take();
+			}
+		}
+
+		let segment = match &mut self.segment {
+			Some(segment) => segment,
+			None => self
+				.segment
+				.insert(Segment::open(self.dir.clone(), &self.broadcast, record.epoch).await?),
+		};
+
+		segment.write(record).await
+	}
+
+	async fn close(&mut self) -> std::io::Result<()> {
+		if let Some(previous) = self.previous.take() {
+			previous.close().await?;
+		}
+
+		match self.segment.take() {
+			Some(segment) => segment.close().await,
+			None => Ok(()),
+		}
+	}
+}
+
+/// Records broadcasts to disk as they are announced.
+///
+/// Each broadcast is written to `<dir>/<broadcast>/<started>/`, see the README for the format.
+#[derive(Clone)]
+pub struct Recorder {
+	config: RecordConfig,
+}
+
+impl Recorder {
+	pub fn new(config: RecordConfig) -> Self {
+		Self { config }
+	}
+
+	/// Record every broadcast announced by the origin until it's closed.
+	pub async fn run(self, mut origin: OriginConsumer) {
+		let mut recordings: HashMap<PathOwned, watch::Sender<Option<BroadcastConsumer>>> = HashMap::new();
+
+		while let Some((path, broadcast)) = origin.announced().await {
+			// Forget about recordings that have finished.
+			recordings.retain(|_, recording| !recording.is_closed());
+
+			if let Some(recording) = recordings.get(&path) {
+				// Continue the existing recording when the publisher reconnects.
+				recording.send_replace(broadcast);
+				continue;
+			}
+
+			let broadcast = match broadcast {
+				Some(broadcast) => broadcast,
+				None => continue,
+			};
+
+			if !is_safe(path.as_str()) {
+				tracing::warn!(broadcast = %path, "not recording broadcast with an unsafe path");
+				continue;
+			}
+
+			let (recording, announced) = watch::channel(Some(broadcast));
+			tokio::spawn(self.clone().run_broadcast(path.clone(), announced));
+			recordings.insert(path, recording);
+		}
+	}
+
+	async fn run_broadcast(self, path: PathOwned, mut announced: watch::Receiver<Option<BroadcastConsumer>>) {
+		tracing::info!(broadcast = %path, "recording broadcast");
+
+		let mut writer = Writer::new(&self.config, path.as_str());
+		let (tx, mut rx) = mpsc::channel(1024);
+
+		// The tracks being recorded, aborted when dropped.
+		let mut tracks: HashMap<String, AbortOnDrop> = HashMap::new();
+		let mut broadcast = announced.borrow_and_update().clone();
+		let mut offline = None;
+
+		// The old tracks are aborted before the new ones start, so records from each epoch are received in order.
+		let mut epoch = 0;
+
+		loop {
+			if let Some(broadcast) = &broadcast {
+				let names = std::iter::once(CATALOG_TRACK.to_string()).chain(self.config.tracks.iter().cloned());
+				for name in names {
+					tracks
+						.entry(name.clone())
+						.or_insert_with(|| record_track(broadcast, name, epoch, tx.clone()));
+				}
+			}
+
+			tokio::select! {
+				res = announced.changed() => {
+					if res.is_err() {
+						break;
+					}
+
+					// Resubscribe to everything if the publisher reconnected.
+					tracks.clear();
+					epoch += 1;
+					broadcast = announced.borrow_and_update().clone();
+					offline = broadcast.is_none().then(|| Instant::now() + RECONNECT_TIMEOUT);
+				}
+				Some(record) = rx.recv() => {
+					match writer.write(&record).await {
+						Ok(()) => {}
+						Err(err) if err.kind() == std::io::ErrorKind::InvalidInput => {
+							tracing::warn!(broadcast = %path, track = %record.track, %err, "skipping frame");
+						}
+						Err(err) => {
+							tracing::warn!(broadcast = %path, %err, "failed to write recording");
+							writer.segment = None;
+							writer.previous = None;
+						}
+					}
+
+					// Discover any new tracks from the catalog.
+					if let (Some(broadcast), CATALOG_TRACK) = (&broadcast, record.track.as_str()) {
+						for name in catalog_tracks(&record.payload) {
+							tracks
+								.entry(name.clone())
+								.or_insert_with(|| record_track(broadcast, name, epoch, tx.clone()));
+						}
+					}
+				}
+				_ = async { tokio::time::sleep_until(offline.unwrap()).await }, if offline.is_some() => break,
+			}
+		}
+
+		if let Err(err) = writer.close().await {
+			tracing::warn!(broadcast = %path, %err, "failed to finish recording");
+		}
+
+		tracing::info!(broadcast = %path, "stopped recording broadcast");
+	}
+}
+
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+	fn drop(&mut self) {
+		self.0.abort();
+	}
+}
+
+fn record_track(broadcast: &BroadcastConsumer, name: String, epoch: u64, tx: mpsc::Sender<Record>) -> AbortOnDrop {
+	let track = broadcast.subscribe_track(&Track::new(&name));
+	let task = tokio::spawn(async move {
+		if let Err(err) = run_track(name.clone(), track, epoch, tx).await {
+			tracing::debug!(track = %name, %err, "stopped recording track");
+		}
+	});
+
+	AbortOnDrop(task.abort_handle())
+}
+
+async fn run_track(
+	name: String,
+	mut track: TrackConsumer,
+	epoch: u64,
+	tx: mpsc::Sender<Record>,
+) -> moq_lite::Result<()> {
+	// Groups are read in parallel, otherwise a slow group would cause the next one to be skipped.
+	let mut groups = FuturesUnordered::new();
+
+	loop {
+		tokio::select! {
+			res = track.next_group() => match res? {
+				Some(group) => groups.push(run_group(name.clone(), group, epoch, tx.clone())),
+				None => break,
+			},
+			Some(res) = groups.next() => if let Err(err) = res {
+				tracing::debug!(track = %name, %err, "failed to record group");
+			},
+		}
+	}
+
+	while groups.next().await.is_some() {}
+
+	Ok(())
+}
+
+async fn run_group(
+	track: String,
+	mut group: GroupConsumer,
+	epoch: u64,
+	tx: mpsc::Sender<Record>,
+) -> moq_lite::Result<()> {
+	while let Some(payload) = group.read_frame().await? {
+		let record = Record {
+			track: track.clone(),
+			group: group.info.sequence,
+			payload,
+			received: Instant::now(),
+			epoch,
+		};
+
+		if tx.send(record).await.is_err() {
+			break;
+		}
+	}
+
+	Ok(())
+}
+
+// Return the tracks listed in a hang catalog.
+fn catalog_tracks(payload: &[u8]) -> Vec<String> {
+	let catalog = match hang::catalog::Catalog::from_slice(payload) {
+		Ok(catalog) => catalog,
+		Err(_) => return Vec::new(),
+	};
+
+	let mut names = Vec::new();
+
+	if let Some(video) = catalog.video {
+		names.extend(video.renditions.into_keys());
+	}
+
+	if let Some(audio) = catalog.audio {
+		names.extend(audio.renditions.into_keys());
+	}
+
+	if let Some(captions) = catalog.captions {
+		names.extend(captions.renditions.into_keys());
+	}
+
+	if let Some(chat) = catalog.chat {
+		names.extend(chat.message.into_iter().chain(chat.typing).map(|track| track.name));
+	}
+
+	names.extend(catalog.preview.map(|track| track.name));
+
+	names
+}
+
+// Make sure the broadcast path can't escape the recording directory.
+fn is_safe(path: &str) -> bool {
+	!path.is_empty()
+		&& Path::new(path)
+			.components()
+			.all(|component| matches!(component, std::path::Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn record(track: &str, group: u64, payload: &'static [u8]) -> Record {
+		Record {
+			track: track.to_string(),
+			group,
+			payload: Bytes::from_static(payload),
+			received: Instant::now(),
+			epoch: 0,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_writer() {
+		let dir = tempfile::tempdir().unwrap();
+		let config = RecordConfig {
+			dir: Some(dir.path().to_path_buf()),
+			rotate_size: Some(100),
+			..Default::default()
+		};
+
+		let mut writer = Writer::new(&config, "room/alice");
+		writer.write(&record("video", 0, b"hello")).await.unwrap();
+		writer.write(&record("audio", 3, b"world")).await.unwrap();
+		writer.write(&record("video", 1, b"")).await.unwrap();
+		writer.write(&record("video", 0, &[0; 80])).await.unwrap();
+
+		let first = writer.segment.as_ref().unwrap().dir.clone();
+
+		// The segment is over the size limit, so the next group rotates.
+		writer.write(&record("video", 2, b"again")).await.unwrap();
+		writer.close().await.unwrap();
+
+		let meta: RecordMeta = serde_json::from_slice(&std::fs::read(first.join("meta.json")).unwrap()).unwrap();
+		assert_eq!(meta.broadcast, "room/alice");
+		assert_eq!(meta.tracks["video"], 0);
+		assert_eq!(meta.tracks["audio"], 1);
+
+		let data = std::fs::read(first.join("0.moq")).unwrap();
+		assert_eq!(&data[..8], RECORD_MAGIC);
+		assert_eq!(&data[8..16], &0u64.to_be_bytes());
+		assert_eq!(&data[24..28], &5u32.to_be_bytes());
+		assert_eq!(&data[28..33], b"hello");
+		assert_eq!(data.len(), 8 + 20 + 5 + 20 + 20 + 80);
+
+		// Every frame is indexed, since the groups are interleaved.
+		let index = std::fs::read(first.join("0.idx")).unwrap();
+		assert_eq!(index.len(), 3 * 24);
+		assert_eq!(&index[0..8], &0u64.to_be_bytes());
+		assert_eq!(&index[16..24], &8u64.to_be_bytes());
+		assert_eq!(&index[24..32], &1u64.to_be_bytes());
+		assert_eq!(&index[40..48], &33u64.to_be_bytes());
+		assert_eq!(&index[48..56], &0u64.to_be_bytes());
+		assert_eq!(&index[64..72], &53u64.to_be_bytes());
+
+		let segments = std::fs::read_dir(dir.path().join("room/alice")).unwrap().count();
+		assert_eq!(segments, 2);
+	}
+
+	#[tokio::test]
+	async fn test_writer_reconnect() {
+		let dir = tempfile::tempdir().unwrap();
+		let config = RecordConfig {
+			dir: Some(dir.path().to_path_buf()),
+			..Default::default()
+		};
+
+		let mut writer = Writer::new(&config, "room/alice");
+		writer.write(&record("video", 5, b"before")).await.unwrap();
+		let first = writer.segment.as_ref().unwrap().dir.clone();
+
+		// The group sequences start over when the publisher reconnects, so a new segment is started.
+		let mut reconnected = record("video", 0, b"after");
+		reconnected.epoch = 1;
+		writer.write(&reconnected).await.unwrap();
+		let second = writer.segment.as_ref().unwrap().dir.clone();
+		writer.close().await.unwrap();
+
+		assert_ne!(first, second);
+
+		let index = std::fs::read(second.join("0.idx")).unwrap();
+		assert_eq!(index.len(), 24);
+		assert_eq!(&index[0..8], &0u64.to_be_bytes());
+	}
+
+	#[tokio::test]
+	async fn test_writer_mid_group() {
+		let dir = tempfile::tempdir().unwrap();
+		let config = RecordConfig {
+			dir: Some(dir.path().to_path_buf()),
+			rotate_size: Some(50),
+			..Default::default()
+		};
+
+		let mut writer = Writer::new(&config, "room/alice");
+		writer.write(&record("audio", 0, b"a")).await.unwrap();
+		writer.write(&record("video", 0, &[0; 40])).await.unwrap();
+		let first = writer.segment.as_ref().unwrap().dir.clone();
+
+		// The segment is over the size limit, but the video group is still open.
+		writer.write(&record("video", 0, b"more")).await.unwrap();
+		assert_eq!(writer.segment.as_ref().unwrap().dir, first);
+
+		// The next video group rotates, and the rest of the open audio group stays in the first segment.
+		writer.write(&record("video", 1, b"next")).await.unwrap();
+		writer.write(&record("audio", 0, b"b")).await.unwrap();
+		writer.write(&record("video", 0, b"late")).await.unwrap();
+		writer.write(&record("audio", 1, b"c")).await.unwrap();
+		let second = writer.segment.as_ref().unwrap().dir.clone();
+		writer.close().await.unwrap();
+
+		assert_ne!(first, second);
+
+		let groups = |dir: &Path, track: &str| -> Vec<u64> {
+			let meta: RecordMeta = serde_json::from_slice(&std::fs::read(dir.join("meta.json")).unwrap()).unwrap();
+			let index = std::fs::read(dir.join(format!("{}.idx", meta.tracks[track]))).unwrap();
+			index
+				.chunks(24)
+				.map(|entry| u64::from_be_bytes(entry[..8].try_into().unwrap()))
+				.collect()
+		};
+
+		assert_eq!(groups(&first, "video"), vec![0, 0, 0]);
+		assert_eq!(groups(&first, "audio"), vec![0, 0]);
+		assert_eq!(groups(&second, "video"), vec![1]);
+		assert_eq!(groups(&second, "audio"), vec![1]);
+	}
+
+	#[test]
+	fn test_catalog_tracks() {
+		let catalog = br#"{
+			"video": { "renditions": { "video0": { "codec": "avc1.64001f" } }, "priority": 2 },
+			"audio": { "renditions": { "audio1": { "codec": "opus", "sampleRate": 48000, "numberOfChannels": 2 } }, "priority": 1 },
+			"captions": { "renditions": { "captions.eng": { "format": "wvtt" } }, "priority": 0 },
+			"user": { "name": "alice", "avatar": "https://example.com/alice.png" },
+			"chat": { "message": { "name": "chat", "priority": 0 } }
+		}"#;
+
+		let mut names = catalog_tracks(catalog);
+		names.sort();
+		assert_eq!(names, vec!["audio1", "captions.eng", "chat", "video0"]);
+
+		assert!(catalog_tracks(b"not json").is_empty());
+	}
+
+	#[test]
+	fn test_safe_path() {
+		assert!(is_safe("room/alice"));
+		assert!(!is_safe("../etc"));
+		assert!(!is_safe("room/../../etc"));
+		assert!(!is_safe("/etc"));
+		assert!(!is_safe(""));
+	}
+}
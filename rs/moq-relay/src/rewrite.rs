@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use moq_lite::{AsPath, Path, PathOwned};
+use serde::{Deserialize, Serialize};
+
+/// Rules that change the names of broadcasts served to (non-cluster) sessions.
+///
+/// Each rule is `from=to`, replacing the `from` prefix with `to`. An empty `from` matches every broadcast.
+/// Sessions are authorized against the new name, and other cluster nodes always see the original name.
+#[derive(clap::Args, Clone, Debug, Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RewriteConfig {
+	/// Also serve matching broadcasts under another name, ex. `studio/main=live/channel1`.
+	/// Can be repeated to serve a broadcast under several names.
+	#[arg(
+		id = "rewrite-alias",
+		long = "rewrite-alias",
+		env = "MOQ_REWRITE_ALIAS",
+		value_delimiter = ','
+	)]
+	pub alias: Vec<String>,
+
+	/// Serve matching broadcasts under another name instead of the original, ex. `studio=live`.
+	#[arg(
+		id = "rewrite-rename",
+		long = "rewrite-rename",
+		env = "MOQ_REWRITE_RENAME",
+		value_delimiter = ','
+	)]
+	pub rename: Vec<String>,
+}
+
+impl RewriteConfig {
+	pub fn init(self) -> anyhow::Result<Rewrite> {
+		Rewrite::new(self)
+	}
+}
+
+struct Rule {
+	from: PathOwned,
+	to: PathOwned,
+
+	// If false, the original name is hidden.
+	keep: bool,
+}
+
+impl Rule {
+	fn parse(rule: &str, keep: bool) -> anyhow::Result<Self> {
+		let (from, to) = rule
+			.split_once('=')
+			.with_context(|| format!("invalid rewrite rule: {rule}; expected from=to"))?;
+
+		Ok(Self {
+			from: Path::new(from).to_owned(),
+			to: Path::new(to).to_owned(),
+			keep,
+		})
+	}
+}
+
+/// Maps each broadcast path to the names it is served under.
+#[derive(Clone, Default)]
+pub struct Rewrite {
+	rules: Arc<Vec<Rule>>,
+}
+
+impl Rewrite {
+	pub fn new(config: RewriteConfig) -> anyhow::Result<Self> {
+		let aliases = config.alias.iter().map(|rule| Rule::parse(rule, true));
+		let renames = config.rename.iter().map(|rule| Rule::parse(rule, false));
+		let rules = aliases.chain(renames).collect::<anyhow::Result<Vec<_>>>()?;
+
+		Ok(Self { rules: Arc::new(rules) })
+	}
+
+	/// Return the names that a broadcast should be served under, which may be empty.
+	pub fn names(&self, path: impl AsPath) -> Vec<PathOwned> {
+		let path = path.as_path();
+
+		let mut names = Vec::new();
+		let mut keep = true;
+
+		for rule in self.rules.iter() {
+			if let Some(suffix) = path.strip_prefix(&rule.from) {
+				let name = rule.to.join(&suffix);
+				if !names.contains(&name) {
+					names.push(name);
+				}
+
+				keep &= rule.keep;
+			}
+		}
+
+		if keep && !names.iter().any(|name| name.as_str() == path.as_str()) {
+			names.insert(0, path.to_owned());
+		}
+
+		names
+	}
+
+	/// Return the original paths that would be served under this name, the reverse of [Self::names].
+	pub fn originals(&self, name: impl AsPath) -> Vec<PathOwned> {
+		let name = name.as_path();
+
+		let candidates = std::iter::once(name.to_owned()).chain(
+			self.rules
+				.iter()
+				.filter_map(|rule| name.strip_prefix(&rule.to).map(|suffix| rule.from.join(&suffix))),
+		);
+
+		let mut originals = Vec::new();
+		for original in candidates {
+			if !originals.contains(&original) && self.names(&original).contains(&name.to_owned()) {
+				originals.push(original);
+			}
+		}
+
+		originals
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rewrite(alias: &[&str], rename: &[&str]) -> Rewrite {
+		Rewrite::new(RewriteConfig {
+			alias: alias.iter().map(|s| s.to_string()).collect(),
+			rename: rename.iter().map(|s| s.to_string()).collect(),
+		})
+		.unwrap()
+	}
+
+	fn names(rewrite: &Rewrite, path: &str) -> Vec<String> {
+		rewrite.names(path).iter().map(|name| name.to_string()).collect()
+	}
+
+	#[test]
+	fn test_no_rules() {
+		let rewrite = Rewrite::default();
+		assert_eq!(names(&rewrite, "studio/main"), vec!["studio/main"]);
+	}
+
+	#[test]
+	fn test_alias() {
+		let rewrite = rewrite(&["studio/main=live/channel1", "studio/main=live/backup"], &[]);
+
+		assert_eq!(
+			names(&rewrite, "studio/main"),
+			vec!["studio/main", "live/channel1", "live/backup"]
+		);
+
+		// Prefixes only match whole components.
+		assert_eq!(names(&rewrite, "studio/mainline"), vec!["studio/mainline"]);
+		assert_eq!(names(&rewrite, "studio/other"), vec!["studio/other"]);
+	}
+
+	#[test]
+	fn test_rename() {
+		let rewrite = rewrite(&["studio/main=live/channel1"], &["studio=internal"]);
+
+		// The original name is hidden, but the alias still applies.
+		assert_eq!(names(&rewrite, "studio/main"), vec!["live/channel1", "internal/main"]);
+		assert_eq!(names(&rewrite, "studio/other"), vec!["internal/other"]);
+
+		// Renaming to an empty prefix strips it.
+		let rewrite = self::rewrite(&[], &["studio="]);
+		assert_eq!(names(&rewrite, "studio/main"), vec!["main"]);
+
+		// An empty prefix matches everything.
+		let rewrite = self::rewrite(&["=mirror"], &[]);
+		assert_eq!(
+			names(&rewrite, "studio/main"),
+			vec!["studio/main", "mirror/studio/main"]
+		);
+	}
+
+	#[test]
+	fn test_originals() {
+		let rewrite = rewrite(&["studio/main=live/channel1"], &["backstage=internal"]);
+		let originals = |name| -> Vec<String> { rewrite.originals(name).iter().map(|p| p.to_string()).collect() };
+
+		// An alias could be either the original broadcast or one published under the alias itself.
+		assert_eq!(originals("live/channel1"), vec!["live/channel1", "studio/main"]);
+		assert_eq!(originals("studio/main"), vec!["studio/main"]);
+
+		// A renamed broadcast is only served under the new name.
+		assert_eq!(originals("internal/feed"), vec!["internal/feed", "backstage/feed"]);
+		assert!(originals("backstage/feed").is_empty());
+	}
+
+	#[test]
+	fn test_invalid() {
+		assert!(Rewrite::new(RewriteConfig {
+			alias: vec!["studio".to_string()],
+			..Default::default()
+		})
+		.is_err());
+	}
+}
@@ -0,0 +1,479 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use moq_lite::{
+	AsPath, Broadcast, BroadcastConsumer, BroadcastProducer, FrameProducer, GroupConsumer, GroupProducer,
+	OriginConsumer, OriginProducer, PathOwned, TrackProducer,
+};
+
+/// The most recent round-trip time to a remote node.
+#[derive(Clone, Default)]
+pub struct Rtt(Arc<AtomicU64>);
+
+impl Rtt {
+	/// An RTT that hasn't been measured, so any measured node is preferred.
+	pub fn unknown() -> Self {
+		Self(Arc::new(AtomicU64::new(u64::MAX)))
+	}
+
+	pub fn get(&self) -> Duration {
+		Duration::from_micros(self.0.load(Ordering::Relaxed))
+	}
+
+	pub fn set(&self, rtt: Duration) {
+		self.0.store(rtt.as_micros() as u64, Ordering::Relaxed);
+	}
+}
+
+// A remote node that announced a broadcast.
+#[derive(Clone)]
+struct Candidate {
+	node: String,
+	broadcast: BroadcastConsumer,
+	rtt: Rtt,
+}
+
+type Candidates = Arc<Mutex<Vec<Candidate>>>;
+
+struct Route {
+	candidates: Candidates,
+
+	// Unannounces the proxy broadcast when dropped.
+	_proxy: BroadcastProducer,
+	task: tokio::task::AbortHandle,
+}
+
+impl Drop for Route {
+	fn drop(&mut self) {
+		self.task.abort();
+	}
+}
+
+/// Merges broadcasts announced by multiple remote nodes into a single origin.
+///
+/// When the same broadcast is available from multiple nodes, each subscription is routed to the node with the lowest RTT.
+/// If that node goes away or the subscription fails, the subscription is transparently moved to the next best node.
+/// Any group in progress is finished using the next node's copy of it, if that node still has it.
+#[derive(Clone)]
+pub struct Router {
+	origin: OriginProducer,
+	routes: Arc<Mutex<HashMap<PathOwned, Route>>>,
+}
+
+impl Router {
+	pub fn new(origin: OriginProducer) -> Self {
+		Self {
+			origin,
+			routes: Default::default(),
+		}
+	}
+
+	/// Add the broadcasts announced by a remote node until the origin is closed.
+	///
+	/// All of the node's broadcasts are removed when this future is dropped.
+	pub async fn run_remote(&self, node: &str, mut origin: OriginConsumer, rtt: Rtt) {
+		let _guard = RemoteGuard { router: self, node };
+
+		while let Some((path, broadcast)) = origin.announced().await {
+			// Remove any previous broadcast from this node first, in case this is a reannounce.
+			self.remove(&path, node);
+
+			if let Some(broadcast) = broadcast {
+				self.insert(path, node, broadcast, rtt.clone());
+			}
+		}
+	}
+
+	fn insert(&self, path: PathOwned, node: &str, broadcast: BroadcastConsumer, rtt: Rtt) {
+		let candidate = Candidate {
+			node: node.to_string(),
+			broadcast,
+			rtt,
+		};
+
+		let mut routes = self.routes.lock().unwrap();
+		if let Some(route) = routes.get(&path) {
+			route.candidates.lock().unwrap().push(candidate);
+			return;
+		}
+
+		let candidates = Arc::new(Mutex::new(vec![candidate]));
+		let proxy = Broadcast::produce();
+		self.origin.publish_broadcast(&path, proxy.consumer);
+
+		let task = tokio::spawn(Self::run_proxy(
+			path.clone(),
+			proxy.producer.clone(),
+			candidates.clone(),
+		));
+
+		routes.insert(
+			path,
+			Route {
+				candidates,
+				_proxy: proxy.producer,
+				task: task.abort_handle(),
+			},
+		);
+	}
+
+	fn remove(&self, path: impl AsPath, node: &str) {
+		let path = path.as_path().to_owned();
+
+		let mut routes = self.routes.lock().unwrap();
+		let route = match routes.get(&path) {
+			Some(route) => route,
+			None => return,
+		};
+
+		let mut candidates = route.candidates.lock().unwrap();
+		candidates.retain(|candidate| candidate.node != node);
+
+		if candidates.is_empty() {
+			drop(candidates);
+			routes.remove(&path);
+		}
+	}
+
+	fn remove_node(&self, node: &str) {
+		let mut routes = self.routes.lock().unwrap();
+
+		routes.retain(|_, route| {
+			let mut candidates = route.candidates.lock().unwrap();
+			candidates.retain(|candidate| candidate.node != node);
+			!candidates.is_empty()
+		});
+	}
+
+	async fn run_proxy(path: PathOwned, mut proxy: BroadcastProducer, candidates: Candidates) {
+		while let Some(track) = proxy.requested_track().await {
+			tokio::spawn(Self::run_track(path.clone(), track, candidates.clone()));
+		}
+	}
+
+	// Pick the candidate with the lowest RTT, skipping any that have already failed.
+	fn best(candidates: &Candidates, failed: &[BroadcastConsumer]) -> Option<Candidate> {
+		candidates
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|candidate| !failed.iter().any(|f| f.is_clone(&candidate.broadcast)))
+			.min_by_key(|candidate| candidate.rtt.get())
+			.cloned()
+	}
+
+	async fn run_track(path: PathOwned, mut track: TrackProducer, candidates: Candidates) {
+		let unused = track.unused();
+		tokio::pin!(unused);
+
+		let mut failed = Vec::new();
+
+		// Groups that an upstream failed to finish, continued by the next upstream if it has the same group.
+		let mut stalled = BTreeMap::new();
+
+		loop {
+			let candidate = match Self::best(&candidates, &failed) {
+				Some(candidate) => candidate,
+				None => {
+					tracing::debug!(broadcast = %path, track = %track.info.name, "no remaining origins");
+					return track.abort(moq_lite::Error::NotFound);
+				}
+			};
+
+			tracing::debug!(broadcast = %path, track = %track.info.name, node = %candidate.node, rtt = ?candidate.rtt.get(), "routing subscription");
+
+			let mut upstream = candidate.broadcast.subscribe_track(&track.info);
+
+			// The groups being forwarded from this upstream.
+			let mut active = HashMap::new();
+			let mut tasks = FuturesUnordered::new();
+
+			let res = loop {
+				tokio::select! {
+					res = upstream.next_group() => match res {
+						Ok(Some(group)) => {
+							let sequence = group.info.sequence;
+
+							// The upstream won't deliver any older groups, so give up on them.
+							stalled = stalled.split_off(&sequence);
+
+							// Groups have the same sequence numbers and contents on every node.
+							let forward = match stalled.remove(&sequence) {
+								Some(forward) => forward,
+								None => match track.create_group(group.info.clone()) {
+									Some(group) => Arc::new(Mutex::new(Forward::new(group))),
+									None => continue,
+								},
+							};
+
+							active.insert(sequence, forward.clone());
+							tasks.push(Forward::run(forward, group).map(move |res| (sequence, res)));
+							failed.clear();
+						}
+						Ok(None) => break Ok(()),
+						Err(err) => break Err(err),
+					},
+					Some((sequence, res)) = tasks.next() => {
+						let forward = active.remove(&sequence).expect("missing group");
+						if let Err(err) = res {
+							tracing::debug!(broadcast = %path, track = %track.info.name, %sequence, %err, "group failed");
+							stalled.insert(sequence, forward);
+						}
+					}
+					_ = candidate.broadcast.closed() => break Err(moq_lite::Error::Cancel),
+					_ = &mut unused => return,
+				}
+			};
+
+			if let Err(err) = res {
+				tracing::info!(broadcast = %path, track = %track.info.name, node = %candidate.node, %err, "origin failed, trying another");
+				failed.push(candidate.broadcast);
+
+				// The next upstream continues any groups that weren't finished.
+				stalled.extend(active);
+				continue;
+			}
+
+			track.close();
+
+			// Finish forwarding the groups that are still in flight.
+			tokio::select! {
+				_ = tasks.collect::<Vec<_>>() => {}
+				_ = &mut unused => {}
+			}
+
+			return;
+		}
+	}
+}
+
+// A group forwarded from an upstream, which can be continued by another upstream on failover.
+struct Forward {
+	group: GroupProducer,
+
+	// The number of frames started so far.
+	frames: usize,
+
+	// The frame being written, and the number of bytes written to it so far.
+	frame: Option<(FrameProducer, usize)>,
+}
+
+impl Forward {
+	fn new(group: GroupProducer) -> Self {
+		Self {
+			group,
+			frames: 0,
+			frame: None,
+		}
+	}
+
+	// Copy the group from the upstream, skipping any frames and bytes that were already written.
+	async fn run(forward: Arc<Mutex<Self>>, mut upstream: GroupConsumer) -> moq_lite::Result<()> {
+		let mut index = 0;
+
+		while let Some(mut frame) = upstream.next_frame().await? {
+			index += 1;
+
+			{
+				let mut forward = forward.lock().unwrap();
+				if index > forward.frames {
+					let producer = forward.group.create_frame(frame.info.clone());
+					forward.frame = Some((producer, 0));
+					forward.frames += 1;
+				} else if index < forward.frames || forward.frame.is_none() {
+					// This frame was already written.
+					continue;
+				}
+			}
+
+			let mut offset = 0;
+
+			while let Some(chunk) = frame.read_chunk().await? {
+				let mut forward = forward.lock().unwrap();
+				let (producer, written) = forward.frame.as_mut().expect("missing frame");
+
+				let skip = written.saturating_sub(offset).min(chunk.len());
+				offset += chunk.len();
+
+				if skip < chunk.len() {
+					*written += chunk.len() - skip;
+					producer.write_chunk(chunk.slice(skip..));
+				}
+			}
+
+			if let Some((producer, _)) = forward.lock().unwrap().frame.take() {
+				producer.close();
+			}
+		}
+
+		forward.lock().unwrap().group.clone().close();
+
+		Ok(())
+	}
+}
+
+// Removes all broadcasts from a node when dropped, in case the task is cancelled.
+struct RemoteGuard<'a> {
+	router: &'a Router,
+	node: &'a str,
+}
+
+impl Drop for RemoteGuard<'_> {
+	fn drop(&mut self) {
+		self.router.remove_node(self.node);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::FutureExt;
+	use moq_lite::{Origin, Track};
+
+	#[tokio::test]
+	async fn test_lowest_rtt() {
+		let output = Origin::produce();
+		let router = Router::new(output.producer);
+
+		let near = Rtt::default();
+		near.set(Duration::from_millis(10));
+		let far = Rtt::default();
+		far.set(Duration::from_millis(100));
+
+		let mut a = Broadcast::produce();
+		let mut b = Broadcast::produce();
+		router.insert("test".into(), "far", a.consumer.clone(), far);
+		router.insert("test".into(), "near", b.consumer.clone(), near);
+
+		let proxy = output.consumer.consume_broadcast("test").unwrap();
+		let mut track = proxy.subscribe_track(&Track::new("video"));
+
+		// The subscription goes to the nearest node.
+		let mut upstream = b.producer.requested_track().await.unwrap();
+		assert!(a.producer.requested_track().now_or_never().is_none());
+
+		upstream.write_frame(bytes::Bytes::from_static(b"hello"));
+		let mut group = track.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "hello");
+	}
+
+	#[tokio::test]
+	async fn test_failover() {
+		let output = Origin::produce();
+		let router = Router::new(output.producer);
+
+		let near = Rtt::default();
+		near.set(Duration::from_millis(10));
+		let far = Rtt::default();
+		far.set(Duration::from_millis(100));
+
+		let mut a = Broadcast::produce();
+		let mut b = Broadcast::produce();
+		router.insert("test".into(), "far", a.consumer.clone(), far);
+		router.insert("test".into(), "near", b.consumer.clone(), near);
+
+		let proxy = output.consumer.consume_broadcast("test").unwrap();
+		let mut track = proxy.subscribe_track(&Track::new("video"));
+
+		let mut upstream = b.producer.requested_track().await.unwrap();
+		upstream.write_frame(bytes::Bytes::from_static(b"first"));
+		let mut group = track.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "first");
+
+		// The nearest node fails, so the subscription moves to the other node.
+		upstream.abort(moq_lite::Error::Cancel);
+
+		let mut upstream = a.producer.requested_track().await.unwrap();
+		upstream.append_group(); // a duplicate of the first group, which is ignored
+		upstream.write_frame(bytes::Bytes::from_static(b"second"));
+
+		let mut group = track.next_group().await.unwrap().unwrap();
+		assert_eq!(group.read_frame().await.unwrap().unwrap(), "second");
+	}
+
+	#[tokio::test]
+	async fn test_failover_mid_group() {
+		let output = Origin::produce();
+		let router = Router::new(output.producer);
+
+		let near = Rtt::default();
+		near.set(Duration::from_millis(10));
+		let far = Rtt::default();
+		far.set(Duration::from_millis(100));
+
+		let mut a = Broadcast::produce();
+		let mut b = Broadcast::produce();
+		router.insert("test".into(), "far", a.consumer.clone(), far);
+		router.insert("test".into(), "near", b.consumer.clone(), near);
+
+		let proxy = output.consumer.consume_broadcast("test").unwrap();
+		let mut track = proxy.subscribe_track(&Track::new("video"));
+
+		// The nearest node sends a frame and half of the next one.
+		let mut upstream = b.producer.requested_track().await.unwrap();
+		let mut group = upstream.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"first"));
+		let mut frame = group.create_frame(moq_lite::Frame { size: 10 });
+		frame.write_chunk(bytes::Bytes::from_static(b"hello"));
+
+		let mut downstream = track.next_group().await.unwrap().unwrap();
+		assert_eq!(downstream.read_frame().await.unwrap().unwrap(), "first");
+		let mut partial = downstream.next_frame().await.unwrap().unwrap();
+		assert_eq!(partial.read_chunk().await.unwrap().unwrap(), "hello");
+
+		// The nearest node fails mid-group, so the other node's copy of the same group is used to finish it.
+		frame.abort(moq_lite::Error::Cancel);
+		group.abort(moq_lite::Error::Cancel);
+		upstream.abort(moq_lite::Error::Cancel);
+
+		let mut upstream = a.producer.requested_track().await.unwrap();
+		let mut group = upstream.append_group();
+		group.write_frame(bytes::Bytes::from_static(b"first"));
+		group.write_frame(bytes::Bytes::from_static(b"helloworld"));
+		group.write_frame(bytes::Bytes::from_static(b"last"));
+		group.close();
+
+		assert_eq!(partial.read_all().await.unwrap(), "world");
+		assert_eq!(downstream.read_frame().await.unwrap().unwrap(), "last");
+		assert_eq!(downstream.read_frame().await.unwrap(), None);
+
+		// The next group is forwarded as usual.
+		upstream.write_frame(bytes::Bytes::from_static(b"next"));
+		let mut downstream = track.next_group().await.unwrap().unwrap();
+		assert_eq!(downstream.info.sequence, 1);
+		assert_eq!(downstream.read_frame().await.unwrap().unwrap(), "next");
+	}
+
+	#[tokio::test]
+	async fn test_remove() {
+		let output = Origin::produce();
+		let mut announced = output.consumer.consume();
+		let router = Router::new(output.producer);
+
+		let a = Broadcast::produce();
+		let b = Broadcast::produce();
+		router.insert("test".into(), "a", a.consumer.clone(), Rtt::default());
+		router.insert("test".into(), "b", b.consumer.clone(), Rtt::default());
+
+		let (path, active) = announced.announced().await.unwrap();
+		assert_eq!(path, "test".into());
+		assert!(active.is_some());
+
+		// Still announced while one node has the broadcast.
+		router.remove_node("a");
+		tokio::task::yield_now().await;
+		assert!(announced.try_announced().is_none());
+
+		router.remove("test", "b");
+		let (path, active) = announced.announced().await.unwrap();
+		assert_eq!(path, "test".into());
+		assert!(active.is_none());
+	}
+}
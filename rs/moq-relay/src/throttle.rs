@@ -0,0 +1,212 @@
+use std::{
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+// The minimum burst size, so a single packet can always be sent at very low bitrates.
+const MTU: f64 = 1500.0;
+
+/// A token bucket shared by all streams of a session.
+struct Bucket {
+	// The refill rate in bytes per second.
+	rate: f64,
+
+	// The maximum number of tokens, allowing for short bursts.
+	capacity: f64,
+
+	tokens: f64,
+	updated: Instant,
+}
+
+impl Bucket {
+	fn new(bitrate: u64) -> Self {
+		assert!(bitrate > 0, "bitrate must be greater than zero");
+
+		let rate = bitrate as f64 / 8.0;
+		let capacity = rate.max(MTU);
+
+		Self {
+			rate,
+			capacity,
+			tokens: capacity,
+			updated: Instant::now(),
+		}
+	}
+
+	// Take up to `size` bytes, or return how long to wait until a byte is available.
+	fn take(&mut self, size: usize) -> Result<usize, Duration> {
+		let now = Instant::now();
+		let elapsed = now.duration_since(self.updated).as_secs_f64();
+		self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+		self.updated = now;
+
+		if self.tokens < 1.0 {
+			return Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate));
+		}
+
+		let size = size.min(self.tokens as usize);
+		self.tokens -= size as f64;
+
+		Ok(size)
+	}
+
+	// Return any tokens that were not used.
+	fn refund(&mut self, size: usize) {
+		self.tokens = (self.tokens + size as f64).min(self.capacity);
+	}
+}
+
+/// Limits the egress bitrate of a session.
+///
+/// All streams opened by either side share the same budget.
+#[derive(Clone)]
+pub struct Throttle<S> {
+	inner: S,
+	bucket: Option<Arc<Mutex<Bucket>>>,
+}
+
+impl<S> Throttle<S> {
+	/// Throttle the session to the given bitrate in bits per second, or unlimited if None.
+	///
+	/// Panics if the bitrate is zero; it's rejected when the config is loaded.
+	pub fn new(inner: S, bitrate: Option<u64>) -> Self {
+		let bucket = bitrate.map(|bitrate| Arc::new(Mutex::new(Bucket::new(bitrate))));
+		Self { inner, bucket }
+	}
+
+	fn send<T>(&self, inner: T) -> ThrottleSend<T> {
+		ThrottleSend {
+			inner,
+			bucket: self.bucket.clone(),
+		}
+	}
+}
+
+impl<S: web_transport_trait::Session> web_transport_trait::Session for Throttle<S> {
+	type SendStream = ThrottleSend<S::SendStream>;
+	type RecvStream = S::RecvStream;
+	type Error = S::Error;
+
+	async fn accept_uni(&self) -> Result<Self::RecvStream, Self::Error> {
+		self.inner.accept_uni().await
+	}
+
+	async fn accept_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), Self::Error> {
+		let (send, recv) = self.inner.accept_bi().await?;
+		Ok((self.send(send), recv))
+	}
+
+	async fn open_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), Self::Error> {
+		let (send, recv) = self.inner.open_bi().await?;
+		Ok((self.send(send), recv))
+	}
+
+	async fn open_uni(&self) -> Result<Self::SendStream, Self::Error> {
+		let send = self.inner.open_uni().await?;
+		Ok(self.send(send))
+	}
+
+	fn send_datagram(&self, payload: Bytes) -> Result<(), Self::Error> {
+		// Datagrams can be dropped, so we don't bother throttling them.
+		self.inner.send_datagram(payload)
+	}
+
+	async fn recv_datagram(&self) -> Result<Bytes, Self::Error> {
+		self.inner.recv_datagram().await
+	}
+
+	fn max_datagram_size(&self) -> usize {
+		self.inner.max_datagram_size()
+	}
+
+	fn close(&self, code: u32, reason: &str) {
+		self.inner.close(code, reason)
+	}
+
+	async fn closed(&self) -> Self::Error {
+		self.inner.closed().await
+	}
+}
+
+pub struct ThrottleSend<T> {
+	inner: T,
+	bucket: Option<Arc<Mutex<Bucket>>>,
+}
+
+impl<T: web_transport_trait::SendStream> web_transport_trait::SendStream for ThrottleSend<T> {
+	type Error = T::Error;
+
+	async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+		let bucket = match &self.bucket {
+			Some(bucket) => bucket,
+			None => return self.inner.write(buf).await,
+		};
+
+		let size = loop {
+			// NOTE: The lock must not be held across the await.
+			let res = bucket.lock().unwrap().take(buf.len());
+			match res {
+				Ok(size) => break size,
+				Err(wait) => tokio::time::sleep(wait).await,
+			}
+		};
+
+		let res = self.inner.write(&buf[..size]).await;
+		let written = *res.as_ref().unwrap_or(&0);
+		bucket.lock().unwrap().refund(size - written);
+
+		res
+	}
+
+	fn set_priority(&mut self, order: u8) {
+		self.inner.set_priority(order)
+	}
+
+	fn finish(&mut self) -> Result<(), Self::Error> {
+		self.inner.finish()
+	}
+
+	fn reset(&mut self, code: u32) {
+		self.inner.reset(code)
+	}
+
+	async fn closed(&mut self) -> Result<(), Self::Error> {
+		self.inner.closed().await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test(start_paused = true)]
+	async fn test_bucket() {
+		// 16 kbps = 2000 bytes per second
+		let mut bucket = Bucket::new(16_000);
+
+		// The initial burst is one second worth.
+		assert_eq!(bucket.take(3000), Ok(2000));
+		assert_eq!(bucket.take(1), Err(Duration::from_micros(500)));
+
+		tokio::time::advance(Duration::from_millis(500)).await;
+		assert_eq!(bucket.take(2000), Ok(1000));
+
+		// Unused tokens are returned.
+		bucket.refund(100);
+		assert_eq!(bucket.take(1000), Ok(100));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn test_bucket_slow() {
+		// 1 bps is less than a byte per second, but a packet can still be sent.
+		let mut bucket = Bucket::new(1);
+		assert_eq!(bucket.take(2000), Ok(1500));
+		assert_eq!(bucket.take(1), Err(Duration::from_secs(8)));
+
+		tokio::time::advance(Duration::from_secs(8)).await;
+		assert_eq!(bucket.take(1), Ok(1));
+	}
+}
@@ -1,5 +1,6 @@
 use futures::{SinkExt, StreamExt};
 use std::{
+	collections::{HashMap, VecDeque},
 	net,
 	path::PathBuf,
 	pin::Pin,
@@ -13,20 +14,23 @@ use web_transport_ws::tungstenite;
 
 use axum::{
 	body::Body,
-	extract::{Path, Query, State, WebSocketUpgrade},
-	http::{Method, StatusCode},
-	response::{IntoResponse, Response},
+	extract::{ConnectInfo, Path, Query, State, WebSocketUpgrade},
+	http::{self, Method, StatusCode},
+	response::{sse, IntoResponse, Response},
 	routing::{any, get},
 	Router,
 };
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 use clap::Parser;
 use moq_lite::{OriginConsumer, OriginProducer};
 use serde::{Deserialize, Serialize};
 use std::future::Future;
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::{Auth, Cluster};
+use crate::{
+	Audit, Auth, Cluster, FetchCache, FetchConfig, FetchRange, Intercept, Limits, RemoteHealth, Rtt, SessionAudit,
+	SessionLimits,
+};
 
 #[derive(Debug, Deserialize)]
 struct Params {
@@ -44,6 +48,10 @@ pub struct WebConfig {
 	#[serde(default)]
 	pub https: HttpsConfig,
 
+	#[command(flatten)]
+	#[serde(default)]
+	pub fetch: FetchConfig,
+
 	// If true (default), expose a WebTransport compatible WebSocket polyfill.
 	#[arg(long = "web-ws", env = "MOQ_WEB_WS", default_value = "true")]
 	#[serde(default = "default_true")]
@@ -75,6 +83,9 @@ pub struct HttpsConfig {
 pub struct WebState {
 	pub auth: Auth,
 	pub cluster: Cluster,
+	pub limits: Limits,
+	pub audit: Audit,
+	pub fetch: FetchCache,
 	pub fingerprints: Vec<String>,
 	pub conn_id: AtomicU64,
 }
@@ -99,7 +110,8 @@ impl Web {
 			.route("/certificate.sha256", get(fingerprint))
 			.route("/announced", get(serve_announced))
 			.route("/announced/{*prefix}", get(serve_announced))
-			.route("/fetch/{*path}", get(serve_fetch));
+			.route("/fetch/{*path}", get(serve_fetch))
+			.route("/cluster", get(serve_cluster));
 
 		// If WebSocket is enabled, add the WebSocket route.
 		let app = match self.config.ws {
@@ -108,7 +120,7 @@ impl Web {
 		}
 		.layer(CorsLayer::new().allow_origin(Any).allow_methods([Method::GET]))
 		.with_state(Arc::new(self.state))
-		.into_make_service();
+		.into_make_service_with_connect_info::<net::SocketAddr>();
 
 		let http = if let Some(listen) = self.config.http.listen {
 			let server = hyper_serve::bind(listen);
@@ -141,14 +153,18 @@ impl Web {
 
 async fn serve_ws(
 	ws: WebSocketUpgrade,
+	ConnectInfo(addr): ConnectInfo<net::SocketAddr>,
 	Path(path): Path<String>,
 	Query(params): Query<Params>,
 	State(state): State<Arc<WebState>>,
 ) -> axum::response::Result<Response> {
 	let ws = ws.protocols(["webtransport"]);
 
-	let token = state.auth.verify(&path, params.jwt.as_deref())?;
-	let publish = state.cluster.publisher(&token);
+	let token = state.auth.authorize(&path, params.jwt.as_deref(), addr.ip()).await?;
+	let limits = state.limits.session(addr.ip(), &token)?;
+	// The RTT isn't measured over WebSockets, so cluster nodes connected via QUIC are preferred.
+	let publisher = state.cluster.publisher(&token, Rtt::unknown());
+	let publish = publisher.as_ref().map(|publisher| publisher.origin.clone());
 	let subscribe = state.cluster.subscriber(&token);
 
 	if publish.is_none() && subscribe.is_none() {
@@ -156,6 +172,11 @@ async fn serve_ws(
 		return Err(StatusCode::UNAUTHORIZED.into());
 	}
 
+	let audit = state.audit.session(addr.ip(), &token);
+	let mut intercept = Intercept::new([limits.hook(), audit.hook()]);
+	let publish = intercept.publisher(publish);
+	let subscribe = intercept.subscriber(subscribe);
+
 	Ok(ws.on_upgrade(async move |socket| {
 		let id = state.conn_id.fetch_add(1, Ordering::Relaxed);
 
@@ -169,7 +190,8 @@ async fn serve_ws(
 				tungstenite::Error::ConnectionClosed
 			})
 			.with(tungstenite_to_axum);
-		let _ = handle_socket(id, socket, publish, subscribe).await;
+		let _ = handle_socket(id, socket, limits, audit, intercept, publish, subscribe).await;
+		drop(publisher);
 	}))
 }
 
@@ -177,6 +199,9 @@ async fn serve_ws(
 async fn handle_socket<T>(
 	_id: u64,
 	socket: T,
+	limits: SessionLimits,
+	audit: SessionAudit,
+	_intercept: Intercept,
 	publish: Option<OriginProducer>,
 	subscribe: Option<OriginConsumer>,
 ) -> anyhow::Result<()>
@@ -189,44 +214,199 @@ where
 {
 	// Wrap the WebSocket in a WebTransport compatibility layer.
 	let ws = web_transport_ws::Session::new(socket, true);
+	let ws = audit.meter(limits.throttle(ws));
 	let session = moq_lite::Session::accept(ws, subscribe, publish).await?;
-	session.closed().await.map_err(Into::into)
+
+	tokio::select! {
+		res = session.closed() => res.map_err(Into::into),
+		err = limits.closed() => {
+			session.close(moq_lite::Error::LimitExceeded);
+			Err(err.into())
+		}
+	}
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AnnouncedFormat {
+	/// A newline separated snapshot of the active broadcasts.
+	#[default]
+	Text,
+
+	/// A stream of server-sent events, named `active` or `ended` with the path as the data.
+	Sse,
+
+	/// A stream of newline delimited JSON objects, each with a `path` and `active` field.
+	Ndjson,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnnouncedParams {
+	#[serde(default)]
+	format: AnnouncedFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct AnnouncedEvent {
+	path: String,
+	active: bool,
 }
 
 /// Serve the announced broadcasts for a given prefix.
+///
+/// Use `?format=sse` or `?format=ndjson` to stream broadcasts as they are announced and unannounced.
 async fn serve_announced(
+	ConnectInfo(addr): ConnectInfo<net::SocketAddr>,
 	path: Option<Path<String>>,
 	Query(params): Query<Params>,
+	Query(announced): Query<AnnouncedParams>,
 	State(state): State<Arc<WebState>>,
-) -> axum::response::Result<String> {
+) -> axum::response::Result<Response> {
 	let prefix = match path {
 		Some(Path(prefix)) => prefix,
 		None => String::new(),
 	};
 
-	let token = state.auth.verify(&prefix, params.jwt.as_deref())?;
+	let token = state.auth.authorize(&prefix, params.jwt.as_deref(), addr.ip()).await?;
 	let mut origin = match state.cluster.subscriber(&token) {
 		Some(origin) => origin,
 		None => return Err(StatusCode::UNAUTHORIZED.into()),
 	};
 
-	let mut broadcasts = Vec::new();
+	match announced.format {
+		AnnouncedFormat::Text => {
+			let mut broadcasts = Vec::new();
 
-	while let Some((suffix, active)) = origin.try_announced() {
-		if active.is_some() {
-			broadcasts.push(suffix);
+			while let Some((suffix, active)) = origin.try_announced() {
+				if active.is_some() {
+					broadcasts.push(suffix);
+				}
+			}
+
+			Ok(broadcasts
+				.iter()
+				.map(|p| p.to_string())
+				.collect::<Vec<_>>()
+				.join("\n")
+				.into_response())
 		}
+		AnnouncedFormat::Sse => Ok(announced_sse(origin)),
+		AnnouncedFormat::Ndjson => Ok(announced_ndjson(origin)),
 	}
+}
+
+fn announced_sse(origin: OriginConsumer) -> Response {
+	let events = announced_events(origin).map(|event| {
+		let name = if event.active { "active" } else { "ended" };
+		Ok::<_, std::convert::Infallible>(sse::Event::default().event(name).data(event.path))
+	});
 
-	Ok(broadcasts.iter().map(|p| p.to_string()).collect::<Vec<_>>().join("\n"))
+	sse::Sse::new(events)
+		.keep_alive(sse::KeepAlive::default())
+		.into_response()
 }
 
-/// Serve the latest group for a given track
+fn announced_ndjson(origin: OriginConsumer) -> Response {
+	let events = announced_events(origin).map(|event| {
+		let mut line = serde_json::to_vec(&event)?;
+		line.push(b'\n');
+		Ok::<_, serde_json::Error>(Bytes::from(line))
+	});
+
+	let mut response = Response::new(Body::from_stream(events));
+	response.headers_mut().insert(
+		http::header::CONTENT_TYPE,
+		http::HeaderValue::from_static("application/x-ndjson"),
+	);
+	response
+}
+
+// The initial announcements include all active broadcasts, so the stream doesn't need a separate snapshot.
+fn announced_events(origin: OriginConsumer) -> impl futures::Stream<Item = AnnouncedEvent> {
+	futures::stream::unfold(origin, |mut origin| async move {
+		let (path, active) = origin.announced().await?;
+		let event = AnnouncedEvent {
+			path: path.to_string(),
+			active: active.is_some(),
+		};
+		Some((event, origin))
+	})
+}
+
+/// Serve the health of each remote cluster node as JSON.
+///
+/// This requires a cluster token in the `Authorization: Bearer` header, so it doesn't end up in URLs or logs.
+async fn serve_cluster(
+	headers: http::HeaderMap,
+	State(state): State<Arc<WebState>>,
+) -> axum::response::Result<axum::Json<HashMap<String, RemoteHealth>>> {
+	let jwt = bearer(&headers).ok_or(StatusCode::UNAUTHORIZED)?;
+	let token = state.auth.verify("", Some(jwt))?;
+	if !token.cluster {
+		return Err(StatusCode::UNAUTHORIZED.into());
+	}
+
+	Ok(axum::Json(state.cluster.remotes()))
+}
+
+// Returns the token from the `Authorization: Bearer` header, if any.
+fn bearer(headers: &http::HeaderMap) -> Option<&str> {
+	let value = headers.get(http::header::AUTHORIZATION)?.to_str().ok()?;
+	let (scheme, token) = value.split_once(' ')?;
+	scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// The query parameters for a fetch, selecting which groups to return.
+///
+/// By default the latest group is returned.
+#[derive(Debug, Deserialize)]
+struct FetchParams {
+	/// A specific group sequence, waiting for it if it's in the future.
+	group: Option<u64>,
+
+	/// The first group after this sequence, waiting for it if necessary (long-poll).
+	after: Option<u64>,
+
+	/// All cached groups starting at this sequence, inclusive.
+	from: Option<u64>,
+
+	/// All cached groups up to this sequence, inclusive.
+	to: Option<u64>,
+
+	/// The latest N cached groups.
+	latest: Option<usize>,
+
+	/// If true, prefix each frame with its group sequence and size.
+	/// This is always the case when multiple groups could be returned.
+	#[serde(default)]
+	framed: bool,
+}
+
+impl FetchParams {
+	fn range(&self) -> Option<FetchRange> {
+		Some(match (self.group, self.after, self.from, self.to, self.latest) {
+			(None, None, None, None, None) => FetchRange::Latest,
+			(Some(group), None, None, None, None) => FetchRange::Group(group),
+			(None, Some(after), None, None, None) => FetchRange::After(after),
+			(None, None, None, None, Some(latest)) => FetchRange::LatestN(latest),
+			(None, None, Some(from), Some(to), None) if from > to => return None,
+			(None, None, from, to, None) => FetchRange::Range(from, to),
+			_ => return None,
+		})
+	}
+}
+
+/// Serve groups for a given track.
+///
+/// A single group is returned as the concatenated frames, with the sequence in the `Moq-Group` header.
+/// Otherwise, the body is framed: each frame is prefixed by a u64 group sequence and a u32 size, both big-endian.
 async fn serve_fetch(
+	ConnectInfo(addr): ConnectInfo<net::SocketAddr>,
 	Path(path): Path<String>,
 	Query(params): Query<Params>,
+	Query(fetch): Query<FetchParams>,
 	State(state): State<Arc<WebState>>,
-) -> axum::response::Result<ServeGroup> {
+) -> axum::response::Result<Response> {
 	// The path containts a broadcast/track
 	let mut path: Vec<&str> = path.split("/").collect();
 	let track = path.pop().unwrap().to_string();
@@ -236,42 +416,103 @@ async fn serve_fetch(
 		return Err(StatusCode::BAD_REQUEST.into());
 	}
 
+	let range = fetch.range().ok_or(StatusCode::BAD_REQUEST)?;
+
 	let broadcast = path.join("/");
-	let token = state.auth.verify(&broadcast, params.jwt.as_deref())?;
+	let token = state
+		.auth
+		.authorize(&broadcast, params.jwt.as_deref(), addr.ip())
+		.await?;
 
 	let origin = match state.cluster.subscriber(&token) {
 		Some(origin) => origin,
 		None => return Err(StatusCode::UNAUTHORIZED.into()),
 	};
 
-	tracing::info!(%broadcast, %track, "fetching track");
+	tracing::info!(%broadcast, %track, ?range, "fetching track");
 
-	let track = moq_lite::Track {
-		name: track,
+	// NOTE: The auth token is already scoped to the broadcast.
+	// The broadcast is pulled from the upstream (if configured) when it's not announced.
+	let consumer = origin.request_broadcast("").ok_or(StatusCode::NOT_FOUND)?;
+	let info = moq_lite::Track {
+		name: track.clone(),
 		priority: 0,
 	};
 
-	// NOTE: The auth token is already scoped to the broadcast.
-	let broadcast = origin.consume_broadcast("").ok_or(StatusCode::NOT_FOUND)?;
-	let mut track = broadcast.subscribe_track(&track);
+	let groups = state
+		.fetch
+		.fetch(origin.absolute("").to_owned(), track, range, || {
+			consumer.subscribe_track(&info)
+		})
+		.await;
+
+	let groups = match groups {
+		Ok(groups) => groups,
+		// Nothing arrived before the deadline, so the client should poll again.
+		Err(moq_lite::Error::Timeout) => return Ok(StatusCode::NO_CONTENT.into_response()),
+		Err(moq_lite::Error::NotFound | moq_lite::Error::Cancel) => return Err(StatusCode::NOT_FOUND.into()),
+		Err(err) => {
+			tracing::warn!(%broadcast, ?range, %err, "failed to fetch track");
+			return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+		}
+	};
+
+	let cache = cache_control(range, &groups);
+
+	let mut response = match range {
+		FetchRange::Latest | FetchRange::Group(_) | FetchRange::After(_) => {
+			let group = groups.into_iter().next().ok_or(StatusCode::NOT_FOUND)?;
+			let sequence = group.info.sequence;
+
+			let mut response = ServeGroup::new(vec![group], fetch.framed).into_response();
+			response.headers_mut().insert("moq-group", sequence.into());
+			response
+		}
+		_ if groups.is_empty() => return Err(StatusCode::NOT_FOUND.into()),
+		_ => ServeGroup::new(groups, true).into_response(),
+	};
+
+	response
+		.headers_mut()
+		.insert(http::header::CACHE_CONTROL, http::HeaderValue::from_static(cache));
+
+	Ok(response)
+}
 
-	let group = match track.next_group().await {
-		Ok(Some(group)) => group,
-		Ok(None) => return Err(StatusCode::NOT_FOUND.into()),
-		Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into()),
+/// Only a complete range of closed groups can be cached by a CDN.
+///
+/// Even then the max-age is short, since the group sequences start over when the publisher reconnects.
+fn cache_control(range: FetchRange, groups: &[moq_lite::GroupConsumer]) -> &'static str {
+	let (from, to) = match range {
+		FetchRange::Group(sequence) => (sequence, sequence),
+		FetchRange::Range(Some(from), Some(to)) => (from, to),
+		_ => return "no-cache",
 	};
 
-	Ok(ServeGroup::new(group))
+	// The groups are sorted and unique, so they're complete if the count matches.
+	let complete = groups.first().is_some_and(|g| g.info.sequence == from)
+		&& to - from == groups.len() as u64 - 1
+		&& groups.iter().all(|g| g.is_closed());
+
+	match complete {
+		true => "public, max-age=10",
+		false => "no-cache",
+	}
 }
 
 struct ServeGroup {
-	group: moq_lite::GroupConsumer,
+	groups: VecDeque<moq_lite::GroupConsumer>,
 	frame: Option<moq_lite::FrameConsumer>,
+	framed: bool,
 }
 
 impl ServeGroup {
-	fn new(group: moq_lite::GroupConsumer) -> Self {
-		Self { group, frame: None }
+	fn new(groups: Vec<moq_lite::GroupConsumer>, framed: bool) -> Self {
+		Self {
+			groups: groups.into(),
+			frame: None,
+			framed,
+		}
 	}
 
 	async fn next(&mut self) -> moq_lite::Result<Option<Bytes>> {
@@ -279,12 +520,31 @@ impl ServeGroup {
 			if let Some(frame) = self.frame.as_mut() {
 				let data = frame.read_all().await?;
 				self.frame.take();
-				return Ok(Some(data));
+
+				if !self.framed {
+					return Ok(Some(data));
+				}
+
+				// Prefix the frame with the group sequence and size.
+				// Fail the response instead of truncating the size of a huge frame.
+				let size = u32::try_from(data.len()).map_err(|_| moq_lite::Error::TooLarge)?;
+				let sequence = self.groups.front().expect("missing group").info.sequence;
+				let mut buf = BytesMut::with_capacity(12 + data.len());
+				buf.put_u64(sequence);
+				buf.put_u32(size);
+				buf.put(data);
+
+				return Ok(Some(buf.freeze()));
 			}
 
-			self.frame = self.group.next_frame().await?;
+			let group = match self.groups.front_mut() {
+				Some(group) => group,
+				None => return Ok(None),
+			};
+
+			self.frame = group.next_frame().await?;
 			if self.frame.is_none() {
-				return Ok(None);
+				self.groups.pop_front();
 			}
 		}
 	}
@@ -292,7 +552,16 @@ impl ServeGroup {
 
 impl IntoResponse for ServeGroup {
 	fn into_response(self) -> Response {
-		Response::new(Body::new(self))
+		let content_type = match self.framed {
+			true => "application/vnd.moq.frames",
+			false => "application/octet-stream",
+		};
+
+		let mut response = Response::new(Body::new(self));
+		response
+			.headers_mut()
+			.insert(http::header::CONTENT_TYPE, http::HeaderValue::from_static(content_type));
+		response
 	}
 }
 
@@ -378,3 +647,110 @@ fn tungstenite_to_axum(
 fn default_true() -> bool {
 	true
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use moq_lite::Group;
+
+	fn params(query: &str) -> Option<FetchRange> {
+		let uri: http::Uri = format!("/fetch/test/video?{query}").parse().unwrap();
+		let Query(params) = Query::<FetchParams>::try_from_uri(&uri).unwrap();
+		params.range()
+	}
+
+	// Read the next chunk of a streamed body.
+	async fn next(body: &mut axum::body::BodyDataStream) -> String {
+		let chunk = body.next().await.unwrap().unwrap();
+		String::from_utf8(chunk.to_vec()).unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_announced_stream() {
+		let origin = moq_lite::Origin::produce();
+		let existing = moq_lite::Broadcast::produce();
+		origin.producer.publish_broadcast("existing", existing.consumer);
+
+		let sse = announced_sse(origin.consumer.consume());
+		assert_eq!(sse.headers()[http::header::CONTENT_TYPE], "text/event-stream");
+		let mut sse = sse.into_body().into_data_stream();
+
+		let ndjson = announced_ndjson(origin.consumer.consume());
+		assert_eq!(ndjson.headers()[http::header::CONTENT_TYPE], "application/x-ndjson");
+		let mut ndjson = ndjson.into_body().into_data_stream();
+
+		// Broadcasts that are already active are included first.
+		assert_eq!(next(&mut sse).await, "event: active\ndata: existing\n\n");
+		assert_eq!(next(&mut ndjson).await, "{\"path\":\"existing\",\"active\":true}\n");
+
+		let broadcast = moq_lite::Broadcast::produce();
+		origin.producer.publish_broadcast("live", broadcast.consumer);
+
+		assert_eq!(next(&mut sse).await, "event: active\ndata: live\n\n");
+		assert_eq!(next(&mut ndjson).await, "{\"path\":\"live\",\"active\":true}\n");
+
+		// Dropping the producer unannounces the broadcast.
+		drop(broadcast.producer);
+
+		assert_eq!(next(&mut sse).await, "event: ended\ndata: live\n\n");
+		assert_eq!(next(&mut ndjson).await, "{\"path\":\"live\",\"active\":false}\n");
+	}
+
+	#[test]
+	fn test_bearer() {
+		let mut headers = http::HeaderMap::new();
+		assert_eq!(bearer(&headers), None);
+
+		headers.insert(http::header::AUTHORIZATION, "Bearer abc.def".parse().unwrap());
+		assert_eq!(bearer(&headers), Some("abc.def"));
+
+		headers.insert(http::header::AUTHORIZATION, "bearer  abc.def".parse().unwrap());
+		assert_eq!(bearer(&headers), Some("abc.def"));
+
+		headers.insert(http::header::AUTHORIZATION, "Basic abc".parse().unwrap());
+		assert_eq!(bearer(&headers), None);
+	}
+
+	#[test]
+	fn test_fetch_range() {
+		assert_eq!(params(""), Some(FetchRange::Latest));
+		assert_eq!(params("group=3"), Some(FetchRange::Group(3)));
+		assert_eq!(params("from=2&to=4"), Some(FetchRange::Range(Some(2), Some(4))));
+		assert_eq!(params("from=4&to=2"), None);
+		assert_eq!(params("group=3&after=2"), None);
+	}
+
+	#[test]
+	fn test_cache_control() {
+		let groups: Vec<_> = (2..5u64).map(|sequence| Group { sequence }.produce()).collect();
+		let consumers: Vec<_> = groups.iter().map(|g| g.consumer.clone()).collect();
+
+		// Groups that are still being written are never cached.
+		assert_eq!(cache_control(FetchRange::Group(2), &consumers[..1]), "no-cache");
+
+		for group in groups {
+			group.producer.close();
+		}
+
+		assert_eq!(
+			cache_control(FetchRange::Group(2), &consumers[..1]),
+			"public, max-age=10"
+		);
+		assert_eq!(
+			cache_control(FetchRange::Range(Some(2), Some(4)), &consumers),
+			"public, max-age=10"
+		);
+
+		// Missing groups make the response partial.
+		assert_eq!(
+			cache_control(FetchRange::Range(Some(2), Some(5)), &consumers),
+			"no-cache"
+		);
+		assert_eq!(
+			cache_control(FetchRange::Range(Some(1), Some(4)), &consumers),
+			"no-cache"
+		);
+		assert_eq!(cache_control(FetchRange::Range(Some(2), None), &consumers), "no-cache");
+		assert_eq!(cache_control(FetchRange::Latest, &consumers[2..]), "no-cache");
+	}
+}
@@ -0,0 +1,453 @@
+use std::{collections::HashMap, net, sync::Mutex, time::Duration};
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use url::Url;
+
+use moq_lite::{AsPath, Path};
+
+use crate::AuthError;
+
+/// The maximum number of cached responses; the least recently used is evicted to make room.
+const CACHE_CAPACITY: usize = 1024;
+
+#[derive(clap::Args, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct WebhookConfig {
+	/// Authorize clients by calling this HTTP endpoint, unless their token is signed by the root key.
+	///
+	/// The relay POSTs `{"path", "token", "address"}` as JSON and expects `{"subject", "publish", "subscribe"}` back,
+	/// where the prefixes are relative to the path. A 401 or 403 response rejects the client.
+	#[arg(long = "auth-webhook", id = "auth-webhook", env = "MOQ_AUTH_WEBHOOK")]
+	pub url: Option<Url>,
+
+	/// How long to cache each response from the webhook.
+	/// Defaults to 60s.
+	#[arg(
+		long = "auth-webhook-cache",
+		id = "auth-webhook-cache",
+		env = "MOQ_AUTH_WEBHOOK_CACHE",
+		value_parser = humantime::parse_duration
+	)]
+	#[serde(with = "humantime_serde")]
+	pub cache: Option<Duration>,
+
+	/// How long to wait for the webhook to respond.
+	/// Defaults to 5s.
+	#[arg(
+		long = "auth-webhook-timeout",
+		id = "auth-webhook-timeout",
+		env = "MOQ_AUTH_WEBHOOK_TIMEOUT",
+		value_parser = humantime::parse_duration
+	)]
+	#[serde(with = "humantime_serde")]
+	pub timeout: Option<Duration>,
+
+	/// Grant subscribe-only access to the requested path when the webhook is unavailable, instead of rejecting the client.
+	/// A response that denies access is always respected.
+	/// Access is never granted to the root or the cluster prefix this way.
+	#[arg(
+		long = "auth-webhook-fail-open",
+		id = "auth-webhook-fail-open",
+		env = "MOQ_AUTH_WEBHOOK_FAIL_OPEN"
+	)]
+	pub fail_open: bool,
+
+	/// Prefixes that failing open never grants access to, set from the cluster prefix.
+	#[arg(skip)]
+	#[serde(skip)]
+	pub protected: Vec<String>,
+}
+
+/// The body sent to the webhook.
+#[derive(Serialize)]
+struct WebhookRequest<'a> {
+	path: &'a str,
+	token: Option<&'a str>,
+	address: net::IpAddr,
+}
+
+/// The body returned by the webhook when access is granted.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct WebhookResponse {
+	subject: Option<String>,
+	publish: Vec<String>,
+	subscribe: Vec<String>,
+}
+
+// The path, token, and client IP.
+type CacheKey = (String, Option<String>, net::IpAddr);
+
+struct CacheEntry {
+	expires: Instant,
+	used: Instant,
+	res: Result<moq_token::Claims, AuthError>,
+}
+
+/// Asks an external HTTP endpoint whether a client may publish or subscribe.
+pub struct Webhook {
+	url: Url,
+	client: reqwest::Client,
+	ttl: Duration,
+	fail_open: bool,
+	protected: Vec<moq_lite::PathOwned>,
+	cache: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl Webhook {
+	pub fn new(config: WebhookConfig) -> anyhow::Result<Option<Self>> {
+		let Some(url) = config.url else {
+			return Ok(None);
+		};
+
+		anyhow::ensure!(
+			matches!(url.scheme(), "http" | "https"),
+			"unsupported webhook scheme: {}",
+			url.scheme()
+		);
+
+		let client = reqwest::Client::builder()
+			.use_rustls_tls()
+			.timeout(config.timeout.unwrap_or(Duration::from_secs(5)))
+			.build()?;
+
+		Ok(Some(Self {
+			url,
+			client,
+			ttl: config.cache.unwrap_or(Duration::from_secs(60)),
+			fail_open: config.fail_open,
+			protected: config.protected.iter().map(|p| p.as_path().to_owned()).collect(),
+			cache: Default::default(),
+		}))
+	}
+
+	/// Return the claims for the client, with the publish and subscribe prefixes relative to the path.
+	///
+	/// Both grants and denials are cached, but failures are not.
+	pub async fn authorize(
+		&self,
+		path: &str,
+		token: Option<&str>,
+		address: net::IpAddr,
+	) -> Result<moq_token::Claims, AuthError> {
+		let address = address.to_canonical();
+		let key = (path.to_string(), token.map(str::to_string), address);
+
+		if let Some(entry) = self.cache.lock().unwrap().get_mut(&key) {
+			let now = Instant::now();
+			if entry.expires > now {
+				entry.used = now;
+				return entry.res.clone();
+			}
+		}
+
+		let res = match self.request(path, token, address).await {
+			Ok(res) => res,
+			Err(err) if self.fail_open(path) => {
+				tracing::warn!(%err, %path, "auth webhook failed; allowing subscribe-only access");
+				return Ok(moq_token::Claims {
+					subscribe: vec!["".to_string()],
+					..Default::default()
+				});
+			}
+			Err(err) => {
+				tracing::warn!(%err, %path, "auth webhook failed; denying access");
+				return Err(AuthError::WebhookFailed);
+			}
+		};
+
+		let mut cache = self.cache.lock().unwrap();
+		let now = Instant::now();
+
+		if cache.len() >= CACHE_CAPACITY {
+			cache.retain(|_, entry| entry.expires > now);
+		}
+
+		// Clients choose the token, so the cache is capped even if nothing has expired.
+		if cache.len() >= CACHE_CAPACITY {
+			let oldest = cache
+				.iter()
+				.min_by_key(|(_, entry)| entry.used)
+				.map(|(key, _)| key.clone());
+			if let Some(oldest) = oldest {
+				cache.remove(&oldest);
+			}
+		}
+
+		cache.insert(
+			key,
+			CacheEntry {
+				expires: now + self.ttl,
+				used: now,
+				res: res.clone(),
+			},
+		);
+
+		res
+	}
+
+	// Whether to grant access to the path when the webhook is unavailable.
+	// Never for the root or anything that overlaps a protected prefix, since the client could read everything below it.
+	fn fail_open(&self, path: &str) -> bool {
+		let path = Path::new(path);
+
+		self.fail_open
+			&& !path.is_empty()
+			&& !self
+				.protected
+				.iter()
+				.any(|prefix| path.has_prefix(prefix) || prefix.has_prefix(&path))
+	}
+
+	async fn request(
+		&self,
+		path: &str,
+		token: Option<&str>,
+		address: net::IpAddr,
+	) -> anyhow::Result<Result<moq_token::Claims, AuthError>> {
+		let request = WebhookRequest { path, token, address };
+		let resp = self.client.post(self.url.clone()).json(&request).send().await?;
+
+		match resp.status() {
+			status if status.is_success() => {
+				let body: WebhookResponse = resp.json().await?;
+				Ok(Ok(moq_token::Claims {
+					subject: body.subject,
+					publish: body.publish,
+					subscribe: body.subscribe,
+					..Default::default()
+				}))
+			}
+			StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(Err(AuthError::Denied)),
+			status => anyhow::bail!("unexpected status: {status}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	};
+
+	use axum::{extract::State, response::IntoResponse, Json};
+
+	use super::*;
+	use crate::{Auth, AuthConfig};
+
+	// A stand-in for a customer's auth service, counting the number of requests.
+	async fn handler(
+		State(calls): State<Arc<AtomicUsize>>,
+		Json(req): Json<serde_json::Value>,
+	) -> axum::response::Response {
+		calls.fetch_add(1, Ordering::SeqCst);
+		assert_eq!(req["address"], "127.0.0.1");
+
+		match req["token"].as_str() {
+			Some("good") => Json(serde_json::json!({
+				"subject": "alice",
+				"publish": ["alice"],
+				"subscribe": [""],
+			}))
+			.into_response(),
+			Some("broken") => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			_ => StatusCode::FORBIDDEN.into_response(),
+		}
+	}
+
+	async fn serve() -> (Url, Arc<AtomicUsize>) {
+		let calls = Arc::new(AtomicUsize::new(0));
+		let app = axum::Router::new()
+			.route("/auth", axum::routing::post(handler))
+			.with_state(calls.clone());
+
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/auth", listener.local_addr().unwrap())
+			.parse()
+			.unwrap();
+		tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+		(url, calls)
+	}
+
+	fn auth(url: Url, fail_open: bool) -> Auth {
+		Auth::new(AuthConfig {
+			webhook: WebhookConfig {
+				url: Some(url),
+				fail_open,
+				protected: vec!["internal/origins".to_string()],
+				..Default::default()
+			},
+			..Default::default()
+		})
+		.unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_webhook_grant() {
+		let (url, calls) = serve().await;
+		let auth = auth(url, false);
+		let address = "::ffff:127.0.0.1".parse().unwrap();
+
+		let token = auth.authorize("/demo", Some("good"), address).await.unwrap();
+		assert_eq!(token.root, "demo".as_path());
+		assert_eq!(token.subject.as_deref(), Some("alice"));
+		assert_eq!(token.publish, vec![Path::new("alice").to_owned()]);
+		assert_eq!(token.subscribe, vec!["".as_path()]);
+		assert!(!token.cluster);
+
+		// The response is cached.
+		auth.authorize("/demo", Some("good"), address).await.unwrap();
+		assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+		// But not for a different path.
+		auth.authorize("/other", Some("good"), address).await.unwrap();
+		assert_eq!(calls.load(Ordering::SeqCst), 2);
+	}
+
+	#[tokio::test]
+	async fn test_webhook_deny() {
+		let (url, calls) = serve().await;
+		let auth = auth(url, true);
+		let address = "127.0.0.1".parse().unwrap();
+
+		// A denial is respected even when failing open, and cached.
+		for _ in 0..2 {
+			let err = auth.authorize("/demo", Some("bad"), address).await.unwrap_err();
+			assert!(matches!(err, AuthError::Denied));
+
+			let err = auth.authorize("/demo", None, address).await.unwrap_err();
+			assert!(matches!(err, AuthError::Denied));
+		}
+
+		assert_eq!(calls.load(Ordering::SeqCst), 2);
+	}
+
+	#[tokio::test]
+	async fn test_webhook_unavailable() {
+		let (url, calls) = serve().await;
+		let address = "127.0.0.1".parse().unwrap();
+
+		// An unexpected status is a failure, which is not cached.
+		let auth = auth(url.clone(), false);
+		for _ in 0..2 {
+			let err = auth.authorize("/demo", Some("broken"), address).await.unwrap_err();
+			assert!(matches!(err, AuthError::WebhookFailed));
+		}
+		assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+		// Failing open grants subscribe-only access to the requested path.
+		let auth = self::auth(url, true);
+		let token = auth.authorize("/demo", Some("broken"), address).await.unwrap();
+		assert_eq!(token.root, "demo".as_path());
+		assert!(token.publish.is_empty());
+		assert_eq!(token.subscribe, vec!["".as_path()]);
+
+		// But never at the root, which would include everything.
+		for path in ["", "/"] {
+			let err = auth.authorize(path, Some("broken"), address).await.unwrap_err();
+			assert!(matches!(err, AuthError::WebhookFailed));
+		}
+
+		// Or within the cluster prefix, or any path containing it.
+		for path in ["/internal/origins", "/internal/origins/node", "/internal"] {
+			let err = auth.authorize(path, Some("broken"), address).await.unwrap_err();
+			assert!(matches!(err, AuthError::WebhookFailed));
+		}
+
+		// Nothing is listening on this port.
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let closed: Url = format!("http://{}/auth", listener.local_addr().unwrap())
+			.parse()
+			.unwrap();
+		drop(listener);
+
+		let auth = self::auth(closed, false);
+		let err = auth.authorize("/demo", Some("good"), address).await.unwrap_err();
+		assert!(matches!(err, AuthError::WebhookFailed));
+	}
+
+	#[tokio::test]
+	async fn test_webhook_cache_capacity() {
+		let (url, calls) = serve().await;
+		let webhook = Webhook::new(WebhookConfig {
+			url: Some(url),
+			..Default::default()
+		})
+		.unwrap()
+		.unwrap();
+		let address = "127.0.0.1".parse().unwrap();
+
+		webhook.authorize("demo", Some("good"), address).await.unwrap();
+
+		// Random tokens can't grow the cache beyond its capacity, even before they expire.
+		for i in 0..CACHE_CAPACITY {
+			let token = format!("random-{i}");
+			let err = webhook.authorize("demo", Some(&token), address).await.unwrap_err();
+			assert!(matches!(err, AuthError::Denied));
+		}
+
+		assert_eq!(webhook.cache.lock().unwrap().len(), CACHE_CAPACITY);
+
+		// The least recently used entry was evicted, so it's requested again.
+		assert_eq!(calls.load(Ordering::SeqCst), CACHE_CAPACITY + 1);
+		webhook.authorize("demo", Some("good"), address).await.unwrap();
+		assert_eq!(calls.load(Ordering::SeqCst), CACHE_CAPACITY + 2);
+	}
+
+	#[tokio::test]
+	async fn test_webhook_https() {
+		// Nothing is listening on this port, but the TLS connector must be available to try.
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let url: Url = format!("https://{}/auth", listener.local_addr().unwrap())
+			.parse()
+			.unwrap();
+		drop(listener);
+
+		let webhook = Webhook::new(WebhookConfig {
+			url: Some(url.clone()),
+			..Default::default()
+		})
+		.unwrap()
+		.unwrap();
+
+		let err = webhook.client.post(url).send().await.unwrap_err();
+		assert!(err.is_connect(), "{err:?}");
+
+		// Other schemes are rejected up front.
+		let res = Webhook::new(WebhookConfig {
+			url: Some("ftp://localhost/auth".parse().unwrap()),
+			..Default::default()
+		});
+		assert!(res.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_webhook_public() {
+		let (url, calls) = serve().await;
+		let address = "127.0.0.1".parse().unwrap();
+
+		let auth = Auth::new(AuthConfig {
+			public: Some("anon".to_string()),
+			webhook: WebhookConfig {
+				url: Some(url),
+				..Default::default()
+			},
+			..Default::default()
+		})
+		.unwrap();
+
+		// Anonymous clients within the public path don't need the webhook.
+		let token = auth.authorize("/anon/room", None, address).await.unwrap();
+		assert_eq!(token.root, "anon/room".as_path());
+		assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+		// Anything else is still sent to the webhook.
+		let err = auth.authorize("/demo", None, address).await.unwrap_err();
+		assert!(matches!(err, AuthError::Denied));
+		assert_eq!(calls.load(Ordering::SeqCst), 1);
+	}
+}
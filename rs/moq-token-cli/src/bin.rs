@@ -43,6 +43,10 @@ enum Commands {
 		#[arg(long, default_value = "")]
 		root: String,
 
+		/// An optional identifier for the user, used by the relay for per-user limits.
+		#[arg(long)]
+		subject: Option<String>,
+
 		/// If specified, the user can publish any matching path prefixes.
 		/// If not specified, the user will not publish any broadcasts.
 		/// This can be specified multiple times to publish multiple paths.
@@ -92,6 +96,7 @@ fn main() -> anyhow::Result<()> {
 
 		Commands::Sign {
 			root,
+			subject,
 			publish,
 			cluster,
 			subscribe,
@@ -102,6 +107,7 @@ fn main() -> anyhow::Result<()> {
 
 			let payload = moq_token::Claims {
 				root,
+				subject,
 				publish,
 				cluster,
 				subscribe,
@@ -32,6 +32,11 @@ pub struct Claims {
 	#[serde(default, rename = "root", skip_serializing_if = "String::is_empty")]
 	pub root: String,
 
+	/// An optional identifier for the user, such as an account or stream key ID.
+	/// The relay uses it to apply per-user limits and in logs.
+	#[serde(rename = "sub")]
+	pub subject: Option<String>,
+
 	/// If specified, the user can publish any matching broadcasts.
 	/// If not specified, the user will not publish any broadcasts.
 	#[serde(
@@ -45,8 +50,8 @@ pub struct Claims {
 	/// If true, then this client is considered a cluster node.
 	/// Both the client and server will only announce broadcasts from non-cluster clients.
 	/// This avoids convoluted routing, as only the primary origin will announce.
-	//
-	// TODO This shouldn't be part of the token.
+	///
+	/// Deprecated: relays should authenticate each other with mutual TLS client certificates instead.
 	#[serde(default, rename = "cluster", skip_serializing_if = "is_false")]
 	pub cluster: bool,
 
@@ -96,6 +101,7 @@ mod tests {
 			subscribe: vec!["test-sub".into()],
 			expires: Some(SystemTime::now() + Duration::from_secs(3600)),
 			issued: Some(SystemTime::now()),
+			subject: None,
 		}
 	}
 
@@ -114,6 +120,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		let result = claims.validate();
@@ -133,6 +140,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		assert!(claims.validate().is_ok());
@@ -147,6 +155,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		assert!(claims.validate().is_ok());
@@ -161,6 +170,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		let result = claims.validate();
@@ -176,6 +186,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		let result = claims.validate();
@@ -191,6 +202,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		assert!(claims.validate().is_ok());
@@ -205,6 +217,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		assert!(claims.validate().is_ok());
@@ -219,6 +232,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		assert!(claims.validate().is_ok());
@@ -233,6 +247,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		assert!(claims.validate().is_ok());
@@ -247,6 +262,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		assert!(claims.validate().is_ok());
@@ -261,6 +277,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		assert!(claims.validate().is_ok());
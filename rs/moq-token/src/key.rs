@@ -521,6 +521,7 @@ mod tests {
 			subscribe: vec!["test-sub".into()],
 			expires: Some(SystemTime::now() + Duration::from_secs(3600)),
 			issued: Some(SystemTime::now()),
+			subject: None,
 		}
 	}
 
@@ -618,6 +619,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 
 		let result = key.encode(&invalid_claims);
@@ -693,6 +695,7 @@ mod tests {
 			cluster: false,
 			expires: None,
 			issued: None,
+			subject: None,
 		};
 		let token = key.encode(&claims).unwrap();
 
@@ -713,6 +716,7 @@ mod tests {
 			cluster: true,
 			expires: Some(SystemTime::now() + Duration::from_secs(3600)),
 			issued: Some(SystemTime::now()),
+			subject: None,
 		};
 
 		let token = key.encode(&original_claims).unwrap();